@@ -7,7 +7,7 @@ fn bench_create_extractor_big(c: &mut Criterion) {
     use nds::Extractor;
 
     c.bench_function("create extract big", move |b| b.iter(|| {
-        let ex = Extractor::new("big.nds", true).unwrap();
+        Extractor::new("big.nds", true).unwrap()
     }));
 }
 
@@ -15,7 +15,7 @@ fn bench_create_extractor_small(c: &mut Criterion) {
     use nds::Extractor;
 
     c.bench_function("create extract small", move |b| b.iter(|| {
-        let ex = Extractor::new("small.nds", true).unwrap();
+        Extractor::new("small.nds", true).unwrap()
     }));
 }
 
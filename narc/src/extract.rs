@@ -1,13 +1,12 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use memmap::Mmap;
-use num::NumCast;
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::NitroFile;
+use nitro_fs::fat::AllocInfo;
 use rayon::prelude::*;
 
-use std::fs::{create_dir_all, File};
-use std::path::Path;
+use std::fs::{create_dir_all, read};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, ensure};
-use thiserror;
 
 // == Errors ==
 #[derive(thiserror::Error, Debug)]
@@ -25,25 +24,24 @@ enum NarcError {
     WriteError(Vec<anyhow::Error>),
 }
 
-enum Header {
-    Size = 0x08,
-    FileCount = 0x18,
-    FatOffset = 0x1C,
-}
+/// Section magics, which are stored reversed.
+const FAT: &[u8; 4] = b"BTAF";
+const FNT: &[u8; 4] = b"BTNF";
+const IMAGE: &[u8; 4] = b"GMIF";
+
+/// Offset of the entries in the FAT section, after the file count and
+/// two reserved bytes.
+const FAT_ENTRIES: usize = 4;
 
 /// Extracts files from an Nitro Archive.
 #[derive(Debug)]
 pub struct Extractor {
-    /// A memmap of the NARC to allow easy reading for potentially large files.
-    data: Mmap,
+    file: NitroFile,
 }
 
 impl Extractor {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let root = path.as_ref();
-
-        let file = File::open(root)?;
-        let data = unsafe { Mmap::map(&file)? };
+        let data = read(path)?;
 
         //  Minimum acceptable NARC size
         ensure!(data.len() > 0x1C, NarcError::NotEnoughData);
@@ -51,69 +49,73 @@ impl Extractor {
         //  All NARC files must start with "NARC"
         ensure!(&data[..4] == b"NARC", NarcError::InvalidHeader);
 
-        let narc_size = (&data[Header::Size as usize..]).read_u32::<LittleEndian>()?;
+        let narc_size = LittleEndian::read_u32(&data[0x08..]);
         ensure!(data.len() == narc_size as usize, NarcError::SizeMismatch);
 
+        let file = NitroFile::parse_as(&data, b"NARC")?;
+
+        for magic in &[FAT, FNT, IMAGE] {
+            file.require(magic)?;
+        }
+
         Ok(Self {
-            data,
+            file,
         })
     }
 
+    /// Extracts every file in the archive. Archives without file names
+    /// have their files named after their IDs, such as `0003.bin`.
     pub fn extract<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         use nitro_fs::FileSystem;
 
-        let file_count = self.read_u16(Header::FileCount as usize)? as usize;
-        let fat_offset = Header::FatOffset as usize;
+        let fat = &self.file.require(FAT)?.data;
+        let fnt = &self.file.require(FNT)?.data;
+        let image = &self.file.require(IMAGE)?.data;
+
+        ensure!(fat.len() >= FAT_ENTRIES, NarcError::NotEnoughData);
+
+        let file_count = LittleEndian::read_u16(fat) as usize;
+        let entries = fat.get(FAT_ENTRIES..FAT_ENTRIES + file_count * 8).ok_or(NarcError::NotEnoughData)?;
 
-        let fat = &self.data[fat_offset..fat_offset + file_count * 8];
+        let fs = FileSystem::new(fnt, entries)?;
+
+        let files: Vec<(PathBuf, AllocInfo)> = if fs.files().is_empty() {
+            entries
+                .chunks_exact(8)
+                .enumerate()
+                .map(|(id, entry)| {
+                    let alloc = AllocInfo {
+                        start: LittleEndian::read_u32(entry),
+                        end: LittleEndian::read_u32(&entry[4..]),
+                    };
+
+                    (PathBuf::from(format!("{:04}.bin", id)), alloc)
+                })
+                .collect()
+        } else {
+            fs.files().into_iter().map(|file| (file.path.clone(), file.alloc)).collect()
+        };
 
         create_dir_all(&path)?;
 
-        let fs = FileSystem::default();
         let base = path.as_ref();
 
-        // TODO: Grab FNT and create a NitroFS FileSystem.
-        let errors = fs.files()
+        let errors = files
             .par_iter()
-            .filter_map(|file| {
-                match self.write(&base.join(&file.path), file.alloc.start, file.alloc.len()) {
-                    Ok(_) => None,
-                    Err(why) => Some(why),
-                }
-            })
+            .filter_map(|(path, alloc)| self.write(base.join(path), image, *alloc).err())
             .collect::<Vec<anyhow::Error>>();
 
         ensure!(errors.is_empty(), NarcError::WriteError(errors));
         Ok(())
     }
 
-    /// Reads a u16 from `data` at the given offset.
-    fn read_u16(&self, offset: usize) -> Result<u16> {
-        let value = (&self.data[offset..]).read_u16::<LittleEndian>()?;
-        Ok(value)
-    }
-
-    /// Reads a u32 from `data` at the given offset.
-    fn read_u32(&self, offset: usize) -> Result<u32> {
-        let value = (&self.data[offset..]).read_u32::<LittleEndian>()?;
-        Ok(value)
-    }
-
-    /// A utility to make it easier to write chunks of the ROM to files.
-    /// Copies `len` bytes from the ROM starting from `offset` into the file 
-    /// denoted by `path`
-    fn write<P, N1, N2>(&self, path: P, offset: N1, len: N2) -> Result<()>
-        where
-            P: AsRef<Path>,
-            N1: NumCast,
-            N2: NumCast
-    {
+    /// Writes the part of the file image given by `alloc` to `path`.
+    fn write<P: AsRef<Path>>(&self, path: P, image: &[u8], alloc: AllocInfo) -> Result<()> {
         use std::fs::write;
 
-        let offset: usize = NumCast::from(offset).unwrap();
-        let len: usize = NumCast::from(len).unwrap();
-
-        ensure!(self.data.len() >= offset + len, NarcError::NotEnoughData);
+        let data = image
+            .get(alloc.start as usize..alloc.end as usize)
+            .ok_or(NarcError::NotEnoughData)?;
 
         {
             let parent = path.as_ref().parent().unwrap_or(Path::new(""));
@@ -123,7 +125,7 @@ impl Extractor {
             }
         }
 
-        write(path, &self.data[offset..offset + len])?;
+        write(path, data)?;
 
         Ok(())
     }
@@ -0,0 +1,259 @@
+//! The chunked container that almost every Nitro file uses, such as NARC
+//! archives, NCLR palettes, NCGR graphics and SDAT sound archives.
+//!
+//! A file starts with a 0x10 byte header:
+//!
+//! | Offset | Size | Field                          |
+//! |--------|------|--------------------------------|
+//! | 0x00   | 4    | Magic, such as `RLCN` or `NARC` |
+//! | 0x04   | 2    | Byte order mark, `0xFEFF`      |
+//! | 0x06   | 2    | Version                        |
+//! | 0x08   | 4    | Size of the whole file         |
+//! | 0x0C   | 2    | Size of the header             |
+//! | 0x0E   | 2    | Number of sections             |
+//!
+//! Some formats have a longer header, which is kept as is. The sections
+//! follow, each with a 4 byte magic and a size that includes its own 8 byte
+//! header. Magics are stored reversed in most formats, so a palette section
+//! that is documented as `PLTT` appears as `TTLP` in the file. They are
+//! kept as they appear in the file.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use anyhow::{ensure, Result};
+
+/// The byte order mark of a little endian file.
+pub const BOM: u16 = 0xFEFF;
+
+/// Size of the common part of the file header.
+pub const HEADER_LEN: usize = 0x10;
+
+/// Size of the magic and size that start every section.
+pub const SECTION_HEADER_LEN: usize = 8;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum ContainerError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Byte order mark is {0:#06X}, not 0xFEFF.")]
+    InvalidBom(u16),
+
+    #[error("File size in the header ({header:#X}) is larger than the data ({actual:#X}).")]
+    SizeMismatch { header: usize, actual: usize },
+
+    #[error("Section {index} has an invalid size: {size:#X}.")]
+    InvalidSectionSize { index: usize, size: usize },
+
+    #[error("Expected a '{expected}' file, found '{found}'.")]
+    WrongMagic { expected: String, found: String },
+
+    #[error("Missing section: '{0}'.")]
+    MissingSection(String),
+}
+
+/// A tagged section of a [`NitroFile`].
+///
+/// [`NitroFile`]: struct.NitroFile.html
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Section {
+    pub magic: [u8; 4],
+    /// The contents after the section header.
+    pub data: Vec<u8>,
+}
+
+impl Section {
+    pub fn new(magic: [u8; 4], data: Vec<u8>) -> Self {
+        Self { magic, data }
+    }
+
+    /// The magic as text, for messages.
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.magic).into_owned()
+    }
+}
+
+/// A file made of a common header and tagged sections.
+///
+/// # Example
+/// ```no_run
+/// use nitro_fs::container::NitroFile;
+///
+/// let data = std::fs::read("palette.nclr").unwrap();
+/// let file = NitroFile::parse(&data).unwrap();
+///
+/// if let Some(palette) = file.section(b"TTLP") {
+///     println!("{} bytes of palette data", palette.data.len());
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct NitroFile {
+    pub magic: [u8; 4],
+    pub version: u16,
+    /// Whatever comes between the common header and the first section.
+    pub extra_header: Vec<u8>,
+    pub sections: Vec<Section>,
+}
+
+impl NitroFile {
+    /// Creates an empty file.
+    pub fn new(magic: [u8; 4], version: u16) -> Self {
+        Self {
+            magic,
+            version,
+            extra_header: Vec::new(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Reads a file and every section in it.
+    ///
+    /// # Errors
+    /// Returns an error if the byte order mark is wrong, if the file is
+    /// shorter than its header says, or if a section doesn't fit.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= HEADER_LEN, ContainerError::NotEnoughData);
+
+        let bom = LittleEndian::read_u16(&data[0x04..]);
+        ensure!(bom == BOM, ContainerError::InvalidBom(bom));
+
+        let size = LittleEndian::read_u32(&data[0x08..]) as usize;
+        ensure!(
+            size <= data.len(),
+            ContainerError::SizeMismatch {
+                header: size,
+                actual: data.len(),
+            }
+        );
+
+        let header_len = LittleEndian::read_u16(&data[0x0C..]) as usize;
+        let count = LittleEndian::read_u16(&data[0x0E..]) as usize;
+
+        ensure!(header_len >= HEADER_LEN && header_len <= size, ContainerError::NotEnoughData);
+
+        let data = &data[..size];
+        let mut sections = Vec::with_capacity(count);
+        let mut offset = header_len;
+
+        for index in 0..count {
+            ensure!(offset + SECTION_HEADER_LEN <= size, ContainerError::NotEnoughData);
+
+            let section_len = LittleEndian::read_u32(&data[offset + 4..]) as usize;
+
+            ensure!(
+                section_len >= SECTION_HEADER_LEN && section_len <= size - offset,
+                ContainerError::InvalidSectionSize {
+                    index,
+                    size: section_len,
+                }
+            );
+
+            let mut magic = [0; 4];
+            magic.copy_from_slice(&data[offset..offset + 4]);
+
+            sections.push(Section {
+                magic,
+                data: data[offset + SECTION_HEADER_LEN..offset + section_len].to_vec(),
+            });
+
+            offset += section_len;
+        }
+
+        let mut magic = [0; 4];
+        magic.copy_from_slice(&data[..4]);
+
+        Ok(Self {
+            magic,
+            version: LittleEndian::read_u16(&data[0x06..]),
+            extra_header: data[HEADER_LEN..header_len].to_vec(),
+            sections,
+        })
+    }
+
+    /// Same as [`parse`], but also checks the magic of the file.
+    ///
+    /// [`parse`]: #method.parse
+    pub fn parse_as(data: &[u8], magic: &[u8; 4]) -> Result<Self> {
+        let file = Self::parse(data)?;
+
+        ensure!(
+            &file.magic == magic,
+            ContainerError::WrongMagic {
+                expected: String::from_utf8_lossy(magic).into_owned(),
+                found: String::from_utf8_lossy(&file.magic).into_owned(),
+            }
+        );
+
+        Ok(file)
+    }
+
+    /// The first section with the given magic.
+    pub fn section(&self, magic: &[u8; 4]) -> Option<&Section> {
+        self.sections.iter().find(|section| &section.magic == magic)
+    }
+
+    pub fn section_mut(&mut self, magic: &[u8; 4]) -> Option<&mut Section> {
+        self.sections.iter_mut().find(|section| &section.magic == magic)
+    }
+
+    /// Same as [`section`], but returns an error if there is no such
+    /// section.
+    ///
+    /// [`section`]: #method.section
+    pub fn require(&self, magic: &[u8; 4]) -> Result<&Section> {
+        self.section(magic)
+            .ok_or_else(|| ContainerError::MissingSection(String::from_utf8_lossy(magic).into_owned()).into())
+    }
+
+    /// Replaces the first section with the same magic, or adds it to the
+    /// end if there is none.
+    pub fn set_section(&mut self, section: Section) {
+        match self.section_mut(&section.magic) {
+            Some(existing) => *existing = section,
+            None => self.sections.push(section),
+        }
+    }
+
+    /// How many bytes the file takes when written.
+    pub fn len(&self) -> usize {
+        HEADER_LEN
+            + self.extra_header.len()
+            + self
+                .sections
+                .iter()
+                .map(|section| SECTION_HEADER_LEN + section.data.len())
+                .sum::<usize>()
+    }
+
+    /// Whether the file has no sections.
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// Writes the file with up to date sizes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; HEADER_LEN];
+
+        data[..4].copy_from_slice(&self.magic);
+        LittleEndian::write_u16(&mut data[0x04..], BOM);
+        LittleEndian::write_u16(&mut data[0x06..], self.version);
+        LittleEndian::write_u32(&mut data[0x08..], self.len() as u32);
+        LittleEndian::write_u16(&mut data[0x0C..], (HEADER_LEN + self.extra_header.len()) as u16);
+        LittleEndian::write_u16(&mut data[0x0E..], self.sections.len() as u16);
+
+        data.extend_from_slice(&self.extra_header);
+
+        for section in &self.sections {
+            let mut header = [0; SECTION_HEADER_LEN];
+
+            header[..4].copy_from_slice(&section.magic);
+            LittleEndian::write_u32(&mut header[4..], (SECTION_HEADER_LEN + section.data.len()) as u32);
+
+            data.extend_from_slice(&header);
+            data.extend_from_slice(&section.data);
+        }
+
+        data
+    }
+}
@@ -0,0 +1,294 @@
+//! Editing an existing file system.
+//!
+//! Every edit keeps the invariants of the FNT: directory IDs are contiguous
+//! from `ROOT_ID`, each directory's files have contiguous IDs in subtable
+//! order, and every directory knows its parent. File IDs index the FAT, so
+//! adding or removing a file shifts the IDs of the files after it.
+
+use std::collections::BTreeMap;
+use std::mem::take;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{ensure, Result};
+
+use crate::fat::AllocInfo;
+use crate::fnt::{Directory, FileEntry, ROOT_ID};
+use crate::{entry_name, FileSystem, FileSystemError};
+
+/// The most directories a FNT can hold, since IDs run from `ROOT_ID` to
+/// `0xFFFF`.
+const MAX_DIRS: usize = 0x1000;
+
+impl FileSystem {
+    /// Adds a file at `path`, returning the ID it was given. The parent
+    /// directory has to exist already.
+    ///
+    /// The file is placed last in its directory, and every file after it
+    /// has its ID shifted up by one.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P, alloc: AllocInfo) -> Result<u16> {
+        let path = path.as_ref();
+        let parent = self.free_path(path)?;
+
+        self.dirs
+            .get_mut(&parent)
+            .unwrap()
+            .files
+            .push(FileEntry::new(0, path, alloc));
+
+        self.renumber_files()?;
+
+        Ok(self.lookup(path).unwrap().id)
+    }
+
+    /// Removes the file at `path` and returns it. Every file after it has
+    /// its ID shifted down by one.
+    pub fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> Result<FileEntry> {
+        let (dir, index) = self.find_file(path.as_ref())?;
+        let file = self.dirs.get_mut(&dir).unwrap().files.remove(index);
+
+        self.renumber_files()?;
+
+        Ok(file)
+    }
+
+    /// Creates an empty directory at `path`, returning its ID. The parent
+    /// directory has to exist already.
+    pub fn mkdir<P: AsRef<Path>>(&mut self, path: P) -> Result<u16> {
+        let path = path.as_ref();
+        let parent = self.free_path(path)?;
+
+        ensure!(self.dirs.len() < MAX_DIRS, FileSystemError::TooManyEntries);
+
+        let id = ROOT_ID + self.dirs.len() as u16;
+
+        //  Sort the new directory after all others until it gets files.
+        self.dirs.insert(id, Directory::from_parts(id, parent, u16::MAX, path));
+        self.dirs.get_mut(&parent).unwrap().add_child(id);
+
+        self.renumber_files()?;
+
+        Ok(id)
+    }
+
+    /// Removes the empty directory at `path`. Every directory with a higher
+    /// ID has its ID shifted down by one.
+    pub fn rmdir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let dir = self.lookup_dir(path).ok_or_else(|| FileSystemError::NotFound(path.to_path_buf()))?;
+
+        ensure!(!dir.is_root(), FileSystemError::RootDirectory);
+        ensure!(
+            dir.files.is_empty() && dir.children().is_empty(),
+            FileSystemError::DirectoryNotEmpty(path.to_path_buf())
+        );
+
+        let id = dir.id();
+        let parent = dir.parent_id();
+
+        self.dirs.remove(&id);
+        self.dirs.get_mut(&parent).unwrap().children_mut().retain(|&child| child != id);
+
+        self.renumber_dirs(id);
+        self.renumber_files()
+    }
+
+    /// Renames the file or directory at `path` without moving it. The entry
+    /// stays where it is in its directory, so no IDs change.
+    ///
+    /// # Errors
+    /// Returns an error if `name` is empty, `.` or `..`, or has a separator.
+    /// [`move_entry`] moves entries to another directory.
+    ///
+    /// [`move_entry`]: #method.move_entry
+    pub fn rename<P: AsRef<Path>>(&mut self, path: P, name: &str) -> Result<()> {
+        let path = path.as_ref();
+        let components = Path::new(name).components().collect::<Vec<_>>();
+
+        ensure!(
+            matches!(components[..], [Component::Normal(part)] if part == OsStr::new(name)),
+            FileSystemError::InvalidName(name.into())
+        );
+
+        let to = path.with_file_name(name);
+
+        if let Ok((dir, index)) = self.find_file(path) {
+            self.free_path(&to)?;
+            self.dirs.get_mut(&dir).unwrap().files[index].path = to;
+
+            return Ok(());
+        }
+
+        let dir = self.lookup_dir(path).ok_or_else(|| FileSystemError::NotFound(path.to_path_buf()))?;
+
+        ensure!(!dir.is_root(), FileSystemError::RootDirectory);
+
+        let id = dir.id();
+
+        self.free_path(&to)?;
+        self.set_dir_path(id, &to);
+
+        Ok(())
+    }
+
+    /// Moves the file or directory at `from` so that it is at `to`, which
+    /// may be in a different directory and have a different name. The
+    /// directory that will contain it has to exist already.
+    ///
+    /// A moved file is placed last in its new directory, which shifts the
+    /// IDs of the files in between. Directories keep their IDs.
+    pub fn move_entry<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> Result<()> {
+        let from = from.as_ref();
+        let to = to.as_ref();
+
+        if let Ok((dir, index)) = self.find_file(from) {
+            let parent = self.free_path(to)?;
+            let mut file = self.dirs.get_mut(&dir).unwrap().files.remove(index);
+
+            file.path = to.to_path_buf();
+            self.dirs.get_mut(&parent).unwrap().files.push(file);
+
+            return self.renumber_files();
+        }
+
+        let dir = self.lookup_dir(from).ok_or_else(|| FileSystemError::NotFound(from.to_path_buf()))?;
+
+        ensure!(!dir.is_root(), FileSystemError::RootDirectory);
+        ensure!(!to.starts_with(from), FileSystemError::InvalidMove);
+
+        let id = dir.id();
+        let old_parent = dir.parent_id();
+        let parent = self.free_path(to)?;
+
+        self.dirs.get_mut(&old_parent).unwrap().children_mut().retain(|&child| child != id);
+        self.dirs.get_mut(&parent).unwrap().add_child(id);
+        self.dirs.get_mut(&id).unwrap().set_parent_id(parent);
+        self.set_dir_path(id, to);
+
+        Ok(())
+    }
+
+    /// Sorts the files of every directory by the IDs in `ids`, and the
+    /// directories by the lowest ID of their files, then numbers the files
+    /// again. The root stays first, since the IDs below it are overlays.
+    /// Files missing from `ids` go last, in the order they had. If `ids`
+    /// holds a valid numbering of every file, each file gets its ID.
+    pub fn order_files(&mut self, ids: &BTreeMap<PathBuf, u16>) -> Result<()> {
+        let key = |file: &FileEntry| ids.get(&file.path).map_or(u32::MAX, |&id| u32::from(id));
+
+        let mut order = Vec::with_capacity(self.dirs.len());
+
+        for dir in self.dirs.values_mut() {
+            dir.files.sort_by_key(key);
+            order.push((!dir.is_root(), dir.files.first().map_or(u32::MAX, key), dir.start_id(), dir.id()));
+        }
+
+        order.sort();
+
+        self.number_files(order.into_iter().map(|(_, _, _, id)| id))
+    }
+
+    /// Makes sure that `path` has a valid name, that its parent directory
+    /// exists and that nothing is at `path` yet. Returns the ID of the
+    /// parent directory.
+    fn free_path(&self, path: &Path) -> Result<u16> {
+        let name = entry_name(path)?;
+        let parent_path = path.parent().unwrap_or_else(|| Path::new(""));
+        let parent = self
+            .lookup_dir(parent_path)
+            .ok_or_else(|| FileSystemError::NotFound(parent_path.to_path_buf()))?;
+
+        let taken = parent.file(name).is_some() || parent
+            .children()
+            .iter()
+            .filter_map(|id| self.dirs.get(id))
+            .any(|child| child.name() == name);
+
+        ensure!(!taken, FileSystemError::AlreadyExists(path.to_path_buf()));
+
+        Ok(parent.id())
+    }
+
+    /// Finds the directory ID and index of the file at `path`.
+    fn find_file(&self, path: &Path) -> Result<(u16, usize)> {
+        let not_found = || FileSystemError::NotFound(path.to_path_buf());
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or_else(not_found)?;
+        let dir = self
+            .lookup_dir(path.parent().unwrap_or_else(|| Path::new("")))
+            .ok_or_else(not_found)?;
+        let index = dir.files.iter().position(|file| file.name() == name).ok_or_else(not_found)?;
+
+        Ok((dir.id(), index))
+    }
+
+    /// Sets the path of a directory, and updates the paths of everything
+    /// inside of it to match.
+    fn set_dir_path(&mut self, id: u16, path: &Path) {
+        let dir = self.dirs.get_mut(&id).unwrap();
+
+        dir.set_path(path);
+
+        for file in &mut dir.files {
+            file.path = path.join(file.name());
+        }
+
+        for child in dir.children().to_vec() {
+            let name = self.dirs[&child].name().to_string();
+            self.set_dir_path(child, &path.join(name));
+        }
+    }
+
+    /// Gives files contiguous IDs per directory, keeping directories in the
+    /// order of their current first IDs. The root keeps its first ID, since
+    /// everything below it belongs to overlays.
+    fn renumber_files(&mut self) -> Result<()> {
+        let mut order = self.dirs
+            .values()
+            .map(|dir| (dir.start_id(), dir.id()))
+            .collect::<Vec<_>>();
+
+        order.sort();
+
+        self.number_files(order.into_iter().map(|(_, id)| id))
+    }
+
+    /// Gives the files contiguous IDs, visiting the directories in `order`.
+    fn number_files(&mut self, order: impl Iterator<Item = u16>) -> Result<()> {
+        let mut next = u32::from(self.start_id());
+
+        for id in order {
+            let dir = self.dirs.get_mut(&id).unwrap();
+
+            ensure!(next + dir.files.len() as u32 <= u32::from(ROOT_ID), FileSystemError::TooManyEntries);
+
+            dir.set_start_id(next as u16);
+
+            for file in &mut dir.files {
+                file.id = next as u16;
+                next += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shifts down every directory ID above `removed` so that they stay
+    /// contiguous.
+    fn renumber_dirs(&mut self, removed: u16) {
+        let shift = |id: u16| if id > removed { id - 1 } else { id };
+
+        self.dirs = take(&mut self.dirs)
+            .into_iter()
+            .map(|(id, mut dir)| {
+                dir.set_id(shift(id));
+                dir.set_parent_id(shift(dir.parent_id()));
+
+                for child in dir.children_mut() {
+                    *child = shift(*child);
+                }
+
+                (shift(id), dir)
+            })
+            .collect::<BTreeMap<_, _>>();
+    }
+}
@@ -10,6 +10,7 @@ struct InvalidFatLen;
 
 /// Represents an entry in the File Allocation Table.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AllocInfo {
     /// The offset to the start of the file relative to the ROM start.
     pub start: u32,
@@ -28,6 +29,11 @@ impl AllocInfo {
     pub fn len(&self) -> u32 {
         self.end - self.start
     }
+
+    /// Whether the allocation covers no data.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
 }
 
 /// Wrapper for handling File Allocation Table stuff
@@ -68,10 +74,6 @@ impl FileAllocTable {
     /// 
     /// If the given ID is not in the list, it will return `None`.
     pub fn get(&self, id: u16) -> Option<AllocInfo> {
-        if self.list.len() >= id as usize {
-            return Some(self.list[id as usize]);
-        }
-
-        None
+        self.list.get(id as usize).copied()
     }
 }
@@ -18,6 +18,7 @@ pub static ROOT_ID: u16 = 0xF000;
 /// `path` will be the full path relative to the root of the
 /// file name table. 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileEntry {
     pub id: u16,
     pub path: PathBuf,
@@ -32,6 +33,11 @@ impl FileEntry {
             alloc,
         }
     }
+
+    /// The name of the file without the directories leading up to it.
+    pub fn name(&self) -> &str {
+        self.path.file_name().and_then(|name| name.to_str()).unwrap_or("")
+    }
 }
 
 /// Represents a NitroROM directory.
@@ -47,11 +53,14 @@ impl FileEntry {
 /// Because the root directory has no parent ID value, a `parent_id`
 /// call on the root directory will return `ROOT_ID`.
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Directory {
     /// Name of the directory
     pub path: PathBuf,
     // Files that are inside this directory
     pub files: Vec<FileEntry>,
+    // IDs of the directories inside this directory, in FNT order
+    children: Vec<u16>,
     offset: u32,
     start_id: u16,
     value: u16,
@@ -64,6 +73,7 @@ impl Directory {
         Ok(Self {
             path: PathBuf::new(),
             files: Vec::new(),
+            children: Vec::new(),
             offset: reader.read_u32::<LittleEndian>()?,
             start_id: reader.read_u16::<LittleEndian>()?,
             value: reader.read_u16::<LittleEndian>()?,
@@ -71,6 +81,22 @@ impl Directory {
         })
     }
 
+    /// Creates an empty directory that has not been read from a FNT.
+    ///
+    /// The offset is left at 0 since it is only known once the table is
+    /// written out again.
+    pub fn from_parts<P: AsRef<Path>>(id: u16, parent_id: u16, start_id: u16, path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            files: Vec::new(),
+            children: Vec::new(),
+            offset: 0,
+            start_id,
+            value: parent_id,
+            id,
+        }
+    }
+
     /// Sets the full path that this directory is referenced by.
     pub fn set_path<P: AsRef<Path>>(&mut self, path: P) {
         self.path = path.as_ref().to_path_buf();
@@ -102,12 +128,65 @@ impl Directory {
         }
     }
 
+    /// The ID of the parent directory, or `None` for the root.
+    pub fn parent(&self) -> Option<u16> {
+        if self.is_root() {
+            None
+        } else {
+            Some(self.value)
+        }
+    }
+
+    /// The IDs of the directories inside this directory, in FNT order.
+    pub fn children(&self) -> &[u16] {
+        &self.children
+    }
+
+    /// The name of the directory, which is empty for the root.
+    pub fn name(&self) -> &str {
+        self.path.file_name().and_then(|name| name.to_str()).unwrap_or("")
+    }
+
+    /// Gets the file in this directory with the given name.
+    pub fn file(&self, name: &str) -> Option<&FileEntry> {
+        self.files.iter().find(|file| file.name() == name)
+    }
+
+    /// Notes that the directory with the given ID is inside this one.
+    pub(crate) fn add_child(&mut self, id: u16) {
+        self.children.push(id);
+    }
+
+    pub(crate) fn children_mut(&mut self) -> &mut Vec<u16> {
+        &mut self.children
+    }
+
+    pub(crate) fn set_id(&mut self, id: u16) {
+        self.id = id;
+    }
+
+    pub(crate) fn set_parent_id(&mut self, id: u16) {
+        if !self.is_root() {
+            self.value = id;
+        }
+    }
+
+    pub(crate) fn set_start_id(&mut self, id: u16) {
+        self.start_id = id;
+    }
+
     /// Whether this directory is the root.
     pub fn is_root(&self) -> bool {
         self.id == ROOT_ID
     }
 
     /// Appends a file to the file list associated with this directory.
+    ///
+    /// This doesn't update the IDs of any other entries, so it should only be
+    /// used while building a file system. Use [`FileSystem::add_file`] to
+    /// edit an existing one.
+    ///
+    /// [`FileSystem::add_file`]: ../struct.FileSystem.html#method.add_file
     pub fn append_file(&mut self, file: FileEntry) {
         self.files.push(file);
     }
@@ -1,20 +1,51 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use rayon::prelude::*;
 
 use std::collections::BTreeMap;
+use std::fs::read_dir;
 use std::io::{Cursor, Read};
 use std::path::Path;
 
 use anyhow::Result;
 
+pub mod container;
+mod edit;
 pub mod fat;
 pub mod fnt;
+pub mod walk;
 
-use self::fat::FileAllocTable;
+use self::fat::{AllocInfo, FileAllocTable};
 use self::fnt::{Directory, FileEntry, ROOT_ID};
+use self::walk::{Entry, Walk};
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum FileSystemError {
+    #[error("Name is too long or not valid UTF-8: {0:?}")]
+    InvalidName(std::ffi::OsString),
+
+    #[error("Too many entries to fit in the file system.")]
+    TooManyEntries,
+
+    #[error("No file or directory at path: '{0}'.")]
+    NotFound(std::path::PathBuf),
+
+    #[error("An entry already exists at path: '{0}'.")]
+    AlreadyExists(std::path::PathBuf),
+
+    #[error("Directory is not empty: '{0}'.")]
+    DirectoryNotEmpty(std::path::PathBuf),
+
+    #[error("The root directory cannot be moved or removed.")]
+    RootDirectory,
+
+    #[error("A directory cannot be moved inside of itself.")]
+    InvalidMove,
+}
 
 /// Represents a NitroROM file system.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileSystem {
     pub dirs: BTreeMap<u16, Directory>,
     overlays: Vec<FileEntry>,
@@ -47,21 +78,253 @@ impl FileSystem {
         Ok(fnt)
     }
 
+    /// Creates a file system that only has an empty root directory, whose
+    /// files will start at `start_id`.
+    pub fn empty(start_id: u16) -> Self {
+        let mut fs = Self::default();
+
+        fs.dirs.insert(ROOT_ID, Directory::from_parts(ROOT_ID, ROOT_ID, start_id, ""));
+
+        fs
+    }
+
+    /// Creates a file system from a directory on disk, such as the `data`
+    /// folder written by an extractor.
+    ///
+    /// Directories are given IDs in depth-first order and each directory's
+    /// files are given contiguous IDs starting at `start_id`. Entries are
+    /// sorted by name so the same tree always produces the same table. The
+    /// allocation info of every file is left empty since it is only known
+    /// once the file has been placed in a ROM.
+    pub fn from_path<P: AsRef<Path>>(root: P, start_id: u16) -> Result<Self> {
+        let mut fs = Self::default();
+        let mut next_dir = ROOT_ID;
+        let mut next_file = start_id;
+
+        fs._from_path(root.as_ref(), Path::new(""), ROOT_ID, &mut next_dir, &mut next_file)?;
+
+        Ok(fs)
+    }
+
+    fn _from_path(&mut self, base: &Path, path: &Path, parent_id: u16, next_dir: &mut u16, next_file: &mut u16) -> Result<()> {
+        let id = *next_dir;
+        *next_dir = next_dir.checked_add(1).ok_or(FileSystemError::TooManyEntries)?;
+
+        let mut entries = read_dir(base.join(path))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        entries.sort();
+
+        for entry in &entries {
+            entry_name(entry)?;
+        }
+
+        let mut dir = Directory::from_parts(id, parent_id, *next_file, path);
+
+        for entry in entries.iter().filter(|entry| !entry.is_dir()) {
+            let file_id = *next_file;
+            *next_file = next_file.checked_add(1).ok_or(FileSystemError::TooManyEntries)?;
+
+            dir.append_file(FileEntry::new(file_id, path.join(entry.file_name().unwrap()), AllocInfo::default()));
+        }
+
+        self.dirs.insert(id, dir);
+
+        for entry in entries.iter().filter(|entry| entry.is_dir()) {
+            let child = *next_dir;
+
+            self._from_path(base, &path.join(entry.file_name().unwrap()), id, next_dir, next_file)?;
+            self.dirs.get_mut(&id).unwrap().add_child(child);
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the directories and files into a raw File Name Table.
+    ///
+    /// Each directory's subtable lists its files in ID order followed by
+    /// its subdirectories in ID order.
+    pub fn to_fnt(&self) -> Result<Vec<u8>> {
+        let mut main = Vec::new();
+        let mut sub = Vec::new();
+        let main_len = self.dirs.len() as u32 * 8;
+
+        for dir in self.dirs.values() {
+            main.write_u32::<LittleEndian>(main_len + sub.len() as u32)?;
+            main.write_u16::<LittleEndian>(dir.start_id())?;
+
+            if dir.is_root() {
+                main.write_u16::<LittleEndian>(self.dirs.len() as u16)?;
+            } else {
+                main.write_u16::<LittleEndian>(dir.parent_id())?;
+            }
+
+            let mut files = dir.files.iter().collect::<Vec<_>>();
+            files.sort_by_key(|file| file.id);
+
+            for file in files {
+                let name = entry_name(&file.path)?;
+                sub.write_u8(name.len() as u8)?;
+                sub.extend_from_slice(name.as_bytes());
+            }
+
+            for child in dir.children().iter().filter_map(|id| self.dirs.get(id)) {
+                let name = entry_name(&child.path)?;
+                sub.write_u8(name.len() as u8 | 0x80)?;
+                sub.extend_from_slice(name.as_bytes());
+                sub.write_u16::<LittleEndian>(child.id())?;
+            }
+
+            sub.write_u8(0)?;
+        }
+
+        main.extend(sub);
+
+        Ok(main)
+    }
+
+    /// Serializes the allocation info of every overlay and file into a raw
+    /// File Allocation Table, where each entry is at the index of its ID.
+    pub fn to_fat(&self) -> Result<Vec<u8>> {
+        let mut list = self.overlays
+            .iter()
+            .map(|overlay| overlay.alloc)
+            .collect::<Vec<_>>();
+
+        list.resize(self.start_id() as usize, AllocInfo::default());
+
+        for file in self.files() {
+            let index = file.id as usize;
+
+            if list.len() <= index {
+                list.resize(index + 1, AllocInfo::default());
+            }
+
+            list[index] = file.alloc;
+        }
+
+        let mut fat = Vec::with_capacity(list.len() * 8);
+
+        for alloc in list {
+            fat.write_u32::<LittleEndian>(alloc.start)?;
+            fat.write_u32::<LittleEndian>(alloc.end)?;
+        }
+
+        Ok(fat)
+    }
+
     /// How many directories there are
     pub fn count(&self) -> usize {
         self.dirs.len()
     }
     
-    /// Get a Vec of all files
+    /// Get a Vec of all files, in the same order as [`walk`].
+    ///
+    /// [`walk`]: #method.walk
     pub fn files(&self) -> Vec<&FileEntry> {
-        self.dirs
-            .par_iter()
-            .flat_map(|(_, ref dir)| {
-                &dir.files
-            })
+        self.walk()
+            .filter_map(|entry| entry.file())
             .collect::<_>()
     }
 
+    /// Gets the file or overlay with the given ID.
+    ///
+    /// Every directory holds a contiguous range of IDs, so only the
+    /// directory that can contain `id` has its files checked.
+    pub fn file(&self, id: u16) -> Option<&FileEntry> {
+        if id < self.start_id() {
+            return self.overlays.get(id as usize);
+        }
+
+        self.dirs
+            .values()
+            .find(|dir| id >= dir.start_id() && ((id - dir.start_id()) as usize) < dir.files.len())
+            .and_then(|dir| dir.files.iter().find(|file| file.id == id))
+    }
+
+    /// Sets where the file or overlay with the given ID is stored.
+    ///
+    /// Returns `false` if there is no entry with that ID.
+    pub fn set_alloc(&mut self, id: u16, alloc: AllocInfo) -> bool {
+        if id < self.start_id() {
+            return match self.overlays.get_mut(id as usize) {
+                Some(overlay) => {
+                    overlay.alloc = alloc;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let file = self.dirs
+            .values_mut()
+            .flat_map(|dir| dir.files.iter_mut())
+            .find(|file| file.id == id);
+
+        match file {
+            Some(file) => {
+                file.alloc = alloc;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gets the directory with the given ID.
+    pub fn dir(&self, id: u16) -> Option<&Directory> {
+        self.dirs.get(&id)
+    }
+
+    /// The root directory.
+    pub fn root(&self) -> Option<&Directory> {
+        self.dirs.get(&ROOT_ID)
+    }
+
+    /// Gets the directory at a path relative to the root, such as `a/0/1`.
+    /// An empty path is the root itself.
+    ///
+    /// Each component is looked up in the subdirectories of the one before
+    /// it, so this doesn't scan the whole file system.
+    pub fn lookup_dir<P: AsRef<Path>>(&self, path: P) -> Option<&Directory> {
+        let mut dir = self.root()?;
+
+        for component in path.as_ref().components() {
+            let name = component.as_os_str().to_str()?;
+
+            dir = dir.children()
+                .iter()
+                .filter_map(|id| self.dirs.get(id))
+                .find(|child| child.name() == name)?;
+        }
+
+        Some(dir)
+    }
+
+    /// Gets the file at a path relative to the root, such as `a/0/1/2`.
+    pub fn lookup<P: AsRef<Path>>(&self, path: P) -> Option<&FileEntry> {
+        let path = path.as_ref();
+        let name = path.file_name()?.to_str()?;
+
+        self.lookup_dir(path.parent()?)?.file(name)
+    }
+
+    /// Lists the entries directly inside the directory at `path`: its files
+    /// in ID order followed by its subdirectories in FNT order.
+    ///
+    /// Returns `None` if there is no directory at `path`.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Option<Vec<Entry<'_>>> {
+        let dir = self.lookup_dir(path)?;
+
+        Some(walk::entries(self, dir).collect())
+    }
+
+    /// A depth-first iterator over every directory and file, starting with
+    /// the root directory. The order is stable and follows the FNT.
+    pub fn walk(&self) -> Walk<'_> {
+        Walk::new(self, self.root())
+    }
+
     /// The lowest ID in the File System. Any ID lower than this in 
     /// the FAT is an overlay file.
     pub fn start_id(&self) -> u16 {
@@ -80,7 +343,7 @@ impl FileSystem {
             .into_par_iter()
             .map(|id| {
                 let alloc_info = fat.get(id).unwrap();
-                FileEntry::new(id, &format!("overlay_{:04}", id), alloc_info)
+                FileEntry::new(id, format!("overlay_{:04}", id), alloc_info)
             })
             .collect::<_>();
 
@@ -109,7 +372,8 @@ impl FileSystem {
                 let pos = cursor.position();
                 let new_path = path.as_ref().join(name);
                 
-                self._populate(&mut cursor, new_path, dir_id, fat)?;
+                self._populate(cursor, new_path, dir_id, fat)?;
+                self.dirs.get_mut(&id).unwrap().add_child(dir_id);
 
                 cursor.set_position(pos);
             } else {
@@ -143,3 +407,14 @@ impl FileSystem {
         Ok(name)
     }
 }
+
+/// Gets the name of the last component of an entry path, making sure that
+/// it fits in a FNT entry.
+fn entry_name(path: &Path) -> Result<&str> {
+    let name = path.file_name().unwrap_or_default();
+
+    match name.to_str() {
+        Some(valid) if !valid.is_empty() && valid.len() < 0x80 => Ok(valid),
+        _ => Err(FileSystemError::InvalidName(name.to_os_string()).into()),
+    }
+}
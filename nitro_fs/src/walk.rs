@@ -0,0 +1,87 @@
+use crate::fnt::{Directory, FileEntry};
+use crate::FileSystem;
+
+/// Either a file or a directory in a [`FileSystem`].
+///
+/// [`FileSystem`]: ../struct.FileSystem.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Entry<'a> {
+    File(&'a FileEntry),
+    Dir(&'a Directory),
+}
+
+impl<'a> Entry<'a> {
+    /// The full path of the entry relative to the root of the file system.
+    pub fn path(&self) -> &'a std::path::Path {
+        match self {
+            Entry::File(file) => &file.path,
+            Entry::Dir(dir) => &dir.path,
+        }
+    }
+
+    /// The file, if this entry is one.
+    pub fn file(&self) -> Option<&'a FileEntry> {
+        match self {
+            Entry::File(file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    /// The directory, if this entry is one.
+    pub fn dir(&self) -> Option<&'a Directory> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(dir) => Some(dir),
+        }
+    }
+}
+
+/// Lists the entries directly inside a directory: its files in ID order
+/// followed by its subdirectories in FNT order.
+pub(crate) fn entries<'a>(fs: &'a FileSystem, dir: &'a Directory) -> impl Iterator<Item = Entry<'a>> {
+    dir.files
+        .iter()
+        .map(Entry::File)
+        .chain(dir.children().iter().filter_map(move |id| fs.dirs.get(id)).map(Entry::Dir))
+}
+
+/// A depth-first iterator over every entry in a [`FileSystem`], created
+/// with [`FileSystem::walk`].
+///
+/// Each directory is yielded before the entries inside of it, which come in
+/// the same order as [`FileSystem::read_dir`].
+///
+/// [`FileSystem`]: ../struct.FileSystem.html
+/// [`FileSystem::walk`]: ../struct.FileSystem.html#method.walk
+/// [`FileSystem::read_dir`]: ../struct.FileSystem.html#method.read_dir
+#[derive(Clone, Debug)]
+pub struct Walk<'a> {
+    fs: &'a FileSystem,
+    stack: Vec<Entry<'a>>,
+}
+
+impl<'a> Walk<'a> {
+    pub(crate) fn new(fs: &'a FileSystem, start: Option<&'a Directory>) -> Self {
+        Self {
+            fs,
+            stack: start.map(Entry::Dir).into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.stack.pop()?;
+
+        if let Entry::Dir(dir) = entry {
+            let start = self.stack.len();
+
+            self.stack.extend(entries(self.fs, dir));
+            self.stack[start..].reverse();
+        }
+
+        Some(entry)
+    }
+}
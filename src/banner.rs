@@ -0,0 +1,247 @@
+//! The banner, which holds the icon and titles shown in the DS menu.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use anyhow::{ensure, Result};
+
+use crate::graphics::nclr::ColorDepth;
+use crate::graphics::{bgr555_to_rgba, ncgr, rgba_to_bgr555, IndexedImage, Rgba, RgbaImage};
+use crate::header::banner_len;
+use crate::util::crc::crc16;
+
+const BITMAP: usize = 0x20;
+const BITMAP_LEN: usize = 0x200;
+const PALETTE: usize = 0x220;
+const TITLES: usize = 0x240;
+const TITLE_LEN: usize = 0x100;
+
+/// Width and height of the icon in pixels.
+pub const ICON_SIZE: usize = 32;
+
+/// Where the data of DSi banners starts, after the last title.
+const DSI_DATA: usize = 0xA40;
+
+/// Ranges covered by each of the checksums after the version, and the
+/// version that added them.
+const CHECKSUMS: [(u16, usize, usize); 4] = [
+    (0x0001, 0x20, 0x840),
+    (0x0002, 0x20, 0x940),
+    (0x0003, 0x20, 0xA40),
+    (0x0103, 0x1240, 0x23C0),
+];
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum BannerError {
+    #[error("Not enough data for a version {0:#06X} banner.")]
+    NotEnoughData(u16),
+
+    #[error("Icon must be 32x32 pixels, found {0}x{1}.")]
+    InvalidIconSize(usize, usize),
+
+    #[error("Icon can have at most 16 colors, found {0}.")]
+    TooManyColors(usize),
+
+    #[error("Icon pixel uses color {0}, which isn't in its palette.")]
+    IndexOutOfRange(u8),
+}
+
+/// An icon and titles in every language, as shown in the DS menu.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Banner {
+    /// 1 for the original DS, 2 adds a Chinese title, 3 adds a Korean title
+    /// and `0x103` adds the animated DSi icon.
+    pub version: u16,
+    /// A 32x32 icon with 4 bits per pixel, stored as 4x4 tiles of 8x8
+    /// pixels.
+    pub bitmap: [u8; BITMAP_LEN],
+    /// Colors in BGR555. The first color is transparent.
+    pub palette: [u16; 16],
+    /// Titles in Japanese, English, French, German, Italian and Spanish,
+    /// followed by Chinese and Korean for later versions. Each has up to
+    /// three lines separated by `\n`.
+    pub titles: Vec<String>,
+    /// Everything after the titles in a DSi banner, such as the animated
+    /// icon. It is kept as is.
+    dsi_data: Vec<u8>,
+}
+
+impl Banner {
+    /// Creates a version 1 banner with a blank icon and the same title in
+    /// every language.
+    pub fn new(title: &str) -> Self {
+        Self {
+            version: 1,
+            bitmap: [0; BITMAP_LEN],
+            palette: [0; 16],
+            titles: vec![title.to_string(); title_count(1)],
+            dsi_data: Vec::new(),
+        }
+    }
+
+    /// Reads a banner, such as the `banner.bin` written by an extractor.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= 2, BannerError::NotEnoughData(0));
+
+        let version = LittleEndian::read_u16(data);
+        let len = banner_len(version);
+
+        ensure!(data.len() >= len, BannerError::NotEnoughData(version));
+
+        let mut bitmap = [0; BITMAP_LEN];
+        bitmap.copy_from_slice(&data[BITMAP..BITMAP + BITMAP_LEN]);
+
+        let mut palette = [0; 16];
+        LittleEndian::read_u16_into(&data[PALETTE..TITLES], &mut palette);
+
+        let titles = (0..title_count(version))
+            .map(|index| {
+                let start = TITLES + index * TITLE_LEN;
+                let mut units = vec![0; TITLE_LEN / 2];
+
+                LittleEndian::read_u16_into(&data[start..start + TITLE_LEN], &mut units);
+
+                let end = units.iter().position(|&unit| unit == 0).unwrap_or(units.len());
+
+                String::from_utf16_lossy(&units[..end])
+            })
+            .collect();
+
+        let dsi_data = if len > DSI_DATA {
+            data[DSI_DATA..len].to_vec()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            version,
+            bitmap,
+            palette,
+            titles,
+            dsi_data,
+        })
+    }
+
+    /// Writes the banner with up to date checksums. Titles that don't fit
+    /// are cut off, and missing ones are left empty.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = banner_len(self.version);
+        let mut data = vec![0; len];
+
+        LittleEndian::write_u16(&mut data, self.version);
+        data[BITMAP..BITMAP + BITMAP_LEN].copy_from_slice(&self.bitmap);
+        LittleEndian::write_u16_into(&self.palette, &mut data[PALETTE..TITLES]);
+
+        for (index, title) in self.titles.iter().take(title_count(self.version)).enumerate() {
+            let start = TITLES + index * TITLE_LEN;
+
+            //  The last unit is left as a terminator.
+            for (pos, unit) in title.encode_utf16().take(TITLE_LEN / 2 - 1).enumerate() {
+                LittleEndian::write_u16(&mut data[start + pos * 2..], unit);
+            }
+        }
+
+        if len > DSI_DATA {
+            let dsi_len = self.dsi_data.len().min(len - DSI_DATA);
+            data[DSI_DATA..DSI_DATA + dsi_len].copy_from_slice(&self.dsi_data[..dsi_len]);
+        }
+
+        for (index, (version, start, end)) in CHECKSUMS.iter().enumerate() {
+            if self.version >= *version && data.len() >= *end {
+                let crc = crc16(&data[*start..*end]);
+                LittleEndian::write_u16(&mut data[2 + index * 2..], crc);
+            }
+        }
+
+        data
+    }
+
+    /// The icon as palette indices.
+    pub fn icon_image(&self) -> IndexedImage {
+        let pixels = ncgr::unpack(&self.bitmap, ColorDepth::Bpp4);
+        let columns = ICON_SIZE / ncgr::TILE_SIZE;
+        let mut image = IndexedImage::new(ICON_SIZE, ICON_SIZE);
+
+        for (index, tile) in pixels.chunks_exact(ncgr::TILE_PIXELS).enumerate() {
+            let mut copy = [0; ncgr::TILE_PIXELS];
+            copy.copy_from_slice(tile);
+
+            let x = (index % columns) * ncgr::TILE_SIZE;
+            let y = (index / columns) * ncgr::TILE_SIZE;
+
+            ncgr::draw_tile(&mut image, &copy, x, y, false, false, 0);
+        }
+
+        image
+    }
+
+    /// Replaces the icon with a 32x32 image and a palette of up to 16
+    /// colors. The first color is always shown as transparent.
+    pub fn set_icon(&mut self, image: &IndexedImage, palette: &[Rgba]) -> Result<()> {
+        ensure!(
+            image.width == ICON_SIZE && image.height == ICON_SIZE,
+            BannerError::InvalidIconSize(image.width, image.height)
+        );
+        ensure!(palette.len() <= self.palette.len(), BannerError::TooManyColors(palette.len()));
+
+        if let Some(&index) = image.pixels.iter().find(|&&index| index as usize >= palette.len().max(1)) {
+            return Err(BannerError::IndexOutOfRange(index).into());
+        }
+
+        let pixels = ncgr::from_bitmap(&image.pixels, ICON_SIZE).concat();
+
+        self.bitmap.copy_from_slice(&ncgr::pack(&pixels, ColorDepth::Bpp4));
+        self.palette = [0; 16];
+
+        for (stored, &color) in self.palette.iter_mut().zip(palette) {
+            *stored = rgba_to_bgr555(color);
+        }
+
+        Ok(())
+    }
+
+    /// The icon palette as RGBA, with the first color transparent.
+    pub fn icon_palette(&self) -> Vec<Rgba> {
+        let mut colors = self.palette.iter().map(|&color| bgr555_to_rgba(color)).collect::<Vec<_>>();
+        colors[0][3] = 0;
+
+        colors
+    }
+
+    /// The icon as a 32x32 RGBA image.
+    pub fn icon(&self) -> RgbaImage {
+        self.icon_image().to_rgba(&self.icon_palette())
+    }
+
+    /// The icon as an indexed PNG, which keeps its palette.
+    #[cfg(feature = "image")]
+    pub fn icon_png(&self) -> Result<Vec<u8>> {
+        self.icon_image().to_png(&self.icon_palette())
+    }
+}
+
+/// The stored and computed value of every checksum that a banner of its
+/// version has, in the order they are stored.
+pub(crate) fn checksums(data: &[u8]) -> Vec<(u16, u16)> {
+    if data.len() < 2 {
+        return Vec::new();
+    }
+
+    let version = LittleEndian::read_u16(data);
+
+    CHECKSUMS
+        .iter()
+        .enumerate()
+        .filter(|(_, (added, _, end))| version >= *added && data.len() >= *end)
+        .map(|(index, (_, start, end))| (LittleEndian::read_u16(&data[2 + index * 2..]), crc16(&data[*start..*end])))
+        .collect()
+}
+
+/// How many languages a version of the banner has titles for.
+fn title_count(version: u16) -> usize {
+    match version {
+        0x0002 => 7,
+        0x0003 | 0x0103 => 8,
+        _ => 6,
+    }
+}
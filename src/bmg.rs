@@ -0,0 +1,714 @@
+//! BMG message files (`MESGbmg1`), which hold the dialogue and other text
+//! of many games.
+//!
+//! A BMG has a 0x20 byte header followed by sections, each padded to 0x20
+//! bytes:
+//!
+//! * `INF1` has an entry for every message, with the offset of its text and
+//!   attributes that the game gives meaning to
+//! * `DAT1` holds the text, each message ending with a null character
+//! * `MID1`, which is optional, gives every message an ID
+//!
+//! Text is stored as CP1252, UTF-16, Shift-JIS or UTF-8, as the header
+//! says. Control codes such as colors, pauses and the name of the player
+//! are escapes that start with the character 0x1A followed by their length
+//! in bytes. They are kept in text as tags of their remaining bytes in hex,
+//! such as `{0100000200}`, and a literal `{` is written as `{{`.
+//!
+//! Text can be exported to a gettext PO file for translation, or to JSON
+//! with the `json` feature, and imported back. Offsets are worked out again
+//! when the file is written, so messages can change length freely.
+//!
+//! Only little endian files, as found on the DS, are supported.
+
+use byteorder::{ByteOrder, LittleEndian};
+use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+use nitro_fs::container::Section;
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Write;
+
+use anyhow::{ensure, Result};
+
+use crate::util::hash::{from_hex_vec, to_hex};
+
+const MAGIC: &[u8; 8] = b"MESGbmg1";
+const INF1: &[u8; 4] = b"INF1";
+const DAT1: &[u8; 4] = b"DAT1";
+const MID1: &[u8; 4] = b"MID1";
+
+const HEADER_LEN: usize = 0x20;
+const SECTION_HEADER_LEN: usize = 0x08;
+const ALIGNMENT: usize = 0x20;
+
+/// The character that starts an escape.
+const ESCAPE: u8 = 0x1A;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum BmgError {
+    #[error("Not a BMG file.")]
+    InvalidHeader,
+
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Missing section: {0}")]
+    MissingSection(&'static str),
+
+    #[error("Unknown text encoding: {0}.")]
+    InvalidEncoding(u8),
+
+    #[error("Text at {0:#X} is not valid for its encoding.")]
+    InvalidText(usize),
+
+    #[error("Invalid tag: {0}")]
+    InvalidTag(String),
+
+    #[error("Text can't be written as {encoding}: {text}")]
+    Unencodable { encoding: &'static str, text: String },
+
+    #[error("There is no message {0}.")]
+    MissingMessage(usize),
+
+    #[error("Invalid PO file at line {0}.")]
+    InvalidPo(usize),
+}
+
+/// How text is stored.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    Cp1252,
+    Utf16,
+    ShiftJis,
+    Utf8,
+}
+
+impl Encoding {
+    /// Reads the encoding of the header. Files with 0 are read as CP1252,
+    /// as the games do.
+    pub fn from_raw(value: u8) -> Result<Self> {
+        match value {
+            0 | 1 => Ok(Encoding::Cp1252),
+            2 => Ok(Encoding::Utf16),
+            3 => Ok(Encoding::ShiftJis),
+            4 => Ok(Encoding::Utf8),
+            _ => Err(BmgError::InvalidEncoding(value).into()),
+        }
+    }
+
+    pub fn to_raw(self) -> u8 {
+        match self {
+            Encoding::Cp1252 => 1,
+            Encoding::Utf16 => 2,
+            Encoding::ShiftJis => 3,
+            Encoding::Utf8 => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Cp1252 => "cp1252",
+            Encoding::Utf16 => "utf-16",
+            Encoding::ShiftJis => "shift-jis",
+            Encoding::Utf8 => "utf-8",
+        }
+    }
+
+    /// How many bytes a character takes at least, which is also the size
+    /// of the null character.
+    fn unit_len(self) -> usize {
+        if self == Encoding::Utf16 {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Message {
+    /// The ID from `MID1`, if the file has one.
+    pub id: Option<u32>,
+    /// The bytes that follow the offset in the `INF1` entry.
+    pub attributes: Vec<u8>,
+    /// The text, with escapes as tags.
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Bmg {
+    pub encoding: Encoding,
+    /// The last 4 bytes of the `INF1` header, which games use for a file ID
+    /// or a default color.
+    pub info_header: [u8; 4],
+    /// The format bytes of `MID1`, if the file has one.
+    pub id_format: Option<[u8; 2]>,
+    pub messages: Vec<Message>,
+    /// Sections other than `INF1`, `DAT1` and `MID1`, such as the `FLW1`
+    /// and `FLI1` flow sections, which are kept as they are.
+    pub other_sections: Vec<Section>,
+}
+
+/// A message as exported to JSON.
+#[cfg(feature = "json")]
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonMessage {
+    index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    attributes: String,
+    text: String,
+}
+
+#[cfg(feature = "json")]
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonFile {
+    #[serde(default)]
+    encoding: String,
+    messages: Vec<JsonMessage>,
+}
+
+impl Bmg {
+    /// Creates a file without messages.
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            info_header: [0; 4],
+            id_format: None,
+            messages: Vec::new(),
+            other_sections: Vec::new(),
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= HEADER_LEN && &data[..8] == MAGIC, BmgError::InvalidHeader);
+
+        let count = LittleEndian::read_u32(&data[0x0C..]) as usize;
+        let encoding = Encoding::from_raw(data[0x10])?;
+
+        let mut info = None;
+        let mut text = None;
+        let mut ids = None;
+        let mut other_sections = Vec::new();
+        let mut offset = HEADER_LEN;
+
+        for _ in 0..count {
+            ensure!(offset + SECTION_HEADER_LEN <= data.len(), BmgError::NotEnoughData);
+
+            let mut magic = [0; 4];
+            magic.copy_from_slice(&data[offset..offset + 4]);
+
+            let len = LittleEndian::read_u32(&data[offset + 4..]) as usize;
+            ensure!(len >= SECTION_HEADER_LEN, BmgError::NotEnoughData);
+
+            let body = data.get(offset + SECTION_HEADER_LEN..offset + len).ok_or(BmgError::NotEnoughData)?;
+
+            match &magic {
+                INF1 => info = Some(body),
+                DAT1 => text = Some(body),
+                MID1 => ids = Some(body),
+                _ => other_sections.push(Section::new(magic, body.to_vec())),
+            }
+
+            offset += len;
+        }
+
+        let info = info.ok_or(BmgError::MissingSection("INF1"))?;
+        let text = text.ok_or(BmgError::MissingSection("DAT1"))?;
+
+        ensure!(info.len() >= 8, BmgError::NotEnoughData);
+
+        let count = LittleEndian::read_u16(info) as usize;
+        let entry_len = LittleEndian::read_u16(&info[0x02..]) as usize;
+        let mut info_header = [0; 4];
+
+        info_header.copy_from_slice(&info[0x04..0x08]);
+        ensure!(entry_len >= 4, BmgError::NotEnoughData);
+
+        let entries = info.get(8..8 + count * entry_len).ok_or(BmgError::NotEnoughData)?;
+
+        let mut messages = entries
+            .chunks_exact(entry_len)
+            .map(|entry| {
+                Ok(Message {
+                    id: None,
+                    attributes: entry[4..].to_vec(),
+                    text: decode_text(text, LittleEndian::read_u32(entry) as usize, encoding)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let id_format = match ids {
+            Some(ids) => {
+                ensure!(ids.len() >= 8, BmgError::NotEnoughData);
+
+                let count = LittleEndian::read_u16(ids) as usize;
+                let table = ids.get(8..8 + count * 4).ok_or(BmgError::NotEnoughData)?;
+
+                for (message, id) in messages.iter_mut().zip(table.chunks_exact(4)) {
+                    message.id = Some(LittleEndian::read_u32(id));
+                }
+
+                Some([ids[0x02], ids[0x03]])
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            encoding,
+            info_header,
+            id_format,
+            messages,
+            other_sections,
+        })
+    }
+
+    /// Writes the file, laying out the text again.
+    ///
+    /// # Errors
+    /// Returns an error if a tag is invalid or if text can't be written in
+    /// the encoding of the file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let null = vec![0; self.encoding.unit_len()];
+        let attributes_len = self.messages.iter().map(|message| message.attributes.len()).max().unwrap_or(0);
+
+        //  Empty messages share the null character at the start.
+        let mut text = null.clone();
+        let mut info = vec![0; 8];
+
+        LittleEndian::write_u16(&mut info[0x00..], self.messages.len() as u16);
+        LittleEndian::write_u16(&mut info[0x02..], (4 + attributes_len) as u16);
+        info[0x04..0x08].copy_from_slice(&self.info_header);
+
+        for message in &self.messages {
+            let encoded = encode_text(&message.text, self.encoding)?;
+            let offset = if encoded.is_empty() { 0 } else { text.len() };
+
+            text.extend_from_slice(&encoded);
+
+            if !encoded.is_empty() {
+                text.extend_from_slice(&null);
+            }
+
+            info.extend_from_slice(&(offset as u32).to_le_bytes());
+            info.extend_from_slice(&message.attributes);
+            info.resize(info.len() + attributes_len - message.attributes.len(), 0);
+        }
+
+        let mut sections = vec![Section::new(*INF1, info), Section::new(*DAT1, text)];
+
+        if let Some(format) = self.id_format {
+            let mut ids = vec![0; 8];
+
+            LittleEndian::write_u16(&mut ids[0x00..], self.messages.len() as u16);
+            ids[0x02..0x04].copy_from_slice(&format);
+
+            for message in &self.messages {
+                ids.extend_from_slice(&message.id.unwrap_or(0).to_le_bytes());
+            }
+
+            sections.push(Section::new(*MID1, ids));
+        }
+
+        sections.extend(self.other_sections.iter().cloned());
+
+        let mut data = vec![0; HEADER_LEN];
+
+        data[..8].copy_from_slice(MAGIC);
+        LittleEndian::write_u32(&mut data[0x0C..], sections.len() as u32);
+        data[0x10] = self.encoding.to_raw();
+
+        for section in &sections {
+            let len = (SECTION_HEADER_LEN + section.data.len()).next_multiple_of(ALIGNMENT);
+
+            data.extend_from_slice(&section.magic);
+            data.extend_from_slice(&(len as u32).to_le_bytes());
+            data.extend_from_slice(&section.data);
+            data.resize(data.len().next_multiple_of(ALIGNMENT), 0);
+        }
+
+        let len = data.len() as u32;
+        LittleEndian::write_u32(&mut data[0x08..], len);
+
+        Ok(data)
+    }
+
+    /// Exports the messages as JSON, with the attributes in hex.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        let file = JsonFile {
+            encoding: self.encoding.name().to_string(),
+            messages: self
+                .messages
+                .iter()
+                .enumerate()
+                .map(|(index, message)| JsonMessage {
+                    index,
+                    id: message.id,
+                    attributes: to_hex(&message.attributes),
+                    text: message.text.clone(),
+                })
+                .collect(),
+        };
+
+        serde_json::to_string_pretty(&file).unwrap_or_default()
+    }
+
+    /// Imports messages exported by [`to_json`], matched by their index.
+    /// Messages that are left out don't change. Returns how many messages
+    /// changed.
+    ///
+    /// # Errors
+    /// Returns an error if the JSON is invalid, if a message doesn't exist,
+    /// or if text can't be written in the encoding of the file.
+    ///
+    /// [`to_json`]: #method.to_json
+    #[cfg(feature = "json")]
+    pub fn import_json(&mut self, json: &str) -> Result<usize> {
+        let file: JsonFile = serde_json::from_str(json)?;
+        let mut changed = 0;
+
+        for entry in file.messages {
+            let encoding = self.encoding;
+            let message = self.messages.get_mut(entry.index).ok_or(BmgError::MissingMessage(entry.index))?;
+
+            let attributes = if entry.attributes.is_empty() {
+                message.attributes.clone()
+            } else {
+                from_hex_vec(&entry.attributes).ok_or_else(|| BmgError::InvalidTag(entry.attributes.clone()))?
+            };
+
+            encode_text(&entry.text, encoding)?;
+
+            if message.text != entry.text || message.attributes != attributes {
+                message.text = entry.text;
+                message.attributes = attributes;
+                changed += 1;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Exports the messages as a PO file, with the index of each message as
+    /// its context and an empty translation. Empty messages are left out.
+    pub fn to_po(&self) -> String {
+        let mut po = String::new();
+
+        po.push_str("msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+
+        for (index, message) in self.messages.iter().enumerate() {
+            if message.text.is_empty() {
+                continue;
+            }
+
+            po.push('\n');
+
+            if let Some(id) = message.id {
+                let _ = writeln!(po, "#. id {}", id);
+            }
+
+            let _ = writeln!(po, "msgctxt \"{}\"", index);
+            write_po_string(&mut po, "msgid", &message.text);
+            po.push_str("msgstr \"\"\n");
+        }
+
+        po
+    }
+
+    /// Imports the translations of a PO file exported by [`to_po`].
+    /// Messages without a translation don't change. Returns how many
+    /// messages changed.
+    ///
+    /// # Errors
+    /// Returns an error if a line can't be read, if a message doesn't
+    /// exist, or if text can't be written in the encoding of the file.
+    ///
+    /// [`to_po`]: #method.to_po
+    pub fn import_po(&mut self, po: &str) -> Result<usize> {
+        let mut changed = 0;
+
+        for entry in parse_po(po)? {
+            //  The header has no context.
+            let Some(context) = entry.context else {
+                continue;
+            };
+
+            let translation = entry.translation;
+
+            if translation.is_empty() {
+                continue;
+            }
+
+            let line = entry.line;
+            let index: usize = context.parse().map_err(|_| BmgError::InvalidPo(line))?;
+            let encoding = self.encoding;
+            let message = self.messages.get_mut(index).ok_or(BmgError::MissingMessage(index))?;
+
+            encode_text(&translation, encoding)?;
+
+            if message.text != translation {
+                message.text = translation;
+                changed += 1;
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Reads the text at `offset` up to its null character, with escapes as
+/// tags.
+fn decode_text(data: &[u8], offset: usize, encoding: Encoding) -> Result<String> {
+    let unit = encoding.unit_len();
+    let mut text = String::new();
+    let mut run = Vec::new();
+    let mut position = offset;
+
+    loop {
+        let char = data.get(position..position + unit).ok_or(BmgError::NotEnoughData)?;
+
+        if char.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        if char[0] == ESCAPE && char[1..].iter().all(|&byte| byte == 0) {
+            let len = *data.get(position + unit).ok_or(BmgError::NotEnoughData)? as usize;
+            let escape = data
+                .get(position + unit + 1..position + len)
+                .ok_or(BmgError::InvalidText(position))?;
+
+            push_plain(&mut text, &run, encoding).ok_or(BmgError::InvalidText(offset))?;
+            run.clear();
+
+            let _ = write!(text, "{{{}}}", to_hex(escape));
+            position += len;
+            continue;
+        }
+
+        run.extend_from_slice(char);
+        position += unit;
+    }
+
+    push_plain(&mut text, &run, encoding).ok_or(BmgError::InvalidText(offset))?;
+    Ok(text)
+}
+
+/// Decodes text without escapes, doubling every `{`.
+fn push_plain(text: &mut String, data: &[u8], encoding: Encoding) -> Option<()> {
+    let plain = match encoding {
+        Encoding::Utf16 => {
+            let units: Vec<u16> = data.chunks_exact(2).map(LittleEndian::read_u16).collect();
+            String::from_utf16(&units).ok()?
+        }
+        Encoding::Utf8 => String::from_utf8(data.to_vec()).ok()?,
+        Encoding::Cp1252 => WINDOWS_1252.decode_without_bom_handling_and_without_replacement(data)?.into_owned(),
+        Encoding::ShiftJis => SHIFT_JIS.decode_without_bom_handling_and_without_replacement(data)?.into_owned(),
+    };
+
+    text.push_str(&plain.replace('{', "{{"));
+    Some(())
+}
+
+/// Encodes text with tags, without the null character at the end.
+fn encode_text(text: &str, encoding: Encoding) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("{{") {
+            plain.push('{');
+            rest = &rest[2..];
+            continue;
+        }
+
+        let end = rest.find('}').ok_or_else(|| BmgError::InvalidTag(rest.to_string()))?;
+        let tag = &rest[..=end];
+        let escape = from_hex_vec(&tag[1..end]).ok_or_else(|| BmgError::InvalidTag(tag.to_string()))?;
+
+        encode_plain(&mut data, &plain, encoding)?;
+        plain.clear();
+
+        //  The length counts the escape character and itself.
+        let len = encoding.unit_len() + 1 + escape.len();
+
+        ensure!(
+            len <= 0xFF && len % encoding.unit_len() == 0,
+            BmgError::InvalidTag(tag.to_string())
+        );
+
+        data.push(ESCAPE);
+        data.resize(data.len() + encoding.unit_len() - 1, 0);
+        data.push(len as u8);
+        data.extend_from_slice(&escape);
+
+        rest = &rest[end + 1..];
+    }
+
+    plain.push_str(rest);
+    encode_plain(&mut data, &plain, encoding)?;
+
+    Ok(data)
+}
+
+fn encode_plain(data: &mut Vec<u8>, text: &str, encoding: Encoding) -> Result<()> {
+    let unencodable = || BmgError::Unencodable {
+        encoding: encoding.name(),
+        text: text.to_string(),
+    };
+
+    //  A null character would end the message early.
+    ensure!(!text.contains('\0'), unencodable());
+
+    match encoding {
+        Encoding::Utf16 => data.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+        Encoding::Utf8 => data.extend_from_slice(text.as_bytes()),
+        Encoding::Cp1252 | Encoding::ShiftJis => {
+            let coder = if encoding == Encoding::Cp1252 { WINDOWS_1252 } else { SHIFT_JIS };
+            let (bytes, _, errors) = coder.encode(text);
+
+            ensure!(!errors, unencodable());
+            data.extend_from_slice(&bytes);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a keyword and a quoted string, on several lines if the string
+/// has line breaks.
+fn write_po_string(po: &mut String, keyword: &str, text: &str) {
+    let quote = |line: &str| {
+        let mut quoted = String::with_capacity(line.len() + 2);
+
+        quoted.push('"');
+
+        for char in line.chars() {
+            match char {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                _ => quoted.push(char),
+            }
+        }
+
+        quoted.push('"');
+        quoted
+    };
+
+    if text.trim_end_matches('\n').contains('\n') {
+        let _ = writeln!(po, "{} \"\"", keyword);
+
+        for line in text.split_inclusive('\n') {
+            let _ = writeln!(po, "{}", quote(line));
+        }
+    } else {
+        let _ = writeln!(po, "{} {}", keyword, quote(text));
+    }
+}
+
+/// Reads a quoted string of a PO file.
+fn parse_po_string(text: &str) -> Option<String> {
+    let text = text.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            result.push(char);
+            continue;
+        }
+
+        match chars.next()? {
+            'n' => result.push('\n'),
+            't' => result.push('\t'),
+            'r' => result.push('\r'),
+            other => result.push(other),
+        }
+    }
+
+    Some(result)
+}
+
+/// An entry of a PO file.
+#[derive(Default)]
+struct PoEntry {
+    context: Option<String>,
+    /// The line of the context, for errors.
+    line: usize,
+    translation: String,
+}
+
+/// Reads the entries of a PO file.
+fn parse_po(po: &str) -> Result<Vec<PoEntry>> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Context,
+        Id,
+        Translation,
+    }
+
+    let mut entries = Vec::new();
+    let mut entry = PoEntry::default();
+    let mut field = Field::None;
+
+    for (index, line) in po.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keyword, value) = match line.split_once(' ') {
+            Some((keyword, value)) if !line.starts_with('"') => (keyword, value),
+            _ => ("", line),
+        };
+
+        let value = parse_po_string(value).ok_or(BmgError::InvalidPo(number))?;
+
+        match keyword {
+            "msgctxt" | "msgid" if field == Field::Translation => {
+                entries.push(std::mem::take(&mut entry));
+                field = Field::None;
+            }
+            _ => {}
+        }
+
+        match keyword {
+            "msgctxt" => {
+                entry.context = Some(value);
+                entry.line = number;
+                field = Field::Context;
+            }
+            "msgid" => field = Field::Id,
+            "msgstr" => {
+                entry.translation = value;
+                field = Field::Translation;
+            }
+            "" => match field {
+                Field::Context => entry.context.get_or_insert_with(String::new).push_str(&value),
+                Field::Id => {}
+                Field::Translation => entry.translation.push_str(&value),
+                Field::None => return Err(BmgError::InvalidPo(number).into()),
+            },
+            _ => return Err(BmgError::InvalidPo(number).into()),
+        }
+    }
+
+    if field == Field::Translation {
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
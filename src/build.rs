@@ -1,7 +1,22 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::fat::AllocInfo;
+use nitro_fs::FileSystem;
+
+use std::collections::BTreeMap;
+use std::fs::{read, write};
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Result};
 
+use crate::compression::{compress, read_sidecar, Compression, SIDECAR_NAME};
+use crate::header::{Header, OVERLAY_ENTRY_LEN, OVERLAY_FILE_ID, UNIT_CODE_TWL};
+#[cfg(feature = "manifest")]
+use crate::manifest::{Manifest, MANIFEST_NAME};
+
+/// Alignment used for every section placed in a built ROM, unless a
+/// manifest says otherwise.
+const ALIGNMENT: usize = 0x200;
+
 // == Errors ==
 #[derive(Debug, thiserror::Error)]
 pub enum BuildError {
@@ -10,6 +25,15 @@ pub enum BuildError {
 
     #[error("Missing required file: '{0}'.")]
     MissingFileError(&'static str),
+
+    #[error("Header is too small to be valid.")]
+    HeaderTooSmall,
+
+    #[error("Overlay table size is not a multiple of the entry size.")]
+    InvalidOverlayTable,
+
+    #[error("Built ROM is larger than the 4GiB address space.")]
+    RomTooLarge,
 }
 
 /// Builds an NDS ROM given a directory with valid structure.
@@ -19,6 +43,8 @@ pub enum BuildError {
 #[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Builder {
     root: PathBuf,
+    /// Whether files listed in a compression sidecar are compressed again.
+    recompress: bool,
 }
 
 impl Builder {
@@ -27,9 +53,19 @@ impl Builder {
 
         Ok(Self {
             root: root.to_path_buf(),
+            recompress: false,
         })
     }
 
+    /// Sets whether files that an [`Extractor`] decompressed are compressed
+    /// again with their original format. The formats are read from the
+    /// sidecar written during extraction; without one nothing is compressed.
+    ///
+    /// [`Extractor`]: struct.Extractor.html
+    pub fn set_recompress(&mut self, recompress: bool) {
+        self.recompress = recompress;
+    }
+
     /// Determines whether a given path is a valid NDS ROM.
     /// A valid NDS ROM directory is made when a ROM is extracted
     /// with an [`Extractor`] and includes the following:
@@ -89,13 +125,342 @@ impl Builder {
     /// Builds a ROM and saves it to the path given. This method will
     /// return an error when the directory is missing required files,
     /// or if there is an issue reading files or saving the ROM.
+    ///
+    /// Sections are laid out in the same order as `ndstool`: ARM9 binary,
+    /// ARM9 overlays, ARM7 binary, ARM7 overlays, FNT, FAT, banner and then
+    /// the files, each aligned to 0x200 bytes. DSi ROMs get their ARM9i and
+    /// ARM7i binaries appended after that.
+    ///
+    /// With the `manifest` feature, which is on by default, and a
+    /// `rom.toml` written by an [`Extractor`] in the directory, the
+    /// header fields, banner titles and overlay tables in it take the place
+    /// of the ones in `header.bin`, `banner.bin` and the overlay tables.
+    /// Files are placed in the order it lists them, with new files last,
+    /// and sections use its alignment and padding.
+    ///
+    /// [`Extractor`]: struct.Extractor.html
     pub fn build<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let _output = path.as_ref();
+        Self::is_nds_dir(&self.root)?;
+
+        let header = read(self.root.join("header.bin"))?;
+        ensure!(header.len() >= 0x180, BuildError::HeaderTooSmall);
+
+        #[cfg(feature = "manifest")]
+        let manifest = {
+            let path = self.root.join(MANIFEST_NAME);
+
+            if path.is_file() {
+                Some(Manifest::from_path(path)?)
+            } else {
+                None
+            }
+        };
+
+        #[cfg(feature = "manifest")]
+        let (header, tables) = match &manifest {
+            Some(manifest) => {
+                let mut header = header;
+                manifest.apply_header(&mut header)?;
+
+                (header, Some((manifest.overlay_table(true)?, manifest.overlay_table(false)?)))
+            }
+            None => (header, None),
+        };
+
+        #[cfg(not(feature = "manifest"))]
+        let tables = None;
+
+        let (arm9_table, arm7_table) = match tables {
+            Some(tables) => tables,
+            None => (read(self.root.join("arm9_overlay.bin"))?, read(self.root.join("arm7_overlay.bin"))?),
+        };
+
+        let arm9_overlays = self.read_overlays(arm9_table)?;
+        let arm7_overlays = self.read_overlays(arm7_table)?;
+        let start_id = arm9_overlays.fat_len().max(arm7_overlays.fat_len());
+
+        let banner_path = self.root.join("banner.bin");
+        let banner = if banner_path.is_file() {
+            Some(read(banner_path)?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "manifest")]
+        let banner = match (banner, &manifest) {
+            (Some(banner), Some(manifest)) => Some(manifest.apply_banner(banner)?),
+            (banner, _) => banner,
+        };
+
+        #[cfg(feature = "manifest")]
+        let (spacing, file_order) = manifest
+            .as_ref()
+            .map(|manifest| (manifest.spacing(), manifest.file_order()))
+            .unwrap_or_default();
 
-        // let header = read(self.root.join("header.bin"))?;
+        #[cfg(not(feature = "manifest"))]
+        let (spacing, file_order) = Default::default();
 
-        // let fs = FileSystem::build(self.root)?;
+        let fs = FileSystem::from_path(self.root.join("data"), start_id as u16)?;
+
+        #[cfg(feature = "manifest")]
+        let fs = match &manifest {
+            Some(manifest) => {
+                let mut fs = fs;
+                fs.order_files(&manifest.file_ids())?;
+
+                fs
+            }
+            None => fs,
+        };
+
+        let sidecar = self.root.join(SIDECAR_NAME);
+        let compressed = if self.recompress && sidecar.is_file() {
+            read_sidecar(sidecar)?
+        } else {
+            BTreeMap::new()
+        };
+
+        let arm9i_path = self.root.join("arm9i.bin");
+        let arm7i_path = self.root.join("arm7i.bin");
+
+        let twl = if header[Header::UnitCode as usize] & UNIT_CODE_TWL != 0 && arm9i_path.is_file() && arm7i_path.is_file() {
+            Some((read(arm9i_path)?, read(arm7i_path)?))
+        } else {
+            None
+        };
+
+        let sections = Sections {
+            header,
+            arm9: read(self.root.join("arm9.bin"))?,
+            arm7: read(self.root.join("arm7.bin"))?,
+            arm9_overlays,
+            arm7_overlays,
+            fs,
+            data_dir: Some(self.root.join("data")),
+            compressed,
+            banner,
+            twl,
+            spacing,
+            file_order,
+        };
+
+        write(path, layout(sections)?)?;
 
         Ok(())
     }
+
+    /// Reads every overlay referenced by an overlay table.
+    fn read_overlays(&self, table: Vec<u8>) -> Result<Overlays> {
+        ensure!(table.len() % OVERLAY_ENTRY_LEN == 0, BuildError::InvalidOverlayTable);
+
+        let files = table
+            .chunks_exact(OVERLAY_ENTRY_LEN)
+            .map(|entry| {
+                let id = LittleEndian::read_u32(&entry[OVERLAY_FILE_ID..]);
+                Ok(read(self.root.join("overlay").join(format!("overlay_{:04}", id)))?)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Overlays { table, files })
+    }
+}
+
+/// Everything that is placed in a ROM. Files in the file system are read
+/// from `data_dir` one at a time as they are placed, but the whole ROM is
+/// put together in memory before it is written.
+pub(crate) struct Sections {
+    /// The header, followed by whatever comes before the ARM9 binary.
+    pub header: Vec<u8>,
+    pub arm9: Vec<u8>,
+    pub arm7: Vec<u8>,
+    pub arm9_overlays: Overlays,
+    pub arm7_overlays: Overlays,
+    /// The file system, with files numbered after the overlays.
+    pub fs: FileSystem,
+    pub data_dir: Option<PathBuf>,
+    /// Files that are compressed before they are placed.
+    pub compressed: BTreeMap<PathBuf, Compression>,
+    pub banner: Option<Vec<u8>>,
+    /// The ARM9i and ARM7i binaries of a DSi ROM.
+    pub twl: Option<(Vec<u8>, Vec<u8>)>,
+    pub spacing: Spacing,
+    /// Where files were placed before, which decides the order they are
+    /// placed in. Files that aren't listed go last, in ID order.
+    pub file_order: BTreeMap<PathBuf, u32>,
+}
+
+/// How sections are spaced out in a ROM.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Spacing {
+    /// What every section starts on a multiple of.
+    pub alignment: usize,
+    /// The byte that fills the gaps between sections.
+    pub padding: u8,
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Self {
+            alignment: ALIGNMENT,
+            padding: 0,
+        }
+    }
+}
+
+/// An overlay table and the contents of each overlay, in table order.
+#[derive(Default)]
+pub(crate) struct Overlays {
+    pub table: Vec<u8>,
+    pub files: Vec<Vec<u8>>,
+}
+
+impl Overlays {
+    /// How many FAT entries are needed to hold every overlay.
+    fn fat_len(&self) -> usize {
+        self.file_ids().map(|id| id + 1).max().unwrap_or(0)
+    }
+
+    fn file_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.table
+            .chunks_exact(OVERLAY_ENTRY_LEN)
+            .map(|entry| LittleEndian::read_u32(&entry[OVERLAY_FILE_ID..]) as usize)
+    }
+}
+
+/// Places every section in a new ROM and points the header at them.
+pub(crate) fn layout(sections: Sections) -> Result<Vec<u8>> {
+    let spacing = sections.spacing;
+    let mut header = sections.header;
+    let mut rom = header.clone();
+    let mut fat = Vec::new();
+
+    let arm9 = append(&mut rom, spacing, &sections.arm9)?;
+    let arm9_overlay = append_overlays(&mut rom, spacing, &sections.arm9_overlays, &mut fat)?;
+    let arm7 = append(&mut rom, spacing, &sections.arm7)?;
+    let arm7_overlay = append_overlays(&mut rom, spacing, &sections.arm7_overlays, &mut fat)?;
+
+    let fs = sections.fs;
+    let fnt = append(&mut rom, spacing, &fs.to_fnt()?)?;
+
+    let mut files = fs.files();
+    let order = &sections.file_order;
+    files.sort_by_key(|file| (order.get(&file.path).copied().unwrap_or(u32::MAX), file.id));
+
+    //  The FAT has to be written before the files it describes, so
+    //  reserve the space now and fill it in once every file is placed.
+    fat.resize(fs.start_id() as usize + files.len(), AllocInfo::default());
+    let fat_alloc = append(&mut rom, spacing, &vec![0; fat.len() * 8])?;
+
+    let banner = match &sections.banner {
+        Some(banner) => append(&mut rom, spacing, banner)?,
+        None => AllocInfo::default(),
+    };
+
+    if let Some(data_dir) = &sections.data_dir {
+        for file in files {
+            let mut data = read(data_dir.join(&file.path))?;
+
+            if let Some(&compression) = sections.compressed.get(&file.path) {
+                data = compress(&data, compression)?;
+            }
+
+            fat[file.id as usize] = append(&mut rom, spacing, &data)?;
+        }
+    }
+
+    {
+        let table = &mut rom[fat_alloc.start as usize..fat_alloc.end as usize];
+
+        for (entry, alloc) in table.chunks_exact_mut(8).zip(&fat) {
+            LittleEndian::write_u32(&mut entry[0..], alloc.start);
+            LittleEndian::write_u32(&mut entry[4..], alloc.end);
+        }
+    }
+
+    set_alloc(&mut header, Header::Arm9Offset, Header::Arm9Len, arm9);
+    set_alloc(&mut header, Header::Arm7Offset, Header::Arm7Len, arm7);
+    set_alloc(&mut header, Header::FntOffset, Header::FntLen, fnt);
+    set_alloc(&mut header, Header::FatOffset, Header::FatLen, fat_alloc);
+    set_alloc(&mut header, Header::Arm9OverlayOffset, Header::Arm9OverlayLen, arm9_overlay);
+    set_alloc(&mut header, Header::Arm7OverlayOffset, Header::Arm7OverlayLen, arm7_overlay);
+    LittleEndian::write_u32(&mut header[Header::BannerOffset as usize..], banner.start);
+    LittleEndian::write_u32(&mut header[Header::RomSize as usize..], rom.len() as u32);
+
+    if let Some((arm9i, arm7i)) = &sections.twl {
+        ensure!(header.len() >= Header::TwlRomSize as usize + 4, BuildError::HeaderTooSmall);
+
+        let arm9i = append(&mut rom, spacing, arm9i)?;
+        let arm7i = append(&mut rom, spacing, arm7i)?;
+
+        set_alloc(&mut header, Header::Arm9iOffset, Header::Arm9iLen, arm9i);
+        set_alloc(&mut header, Header::Arm7iOffset, Header::Arm7iLen, arm7i);
+
+        //  Keep the original total size unless the new data no longer fits.
+        let total = LittleEndian::read_u32(&header[Header::TwlRomSize as usize..]) as usize;
+
+        if total > rom.len() {
+            rom.resize(total, spacing.padding);
+        }
+
+        LittleEndian::write_u32(&mut header[Header::TwlRomSize as usize..], rom.len() as u32);
+    }
+
+    rom[..header.len()].copy_from_slice(&header);
+    write_crc(&mut rom);
+
+    Ok(rom)
+}
+
+/// Recalculates the header CRC.
+pub(crate) fn write_crc(rom: &mut [u8]) {
+    let crc = crate::util::crc::crc16(&rom[0..Header::Crc as usize]);
+    LittleEndian::write_u16(&mut rom[Header::Crc as usize..], crc);
+}
+
+/// Appends an overlay table followed by every overlay it references. The
+/// allocation of each overlay is stored in `fat` at the index of its file
+/// ID.
+///
+/// An empty table is not placed at all, which matches ROMs that have no
+/// overlays for a processor.
+fn append_overlays(rom: &mut Vec<u8>, spacing: Spacing, overlays: &Overlays, fat: &mut Vec<AllocInfo>) -> Result<AllocInfo> {
+    if overlays.table.is_empty() {
+        return Ok(AllocInfo::default());
+    }
+
+    let alloc = append(rom, spacing, &overlays.table)?;
+
+    for (id, data) in overlays.file_ids().zip(&overlays.files) {
+        if fat.len() <= id {
+            fat.resize(id + 1, AllocInfo::default());
+        }
+
+        fat[id] = append(rom, spacing, data)?;
+    }
+
+    Ok(alloc)
+}
+
+/// Pads the ROM to the next aligned offset and appends `data` to it,
+/// returning where the data ended up.
+fn append(rom: &mut Vec<u8>, spacing: Spacing, data: &[u8]) -> Result<AllocInfo> {
+    let start = rom.len().next_multiple_of(spacing.alignment);
+    let end = start + data.len();
+
+    ensure!(end <= u32::MAX as usize, BuildError::RomTooLarge);
+
+    rom.resize(start, spacing.padding);
+    rom.extend_from_slice(data);
+
+    Ok(AllocInfo {
+        start: start as u32,
+        end: end as u32,
+    })
+}
+
+/// Writes an allocation into the header as an offset and length pair.
+fn set_alloc(header: &mut [u8], offset: Header, len: Header, alloc: AllocInfo) {
+    LittleEndian::write_u32(&mut header[offset as usize..], alloc.start);
+    LittleEndian::write_u32(&mut header[len as usize..], alloc.len());
 }
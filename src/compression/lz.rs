@@ -0,0 +1,249 @@
+//! LZ77 variants used by the BIOS with types `0x10` and `0x11`.
+//!
+//! Both formats group eight blocks behind a flag byte, where a set bit
+//! (most significant first) marks a back reference into the last 4KiB of
+//! output and a clear bit marks a literal byte.
+
+use anyhow::{ensure, Result};
+
+use super::CompressionError;
+
+/// How far back a reference can point.
+const WINDOW: usize = 0x1000;
+
+/// The shortest match worth encoding as a reference.
+const MIN_MATCH: usize = 3;
+
+/// References are never made to the directly preceding byte, since the
+/// BIOS functions that write to VRAM can only write 16 bits at a time.
+const MIN_DISP: usize = 2;
+
+const LZ10_MAX_MATCH: usize = 0x12;
+const LZ11_MAX_MATCH: usize = 0x10110;
+
+/// Marks an empty slot in the hash chains.
+const NONE: usize = usize::MAX;
+
+pub(super) fn decompress_lz10(data: &[u8], size: usize) -> Result<(Vec<u8>, usize)> {
+    decompress(data, size, |data, pos| {
+        let b0 = byte(data, *pos)? as usize;
+        let b1 = byte(data, *pos + 1)? as usize;
+        *pos += 2;
+
+        Ok(((b0 >> 4) + 3, ((b0 & 0xF) << 8 | b1) + 1))
+    })
+}
+
+pub(super) fn decompress_lz11(data: &[u8], size: usize) -> Result<(Vec<u8>, usize)> {
+    decompress(data, size, |data, pos| {
+        let b0 = byte(data, *pos)? as usize;
+        let b1 = byte(data, *pos + 1)? as usize;
+
+        let reference = match b0 >> 4 {
+            0 => {
+                let b2 = byte(data, *pos + 2)? as usize;
+                *pos += 3;
+
+                (((b0 & 0xF) << 4 | b1 >> 4) + 0x11, ((b1 & 0xF) << 8 | b2) + 1)
+            }
+            1 => {
+                let b2 = byte(data, *pos + 2)? as usize;
+                let b3 = byte(data, *pos + 3)? as usize;
+                *pos += 4;
+
+                (((b0 & 0xF) << 12 | b1 << 4 | b2 >> 4) + 0x111, ((b2 & 0xF) << 8 | b3) + 1)
+            }
+            len => {
+                *pos += 2;
+
+                (len + 1, ((b0 & 0xF) << 8 | b1) + 1)
+            }
+        };
+
+        Ok(reference)
+    })
+}
+
+pub(super) fn compress_lz10(data: &[u8], output: &mut Vec<u8>) {
+    compress(data, output, LZ10_MAX_MATCH, |output, len, disp| {
+        let disp = disp - 1;
+
+        output.push(((len - 3) << 4 | disp >> 8) as u8);
+        output.push(disp as u8);
+    })
+}
+
+pub(super) fn compress_lz11(data: &[u8], output: &mut Vec<u8>) {
+    compress(data, output, LZ11_MAX_MATCH, |output, len, disp| {
+        let disp = disp - 1;
+
+        if len <= 0x10 {
+            output.push(((len - 1) << 4 | disp >> 8) as u8);
+        } else if len <= 0x110 {
+            let len = len - 0x11;
+
+            output.push((len >> 4) as u8);
+            output.push(((len & 0xF) << 4 | disp >> 8) as u8);
+        } else {
+            let len = len - 0x111;
+
+            output.push((0x10 | len >> 12) as u8);
+            output.push((len >> 4) as u8);
+            output.push(((len & 0xF) << 4 | disp >> 8) as u8);
+        }
+
+        output.push(disp as u8);
+    })
+}
+
+fn byte(data: &[u8], pos: usize) -> Result<u8> {
+    Ok(*data.get(pos).ok_or(CompressionError::NotEnoughData)?)
+}
+
+/// Shared decoding loop. `reference` reads a back reference at the given
+/// position, advances it and returns the length and displacement.
+fn decompress<F>(data: &[u8], size: usize, reference: F) -> Result<(Vec<u8>, usize)>
+    where
+        F: Fn(&[u8], &mut usize) -> Result<(usize, usize)>
+{
+    let mut output = Vec::with_capacity(size);
+    let mut pos = 0;
+
+    while output.len() < size {
+        let flags = byte(data, pos)?;
+        pos += 1;
+
+        for bit in (0..8).rev() {
+            if output.len() >= size {
+                break;
+            }
+
+            if flags & (1 << bit) == 0 {
+                output.push(byte(data, pos)?);
+                pos += 1;
+                continue;
+            }
+
+            let (len, disp) = reference(data, &mut pos)?;
+
+            ensure!(disp <= output.len(), CompressionError::InvalidDisplacement);
+            ensure!(output.len() + len <= size, CompressionError::SizeMismatch);
+
+            let start = output.len() - disp;
+
+            //  The source and destination may overlap, so copy byte by byte.
+            for index in start..start + len {
+                output.push(output[index]);
+            }
+        }
+    }
+
+    Ok((output, pos))
+}
+
+/// Shared greedy encoder. `reference` writes a back reference of the given
+/// length and displacement.
+fn compress<F>(data: &[u8], output: &mut Vec<u8>, max_match: usize, reference: F)
+    where
+        F: Fn(&mut Vec<u8>, usize, usize)
+{
+    let mut matcher = Matcher::new(data.len());
+    let mut pos = 0;
+    let mut flag_pos = 0;
+    let mut block = 8;
+
+    while pos < data.len() {
+        if block == 8 {
+            flag_pos = output.len();
+            output.push(0);
+            block = 0;
+        }
+
+        let (len, disp) = matcher.find(data, pos, max_match);
+
+        if len >= MIN_MATCH {
+            output[flag_pos] |= 0x80 >> block;
+            reference(output, len, disp);
+
+            for index in pos..pos + len {
+                matcher.insert(data, index);
+            }
+
+            pos += len;
+        } else {
+            output.push(data[pos]);
+            matcher.insert(data, pos);
+            pos += 1;
+        }
+
+        block += 1;
+    }
+
+    output.resize(output.len().next_multiple_of(4), 0);
+}
+
+/// Finds earlier occurrences of the bytes at a position by chaining
+/// together positions that start with the same three bytes.
+struct Matcher {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl Matcher {
+    fn new(len: usize) -> Self {
+        Self {
+            head: vec![NONE; 0x10000],
+            prev: vec![NONE; len],
+        }
+    }
+
+    fn hash(data: &[u8], pos: usize) -> Option<usize> {
+        if pos + MIN_MATCH > data.len() {
+            return None;
+        }
+
+        let hash = (data[pos] as usize) << 8 ^ (data[pos + 1] as usize) << 4 ^ data[pos + 2] as usize;
+        Some(hash & 0xFFFF)
+    }
+
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if let Some(hash) = Self::hash(data, pos) {
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// Returns the longest match for `pos` as a length and displacement.
+    fn find(&self, data: &[u8], pos: usize, max_match: usize) -> (usize, usize) {
+        let hash = match Self::hash(data, pos) {
+            Some(hash) => hash,
+            None => return (0, 0),
+        };
+
+        let max_len = max_match.min(data.len() - pos);
+        let mut best = (0, 0);
+        let mut candidate = self.head[hash];
+
+        while candidate != NONE && pos - candidate <= WINDOW {
+            let disp = pos - candidate;
+
+            if disp >= MIN_DISP {
+                let len = (0..max_len)
+                    .take_while(|&index| data[candidate + index] == data[pos + index])
+                    .count();
+
+                if len > best.0 {
+                    best = (len, disp);
+
+                    if len == max_len {
+                        break;
+                    }
+                }
+            }
+
+            candidate = self.prev[candidate];
+        }
+
+        best
+    }
+}
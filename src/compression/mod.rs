@@ -0,0 +1,246 @@
+//! Decompression and compression for the formats supported by the DS BIOS.
+//!
+//! Every format starts with a 4 byte header where the lowest byte is the
+//! type and the upper 24 bits are the decompressed size. Since files in a
+//! ROM don't say whether they are compressed, [`detect_compression`] can be
+//! used to guess it from the data alone.
+//!
+//! [`detect_compression`]: fn.detect_compression.html
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{ensure, Result};
+
+mod lz;
+mod rle;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum CompressionError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown compression type: {0:#04X}.")]
+    UnknownType(u8),
+
+    #[error("Unknown compression name: '{0}'.")]
+    UnknownName(String),
+
+    #[error("Back reference points before the start of the output.")]
+    InvalidDisplacement,
+
+    #[error("Decompressed data does not match the size in the header.")]
+    SizeMismatch,
+
+    #[error("Data is too large to be compressed with {0}.")]
+    TooLarge(Compression),
+
+    #[error("Invalid line in compression sidecar: '{0}'.")]
+    InvalidSidecar(String),
+}
+
+/// Name of the file written next to extracted files that lists which of
+/// them were decompressed and with which format.
+pub const SIDECAR_NAME: &str = "compression.txt";
+
+/// The largest decompressed size [`detect_compression`] will accept. Nothing
+/// on a DS can hold more than this in memory at once.
+///
+/// [`detect_compression`]: fn.detect_compression.html
+const MAX_DETECT_SIZE: usize = 0x100_0000;
+
+/// A compression format supported by the DS BIOS.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Compression {
+    /// LZ77 with 3 to 18 byte matches, type `0x10`.
+    Lz10,
+    /// LZ77 with matches up to 65808 bytes, type `0x11`.
+    Lz11,
+    /// Run length encoding, type `0x30`.
+    Rle,
+}
+
+impl Compression {
+    /// The type byte stored in the header.
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::Lz10 => 0x10,
+            Compression::Lz11 => 0x11,
+            Compression::Rle => 0x30,
+        }
+    }
+
+    /// Gets the format from a header type byte.
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x10 => Some(Compression::Lz10),
+            0x11 => Some(Compression::Lz11),
+            0x30 => Some(Compression::Rle),
+            _ => None,
+        }
+    }
+
+    /// A short lowercase name of the format, used in files that note how
+    /// data was compressed.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Lz10 => "lz10",
+            Compression::Lz11 => "lz11",
+            Compression::Rle => "rle",
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = CompressionError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "lz10" => Ok(Compression::Lz10),
+            "lz11" => Ok(Compression::Lz11),
+            "rle" => Ok(Compression::Rle),
+            _ => Err(CompressionError::UnknownName(name.to_string())),
+        }
+    }
+}
+
+/// Reads the header of compressed data, returning the format, the
+/// decompressed size and where the payload starts.
+fn read_header(data: &[u8]) -> Result<(Compression, usize, usize)> {
+    ensure!(data.len() >= 4, CompressionError::NotEnoughData);
+
+    let compression = Compression::from_id(data[0]).ok_or(CompressionError::UnknownType(data[0]))?;
+    let size = (LittleEndian::read_u32(data) >> 8) as usize;
+
+    //  LZ11 stores sizes that don't fit in 24 bits in an extra word.
+    if size == 0 && compression == Compression::Lz11 {
+        ensure!(data.len() >= 8, CompressionError::NotEnoughData);
+        return Ok((compression, LittleEndian::read_u32(&data[4..]) as usize, 8));
+    }
+
+    Ok((compression, size, 4))
+}
+
+/// Writes the header for `len` bytes of data compressed with `compression`.
+fn write_header(compression: Compression, len: usize) -> Result<Vec<u8>> {
+    let mut header = vec![0; 4];
+
+    if len < 0x100_0000 {
+        LittleEndian::write_u32(&mut header, (len as u32) << 8 | u32::from(compression.id()));
+    } else {
+        ensure!(compression == Compression::Lz11 && len <= u32::MAX as usize, CompressionError::TooLarge(compression));
+
+        header[0] = compression.id();
+        header.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    Ok(header)
+}
+
+/// Decompresses data, reading the format from its header.
+///
+/// # Errors
+/// Returns an error if the header has an unknown type, if the payload ends
+/// early or if it doesn't produce exactly the size given in the header.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    decompress_with_len(data).map(|(output, _)| output)
+}
+
+/// Decompresses data and also returns how many bytes of the input were used.
+fn decompress_with_len(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let (compression, size, start) = read_header(data)?;
+
+    let (output, used) = match compression {
+        Compression::Lz10 => lz::decompress_lz10(&data[start..], size)?,
+        Compression::Lz11 => lz::decompress_lz11(&data[start..], size)?,
+        Compression::Rle => rle::decompress(&data[start..], size)?,
+    };
+
+    Ok((output, start + used))
+}
+
+/// Compresses data with the given format, including the header.
+pub fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    let mut output = write_header(compression, data.len())?;
+
+    match compression {
+        Compression::Lz10 => lz::compress_lz10(data, &mut output),
+        Compression::Lz11 => lz::compress_lz11(data, &mut output),
+        Compression::Rle => rle::compress(data, &mut output),
+    }
+
+    Ok(output)
+}
+
+/// Guesses whether data is compressed and with which format.
+///
+/// The header type has to be known, the decompressed size has to be
+/// plausible, and decoding has to produce exactly that many bytes while
+/// using up the payload. Files are usually padded to 4 bytes, so a short
+/// tail or a tail of padding bytes after the payload is accepted.
+pub fn detect_compression(data: &[u8]) -> Option<Compression> {
+    detect_and_decompress(data).map(|(compression, _)| compression)
+}
+
+/// Same as [`detect_compression`], but also returns the decompressed data,
+/// so that data which is going to be used anyway is only decoded once.
+///
+/// [`detect_compression`]: fn.detect_compression.html
+pub fn detect_and_decompress(data: &[u8]) -> Option<(Compression, Vec<u8>)> {
+    let (compression, size, _) = read_header(data).ok()?;
+
+    if size == 0 || size > MAX_DETECT_SIZE {
+        return None;
+    }
+
+    let (output, used) = decompress_with_len(data).ok()?;
+    let tail = &data[used..];
+
+    if tail.len() < 4 || tail.iter().all(|&byte| byte == 0 || byte == 0xFF) {
+        Some((compression, output))
+    } else {
+        None
+    }
+}
+
+/// Writes a sidecar listing the format of every decompressed file. Each line
+/// holds the format name followed by the path of the file.
+pub(crate) fn write_sidecar<P: AsRef<Path>>(path: P, files: &BTreeMap<PathBuf, Compression>) -> Result<()> {
+    let contents = files
+        .iter()
+        .map(|(file, compression)| format!("{} {}\n", compression, file.display()))
+        .collect::<String>();
+
+    write(path, contents)?;
+
+    Ok(())
+}
+
+/// Reads a sidecar written by [`write_sidecar`].
+///
+/// [`write_sidecar`]: fn.write_sidecar.html
+pub(crate) fn read_sidecar<P: AsRef<Path>>(path: P) -> Result<BTreeMap<PathBuf, Compression>> {
+    let mut files = BTreeMap::new();
+
+    for line in read_to_string(path)?.lines().filter(|line| !line.trim().is_empty()) {
+        let (name, file) = line
+            .split_once(' ')
+            .ok_or_else(|| CompressionError::InvalidSidecar(line.to_string()))?;
+
+        files.insert(PathBuf::from(file), name.parse()?);
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,83 @@
+//! Run length encoding used by the BIOS with type `0x30`.
+//!
+//! Each block starts with a flag byte. If the top bit is set, the next byte
+//! is repeated `(flag & 0x7F) + 3` times, otherwise `(flag & 0x7F) + 1`
+//! literal bytes follow.
+
+use anyhow::{ensure, Result};
+
+use super::CompressionError;
+
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 0x82;
+const MAX_LITERALS: usize = 0x80;
+
+pub(super) fn decompress(data: &[u8], size: usize) -> Result<(Vec<u8>, usize)> {
+    let mut output = Vec::with_capacity(size);
+    let mut pos = 0;
+
+    while output.len() < size {
+        let flag = *data.get(pos).ok_or(CompressionError::NotEnoughData)? as usize;
+        pos += 1;
+
+        if flag & 0x80 != 0 {
+            let len = (flag & 0x7F) + MIN_RUN;
+            let value = *data.get(pos).ok_or(CompressionError::NotEnoughData)?;
+            pos += 1;
+
+            ensure!(output.len() + len <= size, CompressionError::SizeMismatch);
+            output.resize(output.len() + len, value);
+        } else {
+            let len = (flag & 0x7F) + 1;
+
+            ensure!(data.len() >= pos + len, CompressionError::NotEnoughData);
+            ensure!(output.len() + len <= size, CompressionError::SizeMismatch);
+            output.extend_from_slice(&data[pos..pos + len]);
+            pos += len;
+        }
+    }
+
+    Ok((output, pos))
+}
+
+pub(super) fn compress(data: &[u8], output: &mut Vec<u8>) {
+    let mut pos = 0;
+    let mut literals = 0;
+
+    while pos < data.len() {
+        let run = data[pos..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&byte| byte == data[pos])
+            .count();
+
+        if run >= MIN_RUN {
+            flush_literals(data, pos, literals, output);
+            literals = 0;
+
+            output.push(0x80 | (run - MIN_RUN) as u8);
+            output.push(data[pos]);
+            pos += run;
+        } else {
+            literals += 1;
+            pos += 1;
+
+            if literals == MAX_LITERALS {
+                flush_literals(data, pos, literals, output);
+                literals = 0;
+            }
+        }
+    }
+
+    flush_literals(data, pos, literals, output);
+
+    output.resize(output.len().next_multiple_of(4), 0);
+}
+
+/// Writes the `count` literal bytes that end right before `end`.
+fn flush_literals(data: &[u8], end: usize, count: usize, output: &mut Vec<u8>) {
+    if count > 0 {
+        output.push((count - 1) as u8);
+        output.extend_from_slice(&data[end - count..end]);
+    }
+}
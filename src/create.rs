@@ -0,0 +1,280 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::FileSystem;
+
+use std::collections::BTreeMap;
+use std::fs::{read, write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+
+use crate::banner::Banner;
+use crate::build::{layout, write_crc, Overlays, Sections, Spacing};
+use crate::elf::Elf;
+use crate::graphics::{IndexedImage, Rgba};
+use crate::header::{Header, NINTENDO_LOGO, NINTENDO_LOGO_CRC};
+
+/// Where the ARM9 binary is placed in a new ROM. Everything before it is
+/// the header and the unused secure area.
+const ARM9_OFFSET: usize = 0x4000;
+
+/// Default addresses that binaries are loaded to, the same as `ndstool`.
+const ARM9_ADDRESS: u32 = 0x0200_0000;
+const ARM7_ADDRESS: u32 = 0x037F_8000;
+
+/// An ARM7 binary that loops forever, used when no ARM7 binary is given.
+const IDLE_ARM7: [u8; 4] = [0xFE, 0xFF, 0xFF, 0xEA];
+
+/// Card settings used by `ndstool` for homebrew.
+const NORMAL_CARD_CONTROL: u32 = 0x0058_6000;
+const SECURE_CARD_CONTROL: u32 = 0x0018_08F8;
+const SECURE_TRANSFER_TIMEOUT: u16 = 0x051E;
+
+/// The smallest chip size, which `devicecapacity` counts up from in powers
+/// of two.
+const MIN_CAPACITY: usize = 0x2_0000;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum CreateError {
+    #[error("Title must be at most 12 ASCII characters: {0:?}")]
+    Title(String),
+
+    #[error("Gamecode must be 4 ASCII characters: {0:?}")]
+    Gamecode(String),
+
+    #[error("Makercode must be 2 ASCII characters: {0:?}")]
+    Makercode(String),
+}
+
+/// Code for one of the processors, along with where it is loaded in
+/// memory and where it starts running.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Binary {
+    pub data: Vec<u8>,
+    pub load_address: u32,
+    pub entry_address: u32,
+}
+
+impl Binary {
+    /// A binary that starts running at the address it is loaded to.
+    pub fn new(data: Vec<u8>, load_address: u32) -> Self {
+        Self {
+            data,
+            load_address,
+            entry_address: load_address,
+        }
+    }
+
+    /// Reads an ELF file, or a raw binary such as one made with
+    /// `objcopy -O binary`. Raw binaries are loaded to `load_address`,
+    /// while ELF files have their own load and entry addresses.
+    pub fn from_path<P: AsRef<Path>>(path: P, load_address: u32) -> Result<Self> {
+        let data = read(path)?;
+
+        if Elf::is_elf(&data) {
+            Self::from_elf(&data)
+        } else {
+            Ok(Self::new(data, load_address))
+        }
+    }
+}
+
+/// Creates a homebrew ROM from its binaries, like `ndstool -c`, without
+/// an extracted ROM to start from.
+///
+/// # Example
+/// ```no_run
+/// use nds::{Binary, Creator};
+///
+/// let arm9 = Binary::from_path("arm9.bin", 0x0200_0000).unwrap();
+/// let mut creator = Creator::new(arm9);
+///
+/// creator.set_title("HOMEBREW");
+/// creator.set_data_dir("nitrofiles");
+/// creator.build("homebrew.nds").unwrap();
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Creator {
+    arm9: Binary,
+    arm7: Binary,
+    data_dir: Option<PathBuf>,
+    title: String,
+    gamecode: String,
+    makercode: String,
+    banner: Option<Banner>,
+    icon: Option<(IndexedImage, Vec<Rgba>)>,
+}
+
+impl Creator {
+    /// Starts a ROM with the given ARM9 binary and an ARM7 binary that does
+    /// nothing.
+    pub fn new(arm9: Binary) -> Self {
+        Self {
+            arm9,
+            arm7: Binary::new(IDLE_ARM7.to_vec(), ARM7_ADDRESS),
+            data_dir: None,
+            title: String::new(),
+            gamecode: "####".to_string(),
+            makercode: "00".to_string(),
+            banner: None,
+            icon: None,
+        }
+    }
+
+    /// Reads the ARM9 binary from an ELF file or a raw binary, which is
+    /// loaded to the default address of `0x02000000`.
+    pub fn from_arm9<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(Binary::from_path(path, ARM9_ADDRESS)?))
+    }
+
+    pub fn set_arm7(&mut self, arm7: Binary) {
+        self.arm7 = arm7;
+    }
+
+    /// Sets a directory whose contents become the file system of the ROM.
+    /// Without one the file system is empty.
+    pub fn set_data_dir<P: AsRef<Path>>(&mut self, path: P) {
+        self.data_dir = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Sets the title in the header, which is also used for the banner if
+    /// none is given.
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    /// Sets the gamecode, which is `####` by default.
+    pub fn set_gamecode(&mut self, gamecode: &str) {
+        self.gamecode = gamecode.to_string();
+    }
+
+    /// Sets the makercode, which is `00` by default.
+    pub fn set_makercode(&mut self, makercode: &str) {
+        self.makercode = makercode.to_string();
+    }
+
+    /// Sets the banner with the icon and titles shown in the DS menu.
+    pub fn set_banner(&mut self, banner: Banner) {
+        self.banner = Some(banner);
+    }
+
+    /// Sets the icon shown in the DS menu: a 32x32 image with a palette of
+    /// up to 16 colors, where the first color is transparent. It replaces
+    /// the icon of the banner, if one is set. Without an icon, the banner
+    /// has a blank one.
+    pub fn set_icon(&mut self, image: IndexedImage, palette: Vec<Rgba>) {
+        self.icon = Some((image, palette));
+    }
+
+    /// Same as [`set_icon`], but reads the icon from an indexed PNG.
+    ///
+    /// [`set_icon`]: #method.set_icon
+    #[cfg(feature = "image")]
+    pub fn set_icon_png<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let (image, palette) = IndexedImage::from_png(&read(path)?)?;
+        self.set_icon(image, palette);
+
+        Ok(())
+    }
+
+    /// Creates the ROM and saves it to the path given.
+    pub fn build<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write(path, self.create()?)?;
+
+        Ok(())
+    }
+
+    /// Creates the ROM in memory.
+    ///
+    /// # Errors
+    /// Returns an error if the title, gamecode or makercode don't fit in
+    /// the header, if the icon isn't valid, or if the data directory can't
+    /// be read.
+    pub fn create(&self) -> Result<Vec<u8>> {
+        let mut banner = match &self.banner {
+            Some(banner) => banner.clone(),
+            None => Banner::new(&self.title),
+        };
+
+        if let Some((image, palette)) = &self.icon {
+            banner.set_icon(image, palette)?;
+        }
+
+        let fs = match &self.data_dir {
+            Some(dir) => FileSystem::from_path(dir, 0)?,
+            None => FileSystem::empty(0),
+        };
+
+        let sections = Sections {
+            header: self.header()?,
+            arm9: self.arm9.data.clone(),
+            arm7: self.arm7.data.clone(),
+            arm9_overlays: Overlays::default(),
+            arm7_overlays: Overlays::default(),
+            fs,
+            data_dir: self.data_dir.clone(),
+            compressed: BTreeMap::new(),
+            banner: Some(banner.to_bytes()),
+            twl: None,
+            spacing: Spacing::default(),
+            file_order: BTreeMap::new(),
+        };
+
+        let mut rom = layout(sections)?;
+
+        //  The chip size can only be known once everything is placed.
+        let capacity = (0..)
+            .find(|&shift| MIN_CAPACITY << shift >= rom.len())
+            .unwrap_or(0);
+
+        rom[Header::DeviceCapacity as usize] = capacity as u8;
+        write_crc(&mut rom);
+
+        Ok(rom)
+    }
+
+    /// Makes a header with everything except the layout of the sections,
+    /// which is filled in when they are placed.
+    fn header(&self) -> Result<Vec<u8>> {
+        ensure!(
+            self.title.is_ascii() && self.title.len() <= 12,
+            CreateError::Title(self.title.clone())
+        );
+        ensure!(
+            self.gamecode.is_ascii() && self.gamecode.len() == 4,
+            CreateError::Gamecode(self.gamecode.clone())
+        );
+        ensure!(
+            self.makercode.is_ascii() && self.makercode.len() == 2,
+            CreateError::Makercode(self.makercode.clone())
+        );
+
+        let mut header = vec![0; ARM9_OFFSET];
+        let mut put = |offset: Header, data: &[u8]| {
+            let offset = offset as usize;
+            header[offset..offset + data.len()].copy_from_slice(data);
+        };
+
+        put(Header::Title, self.title.as_bytes());
+        put(Header::Gamecode, self.gamecode.as_bytes());
+        put(Header::Makercode, self.makercode.as_bytes());
+        put(Header::Logo, &NINTENDO_LOGO);
+
+        for (offset, value) in &[
+            (Header::Arm9Entry, self.arm9.entry_address),
+            (Header::Arm9Load, self.arm9.load_address),
+            (Header::Arm7Entry, self.arm7.entry_address),
+            (Header::Arm7Load, self.arm7.load_address),
+            (Header::NormalCardControl, NORMAL_CARD_CONTROL),
+            (Header::SecureCardControl, SECURE_CARD_CONTROL),
+            (Header::Size, ARM9_OFFSET as u32),
+        ] {
+            LittleEndian::write_u32(&mut header[*offset as usize..], *value);
+        }
+
+        LittleEndian::write_u16(&mut header[Header::SecureTransferTimeout as usize..], SECURE_TRANSFER_TIMEOUT);
+        LittleEndian::write_u16(&mut header[Header::LogoCrc as usize..], NINTENDO_LOGO_CRC);
+
+        Ok(header)
+    }
+}
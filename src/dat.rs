@@ -0,0 +1,273 @@
+//! Identifying dumps with Logiqx XML DAT files, such as the ones published
+//! by No-Intro and Redump.
+//!
+//! DATs list every known good dump of a system with its size and
+//! checksums. The serial of each entry includes the gamecode from the ROM
+//! header, so only a handful of entries have to be compared with the
+//! hashes of a ROM.
+//!
+//! # Example
+//! ```no_run
+//! use nds::dat::{Dat, Identification};
+//! use nds::Rom;
+//!
+//! let dat = Dat::from_path("Nintendo - Nintendo DS (Decrypted).dat").unwrap();
+//! let rom = Rom::new("game.nds", true).unwrap();
+//!
+//! match dat.identify(&rom) {
+//!     Identification::Verified(game) => println!("{}", game.name),
+//!     Identification::Trimmed(game) => println!("{} (trimmed)", game.name),
+//!     Identification::BadDump(_) => println!("Bad dump"),
+//!     Identification::Unknown => println!("Unknown ROM"),
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::parser::NDSParser;
+use crate::util::hash::{from_hex, Hasher, Hashes};
+use crate::Rom;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum DatError {
+    #[error("DAT file is not valid XML: {0}")]
+    InvalidXml(#[from] roxmltree::Error),
+
+    #[error("DAT file has no datafile element.")]
+    NotADatFile,
+}
+
+/// A parsed DAT file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Dat {
+    pub name: String,
+    pub description: String,
+    pub version: String,
+    pub games: Vec<Game>,
+    /// Indices of `games` by gamecode.
+    by_gamecode: HashMap<String, Vec<usize>>,
+}
+
+/// A game, which for the DS is a single ROM.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Game {
+    /// The full name, such as `Title (USA) (Rev 1)`.
+    pub name: String,
+    pub description: String,
+    pub roms: Vec<DatRom>,
+}
+
+/// One file of a game. Checksums that the DAT leaves out are `None`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DatRom {
+    pub name: String,
+    pub size: u64,
+    pub crc32: Option<u32>,
+    pub md5: Option<[u8; 16]>,
+    pub sha1: Option<[u8; 20]>,
+    /// Product serial, such as `NTR-ABCE-USA`.
+    pub serial: Option<String>,
+    /// Set by the DAT for entries such as `baddump` or `verified`.
+    pub status: Option<String>,
+}
+
+impl DatRom {
+    /// Whether the hashes match every checksum given for this entry.
+    pub fn matches(&self, hashes: &Hashes) -> bool {
+        self.size == hashes.size
+            && self.crc32.map_or(true, |crc| crc == hashes.crc32)
+            && self.md5.map_or(true, |md5| md5 == hashes.md5)
+            && self.sha1.map_or(true, |sha1| sha1 == hashes.sha1)
+    }
+}
+
+/// What a DAT says about a ROM.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Identification<'a> {
+    /// The ROM is a good dump of this game.
+    Verified(&'a Game),
+    /// The ROM is a good dump of this game once the padding at its end is
+    /// put back.
+    Trimmed(&'a Game),
+    /// The DAT has entries with the gamecode of the ROM, but none of them
+    /// match it.
+    BadDump(Vec<&'a Game>),
+    /// The DAT has no entry for the ROM.
+    Unknown,
+}
+
+impl Dat {
+    /// Reads a DAT file from a local path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&read_to_string(path)?)
+    }
+
+    /// Parses the XML of a DAT file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..roxmltree::ParsingOptions::default()
+        };
+        let document = roxmltree::Document::parse_with_options(text, options).map_err(DatError::InvalidXml)?;
+        let root = document.root_element();
+
+        if !root.has_tag_name("datafile") {
+            return Err(DatError::NotADatFile.into());
+        }
+
+        let mut dat = Self::default();
+
+        for node in root.children().filter(|node| node.is_element()) {
+            match node.tag_name().name() {
+                "header" => {
+                    dat.name = child_text(node, "name");
+                    dat.description = child_text(node, "description");
+                    dat.version = child_text(node, "version");
+                }
+                //  Some DATs made by MAME based tools call games machines.
+                "game" | "machine" => dat.games.push(parse_game(node)),
+                _ => (),
+            }
+        }
+
+        for (index, game) in dat.games.iter().enumerate() {
+            for gamecode in game.roms.iter().filter_map(|rom| rom.serial.as_deref()).flat_map(gamecodes) {
+                let games = dat.by_gamecode.entry(gamecode).or_default();
+
+                if games.last() != Some(&index) {
+                    games.push(index);
+                }
+            }
+        }
+
+        Ok(dat)
+    }
+
+    /// Finds the games that have the gamecode of a header, without hashing
+    /// anything. Games whose names have the ROM version of the header, such
+    /// as `(Rev 1)`, come first.
+    pub fn candidates(&self, header: &NDSParser) -> Vec<&Game> {
+        let mut games: Vec<&Game> = self
+            .by_gamecode
+            .get(&header.gamecode.to_uppercase())
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.games[index])
+            .collect();
+
+        let revision = match header.rom_version {
+            0 => None,
+            version => Some(format!("(Rev {})", version)),
+        };
+
+        games.sort_by_key(|game| match &revision {
+            Some(revision) => !game.name.contains(revision.as_str()),
+            None => game.name.contains("(Rev "),
+        });
+
+        games
+    }
+
+    /// Finds the game with a file that matches the hashes.
+    pub fn find(&self, hashes: &Hashes) -> Option<&Game> {
+        self.games.iter().find(|game| game.roms.iter().any(|rom| rom.matches(hashes)))
+    }
+
+    /// Identifies a ROM by hashing it and comparing it with the DAT.
+    ///
+    /// A ROM that doesn't match is also hashed as if it had been padded
+    /// with `0xFF` up to the size of each candidate, so that dumps which
+    /// had their unused space trimmed are still recognized.
+    pub fn identify(&self, rom: &Rom) -> Identification<'_> {
+        let data = rom.data();
+        let hashes = Hashes::new(data);
+        let candidates = self.candidates(rom.header());
+
+        let verified = candidates
+            .iter()
+            .copied()
+            .find(|game| game.roms.iter().any(|entry| entry.matches(&hashes)))
+            .or_else(|| self.find(&hashes));
+
+        if let Some(game) = verified {
+            return Identification::Verified(game);
+        }
+
+        for game in &candidates {
+            for entry in game.roms.iter().filter(|entry| entry.size > hashes.size) {
+                if entry.matches(&padded_hashes(data, entry.size)) {
+                    return Identification::Trimmed(game);
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            Identification::Unknown
+        } else {
+            Identification::BadDump(candidates)
+        }
+    }
+}
+
+fn parse_game(node: roxmltree::Node) -> Game {
+    let roms = node
+        .children()
+        .filter(|child| child.has_tag_name("rom"))
+        .map(|rom| DatRom {
+            name: rom.attribute("name").unwrap_or_default().to_string(),
+            size: rom.attribute("size").and_then(|size| size.trim().parse().ok()).unwrap_or(0),
+            crc32: rom.attribute("crc").and_then(|crc| u32::from_str_radix(crc.trim(), 16).ok()),
+            md5: rom.attribute("md5").and_then(from_hex),
+            sha1: rom.attribute("sha1").and_then(from_hex),
+            serial: rom.attribute("serial").map(str::to_string),
+            status: rom.attribute("status").map(str::to_string),
+        })
+        .collect();
+
+    Game {
+        name: node.attribute("name").unwrap_or_default().to_string(),
+        description: child_text(node, "description"),
+        roms,
+    }
+}
+
+fn child_text(node: roxmltree::Node, name: &str) -> String {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+/// Gets the gamecodes out of a serial such as `NTR-ABCE-USA`, which may also
+/// list several serials separated by commas.
+fn gamecodes(serial: &str) -> impl Iterator<Item = String> + '_ {
+    serial
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| part.len() == 4)
+        .map(str::to_uppercase)
+}
+
+/// Hashes `data` followed by `0xFF` up to `size` bytes.
+fn padded_hashes(data: &[u8], size: u64) -> Hashes {
+    let mut hasher = Hasher::new();
+    let padding = [0xFF; 0x1000];
+    let mut remaining = size - data.len() as u64;
+
+    hasher.update(data);
+
+    while remaining > 0 {
+        let len = remaining.min(padding.len() as u64) as usize;
+
+        hasher.update(&padding[..len]);
+        remaining -= len as u64;
+    }
+
+    hasher.finish()
+}
@@ -0,0 +1,351 @@
+//! Comparing two ROMs, such as two regions or revisions of a game or an
+//! original and a modified copy.
+//!
+//! Files are matched by path. Files that only exist in one of the ROMs but
+//! have the same contents as a file that only exists in the other one are
+//! reported as renamed.
+//!
+//! # Example
+//! ```no_run
+//! use nds::diff::RomDiff;
+//! use nds::Rom;
+//!
+//! let old = Rom::new("original.nds", true).unwrap();
+//! let new = Rom::new("modified.nds", true).unwrap();
+//!
+//! println!("{}", RomDiff::new(&old, &new).unwrap());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::overlay::{Overlay, Processor};
+use crate::parser::NDSParser;
+use crate::util::crc::crc32;
+use crate::Rom;
+
+/// A header field that differs between the two ROMs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeaderChange {
+    /// Name of the field in [`NDSParser`], such as `arm9.size`.
+    ///
+    /// [`NDSParser`]: ../parser/struct.NDSParser.html
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// A file that exists at the same path in both ROMs, but with different
+/// contents.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub old_size: u32,
+    pub new_size: u32,
+}
+
+/// A file that was moved to another path without changing its contents.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// A difference in the overlays of one processor.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OverlayChange {
+    Added { cpu: Processor, id: u32 },
+    Removed { cpu: Processor, id: u32 },
+    /// The overlay exists in both ROMs. `entry` is set if its overlay table
+    /// entry differs, and `data` if its code differs.
+    Changed { cpu: Processor, id: u32, entry: bool, data: bool },
+}
+
+/// Every difference between two ROMs.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RomDiff {
+    pub header: Vec<HeaderChange>,
+    pub arm9: bool,
+    pub arm7: bool,
+    pub overlays: Vec<OverlayChange>,
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub renamed: Vec<Rename>,
+    pub changed: Vec<FileChange>,
+}
+
+impl RomDiff {
+    /// Compares `old` to `new`.
+    pub fn new(old: &Rom, new: &Rom) -> Result<Self> {
+        let mut diff = Self {
+            header: header_changes(old.header(), new.header()),
+            arm9: old.arm9()? != new.arm9()?,
+            arm7: old.arm7()? != new.arm7()?,
+            ..Self::default()
+        };
+
+        for cpu in [Processor::Arm9, Processor::Arm7] {
+            diff.overlays.extend(overlay_changes(old, new, cpu)?);
+        }
+
+        diff.compare_files(old, new)?;
+
+        Ok(diff)
+    }
+
+    /// Whether the two ROMs are the same.
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && !self.arm9
+            && !self.arm7
+            && self.overlays.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.changed.is_empty()
+    }
+
+    fn compare_files(&mut self, old: &Rom, new: &Rom) -> Result<()> {
+        let old_fs = old.file_system();
+        let new_fs = new.file_system();
+
+        for file in old_fs.files() {
+            match new_fs.lookup(&file.path) {
+                Some(other) => {
+                    if old.open_id(file.id)? != new.open_id(other.id)? {
+                        self.changed.push(FileChange {
+                            path: file.path.clone(),
+                            old_size: file.alloc.len(),
+                            new_size: other.alloc.len(),
+                        });
+                    }
+                }
+                None => self.removed.push(file.path.clone()),
+            }
+        }
+
+        let mut added = Vec::new();
+
+        for file in new_fs.files() {
+            if old_fs.lookup(&file.path).is_none() {
+                added.push(file);
+            }
+        }
+
+        //  Index the added files by size and checksum, so each removed file
+        //  only has to be compared byte by byte with likely matches.
+        let mut candidates: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+
+        for (index, file) in added.iter().enumerate() {
+            let key = (file.alloc.len(), crc32(new.open_id(file.id)?));
+            candidates.entry(key).or_default().push(index);
+        }
+
+        let mut renamed_to = vec![false; added.len()];
+        let mut removed = Vec::new();
+
+        for path in self.removed.drain(..) {
+            let data = old.open(&path)?;
+            let key = (data.len() as u32, crc32(data));
+            let mut found = None;
+
+            for &index in candidates.get(&key).into_iter().flatten() {
+                if !renamed_to[index] && new.open_id(added[index].id)? == data {
+                    found = Some(index);
+                    break;
+                }
+            }
+
+            match found {
+                Some(index) => {
+                    renamed_to[index] = true;
+                    self.renamed.push(Rename {
+                        from: path,
+                        to: added[index].path.clone(),
+                    });
+                }
+                None => removed.push(path),
+            }
+        }
+
+        self.removed = removed;
+        self.added = added
+            .iter()
+            .zip(renamed_to)
+            .filter(|(_, renamed)| !renamed)
+            .map(|(file, _)| file.path.clone())
+            .collect();
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for RomDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "ROMs are identical.");
+        }
+
+        if !self.header.is_empty() {
+            writeln!(f, "Header:")?;
+
+            for change in &self.header {
+                writeln!(f, "  {}: {} -> {}", change.field, change.old, change.new)?;
+            }
+        }
+
+        if self.arm9 || self.arm7 || !self.overlays.is_empty() {
+            writeln!(f, "Code:")?;
+
+            if self.arm9 {
+                writeln!(f, "  ~ arm9")?;
+            }
+
+            if self.arm7 {
+                writeln!(f, "  ~ arm7")?;
+            }
+
+            for change in &self.overlays {
+                match change {
+                    OverlayChange::Added { cpu, id } => writeln!(f, "  + {} overlay {}", cpu, id)?,
+                    OverlayChange::Removed { cpu, id } => writeln!(f, "  - {} overlay {}", cpu, id)?,
+                    OverlayChange::Changed { cpu, id, entry, data } => {
+                        let what = match (entry, data) {
+                            (true, true) => "table entry and code",
+                            (true, false) => "table entry",
+                            _ => "code",
+                        };
+
+                        writeln!(f, "  ~ {} overlay {} ({})", cpu, id, what)?;
+                    }
+                }
+            }
+        }
+
+        if !(self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty() && self.changed.is_empty()) {
+            writeln!(f, "Files:")?;
+
+            for path in &self.added {
+                writeln!(f, "  + {}", path.display())?;
+            }
+
+            for path in &self.removed {
+                writeln!(f, "  - {}", path.display())?;
+            }
+
+            for rename in &self.renamed {
+                writeln!(f, "  > {} -> {}", rename.from.display(), rename.to.display())?;
+            }
+
+            for change in &self.changed {
+                writeln!(
+                    f,
+                    "  ~ {} ({} -> {} bytes)",
+                    change.path.display(),
+                    change.old_size,
+                    change.new_size
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Lists the header fields that differ. Numbers are written in hex, since
+/// most of them are offsets, addresses or sizes.
+fn header_changes(old: &NDSParser, new: &NDSParser) -> Vec<HeaderChange> {
+    let mut changes = Vec::new();
+
+    macro_rules! compare {
+        ($format:literal, $($field:ident).+) => {
+            if old.$($field).+ != new.$($field).+ {
+                changes.push(HeaderChange {
+                    field: stringify!($($field).+),
+                    old: format!($format, old.$($field).+),
+                    new: format!($format, new.$($field).+),
+                });
+            }
+        };
+    }
+
+    compare!("{:?}", game_title);
+    compare!("{:?}", gamecode);
+    compare!("{:?}", makercode);
+    compare!("{:#X}", unitcode);
+    compare!("{:#X}", encryption_seed_select);
+    compare!("{:#X}", devicecapacity);
+    compare!("{:#X}", game_revision);
+    compare!("{:#X}", rom_version);
+    compare!("{:#X}", internal_flags);
+
+    compare!("{:#X}", arm9.rom_offset);
+    compare!("{:#X}", arm9.entry_address);
+    compare!("{:#X}", arm9.load_address);
+    compare!("{:#X}", arm9.size);
+    compare!("{:#X}", arm9.overlay_offset);
+    compare!("{:#X}", arm9.overlay_length);
+    compare!("{:#X}", arm9.autoload);
+    compare!("{:#X}", arm7.rom_offset);
+    compare!("{:#X}", arm7.entry_address);
+    compare!("{:#X}", arm7.load_address);
+    compare!("{:#X}", arm7.size);
+    compare!("{:#X}", arm7.overlay_offset);
+    compare!("{:#X}", arm7.overlay_length);
+    compare!("{:#X}", arm7.autoload);
+    compare!("{:#X}", fnt.offset);
+    compare!("{:#X}", fnt.length);
+    compare!("{:#X}", fat.offset);
+    compare!("{:#X}", fat.length);
+    compare!("{:#X}", normal_card_control_register_settings);
+    compare!("{:#X}", secure_card_control_register_settings);
+    compare!("{:#X}", icon_banner_offset);
+    compare!("{:#X}", secure_area);
+    compare!("{:#X}", secure_transfer_timeout);
+    compare!("{:#X}", secure_diable);
+    compare!("{:#X}", ntr_region_rom_size);
+    compare!("{:#X}", header_size);
+    compare!("{:02X?}", nintendo_logo);
+    compare!("{:#X}", nintendo_logo_crc);
+    compare!("{:#X}", header_crc);
+    compare!("{:02X?}", debugger);
+
+    changes
+}
+
+/// Compares the overlays of one processor by their IDs.
+fn overlay_changes(old: &Rom, new: &Rom, cpu: Processor) -> Result<Vec<OverlayChange>> {
+    let old_table = old.overlays(cpu)?;
+    let new_table = new.overlays(cpu)?;
+    let find = |table: &[Overlay], id| table.iter().find(|overlay| overlay.id == id).copied();
+    let mut changes = Vec::new();
+
+    for overlay in &old_table {
+        let id = overlay.id;
+
+        match find(&new_table, id) {
+            Some(other) => {
+                //  File IDs can be renumbered without anything else changing,
+                //  so they are left out of the comparison.
+                let entry = Overlay { file_id: 0, ..*overlay } != Overlay { file_id: 0, ..other };
+                let data = old.open_id(overlay.file_id as u16)? != new.open_id(other.file_id as u16)?;
+
+                if entry || data {
+                    changes.push(OverlayChange::Changed { cpu, id, entry, data });
+                }
+            }
+            None => changes.push(OverlayChange::Removed { cpu, id }),
+        }
+    }
+
+    for overlay in &new_table {
+        if find(&old_table, overlay.id).is_none() {
+            changes.push(OverlayChange::Added { cpu, id: overlay.id });
+        }
+    }
+
+    Ok(changes)
+}
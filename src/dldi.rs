@@ -0,0 +1,265 @@
+//! Patching homebrew with DLDI drivers, the same way as `dlditool`.
+//!
+//! Homebrew that accesses files links in a DLDI stub: a block of space in
+//! the ARM9 binary with a header that says how much space was reserved and
+//! where it will be loaded in memory. A driver for a specific flashcart is
+//! copied into that space, and every address in the driver is moved from
+//! the address it was built for to the address of the stub.
+//!
+//! # Example
+//! ```no_run
+//! use nds::dldi::Driver;
+//! use nds::Rom;
+//!
+//! let driver = Driver::from_path("r4tf.dldi").unwrap();
+//! let mut rom = Rom::writable("homebrew.nds", true).unwrap();
+//!
+//! nds::dldi::patch_rom(&mut rom, &driver).unwrap();
+//! ```
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use std::fs::read;
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+
+use crate::Rom;
+
+/// The magic number and string that start every DLDI header.
+pub const MAGIC: &[u8] = &[0xED, 0xA5, 0x8D, 0xBF, b' ', b'C', b'h', b'i', b's', b'h', b'm', 0];
+
+/// Offsets of the fields in a DLDI header.
+const VERSION: usize = 0x0C;
+const DRIVER_SIZE: usize = 0x0D;
+const FIX_SECTIONS: usize = 0x0E;
+const ALLOCATED_SPACE: usize = 0x0F;
+const FRIENDLY_NAME: usize = 0x10;
+const FRIENDLY_NAME_LEN: usize = 0x30;
+const TEXT_START: usize = 0x40;
+const DATA_END: usize = 0x44;
+const GLUE_START: usize = 0x48;
+const GLUE_END: usize = 0x4C;
+const GOT_START: usize = 0x50;
+const GOT_END: usize = 0x54;
+const BSS_START: usize = 0x58;
+const BSS_END: usize = 0x5C;
+const IO_TYPE: usize = 0x60;
+//  0x64 holds the features of the driver, which is not an address.
+const STARTUP: usize = 0x68;
+const SHUTDOWN: usize = 0x7C;
+const CODE: usize = 0x80;
+
+/// Flags in `FIX_SECTIONS` that say which parts of the driver hold
+/// addresses that have to be moved.
+const FIX_ALL: u8 = 0x01;
+const FIX_GLUE: u8 = 0x02;
+const FIX_GOT: u8 = 0x04;
+const FIX_BSS: u8 = 0x08;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum DldiError {
+    #[error("Not a DLDI driver.")]
+    InvalidDriver,
+
+    #[error("No DLDI stub found.")]
+    StubNotFound,
+
+    #[error("DLDI version of the driver ({driver}) does not match the stub ({stub}).")]
+    VersionMismatch { driver: u8, stub: u8 },
+
+    #[error("Driver needs {needed} bytes, but the stub only has {available}.")]
+    NotEnoughSpace { needed: usize, available: usize },
+
+    #[error("Driver sections point outside of the driver.")]
+    OutOfBounds,
+}
+
+/// A DLDI driver, as found in a `.dldi` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Driver {
+    data: Vec<u8>,
+}
+
+impl Driver {
+    /// Reads a driver from a `.dldi` file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(read(path)?)
+    }
+
+    /// Checks that `data` is a DLDI driver.
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        ensure!(data.len() >= CODE && data.starts_with(MAGIC), DldiError::InvalidDriver);
+        ensure!(data[DRIVER_SIZE] < 32, DldiError::InvalidDriver);
+
+        let driver = Self { data };
+        let start = driver.read(TEXT_START);
+
+        //  Every section must fit in the space the driver says it needs, or
+        //  patching would write outside of the stub.
+        for (section_start, section_end) in &[
+            (TEXT_START, DATA_END),
+            (GLUE_START, GLUE_END),
+            (GOT_START, GOT_END),
+            (BSS_START, BSS_END),
+        ] {
+            let section_start = driver.read(*section_start);
+            let section_end = driver.read(*section_end);
+
+            ensure!(
+                start <= section_start
+                    && section_start <= section_end
+                    && (section_end - start) as usize <= driver.size(),
+                DldiError::OutOfBounds
+            );
+        }
+
+        Ok(driver)
+    }
+
+    /// The name of the driver, such as `R4(DS) - Revolution for DS`.
+    pub fn name(&self) -> &str {
+        text(&self.data[FRIENDLY_NAME..FRIENDLY_NAME + FRIENDLY_NAME_LEN])
+    }
+
+    /// The four letter code of the device, such as `R4TF`.
+    pub fn io_type(&self) -> &str {
+        text(&self.data[IO_TYPE..IO_TYPE + 4])
+    }
+
+    /// The DLDI version that the driver was made for.
+    pub fn version(&self) -> u8 {
+        self.data[VERSION]
+    }
+
+    /// How much space the driver needs in memory, including its BSS.
+    pub fn size(&self) -> usize {
+        1 << self.data[DRIVER_SIZE]
+    }
+
+    /// The raw contents of the driver file.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        LittleEndian::read_u32(&self.data[offset..])
+    }
+}
+
+/// Finds the offset of the DLDI stub in a binary.
+pub fn find_stub(data: &[u8]) -> Option<usize> {
+    data.windows(MAGIC.len()).position(|window| window == MAGIC)
+}
+
+/// Patches the DLDI stub in a binary, such as `arm9.bin`, with a driver.
+///
+/// # Errors
+/// Returns an error if there is no stub, if the stub is for another
+/// version of DLDI, or if the driver doesn't fit in the stub.
+pub fn patch(data: &mut [u8], driver: &Driver) -> Result<()> {
+    let offset = find_stub(data).ok_or(DldiError::StubNotFound)?;
+    let stub = &data[offset..];
+
+    ensure!(stub.len() >= CODE, DldiError::StubNotFound);
+    ensure!(
+        stub[VERSION] == driver.version(),
+        DldiError::VersionMismatch {
+            driver: driver.version(),
+            stub: stub[VERSION],
+        }
+    );
+
+    let allocated_space = stub[ALLOCATED_SPACE];
+    let available = 1usize
+        .checked_shl(u32::from(allocated_space))
+        .unwrap_or(usize::MAX)
+        .min(stub.len());
+
+    ensure!(
+        driver.size() <= available && driver.data.len() <= available,
+        DldiError::NotEnoughSpace {
+            needed: driver.size().max(driver.data.len()),
+            available,
+        }
+    );
+
+    //  Older stubs leave their own address out, but it can be found from
+    //  the address of the startup function, which is the start of the code.
+    let memory_offset = match LittleEndian::read_u32(&stub[TEXT_START..]) {
+        0 => LittleEndian::read_u32(&stub[STARTUP..]).wrapping_sub(CODE as u32),
+        address => address,
+    };
+
+    let driver_start = driver.read(TEXT_START);
+    let driver_end = driver_start.wrapping_add(driver.size() as u32);
+    let relocation = memory_offset.wrapping_sub(driver_start);
+
+    let stub = &mut data[offset..offset + available];
+
+    stub[..driver.data.len()].copy_from_slice(&driver.data);
+    //  Keep the size of the space that the stub reserved.
+    stub[ALLOCATED_SPACE] = allocated_space;
+
+    //  Move the section and function pointers in the header.
+    for field in (TEXT_START..=BSS_END).step_by(4).chain((STARTUP..=SHUTDOWN).step_by(4)) {
+        let address = LittleEndian::read_u32(&stub[field..]);
+        LittleEndian::write_u32(&mut stub[field..], address.wrapping_add(relocation));
+    }
+
+    let fix = driver.data[FIX_SECTIONS];
+    let relocate = |stub: &mut [u8], start: usize, end: usize| {
+        let start = (driver.read(start) - driver_start) as usize;
+        let end = ((driver.read(end) - driver_start) as usize).min(stub.len() - 3);
+
+        for word in (start..end).step_by(4) {
+            let address = LittleEndian::read_u32(&stub[word..]);
+
+            if driver_start <= address && address < driver_end {
+                LittleEndian::write_u32(&mut stub[word..], address.wrapping_add(relocation));
+            }
+        }
+    };
+
+    if fix & FIX_ALL != 0 {
+        relocate(stub, TEXT_START, DATA_END);
+    }
+
+    if fix & FIX_GLUE != 0 {
+        relocate(stub, GLUE_START, GLUE_END);
+    }
+
+    if fix & FIX_GOT != 0 {
+        relocate(stub, GOT_START, GOT_END);
+    }
+
+    if fix & FIX_BSS != 0 {
+        let start = (driver.read(BSS_START) - driver_start) as usize;
+        let end = (driver.read(BSS_END) - driver_start) as usize;
+
+        for byte in &mut stub[start..end] {
+            *byte = 0;
+        }
+    }
+
+    Ok(())
+}
+
+/// Patches the DLDI stub in the ARM9 binary of a ROM.
+///
+/// # Errors
+/// Same as [`patch`], and also returns an error if the ROM was opened
+/// read-only.
+///
+/// [`patch`]: fn.patch.html
+pub fn patch_rom(rom: &mut Rom, driver: &Driver) -> Result<()> {
+    patch(rom.arm9_mut()?, driver)
+}
+
+/// Reads a NUL padded string.
+fn text(data: &[u8]) -> &str {
+    let end = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+
+    std::str::from_utf8(&data[..end]).unwrap_or("")
+}
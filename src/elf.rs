@@ -0,0 +1,179 @@
+//! Reading the code out of ARM ELF files, as `ndstool` does for `-9` and
+//! `-7`.
+//!
+//! Only the loadable segments matter for a ROM. Each one is copied to the
+//! address it is loaded from (its LMA), which for code that runs from ITCM
+//! or DTCM is in main RAM after the rest of the binary, while the address
+//! it runs at (its VMA) is in the TCM. The startup code copies it over.
+//!
+//! Placing these sections at their VMAs instead wouldn't work: the boot
+//! loader only copies the binary into main RAM, in one block starting at
+//! its load address, so it can't reach the TCMs. DTCM is usually mapped
+//! far from main RAM too, which would leave a gap of megabytes in the
+//! binary. The VMAs are still kept in [`Segment::virtual_address`].
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use std::fs::read;
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+
+use crate::Binary;
+
+const MAGIC: &[u8] = b"\x7FELF";
+const CLASS_32: u8 = 1;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const MACHINE_ARM: u16 = 40;
+const PT_LOAD: u32 = 1;
+
+const HEADER_LEN: usize = 0x34;
+const PROGRAM_HEADER_LEN: usize = 0x20;
+
+/// Flattened binaries can't be larger than the biggest block of memory the
+/// DS can load into, which also catches segments placed far apart.
+const MAX_BINARY_LEN: u32 = 0x100_0000;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum ElfError {
+    #[error("Not a 32-bit little endian ELF file.")]
+    InvalidElf,
+
+    #[error("ELF file is not for ARM.")]
+    NotArm,
+
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("ELF file has no loadable segments.")]
+    NoSegments,
+
+    #[error("Loadable segments span {0:#X} bytes, which is more than fits in memory.")]
+    TooLarge(u32),
+}
+
+/// A loadable segment.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Segment {
+    /// The address the segment runs at.
+    pub virtual_address: u32,
+    /// The address the segment is loaded to, and where it is placed in a
+    /// flattened binary.
+    pub load_address: u32,
+    /// How much memory the segment takes, including zeroed data such as
+    /// `.bss` that isn't stored in the file.
+    pub memory_size: u32,
+    pub data: Vec<u8>,
+}
+
+/// The parts of an ELF file that are needed to run it.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Elf {
+    pub entry_address: u32,
+    /// Loadable segments with data, sorted by load address.
+    pub segments: Vec<Segment>,
+}
+
+impl Elf {
+    /// Reads an ELF file from a path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&read(path)?)
+    }
+
+    /// Whether `data` starts like an ELF file.
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Reads the entry point and loadable segments of an ELF file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= HEADER_LEN
+                && Self::is_elf(data)
+                && data[4] == CLASS_32
+                && data[5] == DATA_LITTLE_ENDIAN,
+            ElfError::InvalidElf
+        );
+        ensure!(LittleEndian::read_u16(&data[0x12..]) == MACHINE_ARM, ElfError::NotArm);
+
+        let entry_address = LittleEndian::read_u32(&data[0x18..]);
+        let table = LittleEndian::read_u32(&data[0x1C..]) as usize;
+        let entry_len = LittleEndian::read_u16(&data[0x2A..]) as usize;
+        let count = LittleEndian::read_u16(&data[0x2C..]) as usize;
+
+        ensure!(entry_len >= PROGRAM_HEADER_LEN || count == 0, ElfError::InvalidElf);
+
+        let mut segments = Vec::new();
+
+        for index in 0..count {
+            let start = table + index * entry_len;
+            let header = data.get(start..start + PROGRAM_HEADER_LEN).ok_or(ElfError::NotEnoughData)?;
+            let field = |offset: usize| LittleEndian::read_u32(&header[offset..]);
+
+            let offset = field(0x04) as usize;
+            let file_size = field(0x10) as usize;
+
+            if field(0x00) != PT_LOAD || file_size == 0 {
+                continue;
+            }
+
+            let contents = data.get(offset..offset + file_size).ok_or(ElfError::NotEnoughData)?;
+
+            segments.push(Segment {
+                virtual_address: field(0x08),
+                load_address: field(0x0C),
+                memory_size: field(0x14),
+                data: contents.to_vec(),
+            });
+        }
+
+        segments.sort_by_key(|segment| segment.load_address);
+
+        Ok(Self {
+            entry_address,
+            segments,
+        })
+    }
+
+    /// Copies every segment into a single binary that starts at the lowest
+    /// load address. Gaps between segments are filled with zeros.
+    ///
+    /// Returns the load address of the binary along with its contents.
+    pub fn flatten(&self) -> Result<(u32, Vec<u8>)> {
+        let start = self.segments.first().ok_or(ElfError::NoSegments)?.load_address;
+        let end = self
+            .segments
+            .iter()
+            .map(|segment| u64::from(segment.load_address) + segment.data.len() as u64)
+            .max()
+            .unwrap_or(0);
+
+        let len = end - u64::from(start);
+
+        ensure!(len <= u64::from(MAX_BINARY_LEN), ElfError::TooLarge(len.min(u64::from(u32::MAX)) as u32));
+
+        let mut binary = vec![0; len as usize];
+
+        for segment in &self.segments {
+            let offset = (segment.load_address - start) as usize;
+            binary[offset..offset + segment.data.len()].copy_from_slice(&segment.data);
+        }
+
+        Ok((start, binary))
+    }
+}
+
+impl Binary {
+    /// Reads the code of an ELF file, as `ndstool` does.
+    pub fn from_elf(data: &[u8]) -> Result<Self> {
+        let elf = Elf::parse(data)?;
+        let (load_address, data) = elf.flatten()?;
+
+        Ok(Self {
+            data,
+            load_address,
+            entry_address: elf.entry_address,
+        })
+    }
+}
@@ -3,11 +3,19 @@ use memmap::Mmap;
 use num::NumCast;
 use rayon::prelude::*;
 
+use nitro_fs::fat::AllocInfo;
+
+use std::collections::BTreeMap;
 use std::fs::{create_dir_all, File};
 use std::path::Path;
 
 use anyhow::{ensure, Result};
 
+use crate::compression::{detect_and_decompress, write_sidecar, Compression, SIDECAR_NAME};
+use crate::header::{banner_len, Header, UNIT_CODE_TWL};
+#[cfg(feature = "manifest")]
+use crate::manifest::{Manifest, MANIFEST_NAME};
+
 // == Errors ==
 #[derive(Debug, thiserror::Error)]
 pub enum ExtractError {
@@ -21,23 +29,16 @@ pub enum ExtractError {
     WriteError(Vec<anyhow::Error>),
 }
 
-enum Header {
-    Arm9Offset = 0x20,
-    Arm9Len = 0x2C,
-    Arm7Offset = 0x30,
-    Arm7Len = 0x3C,
-    FntOffset = 0x40,
-    FntLen = 0x44,
-    FatOffset = 0x48,
-    FatLen = 0x4C,
-    Size = 0x84,
-}
-
 /// Extracts files from an NDS ROM to a given path.
 #[derive(Debug)]
 pub struct Extractor {
     /// A memmap of the ROM to allow easy reading for potentially large files.
     data: Mmap,
+    /// Whether compressed files in the file system are written decompressed.
+    decompress: bool,
+    /// Whether the banner icon is also written as `icon.png`.
+    #[cfg(feature = "image")]
+    icon: bool,
 }
 
 impl Extractor {
@@ -58,12 +59,46 @@ impl Extractor {
 
         Ok(Self {
             data,
+            decompress: false,
+            #[cfg(feature = "image")]
+            icon: false,
         })
     }
 
+    /// Sets whether files that look compressed are decompressed when they
+    /// are extracted. The format of every decompressed file is noted in a
+    /// sidecar at the root of the output, so that a [`Builder`] can compress
+    /// them again.
+    ///
+    /// Detection is a heuristic, see [`detect_compression`].
+    ///
+    /// [`Builder`]: struct.Builder.html
+    /// [`detect_compression`]: compression/fn.detect_compression.html
+    pub fn set_decompress(&mut self, decompress: bool) {
+        self.decompress = decompress;
+    }
+
+    /// Sets whether the banner icon is also written as `icon.png`, next to
+    /// `banner.bin`. A [`Builder`] ignores it, so changes to the icon have
+    /// to be made in `banner.bin`.
+    ///
+    /// [`Builder`]: struct.Builder.html
+    #[cfg(feature = "image")]
+    pub fn set_icon_png(&mut self, icon: bool) {
+        self.icon = icon;
+    }
+
     /// Extracts the ROM to the given path. An error is returned
     /// if there are issues with the ROM structure, or if there is
     /// an issue writing files.
+    ///
+    /// With the `manifest` feature, which is on by default, a `rom.toml`
+    /// manifest is written along with the files. It has the fields of the
+    /// header, the overlay tables, the banner titles and where every file
+    /// was placed. A [`Builder`] reads it back, so those can be changed
+    /// without editing the binary files.
+    ///
+    /// [`Builder`]: struct.Builder.html
     pub fn extract<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         use nitro_fs::FileSystem;
 
@@ -74,6 +109,31 @@ impl Extractor {
         self.write(root.join("header.bin"), 0, self.read_u32(Header::Size as usize)?)?;
         self.write(root.join("arm9.bin"), self.read_u32(Header::Arm9Offset as usize)?, self.read_u32(Header::Arm9Len as usize)?)?;
         self.write(root.join("arm7.bin"), self.read_u32(Header::Arm7Offset as usize)?, self.read_u32(Header::Arm7Len as usize)?)?;
+        self.write(root.join("arm9_overlay.bin"), self.read_u32(Header::Arm9OverlayOffset as usize)?, self.read_u32(Header::Arm9OverlayLen as usize)?)?;
+        self.write(root.join("arm7_overlay.bin"), self.read_u32(Header::Arm7OverlayOffset as usize)?, self.read_u32(Header::Arm7OverlayLen as usize)?)?;
+
+        let banner_offset = self.read_u32(Header::BannerOffset as usize)?;
+
+        if banner_offset != 0 {
+            let version = self.read_u16(banner_offset as usize)?;
+            self.write(root.join("banner.bin"), banner_offset, banner_len(version))?;
+
+            #[cfg(feature = "image")]
+            {
+                if self.icon {
+                    let start = banner_offset as usize;
+                    let data = self.data.get(start..).ok_or(ExtractError::NotEnoughData)?;
+                    let banner = crate::banner::Banner::parse(data)?;
+
+                    std::fs::write(root.join("icon.png"), banner.icon_png()?)?;
+                }
+            }
+        }
+
+        if self.data[Header::UnitCode as usize] & UNIT_CODE_TWL != 0 {
+            self.write(root.join("arm9i.bin"), self.read_u32(Header::Arm9iOffset as usize)?, self.read_u32(Header::Arm9iLen as usize)?)?;
+            self.write(root.join("arm7i.bin"), self.read_u32(Header::Arm7iOffset as usize)?, self.read_u32(Header::Arm7iLen as usize)?)?;
+        }
 
         let overlay_path = root.join("overlay");
         let file_path = root.join("data");
@@ -83,33 +143,83 @@ impl Extractor {
 
         let fs = FileSystem::new(self.fnt()?, self.fat()?)?;
 
+        #[cfg(feature = "manifest")]
+        Manifest::from_rom(&self.data, &fs)?.write(root.join(MANIFEST_NAME))?;
+
         let errors = fs.overlays()
             .par_iter()
             .filter_map(|file| {
-                match self.write(&overlay_path.join(&file.path), file.alloc.start, file.alloc.len()) {
-                    Ok(_) => None,
-                    Err(why) => Some(why),
-                }
+                self.write(overlay_path.join(&file.path), file.alloc.start, file.alloc.len()).err()
             })
             .collect::<Vec<anyhow::Error>>();
 
         ensure!(errors.is_empty(), ExtractError::WriteError(errors));
 
-        let errors = fs.files()
+        if !self.decompress {
+            let errors = fs.files()
+                .par_iter()
+                .filter_map(|file| {
+                    self.write(file_path.join(&file.path), file.alloc.start, file.alloc.len()).err()
+                })
+                .collect::<Vec<anyhow::Error>>();
+
+            ensure!(errors.is_empty(), ExtractError::WriteError(errors));
+
+            return Ok(());
+        }
+
+        let results = fs.files()
             .par_iter()
-            .filter_map(|file| {
-                match self.write(&file_path.join(&file.path), file.alloc.start, file.alloc.len()) {
-                    Ok(_) => None,
-                    Err(why) => Some(why),
-                }
+            .map(|file| {
+                self.write_decompressed(file_path.join(&file.path), file.alloc)
+                    .map(|found| found.map(|compression| (file.path.clone(), compression)))
             })
-            .collect::<Vec<anyhow::Error>>();
+            .collect::<Vec<_>>();
+
+        let mut compressed = BTreeMap::new();
+        let mut errors = Vec::new();
+
+        for result in results {
+            match result {
+                Ok(Some((path, compression))) => {
+                    compressed.insert(path, compression);
+                }
+                Ok(None) => {}
+                Err(why) => errors.push(why),
+            }
+        }
 
         ensure!(errors.is_empty(), ExtractError::WriteError(errors));
 
+        write_sidecar(root.join(SIDECAR_NAME), &compressed)?;
+
         Ok(())
     }
 
+    /// Writes a file, decompressing it first if it looks compressed. Returns
+    /// the format that the file was compressed with, if any.
+    fn write_decompressed<P: AsRef<Path>>(&self, path: P, alloc: AllocInfo) -> Result<Option<Compression>> {
+        let offset = alloc.start as usize;
+        let len = alloc.len() as usize;
+
+        ensure!(self.data.len() >= offset + len, ExtractError::NotEnoughData);
+
+        let data = &self.data[offset..offset + len];
+
+        match detect_and_decompress(data) {
+            Some((compression, decompressed)) => {
+                self.write_data(path, &decompressed)?;
+
+                Ok(Some(compression))
+            }
+            None => {
+                self.write_data(path, data)?;
+
+                Ok(None)
+            }
+        }
+    }
+
     /// A utility to make it easier to write chunks of the ROM to files.
     /// Copies `len` bytes from the ROM starting from `offset` into the file 
     /// denoted by `path`
@@ -119,13 +229,18 @@ impl Extractor {
             N1: NumCast,
             N2: NumCast
     {
-        use std::fs::write;
-
         let offset: usize = NumCast::from(offset).unwrap();
         let len: usize = NumCast::from(len).unwrap();
 
         ensure!(self.data.len() >= offset + len, ExtractError::NotEnoughData);
 
+        self.write_data(path, &self.data[offset..offset + len])
+    }
+
+    /// Writes `data` to `path`, creating any missing parent directories.
+    fn write_data<P: AsRef<Path>>(&self, path: P, data: &[u8]) -> Result<()> {
+        use std::fs::write;
+
         {
             let parent = path.as_ref().parent().unwrap_or(Path::new(""));
 
@@ -134,17 +249,27 @@ impl Extractor {
             }
         }
 
-        write(path, &self.data[offset..offset + len])?;
+        write(path, data)?;
 
         Ok(())
     }
 
     /// Reads a u32 from `data` at the given offset.
     fn read_u32(&self, offset: usize) -> Result<u32> {
+        ensure!(self.data.len() >= offset + 4, ExtractError::NotEnoughData);
+
         let value = (&self.data[offset..]).read_u32::<LittleEndian>()?;
         Ok(value)
     }
 
+    /// Reads a u16 from `data` at the given offset.
+    fn read_u16(&self, offset: usize) -> Result<u16> {
+        ensure!(self.data.len() >= offset + 2, ExtractError::NotEnoughData);
+
+        let value = (&self.data[offset..]).read_u16::<LittleEndian>()?;
+        Ok(value)
+    }
+
     fn fat(&self) -> Result<&[u8]> {
         let fat_start = self.read_u32(Header::FatOffset as usize)? as usize;
         let fat_len = self.read_u32(Header::FatLen as usize)? as usize;
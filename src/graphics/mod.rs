@@ -0,0 +1,107 @@
+//! Graphics formats used by the 2D engines of the DS.
+//!
+//! Colors are stored as BGR555: 5 bits each of red, green and blue, from
+//! the lowest bits up. Images are converted to and from 8-bit RGBA, where
+//! every pixel is four bytes.
+//!
+//! With the `image` feature, images can also be read from and written to
+//! PNG files. Without it, renders are plain RGBA buffers, so no image
+//! library is needed.
+
+pub mod nanr;
+pub mod ncer;
+pub mod nclr;
+pub mod ncgr;
+pub mod nscr;
+
+#[cfg(feature = "image")]
+mod png;
+
+/// An 8-bit RGBA color.
+pub type Rgba = [u8; 4];
+
+/// Converts a BGR555 color to RGBA. The top bit is ignored and the color is
+/// opaque.
+pub fn bgr555_to_rgba(color: u16) -> Rgba {
+    let expand = |value: u16| {
+        let value = (value & 0x1F) as u8;
+        (value << 3) | (value >> 2)
+    };
+
+    [expand(color), expand(color >> 5), expand(color >> 10), 0xFF]
+}
+
+/// Converts an RGBA color to BGR555 by dropping the low bits of each
+/// channel. Alpha is ignored.
+pub fn rgba_to_bgr555(color: Rgba) -> u16 {
+    let [red, green, blue, _] = color;
+
+    u16::from(red >> 3) | (u16::from(green >> 3) << 5) | (u16::from(blue >> 3) << 10)
+}
+
+/// An image where every pixel is an index into a palette.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct IndexedImage {
+    pub width: usize,
+    pub height: usize,
+    /// One index per pixel, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl IndexedImage {
+    /// Creates an image where every pixel is index 0.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Looks up every pixel in `palette`. Indices past the end of the
+    /// palette become transparent.
+    pub fn to_rgba(&self, palette: &[Rgba]) -> RgbaImage {
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .flat_map(|&index| palette.get(index as usize).copied().unwrap_or([0; 4]))
+                .collect(),
+        }
+    }
+}
+
+/// An image with four bytes of RGBA per pixel.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    /// Four bytes per pixel, row by row.
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Creates a transparent image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgba {
+        let start = (y * self.width + x) * 4;
+        let mut color = [0; 4];
+
+        color.copy_from_slice(&self.pixels[start..start + 4]);
+        color
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgba) {
+        let start = (y * self.width + x) * 4;
+        self.pixels[start..start + 4].copy_from_slice(&color);
+    }
+}
@@ -0,0 +1,365 @@
+//! NANR animation banks, which play the cells of an NCER as sequences.
+//!
+//! The `ABNK` section lists the sequences. Each has frames that show a
+//! cell for a number of video frames, at 60 per second, optionally moved,
+//! or rotated and scaled (SRT). The optional `LABL` section names the
+//! sequences.
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::NitroFile;
+#[cfg(feature = "json")]
+use serde_json::json;
+
+use anyhow::{ensure, Result};
+
+use super::ncer::{parse_labels, CellBank};
+use super::ncgr::Tiles;
+use super::nclr::Palette;
+use super::RgbaImage;
+
+const MAGIC: &[u8; 4] = b"RNAN";
+const ABNK: &[u8; 4] = b"KNBA";
+const LABL: &[u8; 4] = b"LBAL";
+
+/// Size of the `ABNK` section header.
+const ABNK_HEADER_LEN: usize = 0x18;
+const SEQUENCE_LEN: usize = 0x10;
+const FRAME_LEN: usize = 0x08;
+
+/// Scale of 1.0 in the 20.12 fixed point that SRT frames use.
+pub const SCALE_ONE: i32 = 0x1000;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum NanrError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown frame type: {0}.")]
+    InvalidElement(u16),
+
+    #[error("Unknown playback mode: {0}.")]
+    InvalidPlayback(u32),
+}
+
+/// What the frames of a sequence store besides their cell.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Element {
+    /// Only the cell.
+    Index,
+    /// Rotation, scale and translation.
+    Srt,
+    /// Translation.
+    Translation,
+}
+
+impl Element {
+    pub fn from_raw(value: u16) -> Result<Self> {
+        match value {
+            0 => Ok(Element::Index),
+            1 => Ok(Element::Srt),
+            2 => Ok(Element::Translation),
+            _ => Err(NanrError::InvalidElement(value).into()),
+        }
+    }
+}
+
+/// How a sequence is played.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Playback {
+    /// Once, from the first frame to the last.
+    Forward,
+    /// Over and over, going back to the loop start.
+    ForwardLoop,
+    /// Once forward, then once backward.
+    PingPong,
+    /// Forward and backward, over and over.
+    PingPongLoop,
+}
+
+impl Playback {
+    pub fn from_raw(value: u32) -> Result<Self> {
+        match value {
+            1 => Ok(Playback::Forward),
+            2 => Ok(Playback::ForwardLoop),
+            3 => Ok(Playback::PingPong),
+            4 => Ok(Playback::PingPongLoop),
+            _ => Err(NanrError::InvalidPlayback(value).into()),
+        }
+    }
+
+    /// The name used in timing JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            Playback::Forward => "forward",
+            Playback::ForwardLoop => "forward_loop",
+            Playback::PingPong => "ping_pong",
+            Playback::PingPongLoop => "ping_pong_loop",
+        }
+    }
+}
+
+/// The placement of a cell in a frame. Frames without one of these use
+/// [`Transform::default`], which leaves the cell as it is.
+///
+/// [`Transform::default`]: #impl-Default
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Transform {
+    /// Rotation, where 0x10000 is a full turn.
+    pub rotation: u16,
+    /// Scale in 20.12 fixed point.
+    pub scale_x: i32,
+    pub scale_y: i32,
+    pub x: i16,
+    pub y: i16,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            rotation: 0,
+            scale_x: SCALE_ONE,
+            scale_y: SCALE_ONE,
+            x: 0,
+            y: 0,
+        }
+    }
+}
+
+/// A single frame of a sequence.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Frame {
+    pub cell: u16,
+    /// How many video frames the frame is shown for.
+    pub duration: u16,
+    pub transform: Transform,
+}
+
+/// A single animation.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Sequence {
+    pub element: Element,
+    /// 1 for cells and 2 for multi cells.
+    pub kind: u16,
+    pub playback: Playback,
+    /// The frame that looping sequences go back to.
+    pub loop_start: u16,
+    pub frames: Vec<Frame>,
+}
+
+impl Sequence {
+    /// Renders every frame on canvases of the same size, so that the cells
+    /// stay in place between frames. Rotation and scaling are not applied.
+    pub fn render(&self, cells: &CellBank, tiles: &Tiles, palette: &Palette) -> Animation {
+        let placed = self
+            .frames
+            .iter()
+            .filter_map(|frame| cells.cells.get(frame.cell as usize).map(|cell| (frame, cell)))
+            .filter(|(_, cell)| !cell.oams.is_empty())
+            .map(|(frame, cell)| {
+                let (x, y, width, height) = cell.extent();
+                let x = x + i32::from(frame.transform.x);
+                let y = y + i32::from(frame.transform.y);
+
+                (x, y, x + width as i32, y + height as i32)
+            })
+            .collect::<Vec<_>>();
+
+        let min_x = placed.iter().map(|area| area.0).min().unwrap_or(0);
+        let min_y = placed.iter().map(|area| area.1).min().unwrap_or(0);
+        let max_x = placed.iter().map(|area| area.2).max().unwrap_or(0);
+        let max_y = placed.iter().map(|area| area.3).max().unwrap_or(0);
+
+        let width = (max_x - min_x) as usize;
+        let height = (max_y - min_y) as usize;
+        let origin = (-min_x, -min_y);
+
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let mut image = RgbaImage::new(width, height);
+
+                if let Some(cell) = cells.cells.get(frame.cell as usize) {
+                    let at = (
+                        origin.0 + i32::from(frame.transform.x),
+                        origin.1 + i32::from(frame.transform.y),
+                    );
+
+                    cell.draw(&mut image, at, tiles, palette, cells.mapping);
+                }
+
+                image
+            })
+            .collect();
+
+        Animation {
+            frames,
+            durations: self.frames.iter().map(|frame| frame.duration).collect(),
+            origin,
+            playback: self.playback,
+            loop_start: self.loop_start,
+        }
+    }
+}
+
+/// The rendered frames of a [`Sequence`].
+///
+/// [`Sequence`]: struct.Sequence.html
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Animation {
+    /// One image per frame, all of the same size.
+    pub frames: Vec<RgbaImage>,
+    /// How many video frames each frame is shown for.
+    pub durations: Vec<u16>,
+    /// Where the center of the cells is in each frame.
+    pub origin: (i32, i32),
+    pub playback: Playback,
+    pub loop_start: u16,
+}
+
+impl Animation {
+    /// Width and height of every frame.
+    pub fn frame_size(&self) -> (usize, usize) {
+        self.frames
+            .first()
+            .map(|frame| (frame.width, frame.height))
+            .unwrap_or((0, 0))
+    }
+
+    /// Places the frames next to each other, from left to right.
+    pub fn sprite_sheet(&self) -> RgbaImage {
+        let (width, height) = self.frame_size();
+        let mut sheet = RgbaImage::new(width * self.frames.len(), height);
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            for y in 0..height {
+                let start = (y * sheet.width + index * width) * 4;
+
+                sheet.pixels[start..start + width * 4].copy_from_slice(&frame.pixels[y * width * 4..(y + 1) * width * 4]);
+            }
+        }
+
+        sheet
+    }
+
+    /// Describes the [`sprite_sheet`] for game engines and editors: the
+    /// frame size, the origin, how the sequence is played and where each
+    /// frame is in the sheet with its duration in video frames and in
+    /// milliseconds.
+    ///
+    /// [`sprite_sheet`]: #method.sprite_sheet
+    #[cfg(feature = "json")]
+    pub fn timing_json(&self) -> String {
+        let (width, height) = self.frame_size();
+
+        let value = json!({
+            "frame_width": width,
+            "frame_height": height,
+            "origin_x": self.origin.0,
+            "origin_y": self.origin.1,
+            "playback": self.playback.name(),
+            "loop_start": self.loop_start,
+            "frames": self.durations.iter().enumerate().map(|(index, &duration)| json!({
+                "x": index * width,
+                "y": 0,
+                "duration": duration,
+                "duration_ms": (f64::from(duration) * 1000.0 / 60.0).round() as u64,
+            })).collect::<Vec<_>>(),
+        });
+
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+}
+
+/// The sequences of an NANR file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AnimationBank {
+    pub sequences: Vec<Sequence>,
+    /// Names of the sequences, from the `LABL` section.
+    pub labels: Vec<String>,
+}
+
+impl AnimationBank {
+    /// Reads an NANR file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, MAGIC)?;
+        let abnk = &file.require(ABNK)?.data;
+
+        ensure!(abnk.len() >= ABNK_HEADER_LEN, NanrError::NotEnoughData);
+
+        let count = LittleEndian::read_u16(abnk) as usize;
+        let sequence_table = LittleEndian::read_u32(&abnk[0x04..]) as usize;
+        let frame_table = LittleEndian::read_u32(&abnk[0x08..]) as usize;
+        let frame_data = LittleEndian::read_u32(&abnk[0x0C..]) as usize;
+
+        let read = |offset: usize, len: usize| abnk.get(offset..offset + len).ok_or(NanrError::NotEnoughData);
+
+        let mut sequences = Vec::with_capacity(count);
+
+        for index in 0..count {
+            let entry = read(sequence_table + index * SEQUENCE_LEN, SEQUENCE_LEN)?;
+
+            let frame_count = LittleEndian::read_u16(entry) as usize;
+            let element = Element::from_raw(LittleEndian::read_u16(&entry[0x04..]))?;
+            let frames_at = frame_table + LittleEndian::read_u32(&entry[0x0C..]) as usize;
+
+            let mut frames = Vec::with_capacity(frame_count);
+
+            for frame in 0..frame_count {
+                let frame = read(frames_at + frame * FRAME_LEN, FRAME_LEN)?;
+                let at = frame_data + LittleEndian::read_u32(frame) as usize;
+                let duration = LittleEndian::read_u16(&frame[0x04..]);
+
+                let (cell, transform) = match element {
+                    Element::Index => (LittleEndian::read_u16(read(at, 2)?), Transform::default()),
+                    Element::Srt => {
+                        let data = read(at, 0x10)?;
+
+                        let transform = Transform {
+                            rotation: LittleEndian::read_u16(&data[0x02..]),
+                            scale_x: LittleEndian::read_i32(&data[0x04..]),
+                            scale_y: LittleEndian::read_i32(&data[0x08..]),
+                            x: LittleEndian::read_i16(&data[0x0C..]),
+                            y: LittleEndian::read_i16(&data[0x0E..]),
+                        };
+
+                        (LittleEndian::read_u16(data), transform)
+                    }
+                    Element::Translation => {
+                        let data = read(at, 0x08)?;
+
+                        let transform = Transform {
+                            x: LittleEndian::read_i16(&data[0x04..]),
+                            y: LittleEndian::read_i16(&data[0x06..]),
+                            ..Transform::default()
+                        };
+
+                        (LittleEndian::read_u16(data), transform)
+                    }
+                };
+
+                frames.push(Frame {
+                    cell,
+                    duration,
+                    transform,
+                });
+            }
+
+            sequences.push(Sequence {
+                element,
+                kind: LittleEndian::read_u16(&entry[0x06..]),
+                playback: Playback::from_raw(LittleEndian::read_u32(&entry[0x08..]))?,
+                loop_start: LittleEndian::read_u16(&entry[0x02..]),
+                frames,
+            });
+        }
+
+        let labels = match file.section(LABL) {
+            Some(labl) => parse_labels(&labl.data, count),
+            None => Vec::new(),
+        };
+
+        Ok(Self { sequences, labels })
+    }
+}
@@ -0,0 +1,369 @@
+//! NCER cell banks, which build sprites out of hardware objects.
+//!
+//! The `CEBK` section lists the cells, each with the OAM attributes of the
+//! objects it is made of and, in most files, the bounds of the cell. The
+//! optional `LABL` section names the cells.
+//!
+//! Object positions are relative to the center of the cell, so a cell can
+//! reach into negative coordinates. Rendering places the top left corner
+//! of the cell's [`extent`] at (0, 0).
+//!
+//! [`extent`]: struct.Cell.html#method.extent
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::NitroFile;
+
+use anyhow::{ensure, Result};
+
+use super::ncgr::{Mapping, Tiles, TILE_SIZE};
+use super::nclr::{ColorDepth, Palette};
+use super::RgbaImage;
+
+const MAGIC: &[u8; 4] = b"RECN";
+const CEBK: &[u8; 4] = b"KBEC";
+const LABL: &[u8; 4] = b"LBAL";
+
+/// Size of the `CEBK` section header.
+const CEBK_HEADER_LEN: usize = 0x18;
+/// Size of a cell entry, without and with bounds.
+const CELL_LEN: usize = 0x08;
+const CELL_WITH_BOUNDS_LEN: usize = 0x10;
+/// Size of the attributes of one object.
+const OAM_LEN: usize = 0x06;
+
+/// Object sizes in pixels, by shape and then size.
+const OBJECT_SIZES: [[(usize, usize); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum NcerError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown mapping mode: {0}.")]
+    InvalidMapping(u32),
+}
+
+/// The attributes of a single object, as they are written to OAM.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Oam {
+    pub attributes: [u16; 3],
+}
+
+impl Oam {
+    /// Horizontal position relative to the center of the cell.
+    pub fn x(&self) -> i16 {
+        //  Sign extend the 9-bit field.
+        ((self.attributes[1] << 7) as i16) >> 7
+    }
+
+    /// Vertical position relative to the center of the cell.
+    pub fn y(&self) -> i16 {
+        i16::from(self.attributes[0] as u8 as i8)
+    }
+
+    /// Width and height in pixels. The unused fourth shape is read as a
+    /// square.
+    pub fn size(&self) -> (usize, usize) {
+        let shape = ((self.attributes[0] >> 14) as usize).min(2);
+        let size = (self.attributes[1] >> 14) as usize;
+
+        OBJECT_SIZES[shape][size]
+    }
+
+    pub fn is_affine(&self) -> bool {
+        self.attributes[0] & 0x100 != 0
+    }
+
+    /// Whether an affine object is drawn in an area twice its size.
+    pub fn is_double_size(&self) -> bool {
+        self.is_affine() && self.attributes[0] & 0x200 != 0
+    }
+
+    /// Affine objects can't be flipped, as those bits pick their
+    /// parameters instead.
+    pub fn flip_x(&self) -> bool {
+        !self.is_affine() && self.attributes[1] & 0x1000 != 0
+    }
+
+    pub fn flip_y(&self) -> bool {
+        !self.is_affine() && self.attributes[1] & 0x2000 != 0
+    }
+
+    pub fn depth(&self) -> ColorDepth {
+        if self.attributes[0] & 0x2000 != 0 {
+            ColorDepth::Bpp8
+        } else {
+            ColorDepth::Bpp4
+        }
+    }
+
+    /// The first tile, in units of the mapping boundary.
+    pub fn tile(&self) -> u16 {
+        self.attributes[2] & 0x3FF
+    }
+
+    pub fn priority(&self) -> u8 {
+        ((self.attributes[2] >> 10) & 0x3) as u8
+    }
+
+    pub fn palette(&self) -> u8 {
+        (self.attributes[2] >> 12) as u8
+    }
+
+    /// The area that the object covers, relative to the center of the
+    /// cell, as `(x, y, width, height)`.
+    fn area(&self) -> (i32, i32, usize, usize) {
+        let (width, height) = self.size();
+        let scale = if self.is_double_size() { 2 } else { 1 };
+
+        (i32::from(self.x()), i32::from(self.y()), width * scale, height * scale)
+    }
+}
+
+/// The area that a cell covers, relative to its center.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Bounds {
+    pub min_x: i16,
+    pub min_y: i16,
+    pub max_x: i16,
+    pub max_y: i16,
+}
+
+/// A single sprite made of objects.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Cell {
+    pub attributes: u16,
+    /// The bounds stored in the file, if the bank has them.
+    pub bounds: Option<Bounds>,
+    /// The objects of the cell. The first one is drawn on top.
+    pub oams: Vec<Oam>,
+}
+
+impl Cell {
+    /// The area that the objects cover, as `(x, y, width, height)` relative
+    /// to the center of the cell. This is computed from the objects, since
+    /// the bounds in some files are off.
+    pub fn extent(&self) -> (i32, i32, usize, usize) {
+        if self.oams.is_empty() {
+            return (0, 0, 0, 0);
+        }
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+
+        for oam in &self.oams {
+            let (x, y, width, height) = oam.area();
+
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x + width as i32);
+            max_y = max_y.max(y + height as i32);
+        }
+
+        (min_x, min_y, (max_x - min_x) as usize, (max_y - min_y) as usize)
+    }
+
+    /// Renders the cell over its [`extent`].
+    ///
+    /// [`extent`]: #method.extent
+    pub fn render(&self, tiles: &Tiles, palette: &Palette, mapping: Mapping) -> RgbaImage {
+        let (x, y, width, height) = self.extent();
+        let mut image = RgbaImage::new(width, height);
+
+        self.draw(&mut image, (-x, -y), tiles, palette, mapping);
+        image
+    }
+
+    /// Draws the cell into `image` with its center at `origin`. Affine
+    /// objects are drawn without their rotation and scaling.
+    pub fn draw(&self, image: &mut RgbaImage, origin: (i32, i32), tiles: &Tiles, palette: &Palette, mapping: Mapping) {
+        let tile_len = match tiles.depth {
+            ColorDepth::Bpp4 => 32,
+            ColorDepth::Bpp8 => 64,
+        };
+
+        //  Draw from the back so that the first object ends up on top.
+        for oam in self.oams.iter().rev() {
+            let (width, height) = oam.size();
+            let (x, y, area_width, area_height) = oam.area();
+
+            let colors = palette.palette_rgba(match oam.depth() {
+                ColorDepth::Bpp8 if !palette.extended => 0,
+                _ => oam.palette() as usize,
+            });
+
+            //  Double size objects are centered in their area.
+            let left = origin.0 + x + ((area_width - width) / 2) as i32;
+            let top = origin.1 + y + ((area_height - height) / 2) as i32;
+
+            let columns = width / TILE_SIZE;
+
+            for row in 0..height {
+                for column in 0..width {
+                    let target_x = left + column as i32;
+                    let target_y = top + row as i32;
+
+                    if target_x < 0 || target_y < 0 || target_x as usize >= image.width || target_y as usize >= image.height {
+                        continue;
+                    }
+
+                    let source_x = if oam.flip_x() { width - 1 - column } else { column };
+                    let source_y = if oam.flip_y() { height - 1 - row } else { row };
+
+                    let tile_x = source_x / TILE_SIZE;
+                    let tile_y = source_y / TILE_SIZE;
+
+                    //  In 2D mapping, objects are cut out of a sheet 32
+                    //  4bpp tiles wide. In 1D mapping, their tiles follow
+                    //  each other.
+                    let address = match mapping {
+                        Mapping::TwoD => oam.tile() as usize * 32 + tile_y * 32 * 32 + tile_x * tile_len,
+                        Mapping::OneD { boundary } => {
+                            oam.tile() as usize * boundary + (tile_y * columns + tile_x) * tile_len
+                        }
+                    };
+
+                    let index = match tiles.tiles.get(address / tile_len) {
+                        Some(tile) => tile[(source_y % TILE_SIZE) * TILE_SIZE + source_x % TILE_SIZE],
+                        None => continue,
+                    };
+
+                    if index == 0 {
+                        continue;
+                    }
+
+                    if let Some(&color) = colors.get(index as usize) {
+                        image.set_pixel(target_x as usize, target_y as usize, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The cells of an NCER file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct CellBank {
+    /// How objects find their tiles.
+    pub mapping: Mapping,
+    pub cells: Vec<Cell>,
+    /// Names of the cells, from the `LABL` section.
+    pub labels: Vec<String>,
+}
+
+impl CellBank {
+    /// Reads an NCER file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, MAGIC)?;
+        let cebk = &file.require(CEBK)?.data;
+
+        ensure!(cebk.len() >= CEBK_HEADER_LEN, NcerError::NotEnoughData);
+
+        let count = LittleEndian::read_u16(cebk) as usize;
+        let has_bounds = LittleEndian::read_u16(&cebk[0x02..]) == 1;
+        let table = LittleEndian::read_u32(&cebk[0x04..]) as usize;
+
+        let mapping = match LittleEndian::read_u32(&cebk[0x08..]) {
+            0 => Mapping::TwoD,
+            value @ 1..=4 => Mapping::OneD {
+                boundary: 32 << (value - 1),
+            },
+            value => return Err(NcerError::InvalidMapping(value).into()),
+        };
+
+        let entry_len = if has_bounds { CELL_WITH_BOUNDS_LEN } else { CELL_LEN };
+        let oam_data = table + count * entry_len;
+
+        let mut cells = Vec::with_capacity(count);
+
+        for index in 0..count {
+            let entry = cebk
+                .get(table + index * entry_len..table + (index + 1) * entry_len)
+                .ok_or(NcerError::NotEnoughData)?;
+
+            let oam_count = LittleEndian::read_u16(entry) as usize;
+            let offset = oam_data + LittleEndian::read_u32(&entry[0x04..]) as usize;
+
+            let oams = cebk
+                .get(offset..offset + oam_count * OAM_LEN)
+                .ok_or(NcerError::NotEnoughData)?
+                .chunks_exact(OAM_LEN)
+                .map(|oam| Oam {
+                    attributes: [
+                        LittleEndian::read_u16(oam),
+                        LittleEndian::read_u16(&oam[2..]),
+                        LittleEndian::read_u16(&oam[4..]),
+                    ],
+                })
+                .collect();
+
+            let bounds = if has_bounds {
+                Some(Bounds {
+                    max_x: LittleEndian::read_i16(&entry[0x08..]),
+                    max_y: LittleEndian::read_i16(&entry[0x0A..]),
+                    min_x: LittleEndian::read_i16(&entry[0x0C..]),
+                    min_y: LittleEndian::read_i16(&entry[0x0E..]),
+                })
+            } else {
+                None
+            };
+
+            cells.push(Cell {
+                attributes: LittleEndian::read_u16(&entry[0x02..]),
+                bounds,
+                oams,
+            });
+        }
+
+        let labels = match file.section(LABL) {
+            Some(labl) => parse_labels(&labl.data, count),
+            None => Vec::new(),
+        };
+
+        Ok(Self { mapping, cells, labels })
+    }
+
+    /// Renders a cell, or returns `None` if there is no such cell.
+    pub fn render(&self, index: usize, tiles: &Tiles, palette: &Palette) -> Option<RgbaImage> {
+        self.cells.get(index).map(|cell| cell.render(tiles, palette, self.mapping))
+    }
+}
+
+/// Reads the names in a `LABL` section: a table of offsets, then the names
+/// they point to, one after the other. The section doesn't say how many
+/// names there are, so this takes the longest table of at most `count`
+/// offsets that matches the names that follow it.
+pub(crate) fn parse_labels(data: &[u8], count: usize) -> Vec<String> {
+    let count = count.min(data.len() / 4);
+    let offsets = (0..count)
+        .map(|index| LittleEndian::read_u32(&data[index * 4..]) as usize)
+        .collect::<Vec<_>>();
+
+    for len in (1..=count).rev() {
+        let names = &data[len * 4..];
+        let mut labels = Vec::with_capacity(len);
+        let mut position = 0;
+
+        for &offset in &offsets[..len] {
+            if offset != position || position >= names.len() {
+                break;
+            }
+
+            let name = &names[position..];
+            let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+
+            labels.push(String::from_utf8_lossy(&name[..end]).into_owned());
+            position += end + 1;
+        }
+
+        if labels.len() == len {
+            return labels;
+        }
+    }
+
+    Vec::new()
+}
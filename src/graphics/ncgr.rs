@@ -0,0 +1,405 @@
+//! NCGR graphics, which hold the 8x8 pixel tiles that backgrounds and
+//! sprites are made of.
+//!
+//! The `CHAR` section holds the pixels as palette indices of 4 or 8 bits.
+//! Most files store them tile by tile, but some store a plain bitmap
+//! instead, which is called scanned mode. The optional `CPOS` section gives
+//! the size of the image that the tiles were cut from.
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::{NitroFile, Section};
+
+use anyhow::{ensure, Result};
+
+use super::nclr::{ColorDepth, Palette};
+use super::{IndexedImage, RgbaImage};
+
+const MAGIC: &[u8; 4] = b"RGCN";
+const CHAR: &[u8; 4] = b"RAHC";
+const CPOS: &[u8; 4] = b"SOPC";
+
+const VERSION: u16 = 0x0101;
+
+/// Size of the `CHAR` section header, which is also where its tiles start.
+const CHAR_HEADER_LEN: usize = 0x18;
+const CPOS_LEN: usize = 0x08;
+
+/// Width and height of a tile in pixels.
+pub const TILE_SIZE: usize = 8;
+/// Pixels in a tile.
+pub const TILE_PIXELS: usize = TILE_SIZE * TILE_SIZE;
+
+/// Stored instead of a width and height when the file doesn't have one,
+/// which is common for sprites.
+const NO_SIZE: u16 = 0xFFFF;
+
+/// Width in tiles used when the file doesn't have one. This is the width of
+/// a 2D mapped sprite sheet.
+const DEFAULT_WIDTH: usize = 32;
+
+/// An 8x8 tile of palette indices, row by row.
+pub type Tile = [u8; TILE_PIXELS];
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum NcgrError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown tile mapping: {0:#X}.")]
+    InvalidMapping(u32),
+
+    #[error("Scanned graphics need a width.")]
+    MissingWidth,
+
+    #[error("Image size must be a multiple of 8, got {0}x{1}.")]
+    InvalidImageSize(usize, usize),
+
+    #[error("Image has {found} tiles, but the graphics have {expected}.")]
+    TileCountMismatch { expected: usize, found: usize },
+
+    #[error("Palette index {0} does not fit in the color depth.")]
+    IndexOutOfRange(u8),
+}
+
+/// How sprites find their tiles in VRAM.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Mapping {
+    /// Tiles are laid out in a sheet 32 tiles wide.
+    TwoD,
+    /// Tiles follow each other, and sprites address them in steps of
+    /// `boundary` bytes.
+    OneD { boundary: usize },
+}
+
+impl Mapping {
+    pub fn from_raw(value: u32) -> Result<Self> {
+        match value {
+            0 => Ok(Mapping::TwoD),
+            0x10 | 0x0010_0010 | 0x0020_0010 | 0x0030_0010 => Ok(Mapping::OneD {
+                boundary: 32 << ((value >> 20) & 0x3),
+            }),
+            _ => Err(NcgrError::InvalidMapping(value).into()),
+        }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        match self {
+            Mapping::TwoD => 0,
+            Mapping::OneD { boundary } => {
+                let shift = (boundary / 32).max(1).trailing_zeros().min(3);
+                0x10 | (shift << 20)
+            }
+        }
+    }
+}
+
+/// The tiles of an NCGR file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Tiles {
+    pub depth: ColorDepth,
+    pub mapping: Mapping,
+    /// Whether the pixels are stored as a plain bitmap instead of tile by
+    /// tile.
+    pub scanned: bool,
+    /// Width and height in tiles, if the file has them.
+    pub size: Option<(u16, u16)>,
+    /// The `CPOS` section, if there is one.
+    pub position: Option<(u16, u16)>,
+    pub tiles: Vec<Tile>,
+}
+
+impl Tiles {
+    /// Reads an NCGR file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, MAGIC)?;
+        let char = &file.require(CHAR)?.data;
+
+        ensure!(char.len() >= CHAR_HEADER_LEN, NcgrError::NotEnoughData);
+
+        let height = LittleEndian::read_u16(char);
+        let width = LittleEndian::read_u16(&char[0x02..]);
+        let depth = ColorDepth::from_raw(LittleEndian::read_u32(&char[0x04..]))?;
+        let mapping = Mapping::from_raw(LittleEndian::read_u32(&char[0x08..]))?;
+        let scanned = LittleEndian::read_u32(&char[0x0C..]) & 1 != 0;
+        let len = LittleEndian::read_u32(&char[0x10..]) as usize;
+        let offset = LittleEndian::read_u32(&char[0x14..]) as usize;
+
+        let data = char.get(offset..offset + len).ok_or(NcgrError::NotEnoughData)?;
+        let size = if width == NO_SIZE || height == NO_SIZE {
+            None
+        } else {
+            Some((width, height))
+        };
+
+        let pixels = unpack(data, depth);
+
+        let tiles = if scanned {
+            let (width, _) = size.filter(|&(width, _)| width > 0).ok_or(NcgrError::MissingWidth)?;
+            from_bitmap(&pixels, width as usize * TILE_SIZE)
+        } else {
+            ensure!(pixels.len() % TILE_PIXELS == 0, NcgrError::NotEnoughData);
+
+            pixels
+                .chunks_exact(TILE_PIXELS)
+                .map(|chunk| {
+                    let mut tile = [0; TILE_PIXELS];
+                    tile.copy_from_slice(chunk);
+                    tile
+                })
+                .collect()
+        };
+
+        let position = match file.section(CPOS) {
+            Some(cpos) => {
+                ensure!(cpos.data.len() >= CPOS_LEN, NcgrError::NotEnoughData);
+
+                Some((
+                    LittleEndian::read_u16(&cpos.data[0x04..]),
+                    LittleEndian::read_u16(&cpos.data[0x06..]),
+                ))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            depth,
+            mapping,
+            scanned,
+            size,
+            position,
+            tiles,
+        })
+    }
+
+    /// Writes an NCGR file.
+    ///
+    /// # Errors
+    /// Returns an error if the graphics are scanned but have no width, or a
+    /// width of 0.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let pixels = if self.scanned {
+            let (width, _) = self.size.filter(|&(width, _)| width > 0).ok_or(NcgrError::MissingWidth)?;
+            to_bitmap(&self.tiles, width as usize)
+        } else {
+            self.tiles.concat()
+        };
+
+        let data = pack(&pixels, self.depth);
+        let (width, height) = self.size.unwrap_or((NO_SIZE, NO_SIZE));
+
+        let mut char = vec![0; CHAR_HEADER_LEN];
+
+        LittleEndian::write_u16(&mut char, height);
+        LittleEndian::write_u16(&mut char[0x02..], width);
+        LittleEndian::write_u32(&mut char[0x04..], self.depth.to_raw());
+        LittleEndian::write_u32(&mut char[0x08..], self.mapping.to_raw());
+        LittleEndian::write_u32(&mut char[0x0C..], u32::from(self.scanned));
+        LittleEndian::write_u32(&mut char[0x10..], data.len() as u32);
+        LittleEndian::write_u32(&mut char[0x14..], CHAR_HEADER_LEN as u32);
+        char.extend_from_slice(&data);
+
+        let mut file = NitroFile::new(*MAGIC, VERSION);
+        file.sections.push(Section::new(*CHAR, char));
+
+        if let Some((width, height)) = self.position {
+            let mut cpos = vec![0; CPOS_LEN];
+
+            LittleEndian::write_u16(&mut cpos[0x04..], width);
+            LittleEndian::write_u16(&mut cpos[0x06..], height);
+
+            file.sections.push(Section::new(*CPOS, cpos));
+        }
+
+        Ok(file.to_bytes())
+    }
+
+    /// The width in tiles that the graphics are shown at: the width in the
+    /// file, or 32 tiles if it has none.
+    pub fn width(&self) -> usize {
+        match self.size {
+            Some((width, _)) if width > 0 => width as usize,
+            _ => DEFAULT_WIDTH.min(self.tiles.len()).max(1),
+        }
+    }
+
+    /// Lays every tile out in rows of [`width`] tiles. Space after the last
+    /// tile is index 0.
+    ///
+    /// [`width`]: #method.width
+    pub fn to_image(&self) -> IndexedImage {
+        let width = self.width();
+        let height = self.tiles.len().div_ceil(width);
+        let mut image = IndexedImage::new(width * TILE_SIZE, height * TILE_SIZE);
+
+        for (index, tile) in self.tiles.iter().enumerate() {
+            draw_tile(&mut image, tile, (index % width) * TILE_SIZE, (index / width) * TILE_SIZE, false, false, 0);
+        }
+
+        image
+    }
+
+    /// Renders every tile with one of the palettes in `palette`. For 8bpp
+    /// graphics the palette index picks an extended palette.
+    pub fn render(&self, palette: &Palette, palette_index: usize) -> RgbaImage {
+        self.to_image().to_rgba(&palette.palette_rgba(palette_index))
+    }
+
+    /// Writes the tiles as an indexed PNG with one of the palettes, which
+    /// can be edited and read back with [`IndexedImage::from_png`] and
+    /// [`import`].
+    ///
+    /// [`IndexedImage::from_png`]: ../struct.IndexedImage.html#method.from_png
+    /// [`import`]: #method.import
+    #[cfg(feature = "image")]
+    pub fn to_png(&self, palette: &Palette, palette_index: usize) -> Result<Vec<u8>> {
+        self.to_image().to_png(&palette.palette_rgba(palette_index))
+    }
+
+    /// Replaces the tiles with those of an image, read in rows of 8x8
+    /// tiles.
+    ///
+    /// # Errors
+    /// Returns an error if the image size isn't a multiple of 8, if an
+    /// index doesn't fit in the color depth, or if the number of tiles
+    /// would change and `resize` is false. With `resize`, the size in the
+    /// header is set to that of the image.
+    pub fn import(&mut self, image: &IndexedImage, resize: bool) -> Result<()> {
+        ensure!(
+            image.width % TILE_SIZE == 0 && image.height % TILE_SIZE == 0,
+            NcgrError::InvalidImageSize(image.width, image.height)
+        );
+
+        if let Some(&index) = image.pixels.iter().find(|&&index| index as usize >= self.depth.colors()) {
+            return Err(NcgrError::IndexOutOfRange(index).into());
+        }
+
+        let width = image.width / TILE_SIZE;
+        let height = image.height / TILE_SIZE;
+        let mut tiles = from_bitmap(&image.pixels, image.width);
+
+        if !resize {
+            //  Rows that are only there to fill out the image may be cut,
+            //  as long as they are empty.
+            let expected = self.tiles.len();
+
+            ensure!(
+                tiles.len() >= expected && tiles[expected..].iter().all(|tile| tile.iter().all(|&index| index == 0)),
+                NcgrError::TileCountMismatch {
+                    expected,
+                    found: tiles.len(),
+                }
+            );
+
+            tiles.truncate(expected);
+        } else {
+            self.size = Some((width as u16, height as u16));
+        }
+
+        self.tiles = tiles;
+
+        Ok(())
+    }
+}
+
+/// Draws a tile into an image at the given position, flipped as asked.
+/// `base` is added to every index, which picks the palette of 4bpp tiles
+/// in an image that holds several palettes.
+pub(crate) fn draw_tile(image: &mut IndexedImage, tile: &Tile, x: usize, y: usize, flip_x: bool, flip_y: bool, base: u8) {
+    for row in 0..TILE_SIZE {
+        let target_y = y + if flip_y { TILE_SIZE - 1 - row } else { row };
+
+        if target_y >= image.height {
+            continue;
+        }
+
+        for column in 0..TILE_SIZE {
+            let target_x = x + if flip_x { TILE_SIZE - 1 - column } else { column };
+
+            if target_x >= image.width {
+                continue;
+            }
+
+            let index = tile[row * TILE_SIZE + column];
+            image.pixels[target_y * image.width + target_x] = if index == 0 { 0 } else { base + index };
+        }
+    }
+}
+
+/// A copy of a tile mirrored horizontally, vertically or both.
+pub(crate) fn flip(tile: &Tile, flip_x: bool, flip_y: bool) -> Tile {
+    let mut flipped = [0; TILE_PIXELS];
+
+    for y in 0..TILE_SIZE {
+        for x in 0..TILE_SIZE {
+            let source_x = if flip_x { TILE_SIZE - 1 - x } else { x };
+            let source_y = if flip_y { TILE_SIZE - 1 - y } else { y };
+
+            flipped[y * TILE_SIZE + x] = tile[source_y * TILE_SIZE + source_x];
+        }
+    }
+
+    flipped
+}
+
+/// Cuts a bitmap of the given width in pixels into tiles, row by row. A
+/// partial row of tiles at the bottom is padded with index 0.
+pub(crate) fn from_bitmap(pixels: &[u8], width: usize) -> Vec<Tile> {
+    if width == 0 {
+        return Vec::new();
+    }
+
+    let columns = width / TILE_SIZE;
+    let rows = (pixels.len() / width).div_ceil(TILE_SIZE);
+    let mut tiles = Vec::with_capacity(columns * rows);
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let mut tile = [0; TILE_PIXELS];
+
+            for y in 0..TILE_SIZE {
+                let start = (row * TILE_SIZE + y) * width + column * TILE_SIZE;
+
+                if let Some(line) = pixels.get(start..start + TILE_SIZE) {
+                    tile[y * TILE_SIZE..(y + 1) * TILE_SIZE].copy_from_slice(line);
+                }
+            }
+
+            tiles.push(tile);
+        }
+    }
+
+    tiles
+}
+
+/// The reverse of [`from_bitmap`], for a width in tiles.
+///
+/// [`from_bitmap`]: fn.from_bitmap.html
+fn to_bitmap(tiles: &[Tile], width: usize) -> Vec<u8> {
+    let mut image = IndexedImage::new(width * TILE_SIZE, tiles.len().div_ceil(width.max(1)) * TILE_SIZE);
+
+    for (index, tile) in tiles.iter().enumerate() {
+        draw_tile(&mut image, tile, (index % width) * TILE_SIZE, (index / width) * TILE_SIZE, false, false, 0);
+    }
+
+    image.pixels
+}
+
+/// Splits packed pixels into one index per byte. 4bpp pixels have the left
+/// pixel in the low nibble.
+pub(crate) fn unpack(data: &[u8], depth: ColorDepth) -> Vec<u8> {
+    match depth {
+        ColorDepth::Bpp4 => data.iter().flat_map(|&byte| [byte & 0x0F, byte >> 4]).collect(),
+        ColorDepth::Bpp8 => data.to_vec(),
+    }
+}
+
+pub(crate) fn pack(pixels: &[u8], depth: ColorDepth) -> Vec<u8> {
+    match depth {
+        ColorDepth::Bpp4 => pixels
+            .chunks(2)
+            .map(|pair| (pair[0] & 0x0F) | (pair.get(1).copied().unwrap_or(0) << 4))
+            .collect(),
+        ColorDepth::Bpp8 => pixels.to_vec(),
+    }
+}
@@ -0,0 +1,254 @@
+//! NCLR palettes, which hold the colors used by NCGR graphics.
+//!
+//! The `PLTT` section holds the colors, split into palettes of 16 colors
+//! for 4bpp graphics or 256 colors for 8bpp graphics. Extended palettes
+//! also have a `PCMP` section, which lists the slot that each palette is
+//! loaded into.
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::{NitroFile, Section};
+
+use anyhow::{ensure, Result};
+
+use super::{bgr555_to_rgba, rgba_to_bgr555, Rgba};
+
+const MAGIC: &[u8; 4] = b"RLCN";
+const PLTT: &[u8; 4] = b"TTLP";
+const PCMP: &[u8; 4] = b"PMCP";
+
+const VERSION: u16 = 0x0100;
+
+/// Size of the `PLTT` section header, which is also where its colors start.
+const PLTT_HEADER_LEN: usize = 0x10;
+/// Size of the `PCMP` section header, after which the palette IDs start.
+const PCMP_HEADER_LEN: usize = 0x08;
+/// Value that every known `PCMP` section has after the palette count.
+const PCMP_MARKER: u16 = 0xBEEF;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum NclrError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown color depth: {0}.")]
+    InvalidDepth(u32),
+}
+
+/// How many bits each pixel of the graphics that use a palette has.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ColorDepth {
+    Bpp4,
+    Bpp8,
+}
+
+impl ColorDepth {
+    /// Reads the depth as stored in NCLR and NCGR files.
+    pub fn from_raw(value: u32) -> Result<Self> {
+        match value {
+            3 => Ok(ColorDepth::Bpp4),
+            4 => Ok(ColorDepth::Bpp8),
+            _ => Err(NclrError::InvalidDepth(value).into()),
+        }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        match self {
+            ColorDepth::Bpp4 => 3,
+            ColorDepth::Bpp8 => 4,
+        }
+    }
+
+    pub fn bits(self) -> usize {
+        match self {
+            ColorDepth::Bpp4 => 4,
+            ColorDepth::Bpp8 => 8,
+        }
+    }
+
+    /// How many colors a single palette has.
+    pub fn colors(self) -> usize {
+        1 << self.bits()
+    }
+}
+
+/// The colors of an NCLR file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Palette {
+    pub depth: ColorDepth,
+    /// Whether the palette is an extended palette, which 8bpp backgrounds
+    /// and sprites can choose between.
+    pub extended: bool,
+    /// Every color in BGR555, one palette after another.
+    pub colors: Vec<u16>,
+    /// The slot of each palette, from the `PCMP` section.
+    pub palette_ids: Option<Vec<u16>>,
+}
+
+impl Palette {
+    /// Creates a palette from RGBA colors, such as those of an indexed
+    /// image.
+    pub fn from_rgba(depth: ColorDepth, colors: &[Rgba]) -> Self {
+        Self {
+            depth,
+            extended: false,
+            colors: colors.iter().map(|&color| rgba_to_bgr555(color)).collect(),
+            palette_ids: None,
+        }
+    }
+
+    /// Reads an NCLR file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, MAGIC)?;
+        let pltt = &file.require(PLTT)?.data;
+
+        ensure!(pltt.len() >= PLTT_HEADER_LEN, NclrError::NotEnoughData);
+
+        let depth = ColorDepth::from_raw(LittleEndian::read_u32(pltt))?;
+        let extended = LittleEndian::read_u32(&pltt[0x04..]) != 0;
+        let size = LittleEndian::read_u32(&pltt[0x08..]) as usize;
+        let offset = LittleEndian::read_u32(&pltt[0x0C..]) as usize;
+
+        //  Some files claim more colors than they have, so only read those
+        //  that are there.
+        let start = offset.min(pltt.len());
+        let end = start + size.min(pltt.len() - start);
+        let mut colors = vec![0; (end - start) / 2];
+
+        LittleEndian::read_u16_into(&pltt[start..start + colors.len() * 2], &mut colors);
+
+        let palette_ids = match file.section(PCMP) {
+            Some(pcmp) => {
+                let pcmp = &pcmp.data;
+
+                ensure!(pcmp.len() >= PCMP_HEADER_LEN, NclrError::NotEnoughData);
+
+                let count = LittleEndian::read_u16(pcmp) as usize;
+                let start = LittleEndian::read_u32(&pcmp[0x04..]) as usize;
+                let ids = pcmp.get(start..start + count * 2).ok_or(NclrError::NotEnoughData)?;
+
+                let mut palette_ids = vec![0; count];
+                LittleEndian::read_u16_into(ids, &mut palette_ids);
+
+                Some(palette_ids)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            depth,
+            extended,
+            colors,
+            palette_ids,
+        })
+    }
+
+    /// Writes an NCLR file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut pltt = vec![0; PLTT_HEADER_LEN + self.colors.len() * 2];
+
+        LittleEndian::write_u32(&mut pltt, self.depth.to_raw());
+        LittleEndian::write_u32(&mut pltt[0x04..], u32::from(self.extended));
+        LittleEndian::write_u32(&mut pltt[0x08..], (self.colors.len() * 2) as u32);
+        LittleEndian::write_u32(&mut pltt[0x0C..], PLTT_HEADER_LEN as u32);
+        LittleEndian::write_u16_into(&self.colors, &mut pltt[PLTT_HEADER_LEN..]);
+
+        let mut file = NitroFile::new(*MAGIC, VERSION);
+        file.sections.push(Section::new(*PLTT, pltt));
+
+        if let Some(ids) = &self.palette_ids {
+            let mut pcmp = vec![0; PCMP_HEADER_LEN + ids.len() * 2];
+
+            LittleEndian::write_u16(&mut pcmp, ids.len() as u16);
+            LittleEndian::write_u16(&mut pcmp[0x02..], PCMP_MARKER);
+            LittleEndian::write_u32(&mut pcmp[0x04..], PCMP_HEADER_LEN as u32);
+            LittleEndian::write_u16_into(ids, &mut pcmp[PCMP_HEADER_LEN..]);
+
+            file.sections.push(Section::new(*PCMP, pcmp));
+        }
+
+        file.to_bytes()
+    }
+
+    /// Every color as RGBA.
+    pub fn to_rgba(&self) -> Vec<Rgba> {
+        self.colors.iter().map(|&color| bgr555_to_rgba(color)).collect()
+    }
+
+    /// The colors of a single palette as RGBA, with the first color made
+    /// transparent as it is on the DS.
+    pub fn palette_rgba(&self, index: usize) -> Vec<Rgba> {
+        let mut colors = self
+            .palette(index)
+            .unwrap_or_default()
+            .iter()
+            .map(|&color| bgr555_to_rgba(color))
+            .collect::<Vec<_>>();
+
+        if let Some(first) = colors.first_mut() {
+            first[3] = 0;
+        }
+
+        colors
+    }
+
+    /// How many palettes of the color depth there are. A partial palette
+    /// at the end counts as one.
+    pub fn palette_count(&self) -> usize {
+        self.colors.len().div_ceil(self.depth.colors())
+    }
+
+    /// The colors of a single palette, which may be shorter than the color
+    /// depth allows if it is the last one.
+    pub fn palette(&self, index: usize) -> Option<&[u16]> {
+        self.colors.chunks(self.depth.colors()).nth(index)
+    }
+
+    /// Exports a RIFF palette, as used by Windows and many editors.
+    pub fn to_pal(&self) -> Vec<u8> {
+        let colors = self.to_rgba();
+        let chunk_len = 4 + colors.len() * 4;
+        let mut data = Vec::with_capacity(20 + chunk_len);
+
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&(12 + chunk_len as u32).to_le_bytes());
+        data.extend_from_slice(b"PAL data");
+        data.extend_from_slice(&(chunk_len as u32).to_le_bytes());
+        data.extend_from_slice(&0x0300u16.to_le_bytes());
+        data.extend_from_slice(&(colors.len() as u16).to_le_bytes());
+
+        for [red, green, blue, _] in colors {
+            data.extend_from_slice(&[red, green, blue, 0]);
+        }
+
+        data
+    }
+
+    /// Exports an Adobe Color Table. These hold 256 colors, so only the
+    /// first 256 are exported. The first color is marked as transparent.
+    pub fn to_act(&self) -> Vec<u8> {
+        let colors = self.to_rgba();
+        let count = colors.len().min(256);
+        let mut data = vec![0; 256 * 3];
+
+        for (index, [red, green, blue, _]) in colors.into_iter().take(count).enumerate() {
+            data[index * 3..index * 3 + 3].copy_from_slice(&[red, green, blue]);
+        }
+
+        data.extend_from_slice(&(count as u16).to_be_bytes());
+        data.extend_from_slice(&0u16.to_be_bytes());
+
+        data
+    }
+
+    /// Exports a GIMP palette with the given name.
+    pub fn to_gpl(&self, name: &str) -> String {
+        let mut text = format!("GIMP Palette\nName: {}\nColumns: 16\n#\n", name);
+
+        for (index, [red, green, blue, _]) in self.to_rgba().into_iter().enumerate() {
+            text.push_str(&format!("{:3} {:3} {:3}\tIndex {}\n", red, green, blue, index));
+        }
+
+        text
+    }
+}
@@ -0,0 +1,440 @@
+//! NSCR screens, which are the tilemaps of backgrounds.
+//!
+//! The `SCRN` section lists one entry per 8x8 tile of the background, row
+//! by row. Text backgrounds have 16-bit entries with a tile number, flips
+//! and a palette, while affine backgrounds only have an 8-bit tile number.
+//!
+//! A [`Background`] groups a screen with the graphics and palette it
+//! uses, which is what is needed to render it or to make one from an image.
+//!
+//! [`Background`]: struct.Background.html
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::{NitroFile, Section};
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, Result};
+
+use super::ncgr::{self, Mapping, Tile, Tiles, TILE_SIZE};
+use super::nclr::{ColorDepth, Palette};
+use super::{IndexedImage, Rgba, RgbaImage};
+
+const MAGIC: &[u8; 4] = b"RCSN";
+const SCRN: &[u8; 4] = b"NRCS";
+
+const VERSION: u16 = 0x0100;
+
+/// Size of the `SCRN` section header, after which the entries start.
+const SCRN_HEADER_LEN: usize = 0x0C;
+
+/// Text backgrounds have 10 bits for the tile number.
+const MAX_TILES: usize = 0x400;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum NscrError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown color mode: {0}.")]
+    InvalidDepth(u16),
+
+    #[error("Unknown screen format: {0}.")]
+    InvalidFormat(u16),
+
+    #[error("Image size must be a multiple of 8, got {0}x{1}.")]
+    InvalidImageSize(usize, usize),
+
+    #[error("The tile at ({x}, {y}) uses colors from more than one palette.")]
+    MixedPalettes { x: usize, y: usize },
+
+    #[error("Image needs {0} unique tiles, but a screen can only use 1024.")]
+    TooManyTiles(usize),
+}
+
+/// The kind of background a screen is made for, which decides the size of
+/// its entries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ScreenFormat {
+    /// 16-bit entries with flips and a palette.
+    Text,
+    /// 8-bit entries with only a tile number.
+    Affine,
+    /// 16-bit entries, like text backgrounds, for extended affine
+    /// backgrounds.
+    AffineExtended,
+}
+
+impl ScreenFormat {
+    pub fn from_raw(value: u16) -> Result<Self> {
+        match value {
+            0 => Ok(ScreenFormat::Text),
+            1 => Ok(ScreenFormat::Affine),
+            2 => Ok(ScreenFormat::AffineExtended),
+            _ => Err(NscrError::InvalidFormat(value).into()),
+        }
+    }
+
+    pub fn to_raw(self) -> u16 {
+        match self {
+            ScreenFormat::Text => 0,
+            ScreenFormat::Affine => 1,
+            ScreenFormat::AffineExtended => 2,
+        }
+    }
+
+    /// Size of an entry in bytes.
+    pub fn entry_len(self) -> usize {
+        match self {
+            ScreenFormat::Affine => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// A single tile of a screen.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MapEntry {
+    pub tile: u16,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// The palette for 4bpp graphics, or the extended palette for 8bpp
+    /// graphics.
+    pub palette: u8,
+}
+
+impl MapEntry {
+    /// Reads a 16-bit entry.
+    pub fn from_raw(value: u16) -> Self {
+        Self {
+            tile: value & 0x3FF,
+            flip_x: value & 0x400 != 0,
+            flip_y: value & 0x800 != 0,
+            palette: (value >> 12) as u8,
+        }
+    }
+
+    pub fn to_raw(self) -> u16 {
+        (self.tile & 0x3FF)
+            | (u16::from(self.flip_x) << 10)
+            | (u16::from(self.flip_y) << 11)
+            | (u16::from(self.palette & 0xF) << 12)
+    }
+}
+
+/// The tilemap of an NSCR file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Screen {
+    /// Width in pixels.
+    pub width: u16,
+    /// Height in pixels.
+    pub height: u16,
+    pub depth: ColorDepth,
+    pub format: ScreenFormat,
+    /// One entry per tile, row by row.
+    pub entries: Vec<MapEntry>,
+}
+
+impl Screen {
+    /// Reads an NSCR file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, MAGIC)?;
+        let scrn = &file.require(SCRN)?.data;
+
+        ensure!(scrn.len() >= SCRN_HEADER_LEN, NscrError::NotEnoughData);
+
+        let width = LittleEndian::read_u16(scrn);
+        let height = LittleEndian::read_u16(&scrn[0x02..]);
+
+        let depth = match LittleEndian::read_u16(&scrn[0x04..]) {
+            0 => ColorDepth::Bpp4,
+            1 => ColorDepth::Bpp8,
+            value => return Err(NscrError::InvalidDepth(value).into()),
+        };
+
+        let format = ScreenFormat::from_raw(LittleEndian::read_u16(&scrn[0x06..]))?;
+        let len = LittleEndian::read_u32(&scrn[0x08..]) as usize;
+
+        let data = scrn
+            .get(SCRN_HEADER_LEN..SCRN_HEADER_LEN + len)
+            .ok_or(NscrError::NotEnoughData)?;
+
+        let entries = match format {
+            ScreenFormat::Affine => data
+                .iter()
+                .map(|&tile| MapEntry {
+                    tile: u16::from(tile),
+                    ..MapEntry::default()
+                })
+                .collect(),
+            _ => data
+                .chunks_exact(2)
+                .map(|entry| MapEntry::from_raw(LittleEndian::read_u16(entry)))
+                .collect(),
+        };
+
+        Ok(Self {
+            width,
+            height,
+            depth,
+            format,
+            entries,
+        })
+    }
+
+    /// Writes an NSCR file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = self.entries.len() * self.format.entry_len();
+        let mut scrn = vec![0; SCRN_HEADER_LEN];
+
+        LittleEndian::write_u16(&mut scrn, self.width);
+        LittleEndian::write_u16(&mut scrn[0x02..], self.height);
+        LittleEndian::write_u16(&mut scrn[0x04..], u16::from(self.depth == ColorDepth::Bpp8));
+        LittleEndian::write_u16(&mut scrn[0x06..], self.format.to_raw());
+        LittleEndian::write_u32(&mut scrn[0x08..], len as u32);
+
+        for entry in &self.entries {
+            match self.format {
+                ScreenFormat::Affine => scrn.push(entry.tile as u8),
+                _ => scrn.extend_from_slice(&entry.to_raw().to_le_bytes()),
+            }
+        }
+
+        let mut file = NitroFile::new(*MAGIC, VERSION);
+        file.sections.push(Section::new(*SCRN, scrn));
+
+        file.to_bytes()
+    }
+
+    /// Width in tiles.
+    pub fn columns(&self) -> usize {
+        self.width as usize / TILE_SIZE
+    }
+
+    /// The palette that an entry is drawn with. 8bpp graphics only pick a
+    /// palette when they use extended palettes.
+    fn palette_index(&self, entry: &MapEntry, palette: &Palette) -> usize {
+        if self.depth == ColorDepth::Bpp8 && !palette.extended {
+            0
+        } else {
+            entry.palette as usize
+        }
+    }
+
+    /// Lays out the tiles of the screen. For 4bpp graphics, the palette of
+    /// each tile is added to its indices, so the image can be shown with
+    /// every palette of an NCLR one after the other. Entries that point
+    /// past the last tile are left as index 0.
+    pub fn to_image(&self, tiles: &Tiles) -> IndexedImage {
+        let mut image = IndexedImage::new(self.width as usize, self.height as usize);
+        let columns = self.columns().max(1);
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let Some(tile) = tiles.tiles.get(entry.tile as usize) {
+                let base = match self.depth {
+                    ColorDepth::Bpp4 => entry.palette << 4,
+                    ColorDepth::Bpp8 => 0,
+                };
+
+                ncgr::draw_tile(
+                    &mut image,
+                    tile,
+                    (index % columns) * TILE_SIZE,
+                    (index / columns) * TILE_SIZE,
+                    entry.flip_x,
+                    entry.flip_y,
+                    base,
+                );
+            }
+        }
+
+        image
+    }
+
+    /// Renders the whole background. Index 0 of every palette is
+    /// transparent.
+    pub fn render(&self, tiles: &Tiles, palette: &Palette) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width as usize, self.height as usize);
+        let columns = self.columns().max(1);
+        let palettes = (0..palette.palette_count())
+            .map(|index| palette.palette_rgba(index))
+            .collect::<Vec<_>>();
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let tile = match tiles.tiles.get(entry.tile as usize) {
+                Some(tile) => ncgr::flip(tile, entry.flip_x, entry.flip_y),
+                None => continue,
+            };
+
+            let colors = palettes
+                .get(self.palette_index(entry, palette))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            for (pixel, &color) in tile.iter().enumerate() {
+                let x = (index % columns) * TILE_SIZE + pixel % TILE_SIZE;
+                let y = (index / columns) * TILE_SIZE + pixel / TILE_SIZE;
+
+                if color == 0 || x >= image.width || y >= image.height {
+                    continue;
+                }
+
+                if let Some(&color) = colors.get(color as usize) {
+                    image.set_pixel(x, y, color);
+                }
+            }
+        }
+
+        image
+    }
+}
+
+/// A screen together with its graphics and palette.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Background {
+    pub screen: Screen,
+    pub tiles: Tiles,
+    pub palette: Palette,
+}
+
+impl Background {
+    pub fn new(screen: Screen, tiles: Tiles, palette: Palette) -> Self {
+        Self {
+            screen,
+            tiles,
+            palette,
+        }
+    }
+
+    /// Renders the background.
+    pub fn render(&self) -> RgbaImage {
+        self.screen.render(&self.tiles, &self.palette)
+    }
+
+    /// Writes the background as an indexed PNG with every color of the
+    /// palette, which [`from_png`] reads back. The first color of each
+    /// palette is transparent. Extended palettes can't be shown in one
+    /// indexed image, so 8bpp backgrounds always use the first one.
+    ///
+    /// [`from_png`]: #method.from_png
+    #[cfg(feature = "image")]
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let group = match self.screen.depth {
+            ColorDepth::Bpp4 => 16,
+            ColorDepth::Bpp8 => 256,
+        };
+
+        let mut colors = self.palette.to_rgba();
+        colors.truncate(256);
+
+        for color in colors.iter_mut().step_by(group) {
+            color[3] = 0;
+        }
+
+        self.screen.to_image(&self.tiles).to_png(&colors)
+    }
+
+    /// Makes a text background from an indexed image and its colors.
+    ///
+    /// Tiles that repeat, including flipped copies, are only stored once.
+    /// For 4bpp graphics, the top four bits of an index pick the palette of
+    /// its tile, so every tile must only use colors from one group of 16.
+    /// Index 0 of each group is transparent and fits in any of them.
+    ///
+    /// # Errors
+    /// Returns an error if the image size isn't a multiple of 8, if a 4bpp
+    /// tile mixes palettes, or if there are more than 1024 unique tiles.
+    pub fn import(image: &IndexedImage, colors: &[Rgba], depth: ColorDepth) -> Result<Self> {
+        ensure!(
+            image.width % TILE_SIZE == 0 && image.height % TILE_SIZE == 0,
+            NscrError::InvalidImageSize(image.width, image.height)
+        );
+
+        let columns = image.width / TILE_SIZE;
+        let mut tiles: Vec<Tile> = Vec::new();
+        let mut known: HashMap<Tile, u16> = HashMap::new();
+        let mut entries = Vec::new();
+
+        for (index, mut tile) in ncgr::from_bitmap(&image.pixels, image.width).into_iter().enumerate() {
+            let mut palette = 0;
+
+            if depth == ColorDepth::Bpp4 {
+                let mut banks = tile.iter().filter(|&&pixel| pixel & 0xF != 0).map(|&pixel| pixel >> 4);
+
+                palette = banks.next().unwrap_or(0);
+
+                ensure!(
+                    banks.all(|bank| bank == palette),
+                    NscrError::MixedPalettes {
+                        x: (index % columns) * TILE_SIZE,
+                        y: (index / columns) * TILE_SIZE,
+                    }
+                );
+
+                for pixel in tile.iter_mut() {
+                    *pixel &= 0xF;
+                }
+            }
+
+            //  A tile matches a stored one if flipping it gives that tile.
+            let found = [(false, false), (true, false), (false, true), (true, true)]
+                .iter()
+                .find_map(|&(flip_x, flip_y)| {
+                    known
+                        .get(&ncgr::flip(&tile, flip_x, flip_y))
+                        .map(|&id| (id, flip_x, flip_y))
+                });
+
+            let (id, flip_x, flip_y) = match found {
+                Some(found) => found,
+                None => {
+                    let id = tiles.len() as u16;
+
+                    known.insert(tile, id);
+                    tiles.push(tile);
+
+                    (id, false, false)
+                }
+            };
+
+            entries.push(MapEntry {
+                tile: id,
+                flip_x,
+                flip_y,
+                palette,
+            });
+        }
+
+        ensure!(tiles.len() <= MAX_TILES, NscrError::TooManyTiles(tiles.len()));
+
+        let screen = Screen {
+            width: image.width as u16,
+            height: image.height as u16,
+            depth,
+            format: ScreenFormat::Text,
+            entries,
+        };
+
+        let tiles = Tiles {
+            depth,
+            mapping: Mapping::TwoD,
+            scanned: false,
+            size: None,
+            position: None,
+            tiles,
+        };
+
+        Ok(Self::new(screen, tiles, Palette::from_rgba(depth, colors)))
+    }
+
+    /// Same as [`import`], but reads an indexed PNG.
+    ///
+    /// [`import`]: #method.import
+    #[cfg(feature = "image")]
+    pub fn from_png(data: &[u8], depth: ColorDepth) -> Result<Self> {
+        let (image, colors) = IndexedImage::from_png(data)?;
+
+        Self::import(&image, &colors, depth)
+    }
+}
@@ -0,0 +1,116 @@
+//! PNG import and export of images, behind the `image` feature.
+
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+use std::path::Path;
+
+use anyhow::{ensure, Result};
+
+use super::{IndexedImage, Rgba, RgbaImage};
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum PngError {
+    #[error("Only indexed PNG images can be imported, found {0:?}.")]
+    NotIndexed(ColorType),
+
+    #[error("PNG images can have at most 256 colors, got {0}.")]
+    TooManyColors(usize),
+}
+
+impl RgbaImage {
+    /// Writes the image as an RGBA PNG.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+
+        {
+            let mut encoder = Encoder::new(&mut data, self.width as u32, self.height as u32);
+
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_depth(BitDepth::Eight);
+
+            encoder.write_header()?.write_image_data(&self.pixels)?;
+        }
+
+        Ok(data)
+    }
+
+    /// Writes the image to a PNG file.
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_png()?)?;
+
+        Ok(())
+    }
+}
+
+impl IndexedImage {
+    /// Writes the image as an indexed PNG with the given palette. The alpha
+    /// of each color is kept, so the first color of a DS palette stays
+    /// transparent.
+    pub fn to_png(&self, palette: &[Rgba]) -> Result<Vec<u8>> {
+        ensure!(palette.len() <= 256, PngError::TooManyColors(palette.len()));
+
+        let mut data = Vec::new();
+
+        {
+            let mut encoder = Encoder::new(&mut data, self.width as u32, self.height as u32);
+
+            encoder.set_color(ColorType::Indexed);
+            encoder.set_depth(BitDepth::Eight);
+            encoder.set_palette(palette.iter().flat_map(|color| [color[0], color[1], color[2]]).collect::<Vec<_>>());
+            encoder.set_trns(palette.iter().map(|color| color[3]).collect::<Vec<_>>());
+
+            encoder.write_header()?.write_image_data(&self.pixels)?;
+        }
+
+        Ok(data)
+    }
+
+    /// Reads an indexed PNG, along with its palette. Colors without an
+    /// alpha in the file are opaque.
+    ///
+    /// # Errors
+    /// Returns an error if the PNG isn't indexed. Importing keeps the
+    /// indices as they are, so converting other images would be guesswork.
+    pub fn from_png(data: &[u8]) -> Result<(Self, Vec<Rgba>)> {
+        let mut decoder = Decoder::new(data);
+        decoder.set_transformations(Transformations::IDENTITY);
+
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer)?;
+
+        ensure!(frame.color_type == ColorType::Indexed, PngError::NotIndexed(frame.color_type));
+
+        let info = reader.info();
+        let width = frame.width as usize;
+        let height = frame.height as usize;
+        let bits = frame.bit_depth as usize;
+
+        //  Rows of smaller depths are packed from the highest bits down and
+        //  padded to a whole byte.
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for row in buffer.chunks(frame.line_size).take(height) {
+            for x in 0..width {
+                let bit = x * bits;
+                let shift = 8 - bits - bit % 8;
+                let mask = ((1u16 << bits) - 1) as u8;
+
+                pixels.push((row[bit / 8] >> shift) & mask);
+            }
+        }
+
+        let trns = info.trns.as_deref().unwrap_or_default();
+        let palette = info
+            .palette
+            .as_deref()
+            .unwrap_or_default()
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(index, color)| [color[0], color[1], color[2], trns.get(index).copied().unwrap_or(0xFF)])
+            .collect();
+
+        Ok((Self { width, height, pixels }, palette))
+    }
+}
@@ -0,0 +1,96 @@
+/// Offsets of the header fields that the extractor and builder need to
+/// read or patch. Values are taken from [this table].
+///
+/// [this table]: https://dsibrew.org/wiki/DSi_Cartridge_Header
+//  Some fields are only read by the manifest.
+#[cfg_attr(not(feature = "manifest"), allow(dead_code))]
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Header {
+    Title = 0x00,
+    Gamecode = 0x0C,
+    Makercode = 0x10,
+    UnitCode = 0x12,
+    EncryptionSeed = 0x13,
+    DeviceCapacity = 0x14,
+    Revision = 0x1C,
+    RomVersion = 0x1E,
+    InternalFlags = 0x1F,
+    Arm9Offset = 0x20,
+    Arm9Entry = 0x24,
+    Arm9Load = 0x28,
+    Arm9Len = 0x2C,
+    Arm7Offset = 0x30,
+    Arm7Entry = 0x34,
+    Arm7Load = 0x38,
+    Arm7Len = 0x3C,
+    FntOffset = 0x40,
+    FntLen = 0x44,
+    FatOffset = 0x48,
+    FatLen = 0x4C,
+    Arm9OverlayOffset = 0x50,
+    Arm9OverlayLen = 0x54,
+    Arm7OverlayOffset = 0x58,
+    Arm7OverlayLen = 0x5C,
+    NormalCardControl = 0x60,
+    SecureCardControl = 0x64,
+    BannerOffset = 0x68,
+    SecureAreaCrc = 0x6C,
+    SecureTransferTimeout = 0x6E,
+    Arm9Autoload = 0x70,
+    Arm7Autoload = 0x74,
+    SecureDisable = 0x78,
+    RomSize = 0x80,
+    Size = 0x84,
+    Logo = 0xC0,
+    LogoCrc = 0x15C,
+    Crc = 0x15E,
+    Debugger = 0x160,
+    Arm9iOffset = 0x1C0,
+    Arm9iLen = 0x1CC,
+    Arm7iOffset = 0x1D0,
+    Arm7iLen = 0x1DC,
+    TwlRomSize = 0x210,
+}
+
+/// Bit of the unit code that is set for ROMs with a DSi region.
+pub(crate) const UNIT_CODE_TWL: u8 = 0x02;
+
+/// Size of a single entry in an overlay table.
+pub(crate) const OVERLAY_ENTRY_LEN: usize = 0x20;
+
+/// Offset of the FAT file ID inside an overlay table entry.
+pub(crate) const OVERLAY_FILE_ID: usize = 0x18;
+
+/// The Nintendo logo that the DS checks before it boots a ROM.
+pub(crate) const NINTENDO_LOGO: [u8; 156] = [
+    0x24, 0xFF, 0xAE, 0x51, 0x69, 0x9A, 0xA2, 0x21, 0x3D, 0x84, 0x82, 0x0A,
+    0x84, 0xE4, 0x09, 0xAD, 0x11, 0x24, 0x8B, 0x98, 0xC0, 0x81, 0x7F, 0x21,
+    0xA3, 0x52, 0xBE, 0x19, 0x93, 0x09, 0xCE, 0x20, 0x10, 0x46, 0x4A, 0x4A,
+    0xF8, 0x27, 0x31, 0xEC, 0x58, 0xC7, 0xE8, 0x33, 0x82, 0xE3, 0xCE, 0xBF,
+    0x85, 0xF4, 0xDF, 0x94, 0xCE, 0x4B, 0x09, 0xC1, 0x94, 0x56, 0x8A, 0xC0,
+    0x13, 0x72, 0xA7, 0xFC, 0x9F, 0x84, 0x4D, 0x73, 0xA3, 0xCA, 0x9A, 0x61,
+    0x58, 0x97, 0xA3, 0x27, 0xFC, 0x03, 0x98, 0x76, 0x23, 0x1D, 0xC7, 0x61,
+    0x03, 0x04, 0xAE, 0x56, 0xBF, 0x38, 0x84, 0x00, 0x40, 0xA7, 0x0E, 0xFD,
+    0xFF, 0x52, 0xFE, 0x03, 0x6F, 0x95, 0x30, 0xF1, 0x97, 0xFB, 0xC0, 0x85,
+    0x60, 0xD6, 0x80, 0x25, 0xA9, 0x63, 0xBE, 0x03, 0x01, 0x4E, 0x38, 0xE2,
+    0xF9, 0xA2, 0x34, 0xFF, 0xBB, 0x3E, 0x03, 0x44, 0x78, 0x00, 0x90, 0xCB,
+    0x88, 0x11, 0x3A, 0x94, 0x65, 0xC0, 0x7C, 0x63, 0x87, 0xF0, 0x3C, 0xAF,
+    0xD6, 0x25, 0xE4, 0x8B, 0x38, 0x0A, 0xAC, 0x72, 0x21, 0xD4, 0xF8, 0x07,
+];
+
+/// CRC16 of [`NINTENDO_LOGO`].
+///
+/// [`NINTENDO_LOGO`]: constant.NINTENDO_LOGO.html
+pub(crate) const NINTENDO_LOGO_CRC: u16 = 0xCF56;
+
+/// Gets the size of a banner from its version number.
+///
+/// Unknown versions are treated as the original version 1 banner.
+pub(crate) fn banner_len(version: u16) -> usize {
+    match version {
+        0x0002 => 0x940,
+        0x0003 => 0xA40,
+        0x0103 => 0x23C0,
+        _ => 0x840,
+    }
+}
@@ -1,10 +1,28 @@
 mod build;
+mod create;
 mod extract;
+mod header;
+#[cfg(feature = "manifest")]
+mod manifest;
+mod rom;
 pub mod parser;
 
 // == Public API ==
+pub mod banner;
+pub mod bmg;
+pub mod compression;
+pub mod dat;
+pub mod diff;
+pub mod dldi;
+pub mod elf;
+pub mod graphics;
+pub mod overlay;
+pub mod patch;
+pub mod sdat;
 pub mod util;
 
 pub use crate::build::Builder;
+pub use crate::create::{Binary, Creator};
 pub use crate::extract::Extractor;
+pub use crate::rom::{Checksum, Rom};
 // pub use crate::parser::NDSParser;
@@ -0,0 +1,326 @@
+//! The `nds` command line tool, which wraps the library for quick looks at
+//! a ROM and for scripts.
+//!
+//! Exit codes are 0 on success, 1 when `verify` finds a bad checksum and 2
+//! for any other error, including bad arguments.
+
+use nds::overlay::Processor;
+use nds::{Builder, Extractor, Rom};
+use nitro_fs::walk::Entry;
+use serde_json::{json, Value};
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::exit;
+
+use anyhow::Result;
+
+const USAGE: &str = "\
+Usage: nds <command> [options]
+
+Commands:
+    info <rom>                  Show the header, banner titles and overlays
+    ls <rom>                    List the file system with IDs, offsets and sizes
+    extract <rom> <dir>         Extract a ROM into a directory
+    build <dir> <rom>           Build a ROM from an extracted directory
+    cat <rom> <path>            Write a file from the ROM to stdout
+    verify <rom>                Check the header, logo and banner checksums
+
+Options:
+    --json                      Print info, ls and verify output as JSON
+    --decompress                Decompress files when extracting
+    --recompress                Compress files again when building
+    --no-crc                    Don't check the header checksum when opening
+    -h, --help                  Show this message";
+
+const EXIT_INVALID: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+
+enum Command {
+    Info(PathBuf),
+    Ls(PathBuf),
+    Extract(PathBuf, PathBuf),
+    Build(PathBuf, PathBuf),
+    Cat(PathBuf, String),
+    Verify(PathBuf),
+}
+
+struct Args {
+    command: Command,
+    json: bool,
+    decompress: bool,
+    recompress: bool,
+    check_crc: bool,
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(why) => {
+            eprintln!("error: {}\n\n{}", why, USAGE);
+            exit(EXIT_ERROR);
+        }
+    };
+
+    match run(&args) {
+        Ok(code) => exit(code),
+        Err(why) => {
+            eprintln!("error: {:#}", why);
+            exit(EXIT_ERROR);
+        }
+    }
+}
+
+/// Reads the command line. Returns `None` if help was asked for.
+fn parse_args() -> Result<Option<Args>, lexopt::Error> {
+    use lexopt::prelude::*;
+
+    let mut parser = lexopt::Parser::from_env();
+    let mut positional: Vec<OsString> = Vec::new();
+
+    let mut args = Args {
+        command: Command::Info(PathBuf::new()),
+        json: false,
+        decompress: false,
+        recompress: false,
+        check_crc: true,
+    };
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("json") => args.json = true,
+            Long("decompress") => args.decompress = true,
+            Long("recompress") => args.recompress = true,
+            Long("no-crc") => args.check_crc = false,
+            Short('h') | Long("help") => return Ok(None),
+            Value(value) => positional.push(value),
+            _ => return Err(arg.unexpected()),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let name = positional.next().ok_or("missing command")?.string()?;
+    let mut next = |what: &str| positional.next().ok_or_else(|| format!("missing {}", what));
+
+    args.command = match name.as_str() {
+        "info" => Command::Info(next("ROM")?.into()),
+        "ls" => Command::Ls(next("ROM")?.into()),
+        "extract" => Command::Extract(next("ROM")?.into(), next("directory")?.into()),
+        "build" => Command::Build(next("directory")?.into(), next("ROM")?.into()),
+        "cat" => Command::Cat(next("ROM")?.into(), next("path")?.string()?),
+        "verify" => Command::Verify(next("ROM")?.into()),
+        _ => return Err(format!("unknown command '{}'", name).into()),
+    };
+
+    if let Some(extra) = positional.next() {
+        return Err(lexopt::Error::UnexpectedArgument(extra));
+    }
+
+    Ok(Some(args))
+}
+
+fn run(args: &Args) -> Result<i32> {
+    match &args.command {
+        Command::Info(path) => info(&Rom::new(path, args.check_crc)?, args.json)?,
+        Command::Ls(path) => ls(&Rom::new(path, args.check_crc)?, args.json),
+        Command::Extract(path, dir) => {
+            let mut extractor = Extractor::new(path, args.check_crc)?;
+
+            extractor.set_decompress(args.decompress);
+            extractor.extract(dir)?;
+        }
+        Command::Build(dir, path) => {
+            let mut builder = Builder::new(dir)?;
+
+            builder.set_recompress(args.recompress);
+            builder.build(path)?;
+        }
+        Command::Cat(path, file) => {
+            let rom = Rom::new(path, args.check_crc)?;
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+
+            stdout.write_all(rom.open(file)?)?;
+            stdout.flush()?;
+        }
+        //  The header checksum is one of the things being checked.
+        Command::Verify(path) => return verify(&Rom::new(path, false)?, args.json),
+    }
+
+    Ok(0)
+}
+
+fn info(rom: &Rom, json: bool) -> Result<()> {
+    let header = rom.header();
+    let banner = rom.banner()?;
+    let capacity = rom.capacity().unwrap_or(0);
+
+    let mut overlays = Vec::new();
+
+    for cpu in &[Processor::Arm9, Processor::Arm7] {
+        overlays.extend(rom.overlays(*cpu)?.into_iter().map(|overlay| (*cpu, overlay)));
+    }
+
+    if json {
+        let cpu = |cpu: &nds::parser::Cpu| {
+            json!({
+                "offset": cpu.rom_offset,
+                "size": cpu.size,
+                "load_address": cpu.load_address,
+                "entry_address": cpu.entry_address,
+            })
+        };
+
+        let value = json!({
+            "title": header.game_title,
+            "gamecode": header.gamecode,
+            "makercode": header.makercode,
+            "unitcode": header.unitcode,
+            "revision": header.game_revision,
+            "rom_version": header.rom_version,
+            "capacity": capacity,
+            "arm9": cpu(&header.arm9),
+            "arm7": cpu(&header.arm7),
+            "banner": banner.map(|banner| json!({
+                "version": banner.version,
+                "titles": banner.titles,
+            })),
+            "overlays": overlays.iter().map(|(cpu, overlay)| json!({
+                "cpu": cpu.to_string(),
+                "id": overlay.id,
+                "file_id": overlay.file_id,
+                "ram_address": overlay.ram_address,
+                "ram_size": overlay.ram_size,
+                "bss_size": overlay.bss_size,
+                "compressed": overlay.is_compressed(),
+            })).collect::<Vec<_>>(),
+        });
+
+        println!("{}", serde_json::to_string_pretty(&value)?);
+
+        return Ok(());
+    }
+
+    println!("Title:        {}", header.game_title);
+    println!("Gamecode:     {}", header.gamecode);
+    println!("Makercode:    {}", header.makercode);
+    println!("Unit code:    {:#04X}", header.unitcode);
+    println!("Revision:     {}", header.game_revision);
+    println!("ROM version:  {}", header.rom_version);
+    println!("Capacity:     {} KiB", capacity / 1024);
+
+    for (name, cpu) in &[("ARM9", &header.arm9), ("ARM7", &header.arm7)] {
+        println!(
+            "{}:         offset {:#010X}, size {:#X}, load {:#010X}, entry {:#010X}",
+            name, cpu.rom_offset, cpu.size, cpu.load_address, cpu.entry_address
+        );
+    }
+
+    if let Some(banner) = banner {
+        println!("Banner:       version {:#X}", banner.version);
+
+        for title in &banner.titles {
+            println!("  {}", title.replace('\n', " / "));
+        }
+    }
+
+    if !overlays.is_empty() {
+        println!("Overlays:");
+
+        for (cpu, overlay) in &overlays {
+            println!(
+                "  {} {:>3}: file {}, address {:#010X}, size {:#X}, bss {:#X}{}",
+                cpu,
+                overlay.id,
+                overlay.file_id,
+                overlay.ram_address,
+                overlay.ram_size,
+                overlay.bss_size,
+                if overlay.is_compressed() { ", compressed" } else { "" }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn ls(rom: &Rom, json: bool) {
+    let entries = rom.file_system().walk().skip(1);
+
+    if json {
+        let value = entries
+            .map(|entry| match entry {
+                Entry::File(file) => json!({
+                    "type": "file",
+                    "path": file.path,
+                    "id": file.id,
+                    "offset": file.alloc.start,
+                    "size": file.alloc.len(),
+                }),
+                Entry::Dir(dir) => json!({
+                    "type": "dir",
+                    "path": dir.path,
+                }),
+            })
+            .collect::<Value>();
+
+        println!("{}", value);
+
+        return;
+    }
+
+    for entry in entries {
+        let depth = entry.path().components().count() - 1;
+        let name = entry.path().file_name().unwrap_or_default().to_string_lossy();
+
+        match entry {
+            Entry::File(file) => println!(
+                "{:indent$}{}  (id {}, offset {:#X}, {} bytes)",
+                "",
+                name,
+                file.id,
+                file.alloc.start,
+                file.alloc.len(),
+                indent = depth * 2
+            ),
+            Entry::Dir(_) => println!("{:indent$}{}/", "", name, indent = depth * 2),
+        }
+    }
+}
+
+fn verify(rom: &Rom, json: bool) -> Result<i32> {
+    let checksums = rom.checksums()?;
+    let valid = checksums.iter().all(|checksum| checksum.is_valid());
+
+    if json {
+        let value = json!({
+            "valid": valid,
+            "checksums": checksums.iter().map(|checksum| json!({
+                "name": checksum.name,
+                "stored": checksum.stored,
+                "computed": checksum.computed,
+                "valid": checksum.is_valid(),
+            })).collect::<Vec<_>>(),
+        });
+
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        for checksum in &checksums {
+            if checksum.is_valid() {
+                println!("{:<18} OK   {:#06X}", checksum.name, checksum.stored);
+            } else {
+                println!(
+                    "{:<18} BAD  {:#06X}, expected {:#06X}",
+                    checksum.name, checksum.stored, checksum.computed
+                );
+            }
+        }
+    }
+
+    Ok(if valid { 0 } else { EXIT_INVALID })
+}
@@ -0,0 +1,488 @@
+//! The `rom.toml` manifest written next to the files of an extracted ROM.
+//!
+//! It describes everything that isn't a file of its own: the fields of the
+//! header, the overlay tables, where each file was placed and how the
+//! sections were spaced out. A [`Builder`] reads it back, so the title or
+//! the overlays can be changed by editing text instead of `header.bin`.
+//!
+//! [`Builder`]: ../struct.Builder.html
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::fat::AllocInfo;
+use nitro_fs::FileSystem;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+
+use crate::banner::Banner;
+use crate::build::Spacing;
+use crate::header::{banner_len, Header};
+use crate::overlay::Overlay;
+use crate::parser::{Cpu, NDSParser};
+use crate::util::hash::{from_hex, to_hex};
+
+/// Name of the manifest at the root of an extracted ROM.
+pub(crate) const MANIFEST_NAME: &str = "rom.toml";
+
+/// The largest alignment that is looked for when extracting, which is the
+/// one `ndstool` uses.
+const MAX_ALIGNMENT: u32 = 0x200;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("Manifest field '{field}' must be at most {max} ASCII characters.")]
+    TextTooLong { field: &'static str, max: usize },
+
+    #[error("Manifest field '{0}' is not valid hex of the right length.")]
+    InvalidHex(&'static str),
+
+    #[error("Alignment must be a power of two: {0:#X}")]
+    InvalidAlignment(u32),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub layout: Layout,
+    pub header: HeaderFields,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<BannerFields>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arm9_overlays: Vec<OverlayFields>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arm7_overlays: Vec<OverlayFields>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileFields>,
+}
+
+/// How sections are spaced out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Layout {
+    /// What every section starts on a multiple of.
+    pub alignment: u32,
+    /// The byte that fills the gaps between sections.
+    pub padding: u8,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            alignment: MAX_ALIGNMENT,
+            padding: 0,
+        }
+    }
+}
+
+/// Every field of [`NDSParser`], with arrays written as hex.
+///
+/// Offsets, sizes and checksums are recomputed when building, so changing
+/// them has no effect.
+///
+/// [`NDSParser`]: ../parser/struct.NDSParser.html
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct HeaderFields {
+    pub game_title: String,
+    pub gamecode: String,
+    pub makercode: String,
+    pub unitcode: u8,
+    pub encryption_seed_select: u8,
+    pub devicecapacity: u8,
+    pub game_revision: u16,
+    pub rom_version: u8,
+    pub internal_flags: u8,
+    pub normal_card_control_register_settings: u32,
+    pub secure_card_control_register_settings: u32,
+    pub icon_banner_offset: u32,
+    pub secure_area: u16,
+    pub secure_transfer_timeout: u16,
+    pub secure_diable: String,
+    pub ntr_region_rom_size: u32,
+    pub header_size: u32,
+    pub nintendo_logo: String,
+    pub nintendo_logo_crc: u16,
+    pub header_crc: u16,
+    pub debugger: String,
+    //  Tables have to come after plain values in TOML.
+    pub arm9: CpuFields,
+    pub arm7: CpuFields,
+    pub fnt: TableFields,
+    pub fat: TableFields,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CpuFields {
+    pub rom_offset: u32,
+    pub entry_address: u32,
+    pub load_address: u32,
+    pub size: u32,
+    pub overlay_offset: u32,
+    pub overlay_length: u32,
+    pub autoload: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TableFields {
+    pub offset: u32,
+    pub length: u32,
+}
+
+/// The parts of the banner that are text. The icon stays in `banner.bin`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BannerFields {
+    pub version: u16,
+    pub titles: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OverlayFields {
+    pub id: u32,
+    pub ram_address: u32,
+    pub ram_size: u32,
+    pub bss_size: u32,
+    pub static_init_start: u32,
+    pub static_init_end: u32,
+    pub file_id: u32,
+    pub flags: u32,
+}
+
+/// A file of the file system and where it was placed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FileFields {
+    pub id: u16,
+    /// The path in the file system, separated by `/`.
+    pub path: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl Manifest {
+    /// Describes a ROM and its parsed file system.
+    pub fn from_rom(data: &[u8], fs: &FileSystem) -> Result<Self> {
+        let parser = NDSParser::try_from(data)?;
+
+        let overlays = |cpu: &Cpu| -> Result<Vec<OverlayFields>> {
+            let start = cpu.overlay_offset as usize;
+            let table = data.get(start..start + cpu.overlay_length as usize).unwrap_or_default();
+
+            Ok(Overlay::parse_table(table)?.into_iter().map(OverlayFields::from).collect())
+        };
+
+        let arm9_overlays = overlays(&parser.arm9)?;
+        let arm7_overlays = overlays(&parser.arm7)?;
+
+        let banner = match parser.icon_banner_offset as usize {
+            0 => None,
+            offset => data
+                .get(offset..offset + 2)
+                .map(LittleEndian::read_u16)
+                .and_then(|version| data.get(offset..offset + banner_len(version)))
+                .and_then(|banner| Banner::parse(banner).ok())
+                .map(|banner| BannerFields {
+                    version: banner.version,
+                    titles: banner.titles,
+                }),
+        };
+
+        let mut files = fs
+            .files()
+            .into_iter()
+            .map(|file| FileFields {
+                id: file.id,
+                path: to_manifest_path(&file.path),
+                offset: file.alloc.start,
+                size: file.alloc.len(),
+            })
+            .collect::<Vec<_>>();
+
+        files.sort_by_key(|file| file.id);
+
+        //  Every section, to find out how they were spaced out.
+        let mut sections = vec![
+            alloc(parser.arm9.rom_offset, parser.arm9.size),
+            alloc(parser.arm7.rom_offset, parser.arm7.size),
+            alloc(parser.arm9.overlay_offset, parser.arm9.overlay_length),
+            alloc(parser.arm7.overlay_offset, parser.arm7.overlay_length),
+            alloc(parser.fnt.offset, parser.fnt.length),
+            alloc(parser.fat.offset, parser.fat.length),
+        ];
+
+        if let Some(banner) = &banner {
+            sections.push(alloc(parser.icon_banner_offset, banner_len(banner.version) as u32));
+        }
+
+        sections.extend(fs.overlays().iter().map(|file| file.alloc));
+        sections.extend(fs.files().iter().map(|file| file.alloc));
+
+        Ok(Self {
+            layout: Layout::detect(data, sections),
+            header: HeaderFields::from(&parser),
+            banner,
+            arm9_overlays,
+            arm7_overlays,
+            files,
+        })
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let manifest: Self = toml::from_str(&read_to_string(path)?)?;
+
+        ensure!(
+            manifest.layout.alignment.is_power_of_two(),
+            ManifestError::InvalidAlignment(manifest.layout.alignment)
+        );
+
+        Ok(manifest)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write(path, toml::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn spacing(&self) -> Spacing {
+        Spacing {
+            alignment: self.layout.alignment as usize,
+            padding: self.layout.padding,
+        }
+    }
+
+    /// Writes every field that differs from `header` into it. Fields that
+    /// match are left alone, so unusual padding in the original header
+    /// survives.
+    pub fn apply_header(&self, header: &mut [u8]) -> Result<()> {
+        let current = HeaderFields::from(&NDSParser::try_from(&*header)?);
+        let new = &self.header;
+
+        let mut text = |field: &'static str, offset: Header, max: usize, old: &str, value: &str| {
+            if old != value {
+                ensure!(value.is_ascii() && value.len() <= max, ManifestError::TextTooLong { field, max });
+
+                let offset = offset as usize;
+                let target = &mut header[offset..offset + max];
+
+                target.iter_mut().for_each(|byte| *byte = 0);
+                target[..value.len()].copy_from_slice(value.as_bytes());
+            }
+
+            Ok(())
+        };
+
+        text("game_title", Header::Title, 12, &current.game_title, &new.game_title)?;
+        text("gamecode", Header::Gamecode, 4, &current.gamecode, &new.gamecode)?;
+        text("makercode", Header::Makercode, 2, &current.makercode, &new.makercode)?;
+
+        header[Header::UnitCode as usize] = new.unitcode;
+        header[Header::EncryptionSeed as usize] = new.encryption_seed_select;
+        header[Header::DeviceCapacity as usize] = new.devicecapacity;
+        header[Header::RomVersion as usize] = new.rom_version;
+        header[Header::InternalFlags as usize] = new.internal_flags;
+
+        for (offset, value) in &[
+            (Header::Arm9Entry, new.arm9.entry_address),
+            (Header::Arm9Load, new.arm9.load_address),
+            (Header::Arm7Entry, new.arm7.entry_address),
+            (Header::Arm7Load, new.arm7.load_address),
+            (Header::NormalCardControl, new.normal_card_control_register_settings),
+            (Header::SecureCardControl, new.secure_card_control_register_settings),
+            (Header::Arm9Autoload, new.arm9.autoload),
+            (Header::Arm7Autoload, new.arm7.autoload),
+        ] {
+            LittleEndian::write_u32(&mut header[*offset as usize..], *value);
+        }
+
+        for (offset, value) in &[
+            (Header::Revision, new.game_revision),
+            (Header::SecureAreaCrc, new.secure_area),
+            (Header::SecureTransferTimeout, new.secure_transfer_timeout),
+            (Header::LogoCrc, new.nintendo_logo_crc),
+        ] {
+            LittleEndian::write_u16(&mut header[*offset as usize..], *value);
+        }
+
+        let secure_disable = from_hex::<8>(&new.secure_diable).ok_or(ManifestError::InvalidHex("secure_diable"))?;
+        let logo = from_hex::<156>(&new.nintendo_logo).ok_or(ManifestError::InvalidHex("nintendo_logo"))?;
+        let debugger = from_hex::<32>(&new.debugger).ok_or(ManifestError::InvalidHex("debugger"))?;
+
+        for (offset, value) in &[
+            (Header::SecureDisable, &secure_disable[..]),
+            (Header::Logo, &logo[..]),
+            (Header::Debugger, &debugger[..]),
+        ] {
+            let offset = *offset as usize;
+            header[offset..offset + value.len()].copy_from_slice(value);
+        }
+
+        Ok(())
+    }
+
+    /// Puts the banner titles into `banner` if they were changed.
+    pub fn apply_banner(&self, banner: Vec<u8>) -> Result<Vec<u8>> {
+        let fields = match &self.banner {
+            Some(fields) => fields,
+            None => return Ok(banner),
+        };
+
+        let mut parsed = Banner::parse(&banner)?;
+
+        if parsed.version == fields.version && parsed.titles == fields.titles {
+            return Ok(banner);
+        }
+
+        parsed.version = fields.version;
+        parsed.titles = fields.titles.clone();
+
+        Ok(parsed.to_bytes())
+    }
+
+    /// The raw overlay table of one of the processors.
+    pub fn overlay_table(&self, arm9: bool) -> Result<Vec<u8>> {
+        let entries = if arm9 { &self.arm9_overlays } else { &self.arm7_overlays };
+        let overlays = entries.iter().copied().map(Overlay::from).collect::<Vec<_>>();
+
+        Overlay::write_table(&overlays)
+    }
+
+    /// Where each file was placed, which decides the order they are placed
+    /// in again.
+    pub fn file_order(&self) -> BTreeMap<PathBuf, u32> {
+        self.files
+            .iter()
+            .map(|file| (file.path.split('/').collect(), file.offset))
+            .collect()
+    }
+
+    /// The ID of each file, which decides the order of the files in the FNT
+    /// and the FAT.
+    pub fn file_ids(&self) -> BTreeMap<PathBuf, u16> {
+        self.files
+            .iter()
+            .map(|file| (file.path.split('/').collect(), file.id))
+            .collect()
+    }
+}
+
+impl Layout {
+    /// Finds the alignment of the sections and the byte in the first gap
+    /// between two of them.
+    fn detect(data: &[u8], mut sections: Vec<AllocInfo>) -> Self {
+        sections.retain(|alloc| alloc.end > alloc.start);
+        sections.sort_by_key(|alloc| alloc.start);
+
+        let mut alignment = MAX_ALIGNMENT;
+
+        while alignment > 1 && sections.iter().any(|alloc| alloc.start % alignment != 0) {
+            alignment /= 2;
+        }
+
+        let padding = sections
+            .windows(2)
+            .find(|pair| pair[0].end < pair[1].start)
+            .and_then(|pair| data.get(pair[0].end as usize).copied())
+            .unwrap_or(0);
+
+        Self { alignment, padding }
+    }
+}
+
+impl From<&NDSParser> for HeaderFields {
+    fn from(parser: &NDSParser) -> Self {
+        Self {
+            game_title: parser.game_title.clone(),
+            gamecode: parser.gamecode.clone(),
+            makercode: parser.makercode.clone(),
+            unitcode: parser.unitcode,
+            encryption_seed_select: parser.encryption_seed_select,
+            devicecapacity: parser.devicecapacity,
+            game_revision: parser.game_revision,
+            rom_version: parser.rom_version,
+            internal_flags: parser.internal_flags,
+            normal_card_control_register_settings: parser.normal_card_control_register_settings,
+            secure_card_control_register_settings: parser.secure_card_control_register_settings,
+            icon_banner_offset: parser.icon_banner_offset,
+            secure_area: parser.secure_area,
+            secure_transfer_timeout: parser.secure_transfer_timeout,
+            secure_diable: to_hex(&parser.secure_diable.to_le_bytes()),
+            ntr_region_rom_size: parser.ntr_region_rom_size,
+            header_size: parser.header_size,
+            nintendo_logo: to_hex(&parser.nintendo_logo),
+            nintendo_logo_crc: parser.nintendo_logo_crc,
+            header_crc: parser.header_crc,
+            debugger: to_hex(&parser.debugger),
+            arm9: CpuFields::from(&parser.arm9),
+            arm7: CpuFields::from(&parser.arm7),
+            fnt: TableFields {
+                offset: parser.fnt.offset,
+                length: parser.fnt.length,
+            },
+            fat: TableFields {
+                offset: parser.fat.offset,
+                length: parser.fat.length,
+            },
+        }
+    }
+}
+
+impl From<&Cpu> for CpuFields {
+    fn from(cpu: &Cpu) -> Self {
+        Self {
+            rom_offset: cpu.rom_offset,
+            entry_address: cpu.entry_address,
+            load_address: cpu.load_address,
+            size: cpu.size,
+            overlay_offset: cpu.overlay_offset,
+            overlay_length: cpu.overlay_length,
+            autoload: cpu.autoload,
+        }
+    }
+}
+
+impl From<Overlay> for OverlayFields {
+    fn from(overlay: Overlay) -> Self {
+        Self {
+            id: overlay.id,
+            ram_address: overlay.ram_address,
+            ram_size: overlay.ram_size,
+            bss_size: overlay.bss_size,
+            static_init_start: overlay.static_init_start,
+            static_init_end: overlay.static_init_end,
+            file_id: overlay.file_id,
+            flags: overlay.flags,
+        }
+    }
+}
+
+impl From<OverlayFields> for Overlay {
+    fn from(fields: OverlayFields) -> Self {
+        Self {
+            id: fields.id,
+            ram_address: fields.ram_address,
+            ram_size: fields.ram_size,
+            bss_size: fields.bss_size,
+            static_init_start: fields.static_init_start,
+            static_init_end: fields.static_init_end,
+            file_id: fields.file_id,
+            flags: fields.flags,
+        }
+    }
+}
+
+fn alloc(start: u32, len: u32) -> AllocInfo {
+    AllocInfo {
+        start,
+        end: start.saturating_add(len),
+    }
+}
+
+/// Joins the components of a path with `/`, whatever the platform.
+fn to_manifest_path(path: &Path) -> String {
+    path.iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
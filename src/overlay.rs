@@ -0,0 +1,104 @@
+//! Overlay tables, which describe the code that the ARM9 and ARM7 can load
+//! into memory on demand.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use std::fmt;
+use std::io::Cursor;
+
+use anyhow::{ensure, Result};
+
+use crate::header::OVERLAY_ENTRY_LEN;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum OverlayError {
+    #[error("Overlay table size is not a multiple of the entry size.")]
+    InvalidTableLen,
+}
+
+/// One of the two processors of the DS.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Processor {
+    Arm9,
+    Arm7,
+}
+
+impl fmt::Display for Processor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Processor::Arm9 => f.write_str("arm9"),
+            Processor::Arm7 => f.write_str("arm7"),
+        }
+    }
+}
+
+/// An entry in an overlay table.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Overlay {
+    pub id: u32,
+    pub ram_address: u32,
+    pub ram_size: u32,
+    pub bss_size: u32,
+    pub static_init_start: u32,
+    pub static_init_end: u32,
+    /// The ID of the file in the FAT that holds the overlay's code.
+    pub file_id: u32,
+    /// Flags, including whether the overlay is compressed, in the top byte
+    /// and the compressed size in the lower 24 bits.
+    pub flags: u32,
+}
+
+impl Overlay {
+    /// Reads every entry of a raw overlay table.
+    pub fn parse_table(table: &[u8]) -> Result<Vec<Self>> {
+        ensure!(table.len() % OVERLAY_ENTRY_LEN == 0, OverlayError::InvalidTableLen);
+
+        let mut cursor = Cursor::new(table);
+        let mut overlays = Vec::with_capacity(table.len() / OVERLAY_ENTRY_LEN);
+
+        for _ in 0..table.len() / OVERLAY_ENTRY_LEN {
+            overlays.push(Self {
+                id: cursor.read_u32::<LittleEndian>()?,
+                ram_address: cursor.read_u32::<LittleEndian>()?,
+                ram_size: cursor.read_u32::<LittleEndian>()?,
+                bss_size: cursor.read_u32::<LittleEndian>()?,
+                static_init_start: cursor.read_u32::<LittleEndian>()?,
+                static_init_end: cursor.read_u32::<LittleEndian>()?,
+                file_id: cursor.read_u32::<LittleEndian>()?,
+                flags: cursor.read_u32::<LittleEndian>()?,
+            });
+        }
+
+        Ok(overlays)
+    }
+
+    /// Writes entries back into a raw overlay table.
+    pub fn write_table(overlays: &[Self]) -> Result<Vec<u8>> {
+        let mut table = Vec::with_capacity(overlays.len() * OVERLAY_ENTRY_LEN);
+
+        for overlay in overlays {
+            for value in &[
+                overlay.id,
+                overlay.ram_address,
+                overlay.ram_size,
+                overlay.bss_size,
+                overlay.static_init_start,
+                overlay.static_init_end,
+                overlay.file_id,
+                overlay.flags,
+            ] {
+                table.write_u32::<LittleEndian>(*value)?;
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Whether the overlay's code is compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.flags & 0x0100_0000 != 0
+    }
+}
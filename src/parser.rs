@@ -8,13 +8,14 @@
 //! Here's an example how you might want to use it (example taken from an
 //! example of the [`NDSParser`] struct):
 //!
-//! ```
-//! use nds::NDSParser;
+//! ```no_run
+//! use nds::parser::NDSParser;
+//! use std::convert::TryFrom;
 //!
 //! fn main() {
 //!     let nds_parser = match NDSParser::try_from("path/to/some.nds") {
 //!         Ok(parsed) => parsed,
-//!         Err(err) => println!("Houston, we've got a problem: {}", err),
+//!         Err(err) => panic!("Houston, we've got a problem: {}", err),
 //!     };
 //! }
 //! ```
@@ -45,7 +46,8 @@ pub enum NDSParserError {
 ///
 /// [`arm7`]: struct.NDSParser.html#structfield.arm7
 /// [`arm9`]: struct.NDSParser.html#structfield.arm9
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cpu {
     pub rom_offset:    u32,
     pub entry_address: u32,
@@ -58,20 +60,6 @@ pub struct Cpu {
     pub autoload: u32,
 }
 
-impl Default for Cpu {
-    fn default() -> Self {
-        Self {
-            rom_offset:     0,
-            entry_address:  0,
-            load_address:   0,
-            size:           0,
-            overlay_offset: 0,
-            overlay_length: 0,
-            autoload:       0,
-        }
-    }
-}
-
 // -- Table --
 /// Returns the relevant data for the FNT (**F**ile **N**ame **T**able) and FAT
 /// (**F**ile **A**llocation **T**able). It should just help to avoid duplicated
@@ -79,23 +67,16 @@ impl Default for Cpu {
 ///
 /// [`fnt`]: struct.NDSParser.html#structfield.fnt
 /// [`fat`]: struct.NDSParser.html#structfield.fat
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Table {
     pub offset: u32,
     pub length: u32,
 }
 
-impl Default for Table {
-    fn default() -> Self {
-        Self {
-            offset: 0,
-            length: 0,
-        }
-    }
-}
-
 // -- NDS Parser --
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Let you parse a `.nds` file and stores the values of it. For example the
 /// game title. Each value is taken from [this table].
 ///
@@ -125,22 +106,25 @@ pub struct NDSParser {
     pub secure_diable:       u64,
     pub ntr_region_rom_size: u32,
     pub header_size:         u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::hex"))]
     pub nintendo_logo:       [u8; 156],
     pub nintendo_logo_crc:   u16,
     pub header_crc:          u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::util::hex"))]
     pub debugger:            [u8; 32],
 }
 
 /// Reads the ROM file from a given path and stores each value in to the struct.
 ///
 /// # Example
-/// ```
-/// use nds::NDSParser;
+/// ```no_run
+/// use nds::parser::NDSParser;
+/// use std::convert::TryFrom;
 ///
 /// fn main() {
 ///     let nds_parser = match NDSParser::try_from("path/to/some.nds") {
 ///         Ok(parsed) => parsed,
-///         Err(err) => println!("Houston, we've got a problem: {}", err),
+///         Err(err) => panic!("Houston, we've got a problem: {}", err),
 ///     };
 /// }
 /// ```
@@ -169,8 +153,10 @@ impl TryFrom<&str> for NDSParser {
 /// trait implementation.
 ///
 /// ```no_run
-/// use nds::NDSParser;
+/// use nds::parser::NDSParser;
+/// use std::convert::TryFrom;
 /// use std::fs::File;
+/// use std::io::Read;
 ///
 /// fn main() {
 ///     let mut file = File::open("some.nds").unwrap();
@@ -192,6 +178,16 @@ impl TryFrom<&Vec<u8>> for NDSParser {
     type Error = NDSParserError;
 
     fn try_from(content: &Vec<u8>) -> Result<Self, Self::Error> {
+        NDSParser::try_from(content.as_slice())
+    }
+}
+
+/// Same as the `TryFrom<&Vec<u8>>` implementation, but for any byte slice,
+/// such as a memory mapped ROM.
+impl TryFrom<&[u8]> for NDSParser {
+    type Error = NDSParserError;
+
+    fn try_from(content: &[u8]) -> Result<Self, Self::Error> {
         // Make sure that the byte-vector includes enough information
         if content.len() < 0x181 {
             return Err(NDSParserError::NotEnoughData);
@@ -201,14 +197,17 @@ impl TryFrom<&Vec<u8>> for NDSParser {
         // https://dsibrew.org/wiki/DSi_Cartridge_Header
         //
         // We are trimming the strings because it might happen that some titles don't fully use the
-        // given 12 bytes
+        // given 12 bytes, in which case the rest is padded with null bytes
         let game_title = String::from_utf8(content[0..0xc].to_vec())?
+            .trim_end_matches(char::from(0))
             .trim()
             .to_string();
         let gamecode = String::from_utf8(content[0xc..0x10].to_vec())?
+            .trim_end_matches(char::from(0))
             .trim()
             .to_string();
         let makercode = String::from_utf8(content[0x10..0x12].to_vec())?
+            .trim_end_matches(char::from(0))
             .trim()
             .to_string();
         let unitcode = u8::from_ne_bytes(content[0x12..0x13].try_into().unwrap());
@@ -0,0 +1,233 @@
+//! Binary Patching System patches.
+//!
+//! The target is described by a list of actions: copy from the source at
+//! the same offset, insert literal bytes from the patch, or copy from any
+//! offset of the source or of the target written so far.
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, Result};
+
+use super::{check_footer, read_vlq, write_footer, write_vlq, PatchError};
+use crate::util::crc::crc32;
+
+pub(super) const MAGIC: &[u8] = b"BPS1";
+
+const SOURCE_READ: u64 = 0;
+const TARGET_READ: u64 = 1;
+const SOURCE_COPY: u64 = 2;
+const TARGET_COPY: u64 = 3;
+
+/// Length of the blocks that are hashed to find data that was copied or
+/// moved. Only every block-aligned position of the source is indexed, which
+/// keeps memory use low for large ROMs while still finding any copy that is
+/// at least twice this long.
+const BLOCK: usize = 16;
+
+/// Matches shorter than this are cheaper to store as literal bytes.
+const MIN_READ: usize = 4;
+const MIN_COPY: usize = 8;
+
+pub(super) fn create(source: &[u8], target: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = Encoder {
+        output: MAGIC.to_vec(),
+        source_offset: 0,
+        target_offset: 0,
+    };
+
+    write_vlq(&mut encoder.output, source.len() as u64);
+    write_vlq(&mut encoder.output, target.len() as u64);
+    //  No metadata.
+    write_vlq(&mut encoder.output, 0);
+
+    let mut source_index = HashMap::new();
+
+    for start in (0..source.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        source_index.entry(hash(&source[start..start + BLOCK])).or_insert(start);
+    }
+
+    let mut target_index = HashMap::new();
+    let mut indexed = 0;
+    let mut literal_start = 0;
+    let mut pos = 0;
+
+    while pos < target.len() {
+        //  Only data that is already written can be copied from the target.
+        while indexed + BLOCK <= pos {
+            target_index.entry(hash(&target[indexed..indexed + BLOCK])).or_insert(indexed);
+            indexed += BLOCK;
+        }
+
+        let mut best = (SOURCE_READ, match_len(source, pos, target, pos), pos);
+
+        if pos + BLOCK <= target.len() {
+            let block = hash(&target[pos..pos + BLOCK]);
+
+            if let Some(&from) = source_index.get(&block) {
+                let len = match_len(source, from, target, pos);
+
+                if len > best.1 {
+                    best = (SOURCE_COPY, len, from);
+                }
+            }
+
+            if let Some(&from) = target_index.get(&block) {
+                let len = match_len(target, from, target, pos);
+
+                if len > best.1 {
+                    best = (TARGET_COPY, len, from);
+                }
+            }
+        }
+
+        let (action, mut len, mut from) = best;
+        let min = if action == SOURCE_READ { MIN_READ } else { MIN_COPY };
+
+        if len < min {
+            pos += 1;
+            continue;
+        }
+
+        //  Grow the match backwards into bytes that would be literals.
+        let data = if action == TARGET_COPY { target } else { source };
+
+        while pos > literal_start && from > 0 && data[from - 1] == target[pos - 1] {
+            pos -= 1;
+            from -= 1;
+            len += 1;
+        }
+
+        encoder.literal(&target[literal_start..pos]);
+        encoder.action(action, len, from);
+
+        pos += len;
+        literal_start = pos;
+    }
+
+    encoder.literal(&target[literal_start..]);
+
+    let mut output = encoder.output;
+    write_footer(&mut output, source, target);
+
+    Ok(output)
+}
+
+struct Encoder {
+    output: Vec<u8>,
+    /// Where the last source copy ended.
+    source_offset: usize,
+    /// Where the last target copy ended.
+    target_offset: usize,
+}
+
+impl Encoder {
+    fn literal(&mut self, data: &[u8]) {
+        if !data.is_empty() {
+            write_vlq(&mut self.output, ((data.len() as u64 - 1) << 2) | TARGET_READ);
+            self.output.extend_from_slice(data);
+        }
+    }
+
+    fn action(&mut self, action: u64, len: usize, from: usize) {
+        write_vlq(&mut self.output, ((len as u64 - 1) << 2) | action);
+
+        let offset = match action {
+            SOURCE_COPY => &mut self.source_offset,
+            TARGET_COPY => &mut self.target_offset,
+            _ => return,
+        };
+
+        let delta = from as i64 - *offset as i64;
+        write_vlq(&mut self.output, (delta.unsigned_abs() << 1) | (delta < 0) as u64);
+
+        *offset = from + len;
+    }
+}
+
+/// A 64-bit FNV-1a hash of a block.
+fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// How many bytes match between `data` from `from` and `target` from `pos`.
+fn match_len(data: &[u8], from: usize, target: &[u8], pos: usize) -> usize {
+    if from >= data.len() {
+        return 0;
+    }
+
+    data[from..]
+        .iter()
+        .zip(&target[pos..])
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+pub(super) fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let expected = check_footer(patch, source)?;
+    let body = &patch[..patch.len() - 12];
+    let mut pos = MAGIC.len();
+
+    let source_len = read_vlq(body, &mut pos)? as usize;
+    let target_len = read_vlq(body, &mut pos)? as usize;
+    let metadata_len = read_vlq(body, &mut pos)? as usize;
+
+    ensure!(source.len() == source_len, PatchError::SourceMismatch);
+
+    pos = pos.checked_add(metadata_len).ok_or(PatchError::OutOfBounds)?;
+
+    let mut output: Vec<u8> = Vec::with_capacity(target_len);
+    let mut source_offset: i64 = 0;
+    let mut target_offset: i64 = 0;
+
+    while pos < body.len() {
+        let data = read_vlq(body, &mut pos)?;
+        let len = (data >> 2) as usize + 1;
+
+        ensure!(output.len() + len <= target_len, PatchError::OutOfBounds);
+
+        match data & 3 {
+            SOURCE_READ => {
+                let start = output.len();
+
+                ensure!(source.len() >= start + len, PatchError::OutOfBounds);
+                output.extend_from_slice(&source[start..start + len]);
+            }
+            TARGET_READ => {
+                ensure!(body.len() >= pos + len, PatchError::NotEnoughData);
+                output.extend_from_slice(&body[pos..pos + len]);
+                pos += len;
+            }
+            action => {
+                let delta = read_vlq(body, &mut pos)?;
+                let delta = if delta & 1 != 0 { -((delta >> 1) as i64) } else { (delta >> 1) as i64 };
+                let offset = if action == SOURCE_COPY { &mut source_offset } else { &mut target_offset };
+
+                *offset += delta;
+                ensure!(*offset >= 0, PatchError::OutOfBounds);
+
+                let start = *offset as usize;
+
+                if action == SOURCE_COPY {
+                    ensure!(source.len() >= start + len, PatchError::OutOfBounds);
+                    output.extend_from_slice(&source[start..start + len]);
+                } else {
+                    //  The copy may overlap with what it writes, so copy byte by byte.
+                    ensure!(start < output.len(), PatchError::OutOfBounds);
+
+                    for index in start..start + len {
+                        output.push(output[index]);
+                    }
+                }
+
+                *offset += len as i64;
+            }
+        }
+    }
+
+    ensure!(output.len() == target_len, PatchError::TargetMismatch);
+    ensure!(crc32(&output) == expected, PatchError::TargetMismatch);
+
+    Ok(output)
+}
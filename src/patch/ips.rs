@@ -0,0 +1,181 @@
+//! International Patching System patches.
+//!
+//! A patch is a list of records that each write bytes at a 24-bit offset,
+//! either as given or as a run of one repeated byte. A 24-bit size after the
+//! `EOF` marker truncates the output.
+
+use byteorder::{BigEndian, ByteOrder};
+
+use anyhow::{ensure, Result};
+
+use super::PatchError;
+
+pub(super) const MAGIC: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+
+/// The first offset that can't be written in 24 bits.
+const MAX_OFFSET: usize = 0x100_0000;
+
+/// Record offsets can't be this value, since it reads as `EOF`.
+const EOF_OFFSET: usize = 0x45_4F46;
+
+const MAX_RECORD: usize = 0xFFFF;
+
+/// Equal bytes shorter than a record header are cheaper to include in the
+/// record around them than to split it.
+const MERGE_GAP: usize = 5;
+
+/// The shortest run that gets its own RLE record.
+const MIN_RUN: usize = 9;
+
+pub(super) fn create(source: &[u8], target: &[u8]) -> Result<Vec<u8>> {
+    let mut output = MAGIC.to_vec();
+    let differs = |pos: usize| pos >= source.len() || source[pos] != target[pos];
+    let mut pos = 0;
+
+    while pos < target.len() {
+        if !differs(pos) {
+            pos += 1;
+            continue;
+        }
+
+        //  Find the end of the changed region, skipping short equal gaps.
+        let mut end = pos + 1;
+
+        loop {
+            while end < target.len() && differs(end) {
+                end += 1;
+            }
+
+            let gap = (end..target.len().min(end + MERGE_GAP)).take_while(|&pos| !differs(pos)).count();
+
+            if gap < MERGE_GAP && end + gap < target.len() {
+                end += gap;
+            } else {
+                break;
+            }
+        }
+
+        write_records(&mut output, target, pos, end)?;
+        pos = end;
+    }
+
+    output.extend_from_slice(FOOTER);
+
+    if target.len() < source.len() {
+        ensure!(target.len() < MAX_OFFSET, PatchError::TooLargeForIps);
+
+        let mut size = [0; 3];
+        BigEndian::write_u24(&mut size, target.len() as u32);
+        output.extend_from_slice(&size);
+    }
+
+    Ok(output)
+}
+
+/// Writes records for `target[start..end]`, using RLE records for long runs.
+fn write_records(output: &mut Vec<u8>, target: &[u8], start: usize, end: usize) -> Result<()> {
+    let mut pos = start;
+
+    while pos < end {
+        let run = target[pos..end]
+            .iter()
+            .take(MAX_RECORD)
+            .take_while(|&&byte| byte == target[pos])
+            .count();
+
+        if run >= MIN_RUN && pos != EOF_OFFSET {
+            write_offset(output, pos)?;
+            output.extend_from_slice(&[0, 0]);
+            output.extend_from_slice(&(run as u16).to_be_bytes());
+            output.push(target[pos]);
+            pos += run;
+            continue;
+        }
+
+        //  A record can't start at the offset that reads as "EOF", so start
+        //  one byte earlier. The byte before is already correct in the target.
+        let start = if pos == EOF_OFFSET { pos - 1 } else { pos };
+        let mut len = pos + 1 - start;
+
+        //  Stop a literal record where the next long run begins.
+        while start + len < end && len < MAX_RECORD {
+            let next = start + len;
+            let run = target[next..end].iter().take(MIN_RUN).take_while(|&&byte| byte == target[next]).count();
+
+            if run >= MIN_RUN {
+                break;
+            }
+
+            len += 1;
+        }
+
+        write_offset(output, start)?;
+        output.extend_from_slice(&(len as u16).to_be_bytes());
+        output.extend_from_slice(&target[start..start + len]);
+        pos = start + len;
+    }
+
+    Ok(())
+}
+
+fn write_offset(output: &mut Vec<u8>, offset: usize) -> Result<()> {
+    ensure!(offset < MAX_OFFSET, PatchError::TooLargeForIps);
+
+    let mut bytes = [0; 3];
+    BigEndian::write_u24(&mut bytes, offset as u32);
+    output.extend_from_slice(&bytes);
+
+    Ok(())
+}
+
+pub(super) fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let mut output = source.to_vec();
+    let mut pos = MAGIC.len();
+
+    loop {
+        ensure!(patch.len() >= pos + 3, PatchError::NotEnoughData);
+
+        if &patch[pos..pos + 3] == FOOTER {
+            pos += 3;
+            break;
+        }
+
+        ensure!(patch.len() >= pos + 5, PatchError::NotEnoughData);
+
+        let offset = BigEndian::read_u24(&patch[pos..]) as usize;
+        let len = BigEndian::read_u16(&patch[pos + 3..]) as usize;
+        pos += 5;
+
+        if len == 0 {
+            ensure!(patch.len() >= pos + 3, PatchError::NotEnoughData);
+
+            let run = BigEndian::read_u16(&patch[pos..]) as usize;
+            let value = patch[pos + 2];
+            pos += 3;
+
+            if output.len() < offset + run {
+                output.resize(offset + run, 0);
+            }
+
+            for byte in &mut output[offset..offset + run] {
+                *byte = value;
+            }
+        } else {
+            ensure!(patch.len() >= pos + len, PatchError::NotEnoughData);
+
+            if output.len() < offset + len {
+                output.resize(offset + len, 0);
+            }
+
+            output[offset..offset + len].copy_from_slice(&patch[pos..pos + len]);
+            pos += len;
+        }
+    }
+
+    if patch.len() >= pos + 3 {
+        output.truncate(BigEndian::read_u24(&patch[pos..]) as usize);
+    }
+
+    Ok(output)
+}
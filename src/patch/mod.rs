@@ -0,0 +1,179 @@
+//! Creating and applying IPS, UPS and BPS patches between two ROM images.
+//!
+//! IPS is the simplest format but can't describe changes past 16MiB and has
+//! no checksums. UPS and BPS store the CRC32 of the source, target and patch,
+//! which are checked when a patch is applied. BPS can also copy data from
+//! anywhere in the source or target, so moved files produce small patches.
+//!
+//! # Example
+//! ```no_run
+//! use nds::patch::{apply, create, PatchFormat};
+//! use std::fs::read;
+//!
+//! let original = read("original.nds").unwrap();
+//! let modified = read("modified.nds").unwrap();
+//!
+//! let patch = create(&original, &modified, PatchFormat::Bps).unwrap();
+//!
+//! assert_eq!(apply(&original, &patch).unwrap(), modified);
+//! ```
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use anyhow::{ensure, Result};
+
+use crate::util::crc::crc32;
+
+mod bps;
+mod ips;
+mod ups;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Patch format is not recognized.")]
+    UnknownFormat,
+
+    #[error("IPS patches can't describe changes past 16MiB, use BPS instead.")]
+    TooLargeForIps,
+
+    #[error("Source does not match the one the patch was made for.")]
+    SourceMismatch,
+
+    #[error("Patched data does not match the expected target.")]
+    TargetMismatch,
+
+    #[error("Patch checksum does not match contents.")]
+    InvalidChecksum,
+
+    #[error("Patch reads outside of the source or target.")]
+    OutOfBounds,
+}
+
+/// A patch format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+impl PatchFormat {
+    /// Guesses the format of a patch from its magic number.
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(ips::MAGIC) {
+            Some(PatchFormat::Ips)
+        } else if patch.starts_with(ups::MAGIC) {
+            Some(PatchFormat::Ups)
+        } else if patch.starts_with(bps::MAGIC) {
+            Some(PatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+
+    /// The file extension usually used for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            PatchFormat::Ips => "ips",
+            PatchFormat::Ups => "ups",
+            PatchFormat::Bps => "bps",
+        }
+    }
+}
+
+/// Creates a patch that turns `source` into `target`.
+///
+/// # Errors
+/// IPS patches return an error if anything past 16MiB differs, since the
+/// format has no way to address it.
+pub fn create(source: &[u8], target: &[u8], format: PatchFormat) -> Result<Vec<u8>> {
+    match format {
+        PatchFormat::Ips => ips::create(source, target),
+        PatchFormat::Ups => ups::create(source, target),
+        PatchFormat::Bps => bps::create(source, target),
+    }
+}
+
+/// Applies a patch to `source`, detecting its format from its magic number.
+///
+/// # Errors
+/// For UPS and BPS patches, an error is returned if the checksum of the
+/// source, the patched data or the patch itself doesn't match the one
+/// stored in the patch.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    match PatchFormat::detect(patch).ok_or(PatchError::UnknownFormat)? {
+        PatchFormat::Ips => ips::apply(source, patch),
+        PatchFormat::Ups => ups::apply(source, patch),
+        PatchFormat::Bps => bps::apply(source, patch),
+    }
+}
+
+/// Writes a number in the variable length encoding shared by UPS and BPS.
+fn write_vlq(output: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            output.push(0x80 | byte);
+            break;
+        }
+
+        output.push(byte);
+        value -= 1;
+    }
+}
+
+/// Reads a number written by [`write_vlq`], advancing `pos`.
+///
+/// [`write_vlq`]: fn.write_vlq.html
+fn read_vlq(data: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value: u64 = 0;
+    let mut shift: u64 = 1;
+
+    loop {
+        let byte = *data.get(*pos).ok_or(PatchError::NotEnoughData)?;
+        *pos += 1;
+
+        value = value
+            .checked_add(u64::from(byte & 0x7F).checked_mul(shift).ok_or(PatchError::OutOfBounds)?)
+            .ok_or(PatchError::OutOfBounds)?;
+
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+
+        shift = shift.checked_shl(7).filter(|&shift| shift != 0).ok_or(PatchError::OutOfBounds)?;
+        value = value.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+    }
+}
+
+/// Appends the source, target and patch checksums that end UPS and BPS
+/// patches.
+fn write_footer(output: &mut Vec<u8>, source: &[u8], target: &[u8]) {
+    output.extend_from_slice(&crc32(source).to_le_bytes());
+    output.extend_from_slice(&crc32(target).to_le_bytes());
+
+    let patch_crc = crc32(output);
+    output.extend_from_slice(&patch_crc.to_le_bytes());
+}
+
+/// Checks the checksums at the end of a UPS or BPS patch against the patch
+/// itself and the source. Returns the expected checksum of the target.
+fn check_footer(patch: &[u8], source: &[u8]) -> Result<u32> {
+    ensure!(patch.len() >= 12, PatchError::NotEnoughData);
+
+    let footer = &patch[patch.len() - 12..];
+
+    ensure!(
+        crc32(&patch[..patch.len() - 4]) == LittleEndian::read_u32(&footer[8..]),
+        PatchError::InvalidChecksum
+    );
+    ensure!(crc32(source) == LittleEndian::read_u32(footer), PatchError::SourceMismatch);
+
+    Ok(LittleEndian::read_u32(&footer[4..]))
+}
@@ -0,0 +1,91 @@
+//! Universal Patching System patches.
+//!
+//! Changes are stored as runs of bytes to XOR with the source, each after
+//! the number of unchanged bytes that come before it.
+
+use anyhow::{ensure, Result};
+
+use super::{check_footer, read_vlq, write_footer, write_vlq, PatchError};
+use crate::util::crc::crc32;
+
+pub(super) const MAGIC: &[u8] = b"UPS1";
+
+pub(super) fn create(source: &[u8], target: &[u8]) -> Result<Vec<u8>> {
+    let mut output = MAGIC.to_vec();
+
+    write_vlq(&mut output, source.len() as u64);
+    write_vlq(&mut output, target.len() as u64);
+
+    let len = source.len().max(target.len());
+    let byte = |data: &[u8], pos: usize| data.get(pos).copied().unwrap_or(0);
+    let mut unchanged = 0;
+    let mut pos = 0;
+
+    while pos < len {
+        let xor = byte(source, pos) ^ byte(target, pos);
+
+        if xor == 0 {
+            unchanged += 1;
+            pos += 1;
+            continue;
+        }
+
+        write_vlq(&mut output, unchanged);
+
+        while pos < len && byte(source, pos) != byte(target, pos) {
+            output.push(byte(source, pos) ^ byte(target, pos));
+            pos += 1;
+        }
+
+        //  The run ends with a zero, which also covers the unchanged byte
+        //  after it.
+        output.push(0);
+        pos += 1;
+        unchanged = 0;
+    }
+
+    write_footer(&mut output, source, target);
+
+    Ok(output)
+}
+
+pub(super) fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let expected = check_footer(patch, source)?;
+    let body = &patch[..patch.len() - 12];
+    let mut pos = MAGIC.len();
+
+    let source_len = read_vlq(body, &mut pos)? as usize;
+    let target_len = read_vlq(body, &mut pos)? as usize;
+
+    ensure!(source.len() == source_len, PatchError::SourceMismatch);
+
+    let mut output = source.to_vec();
+    output.resize(target_len, 0);
+
+    let mut offset: usize = 0;
+
+    while pos < body.len() {
+        offset = offset
+            .checked_add(read_vlq(body, &mut pos)? as usize)
+            .ok_or(PatchError::OutOfBounds)?;
+
+        loop {
+            let xor = *body.get(pos).ok_or(PatchError::NotEnoughData)?;
+            pos += 1;
+
+            if offset < target_len {
+                output[offset] = source.get(offset).copied().unwrap_or(0) ^ xor;
+            }
+
+            offset += 1;
+
+            if xor == 0 {
+                break;
+            }
+        }
+    }
+
+    ensure!(crc32(&output) == expected, PatchError::TargetMismatch);
+
+    Ok(output)
+}
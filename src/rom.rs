@@ -0,0 +1,634 @@
+use byteorder::{ByteOrder, LittleEndian};
+use memmap::{Mmap, MmapMut};
+use nitro_fs::fat::AllocInfo;
+use nitro_fs::fnt::FileEntry;
+use nitro_fs::FileSystem;
+use rayon::prelude::*;
+
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::Cursor;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+
+use crate::banner::{checksums as banner_checksums, Banner};
+use crate::header::{banner_len, Header, UNIT_CODE_TWL};
+use crate::overlay::{Overlay, Processor};
+use crate::parser::NDSParser;
+use crate::util::crc::crc16;
+use crate::util::hash::Hashes;
+
+/// Alignment used when a file has to be moved to the end of the ROM.
+const ALIGNMENT: usize = 0x200;
+
+/// Magic and length of the RSA signature that retail DS ROMs made since
+/// 2007 have right after the area given by `ntr_region_rom_size`.
+const RSA_MAGIC: &[u8] = b"ac";
+const RSA_SIGNATURE_LEN: usize = 0x88;
+
+/// `devicecapacity` is the chip size as a power of two times 128KiB, which
+/// is 1 shifted left by 17.
+const MIN_CAPACITY_SHIFT: u32 = 17;
+
+/// The largest `devicecapacity` whose chip fits in the 4GiB address space.
+const MAX_DEVICE_CAPACITY: u8 = 15;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum RomError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Header checksum does not match contents.")]
+    InvalidChecksum,
+
+    #[error("No file at path: '{0}'.")]
+    FileNotFound(PathBuf),
+
+    #[error("No file with ID: {0}.")]
+    IdNotFound(u16),
+
+    #[error("ROM was opened read-only.")]
+    ReadOnly,
+
+    #[error("No space for '{0}': the ROM has a DSi region after its files.")]
+    NoSpace(PathBuf),
+
+    #[error("ROM is larger than the 4GiB address space.")]
+    RomTooLarge,
+
+    #[error("Can't trim the ROM: file {0} ends past the used size.")]
+    FileAfterEnd(u16),
+
+    #[error("Device capacity {0} is larger than the 4GiB address space.")]
+    InvalidCapacity(u8),
+}
+
+/// Names of the banner checksums, in the order they are stored.
+const BANNER_CHECKSUMS: [&str; 4] = ["banner", "banner_chinese", "banner_korean", "banner_animation"];
+
+/// A checksum stored in a ROM, along with the value it should have.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Checksum {
+    /// What the checksum covers, such as `header` or `banner`.
+    pub name: &'static str,
+    pub stored: u16,
+    pub computed: u16,
+}
+
+impl Checksum {
+    pub fn is_valid(&self) -> bool {
+        self.stored == self.computed
+    }
+}
+
+/// Where the bytes of a ROM live.
+#[derive(Debug)]
+enum Storage {
+    /// A read-only memmap of a file.
+    Map(Mmap),
+    /// A writable memmap, where every change goes straight to the file.
+    MapMut(File, MmapMut),
+    /// A ROM that only exists in memory.
+    Owned(Vec<u8>),
+}
+
+impl Storage {
+    fn as_mut(&mut self) -> Result<&mut [u8]> {
+        match self {
+            Storage::Map(_) => Err(RomError::ReadOnly.into()),
+            Storage::MapMut(_, data) => Ok(data),
+            Storage::Owned(data) => Ok(data),
+        }
+    }
+
+    /// Grows or shrinks the ROM to `len` bytes. New bytes are zeroed.
+    fn resize(&mut self, len: usize) -> Result<()> {
+        match self {
+            Storage::Map(_) => return Err(RomError::ReadOnly.into()),
+            Storage::MapMut(file, data) => {
+                data.flush()?;
+                file.set_len(len as u64)?;
+                *data = unsafe { MmapMut::map_mut(&*file)? };
+            }
+            Storage::Owned(data) => data.resize(len, 0),
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Map(data) => data,
+            Storage::MapMut(_, data) => data,
+            Storage::Owned(data) => data,
+        }
+    }
+}
+
+/// Gives random access to the files in an NDS ROM without extracting it.
+///
+/// Files are returned as slices into a memmap of the ROM, so opening a few
+/// files from a large ROM only reads what is needed. A ROM opened with
+/// [`writable`] or [`from_bytes`] can also have its files replaced.
+///
+/// [`writable`]: #method.writable
+/// [`from_bytes`]: #method.from_bytes
+#[derive(Debug)]
+pub struct Rom {
+    data: Storage,
+    header: NDSParser,
+    fs: FileSystem,
+}
+
+impl Rom {
+    /// Opens a ROM read-only.
+    pub fn new<P: AsRef<Path>>(path: P, check_crc: bool) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        let data = unsafe { Mmap::map(&file)? };
+
+        Self::from_storage(Storage::Map(data), check_crc)
+    }
+
+    /// Opens a ROM so that it can be edited in place. Every change is
+    /// written straight to the file.
+    pub fn writable<P: AsRef<Path>>(path: P, check_crc: bool) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path.as_ref())?;
+        let data = unsafe { MmapMut::map_mut(&file)? };
+
+        Self::from_storage(Storage::MapMut(file, data), check_crc)
+    }
+
+    /// Uses a ROM that is already in memory. Changes can be written out
+    /// with [`save`].
+    ///
+    /// [`save`]: #method.save
+    pub fn from_bytes(data: Vec<u8>, check_crc: bool) -> Result<Self> {
+        Self::from_storage(Storage::Owned(data), check_crc)
+    }
+
+    fn from_storage(data: Storage, check_crc: bool) -> Result<Self> {
+        ensure!(data.len() >= 0x180, RomError::NotEnoughData);
+
+        if check_crc {
+            let checksum = LittleEndian::read_u16(&data[Header::Crc as usize..]);
+            let crc = crate::util::crc::crc16(&data[0..Header::Crc as usize]);
+
+            ensure!(crc == checksum, RomError::InvalidChecksum);
+        }
+
+        let header = NDSParser::try_from(&data[..])?;
+        let fnt = slice(&data, header.fnt.offset, header.fnt.length)?;
+        let fat = slice(&data, header.fat.offset, header.fat.length)?;
+        let fs = FileSystem::new(fnt, fat)?;
+
+        Ok(Self {
+            data,
+            header,
+            fs,
+        })
+    }
+
+    /// Makes sure that every change to a writable ROM has reached the file.
+    pub fn flush(&self) -> Result<()> {
+        if let Storage::MapMut(_, data) = &self.data {
+            data.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the whole ROM image to `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, &self.data[..])?;
+
+        Ok(())
+    }
+
+    /// The parsed ROM header.
+    pub fn header(&self) -> &NDSParser {
+        &self.header
+    }
+
+    /// The file system of the ROM, including overlays.
+    pub fn file_system(&self) -> &FileSystem {
+        &self.fs
+    }
+
+    /// The whole ROM image.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The ARM9 binary.
+    pub fn arm9(&self) -> Result<&[u8]> {
+        slice(&self.data, self.header.arm9.rom_offset, self.header.arm9.size)
+    }
+
+    pub(crate) fn arm9_mut(&mut self) -> Result<&mut [u8]> {
+        let start = self.header.arm9.rom_offset as usize;
+        let end = start + self.header.arm9.size as usize;
+
+        ensure!(self.data.len() >= end, RomError::NotEnoughData);
+
+        Ok(&mut self.data.as_mut()?[start..end])
+    }
+
+    /// The ARM7 binary.
+    pub fn arm7(&self) -> Result<&[u8]> {
+        slice(&self.data, self.header.arm7.rom_offset, self.header.arm7.size)
+    }
+
+    /// The size, CRC32, MD5 and SHA-1 of the whole ROM image.
+    pub fn hashes(&self) -> Hashes {
+        Hashes::new(&self.data)
+    }
+
+    /// The hashes of every overlay, followed by every file in the order of
+    /// [`FileSystem::files`].
+    ///
+    /// [`FileSystem::files`]: ../nitro_fs/struct.FileSystem.html#method.files
+    pub fn file_hashes(&self) -> Result<Vec<(&FileEntry, Hashes)>> {
+        let entries: Vec<&FileEntry> = self.fs.overlays().iter().chain(self.fs.files()).collect();
+
+        entries
+            .into_par_iter()
+            .map(|entry| Ok((entry, Hashes::new(slice(&self.data, entry.alloc.start, entry.alloc.len())?))))
+            .collect()
+    }
+
+    /// The banner, if the ROM has one.
+    pub fn banner(&self) -> Result<Option<Banner>> {
+        match self.banner_data()? {
+            Some(data) => Ok(Some(Banner::parse(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks the CRC16 of the header, the Nintendo logo and the banner.
+    ///
+    /// The secure area checksum is left out, since it covers the encrypted
+    /// secure area and so never matches a decrypted dump.
+    pub fn checksums(&self) -> Result<Vec<Checksum>> {
+        let stored = |offset: Header| LittleEndian::read_u16(&self.data[offset as usize..]);
+        let logo = Header::Logo as usize;
+
+        let mut checksums = vec![
+            Checksum {
+                name: "header",
+                stored: stored(Header::Crc),
+                computed: crc16(&self.data[..Header::Crc as usize]),
+            },
+            Checksum {
+                name: "logo",
+                stored: stored(Header::LogoCrc),
+                computed: crc16(&self.data[logo..Header::LogoCrc as usize]),
+            },
+        ];
+
+        if let Some(banner) = self.banner_data()? {
+            checksums.extend(
+                banner_checksums(banner)
+                    .into_iter()
+                    .zip(&BANNER_CHECKSUMS)
+                    .map(|((stored, computed), name)| Checksum { name, stored, computed }),
+            );
+        }
+
+        Ok(checksums)
+    }
+
+    /// The entries of the overlay table of a processor.
+    pub fn overlays(&self, cpu: Processor) -> Result<Vec<Overlay>> {
+        let cpu = match cpu {
+            Processor::Arm9 => &self.header.arm9,
+            Processor::Arm7 => &self.header.arm7,
+        };
+
+        Overlay::parse_table(slice(&self.data, cpu.overlay_offset, cpu.overlay_length)?)
+    }
+
+    fn banner_data(&self) -> Result<Option<&[u8]>> {
+        let offset = self.header.icon_banner_offset;
+
+        if offset == 0 {
+            return Ok(None);
+        }
+
+        let version = LittleEndian::read_u16(slice(&self.data, offset, 2)?);
+
+        Ok(Some(slice(&self.data, offset, banner_len(version) as u32)?))
+    }
+
+    /// Gets the contents of a file by its path relative to the root of the
+    /// file system, such as `a/0/1/2`.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<&[u8]> {
+        let entry = self.entry(path)?;
+
+        slice(&self.data, entry.alloc.start, entry.alloc.len())
+    }
+
+    /// Gets the contents of a file or overlay by its ID.
+    pub fn open_id(&self, id: u16) -> Result<&[u8]> {
+        let entry = self.entry_id(id)?;
+
+        slice(&self.data, entry.alloc.start, entry.alloc.len())
+    }
+
+    /// Same as [`open`], but wraps the contents in a reader that
+    /// implements `Read` and `Seek`.
+    ///
+    /// [`open`]: #method.open
+    pub fn reader<P: AsRef<Path>>(&self, path: P) -> Result<Cursor<&[u8]>> {
+        Ok(Cursor::new(self.open(path)?))
+    }
+
+    /// Same as [`open_id`], but wraps the contents in a reader that
+    /// implements `Read` and `Seek`.
+    ///
+    /// [`open_id`]: #method.open_id
+    pub fn reader_id(&self, id: u16) -> Result<Cursor<&[u8]>> {
+        Ok(Cursor::new(self.open_id(id)?))
+    }
+
+    /// Replaces the contents of the file at `path` without rebuilding the
+    /// ROM.
+    ///
+    /// If the new data fits in the space between the file and whatever
+    /// comes after it, the file is overwritten in place and the rest of its
+    /// old contents are padded. Otherwise, or if the file was empty and so
+    /// has no space of its own, the file is moved to the end of the ROM, which grows `ntr_region_rom_size`. A moved file is placed
+    /// after the RSA signature, if there is one, and the signature is copied
+    /// after the file. In both cases the FAT entry and the header CRC are
+    /// updated.
+    ///
+    /// # Errors
+    /// Returns an error if the ROM was opened read-only, if there is no file
+    /// at `path`, or if the file has to be moved but the ROM has a DSi region
+    /// after its files.
+    pub fn replace_file<P: AsRef<Path>>(&mut self, path: P, data: &[u8]) -> Result<()> {
+        let path = path.as_ref();
+        let entry = self.entry(path)?;
+        let id = entry.id;
+        let old = entry.alloc;
+
+        self.data.as_mut()?;
+
+        let start = old.start as usize;
+        let limit = self.slot_end(id, old);
+
+        //  Empty files are often placed at 0, which would put them over the
+        //  header.
+        let in_place = !old.is_empty() && start >= self.header.header_size as usize && start + data.len() <= limit;
+
+        let alloc = if in_place {
+            let end = old.end as usize;
+
+            //  Reuse whatever already pads the slot, if there is any.
+            let padding = if end < limit { self.data[end] } else { 0xFF };
+            let rom = self.data.as_mut()?;
+
+            rom[start..start + data.len()].copy_from_slice(data);
+
+            if data.len() < old.len() as usize {
+                for byte in &mut rom[start + data.len()..end] {
+                    *byte = padding;
+                }
+            }
+
+            AllocInfo {
+                start: old.start,
+                end: (start + data.len()) as u32,
+            }
+        } else {
+            ensure!(!self.has_twl_region(), RomError::NoSpace(path.to_path_buf()));
+
+            //  Start after the RSA signature so that it isn't overwritten,
+            //  and copy it after the file so that it still follows
+            //  `ntr_region_rom_size`.
+            let signature = self.signature().map(<[u8]>::to_vec).unwrap_or_default();
+            let start = self.used_size().next_multiple_of(ALIGNMENT);
+            let end = start + data.len();
+            let used = end + signature.len();
+
+            ensure!(used <= u32::MAX as usize, RomError::RomTooLarge);
+
+            if used > self.data.len() {
+                self.data.resize(used)?;
+            }
+
+            let rom = self.data.as_mut()?;
+
+            rom[start..end].copy_from_slice(data);
+            rom[end..used].copy_from_slice(&signature);
+            self.write_u32(Header::RomSize as usize, end as u32)?;
+
+            AllocInfo {
+                start: start as u32,
+                end: end as u32,
+            }
+        };
+
+        let fat_entry = self.header.fat.offset as usize + id as usize * 8;
+
+        self.write_u32(fat_entry, alloc.start)?;
+        self.write_u32(fat_entry + 4, alloc.end)?;
+        self.fs.set_alloc(id, alloc);
+        self.update_crc()?;
+
+        Ok(())
+    }
+
+    /// Cuts off the padding after the data that the ROM uses, and returns
+    /// how many bytes were removed.
+    ///
+    /// The ROM is cut at `ntr_region_rom_size`, keeping the RSA signature
+    /// that follows it if there is one, or at the end of the DSi region for
+    /// ROMs that have one. The header is left as is.
+    ///
+    /// # Errors
+    /// Returns an error if the ROM was opened read-only, or if a file or
+    /// overlay ends past the cut.
+    pub fn trim(&mut self) -> Result<u64> {
+        self.data.as_mut()?;
+
+        let cut = self.used_size();
+
+        if let Some(entry) = self
+            .fs
+            .overlays()
+            .iter()
+            .chain(self.fs.files())
+            .find(|entry| entry.alloc.end as usize > cut)
+        {
+            return Err(RomError::FileAfterEnd(entry.id).into());
+        }
+
+        let len = self.data.len();
+
+        if cut >= len {
+            return Ok(0);
+        }
+
+        self.data.resize(cut)?;
+
+        Ok((len - cut) as u64)
+    }
+
+    /// Pads the ROM with `0xFF` up to the size of the chip given by
+    /// `devicecapacity`, and returns how many bytes were added.
+    ///
+    /// # Errors
+    /// Returns an error if the ROM was opened read-only or if the capacity is
+    /// larger than 4GiB.
+    pub fn untrim(&mut self) -> Result<u64> {
+        self.data.as_mut()?;
+
+        let capacity = self.capacity()?;
+        let len = self.data.len();
+
+        if capacity <= len as u64 {
+            return Ok(0);
+        }
+
+        self.data.resize(capacity as usize)?;
+
+        for byte in &mut self.data.as_mut()?[len..] {
+            *byte = 0xFF;
+        }
+
+        Ok(capacity - len as u64)
+    }
+
+    /// The size of the chip given by `devicecapacity`.
+    ///
+    /// # Errors
+    /// Returns an error if the capacity is larger than 4GiB.
+    pub fn capacity(&self) -> Result<u64> {
+        let capacity = self.header.devicecapacity;
+
+        ensure!(capacity <= MAX_DEVICE_CAPACITY, RomError::InvalidCapacity(capacity));
+
+        Ok(1 << (MIN_CAPACITY_SHIFT + u32::from(capacity)))
+    }
+
+    /// How much of the image the ROM uses, including the RSA signature or
+    /// the DSi region.
+    fn used_size(&self) -> usize {
+        if self.has_twl_region() {
+            return LittleEndian::read_u32(&self.data[Header::TwlRomSize as usize..]) as usize;
+        }
+
+        let end = self.header.ntr_region_rom_size as usize;
+
+        end + self.signature().map_or(0, <[u8]>::len)
+    }
+
+    /// The RSA signature right after `ntr_region_rom_size`, if there is one.
+    fn signature(&self) -> Option<&[u8]> {
+        let end = self.header.ntr_region_rom_size as usize;
+
+        self.data
+            .get(end..end + RSA_SIGNATURE_LEN)
+            .filter(|signature| signature.starts_with(RSA_MAGIC))
+    }
+
+    /// Finds where the space that a file can use ends: the start of the
+    /// closest file or section after it.
+    fn slot_end(&self, id: u16, alloc: AllocInfo) -> usize {
+        let header = &self.header;
+        let mut starts = vec![
+            header.arm9.rom_offset,
+            header.arm7.rom_offset,
+            header.arm9.overlay_offset,
+            header.arm7.overlay_offset,
+            header.fnt.offset,
+            header.fat.offset,
+            header.icon_banner_offset,
+            header.ntr_region_rom_size,
+        ];
+
+        if self.has_twl_region() {
+            starts.push(LittleEndian::read_u32(&self.data[Header::Arm9iOffset as usize..]));
+            starts.push(LittleEndian::read_u32(&self.data[Header::Arm7iOffset as usize..]));
+        }
+
+        starts.extend(
+            self.fs
+                .overlays()
+                .iter()
+                .chain(self.fs.files())
+                .filter(|entry| entry.id != id && !entry.alloc.is_empty())
+                .map(|entry| entry.alloc.start),
+        );
+
+        starts
+            .into_iter()
+            .map(|start| start as usize)
+            .filter(|&start| start >= alloc.end as usize)
+            .min()
+            .unwrap_or(self.data.len())
+            .min(self.data.len())
+    }
+
+    /// Whether the ROM has a DSi region, which starts right after the
+    /// region used by the DS.
+    fn has_twl_region(&self) -> bool {
+        self.header.unitcode & UNIT_CODE_TWL != 0
+            && self.data.len() >= Header::TwlRomSize as usize + 4
+            && LittleEndian::read_u32(&self.data[Header::Arm9iOffset as usize..]) != 0
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) -> Result<()> {
+        ensure!(self.data.len() >= offset + 4, RomError::NotEnoughData);
+
+        LittleEndian::write_u32(&mut self.data.as_mut()?[offset..], value);
+
+        Ok(())
+    }
+
+    /// Recalculates the header CRC and parses the header again so that it
+    /// matches any fields that were changed.
+    fn update_crc(&mut self) -> Result<()> {
+        let crc = crate::util::crc::crc16(&self.data[0..Header::Crc as usize]);
+        let rom = self.data.as_mut()?;
+
+        LittleEndian::write_u16(&mut rom[Header::Crc as usize..], crc);
+        self.header = NDSParser::try_from(&self.data[..])?;
+
+        Ok(())
+    }
+
+    /// Gets the file entry at the given path.
+    pub fn entry<P: AsRef<Path>>(&self, path: P) -> Result<&FileEntry> {
+        let path = path.as_ref();
+
+        self.fs
+            .lookup(path)
+            .ok_or_else(|| RomError::FileNotFound(path.to_path_buf()).into())
+    }
+
+    /// Gets the file or overlay entry with the given ID.
+    pub fn entry_id(&self, id: u16) -> Result<&FileEntry> {
+        self.fs
+            .file(id)
+            .ok_or_else(|| RomError::IdNotFound(id).into())
+    }
+}
+
+/// Gets `len` bytes of `data` starting from `offset`, making sure that they
+/// are all in bounds.
+fn slice(data: &[u8], offset: u32, len: u32) -> Result<&[u8]> {
+    let start = offset as usize;
+    let end = start + len as usize;
+
+    ensure!(data.len() >= end, RomError::NotEnoughData);
+
+    Ok(&data[start..end])
+}
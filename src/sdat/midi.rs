@@ -0,0 +1,478 @@
+//! Conversion of sequences to type 1 Standard MIDI Files.
+//!
+//! The first MIDI track holds the tempo changes, and every sequence track
+//! that is played becomes a MIDI track on the channel with the same number.
+//! Commands that only change how the DS plays notes, such as envelopes,
+//! become the closest controller where there is one and are left out
+//! otherwise.
+//!
+//! Tracks are played one after the other, each with its own variables, and
+//! random values come from the same generator as the DS, always with the
+//! same seed, so a sequence always converts to the same file.
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, Result};
+
+use super::sseq::{read_command, Argument, Command, VariableOp, TICKS_PER_BEAT};
+
+/// How many calls and loops can be nested, as on the DS.
+const STACK_SIZE: usize = 3;
+/// How many commands a track may run, so that loops which never wait
+/// can't hang the conversion.
+const MAX_COMMANDS: usize = 1 << 20;
+
+const VARIABLES: usize = 32;
+const SEED: u32 = 0x1234_5678;
+
+/// The tempo of a sequence until it sets one.
+const DEFAULT_TEMPO: u32 = 120;
+
+/// The longest beat a MIDI tempo event can hold, in microseconds. Tempos
+/// below 4 beats per minute are clamped to it.
+const MAX_BEAT_LEN: u32 = 0xFF_FFFF;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum MidiError {
+    #[error("Calls and loops nested too deep at {0:#X}.")]
+    StackOverflow(u32),
+
+    #[error("Track {0} runs too many commands.")]
+    TooManyCommands(u8),
+}
+
+/// Converts sequences to MIDI files.
+///
+/// # Example
+/// ```no_run
+/// use nds::sdat::midi::MidiConverter;
+/// use nds::sdat::sseq::Sequence;
+///
+/// let sequence = Sequence::parse(&std::fs::read("BGM_TITLE.sseq").unwrap()).unwrap();
+///
+/// let mut converter = MidiConverter::new();
+/// converter.set_loop_count(3);
+///
+/// std::fs::write("BGM_TITLE.mid", converter.convert(&sequence.data, 0).unwrap()).unwrap();
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MidiConverter {
+    loop_count: u32,
+}
+
+impl Default for MidiConverter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiConverter {
+    pub fn new() -> Self {
+        Self { loop_count: 2 }
+    }
+
+    /// How many times loops that never end are played, which includes
+    /// jumps back to an earlier command. Loops with a count are always
+    /// played that many times. Defaults to 2, and is at least 1.
+    pub fn set_loop_count(&mut self, count: u32) {
+        self.loop_count = count.max(1);
+    }
+
+    /// Converts the sequence whose track 0 starts at `start`. `data` is
+    /// the commands of an SSEQ or SSAR.
+    ///
+    /// # Errors
+    /// Returns an error if a command can't be read, if calls and loops are
+    /// nested deeper than the DS allows, or if a track runs too many
+    /// commands.
+    pub fn convert(&self, data: &[u8], start: u32) -> Result<Vec<u8>> {
+        let mut tempo = Vec::new();
+        let mut tracks = Vec::new();
+        let mut pending = vec![(0, start)];
+
+        while let Some((number, offset)) = pending.pop() {
+            if tracks.iter().any(|(existing, _)| *existing == number) {
+                continue;
+            }
+
+            let mut track = Track::new(self, data, number, offset);
+            track.play(&mut tempo)?;
+
+            //  Open tracks in the order they were opened.
+            pending.extend(track.opened.iter().rev());
+            tracks.push((number, track.events));
+        }
+
+        tracks.sort_by_key(|(number, _)| *number);
+
+        let mut midi = Vec::new();
+
+        midi.extend_from_slice(b"MThd");
+        midi.extend_from_slice(&6u32.to_be_bytes());
+        midi.extend_from_slice(&1u16.to_be_bytes());
+        midi.extend_from_slice(&(tracks.len() as u16 + 1).to_be_bytes());
+        midi.extend_from_slice(&TICKS_PER_BEAT.to_be_bytes());
+
+        if !tempo.iter().any(|(time, _)| *time == 0) {
+            tempo.insert(0, (0, tempo_event(DEFAULT_TEMPO)));
+        }
+
+        write_track(&mut midi, tempo);
+
+        for (number, mut events) in tracks {
+            let mut name = vec![0xFF, 0x03];
+            let text = format!("Track {}", number);
+
+            write_var_len(&mut name, text.len() as u32);
+            name.extend_from_slice(text.as_bytes());
+            events.insert(0, (0, name));
+
+            write_track(&mut midi, events);
+        }
+
+        Ok(midi)
+    }
+}
+
+enum Frame {
+    Call { returns: u32 },
+    Loop { start: u32, remaining: u32 },
+}
+
+/// What to do after a command.
+enum Flow {
+    Next,
+    Goto(u32),
+    Stop,
+}
+
+/// The state of a sequence track while it plays.
+struct Track<'a> {
+    converter: &'a MidiConverter,
+    data: &'a [u8],
+    number: u8,
+    channel: u8,
+    offset: u32,
+    time: u32,
+    stack: Vec<Frame>,
+    /// How many times each backwards jump was taken.
+    jumps: HashMap<u32, u32>,
+    variables: [i16; VARIABLES],
+    condition: bool,
+    random: u32,
+    transpose: i32,
+    note_wait: bool,
+    tie: bool,
+    held: Option<u8>,
+    events: Vec<(u32, Vec<u8>)>,
+    opened: Vec<(u8, u32)>,
+}
+
+impl<'a> Track<'a> {
+    fn new(converter: &'a MidiConverter, data: &'a [u8], number: u8, offset: u32) -> Self {
+        Self {
+            converter,
+            data,
+            number,
+            channel: number & 0x0F,
+            offset,
+            time: 0,
+            stack: Vec::new(),
+            jumps: HashMap::new(),
+            variables: [-1; VARIABLES],
+            condition: true,
+            random: SEED,
+            transpose: 0,
+            note_wait: true,
+            tie: false,
+            held: None,
+            events: Vec::new(),
+            opened: Vec::new(),
+        }
+    }
+
+    fn play(&mut self, tempo: &mut Vec<(u32, Vec<u8>)>) -> Result<()> {
+        for _ in 0..MAX_COMMANDS {
+            let (command, next) = read_command(self.data, self.offset)?;
+
+            match self.run(&command, next, tempo)? {
+                Flow::Next => self.offset = next,
+                Flow::Goto(offset) => self.offset = offset,
+                Flow::Stop => {
+                    self.release();
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(MidiError::TooManyCommands(self.number).into())
+    }
+
+    fn run(&mut self, command: &Command, next: u32, tempo: &mut Vec<(u32, Vec<u8>)>) -> Result<Flow> {
+        match *command {
+            Command::Note {
+                key,
+                velocity,
+                duration,
+            } => {
+                let key = (i32::from(key) + self.transpose).clamp(0, 127) as u8;
+                let duration = self.value(duration).max(0) as u32;
+
+                self.release();
+                //  A velocity of 0 would end the note.
+                self.message(&[0x90, key, velocity.clamp(1, 127)]);
+
+                if self.tie {
+                    self.held = Some(key);
+                } else {
+                    self.events.push((self.time + duration, vec![0x80 | self.channel, key, 0]));
+                }
+
+                if self.note_wait {
+                    self.time += duration;
+                }
+            }
+            Command::Wait(ticks) => self.time += self.value(ticks).max(0) as u32,
+            Command::Program(program) => {
+                let program = self.value(program).max(0);
+
+                if program > 0x7F {
+                    self.message(&[0xB0, 0, (program >> 7).min(0x7F) as u8]);
+                }
+
+                self.message(&[0xC0, (program & 0x7F) as u8]);
+            }
+            Command::OpenTrack { track, offset } => self.opened.push((track, offset)),
+            Command::Jump(target) => {
+                if target <= self.offset {
+                    let count = self.jumps.entry(self.offset).or_insert(1);
+
+                    if *count >= self.converter.loop_count {
+                        return Ok(Flow::Stop);
+                    }
+
+                    *count += 1;
+                }
+
+                return Ok(Flow::Goto(target));
+            }
+            Command::Call(target) => {
+                self.push(Frame::Call { returns: next })?;
+                return Ok(Flow::Goto(target));
+            }
+            Command::If(ref command) => {
+                if self.condition {
+                    return self.run(command, next, tempo);
+                }
+            }
+            Command::Variable { op, variable, value } => {
+                let value = self.value(value) as i16;
+                self.variable(op, variable as usize, value);
+            }
+            Command::Pan(pan) => self.controller(10, pan),
+            Command::Volume(volume) => self.controller(7, volume),
+            Command::Expression(expression) => self.controller(11, expression),
+            Command::MasterVolume(_) => {}
+            Command::Transpose(semitones) => self.transpose = self.value(semitones),
+            Command::PitchBend(bend) => {
+                let bend = (8192 + self.value(bend) * 64).clamp(0, 0x3FFF);
+                self.message(&[0xE0, (bend & 0x7F) as u8, (bend >> 7) as u8]);
+            }
+            Command::PitchBendRange(range) => {
+                //  Registered parameter 0, then data entry.
+                let range = self.value(range).clamp(0, 127) as u8;
+
+                self.message(&[0xB0, 101, 0]);
+                self.message(&[0xB0, 100, 0]);
+                self.message(&[0xB0, 6, range]);
+                self.message(&[0xB0, 38, 0]);
+            }
+            Command::Tempo(bpm) => {
+                let bpm = self.value(bpm).max(1) as u32;
+                tempo.push((self.time, tempo_event(bpm)));
+            }
+            Command::NoteWait(wait) => self.note_wait = self.value(wait) != 0,
+            Command::Tie(tie) => {
+                self.tie = self.value(tie) != 0;
+
+                if !self.tie {
+                    self.release();
+                }
+            }
+            Command::LoopStart(count) => {
+                let count = match self.value(count).max(0) as u32 {
+                    0 => self.converter.loop_count,
+                    count => count,
+                };
+
+                self.push(Frame::Loop {
+                    start: next,
+                    remaining: count,
+                })?;
+            }
+            Command::LoopEnd => {
+                if let Some(Frame::Loop { start, remaining }) = self.stack.last_mut() {
+                    *remaining -= 1;
+
+                    if *remaining > 0 {
+                        return Ok(Flow::Goto(*start));
+                    }
+
+                    self.stack.pop();
+                }
+            }
+            Command::Return => {
+                return Ok(match self.stack.pop() {
+                    Some(Frame::Call { returns }) => Flow::Goto(returns),
+                    _ => Flow::Stop,
+                });
+            }
+            Command::AllocateTracks(_) => {}
+            Command::Other { command, value } => {
+                let controller = match command {
+                    0xC9 => 84,
+                    0xCA => 1,
+                    0xCE => 65,
+                    0xCF => 5,
+                    0xD0 => 73,
+                    0xD1 => 75,
+                    0xD3 => 72,
+                    _ => return Ok(Flow::Next),
+                };
+
+                self.controller(controller, value);
+            }
+            Command::End => return Ok(Flow::Stop),
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<()> {
+        ensure!(self.stack.len() < STACK_SIZE, MidiError::StackOverflow(self.offset));
+
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    /// Ends the note held by a tie.
+    fn release(&mut self) {
+        if let Some(key) = self.held.take() {
+            self.message(&[0x80, key, 0]);
+        }
+    }
+
+    /// Adds a message for the channel of the track now.
+    fn message(&mut self, message: &[u8]) {
+        let mut message = message.to_vec();
+
+        message[0] |= self.channel;
+        self.events.push((self.time, message));
+    }
+
+    fn controller(&mut self, controller: u8, value: Argument) {
+        let value = self.value(value).clamp(0, 127) as u8;
+        self.message(&[0xB0, controller, value]);
+    }
+
+    fn value(&mut self, argument: Argument) -> i32 {
+        match argument {
+            Argument::Value(value) => value,
+            Argument::Random { min, max } => {
+                let (min, max) = (i64::from(min), i64::from(max));
+                (min + (self.next_random() * (max - min + 1).max(0)) / 0x10000) as i32
+            }
+            Argument::Variable(index) => self.variables.get(index as usize).copied().map(i32::from).unwrap_or(0),
+        }
+    }
+
+    /// The next 16-bit value of the generator the DS uses, widened so that
+    /// it can be scaled by a range of up to 0x10000.
+    fn next_random(&mut self) -> i64 {
+        self.random = self.random.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        i64::from(self.random >> 16)
+    }
+
+    fn variable(&mut self, op: VariableOp, index: usize, value: i16) {
+        let Some(&current) = self.variables.get(index) else {
+            return;
+        };
+
+        let result = match op {
+            VariableOp::Set => value,
+            VariableOp::Add => current.wrapping_add(value),
+            VariableOp::Subtract => current.wrapping_sub(value),
+            VariableOp::Multiply => current.wrapping_mul(value),
+            VariableOp::Divide if value != 0 => current.wrapping_div(value),
+            VariableOp::Divide => current,
+            VariableOp::Shift if value >= 0 => current.wrapping_shl(value as u32),
+            VariableOp::Shift => current.wrapping_shr(value.unsigned_abs() as u32),
+            VariableOp::Random => {
+                let range = i64::from(value.unsigned_abs()) + 1;
+                let random = self.next_random() * range / 0x10000;
+
+                //  Negate before narrowing, since 32768 only fits as -32768.
+                if value < 0 {
+                    -random as i16
+                } else {
+                    random as i16
+                }
+            }
+            comparison => {
+                self.condition = match comparison {
+                    VariableOp::Equal => current == value,
+                    VariableOp::GreaterOrEqual => current >= value,
+                    VariableOp::Greater => current > value,
+                    VariableOp::LessOrEqual => current <= value,
+                    VariableOp::Less => current < value,
+                    _ => current != value,
+                };
+
+                return;
+            }
+        };
+
+        self.variables[index] = result;
+    }
+}
+
+fn tempo_event(bpm: u32) -> Vec<u8> {
+    let microseconds = (60_000_000 / bpm).min(MAX_BEAT_LEN);
+    let mut event = vec![0xFF, 0x51, 0x03];
+
+    event.extend_from_slice(&microseconds.to_be_bytes()[1..]);
+    event
+}
+
+fn write_var_len(data: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+
+    data.extend(bytes.iter().rev());
+}
+
+/// Writes a track chunk, with the events in order of time.
+fn write_track(midi: &mut Vec<u8>, mut events: Vec<(u32, Vec<u8>)>) {
+    events.sort_by_key(|(time, _)| *time);
+
+    let mut chunk = Vec::new();
+    let mut time = 0;
+
+    for (at, event) in &events {
+        write_var_len(&mut chunk, at - time);
+        chunk.extend_from_slice(event);
+        time = *at;
+    }
+
+    chunk.extend_from_slice(&[0, 0xFF, 0x2F, 0]);
+
+    midi.extend_from_slice(b"MTrk");
+    midi.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    midi.extend_from_slice(&chunk);
+}
@@ -0,0 +1,572 @@
+//! SDAT sound archives, which hold the sequences, instruments, samples and
+//! streams of a game, usually as `sound_data.sdat`.
+//!
+//! An SDAT has four blocks, found through offsets in its 0x40 byte header:
+//!
+//! * `SYMB`, which is optional, names the records
+//! * `INFO` has a list of records for each kind of item, such as the bank
+//!   and volume of a sequence
+//! * `FAT` gives the offset and size of every file
+//! * `FILE` holds the files
+//!
+//! Records refer to files by their ID, which is their index in the FAT.
+//! Unlike most Nitro files, the block magics are not stored reversed.
+
+pub mod midi;
+mod records;
+pub mod sseq;
+pub mod strm;
+pub mod wave;
+
+pub use self::records::*;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read, write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{ensure, Result};
+
+const MAGIC: &[u8; 4] = b"SDAT";
+const SYMB: &[u8; 4] = b"SYMB";
+const INFO: &[u8; 4] = b"INFO";
+const FAT: &[u8; 4] = b"FAT ";
+const FILE: &[u8; 4] = b"FILE";
+
+const BOM: u16 = 0xFEFF;
+const VERSION: u16 = 0x0100;
+
+const HEADER_LEN: usize = 0x40;
+/// Where the offset and size of the first block are in the header.
+const BLOCK_TABLE: usize = 0x10;
+
+/// Size of the `SYMB` and `INFO` headers: the magic, the size, eight record
+/// list offsets and 24 reserved bytes.
+const TABLE_BLOCK_HEADER_LEN: usize = 0x40;
+const FAT_HEADER_LEN: usize = 0x0C;
+const FAT_ENTRY_LEN: usize = 0x10;
+const FILE_HEADER_LEN: usize = 0x10;
+
+/// Files in the `FILE` block start on 32 byte boundaries.
+const FILE_ALIGNMENT: usize = 0x20;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum SdatError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Not an SDAT file.")]
+    InvalidHeader,
+
+    #[error("Missing block: '{0}'.")]
+    MissingBlock(&'static str),
+
+    #[error("Record points to file {0}, which is not in the archive.")]
+    MissingFile(u16),
+
+    #[error("Name '{0}' is not a plain file name.")]
+    InvalidName(String),
+}
+
+/// A record together with its name.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Item<T> {
+    /// The name from the `SYMB` block, such as `SEQ_BGM_TITLE`.
+    pub name: Option<String>,
+    /// The record from the `INFO` block. Some lists have gaps, which are
+    /// `None`.
+    pub info: Option<T>,
+    /// For sequence archives, the names of the sequences inside. Empty for
+    /// everything else.
+    pub children: Vec<Option<String>>,
+}
+
+/// A parsed SDAT file.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SoundArchive {
+    pub sequences: Vec<Item<SequenceInfo>>,
+    pub sequence_archives: Vec<Item<ArchiveInfo>>,
+    pub banks: Vec<Item<BankInfo>>,
+    pub wave_archives: Vec<Item<ArchiveInfo>>,
+    pub players: Vec<Item<PlayerInfo>>,
+    pub groups: Vec<Item<GroupInfo>>,
+    pub stream_players: Vec<Item<StreamPlayerInfo>>,
+    pub streams: Vec<Item<StreamInfo>>,
+    /// Whether the archive has a `SYMB` block. Archives without one are
+    /// written without one.
+    pub has_symbols: bool,
+    /// Every file, by ID.
+    pub files: Vec<Vec<u8>>,
+}
+
+impl SoundArchive {
+    /// Reads an SDAT file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= HEADER_LEN, SdatError::NotEnoughData);
+        ensure!(
+            &data[..4] == MAGIC && LittleEndian::read_u16(&data[0x04..]) == BOM,
+            SdatError::InvalidHeader
+        );
+
+        let symb = block(data, 0, SYMB)?;
+        let info = block(data, 1, INFO)?.ok_or(SdatError::MissingBlock("INFO"))?;
+        let fat = block(data, 2, FAT)?.ok_or(SdatError::MissingBlock("FAT"))?;
+
+        //  Read the names of every list, or none if there is no SYMB.
+        let names = |index: usize| -> Result<Vec<Option<String>>> {
+            match symb {
+                Some(symb) => name_list(symb, list_offset(symb, index)?),
+                None => Ok(Vec::new()),
+            }
+        };
+
+        let mut archive = Self {
+            has_symbols: symb.is_some(),
+            ..Self::default()
+        };
+
+        archive.sequences = items(names(0)?, record_list(info, 0)?);
+        archive.sequence_archives = items(Vec::new(), record_list(info, 1)?);
+        archive.banks = items(names(2)?, record_list(info, 2)?);
+        archive.wave_archives = items(names(3)?, record_list(info, 3)?);
+        archive.players = items(names(4)?, record_list(info, 4)?);
+        archive.groups = items(names(5)?, record_list(info, 5)?);
+        archive.stream_players = items(names(6)?, record_list(info, 6)?);
+        archive.streams = items(names(7)?, record_list(info, 7)?);
+
+        //  Sequence archives list their name and the names inside them.
+        if let Some(symb) = symb {
+            let offset = list_offset(symb, 1)?;
+
+            if offset != 0 {
+                let count = read_u32(symb, offset)? as usize;
+
+                if archive.sequence_archives.len() < count {
+                    archive.sequence_archives.resize(count, Item::default());
+                }
+
+                for (index, item) in archive.sequence_archives.iter_mut().take(count).enumerate() {
+                    let entry = offset + 4 + index * 8;
+
+                    item.name = name_at(symb, read_u32(symb, entry)? as usize)?;
+                    item.children = name_list(symb, read_u32(symb, entry + 4)? as usize)?;
+                }
+            }
+        }
+
+        let count = read_u32(fat, 0x08)? as usize;
+
+        archive.files = (0..count)
+            .map(|index| {
+                let entry = FAT_HEADER_LEN + index * FAT_ENTRY_LEN;
+                let offset = read_u32(fat, entry)? as usize;
+                let len = read_u32(fat, entry + 4)? as usize;
+
+                Ok(data.get(offset..offset + len).ok_or(SdatError::NotEnoughData)?.to_vec())
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(archive)
+    }
+
+    /// Writes the archive with up to date offsets.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let symb = if self.has_symbols { self.symb() } else { Vec::new() };
+        let info = self.info();
+
+        let fat_offset = HEADER_LEN + symb.len() + info.len();
+        let fat_len = FAT_HEADER_LEN + self.files.len() * FAT_ENTRY_LEN;
+        let file_offset = fat_offset + fat_len;
+
+        //  Lay out the files, which are aligned to the start of the SDAT.
+        let mut file = vec![0; FILE_HEADER_LEN];
+        let mut fat = vec![0; FAT_HEADER_LEN];
+
+        for data in &self.files {
+            let position = (file_offset + file.len()).next_multiple_of(FILE_ALIGNMENT);
+            file.resize(position - file_offset, 0);
+
+            let mut entry = [0; FAT_ENTRY_LEN];
+            LittleEndian::write_u32(&mut entry, position as u32);
+            LittleEndian::write_u32(&mut entry[4..], data.len() as u32);
+            fat.extend_from_slice(&entry);
+
+            file.extend_from_slice(data);
+        }
+
+        LittleEndian::write_u32(&mut fat[0x08..], self.files.len() as u32);
+        LittleEndian::write_u32(&mut file[0x08..], self.files.len() as u32);
+
+        let fat = finish_block(fat, FAT);
+        let file = finish_block(file, FILE);
+
+        let mut data = vec![0; HEADER_LEN];
+        let mut blocks = vec![];
+
+        if !symb.is_empty() {
+            blocks.push((0, symb));
+        }
+
+        blocks.extend(vec![(1, info), (2, fat), (3, file)]);
+
+        data[..4].copy_from_slice(MAGIC);
+        LittleEndian::write_u16(&mut data[0x04..], BOM);
+        LittleEndian::write_u16(&mut data[0x06..], VERSION);
+        LittleEndian::write_u16(&mut data[0x0C..], HEADER_LEN as u16);
+        LittleEndian::write_u16(&mut data[0x0E..], blocks.len() as u16);
+
+        for (index, block) in blocks {
+            let entry = BLOCK_TABLE + index * 8;
+            let offset = data.len() as u32;
+
+            LittleEndian::write_u32(&mut data[entry..], offset);
+            LittleEndian::write_u32(&mut data[entry + 4..], block.len() as u32);
+
+            data.extend_from_slice(&block);
+        }
+
+        let len = data.len() as u32;
+        LittleEndian::write_u32(&mut data[0x08..], len);
+
+        data
+    }
+
+    /// A name for every file that a record points to, with the extension
+    /// of its kind, such as `SEQ_BGM_TITLE.sseq`. Records without a name
+    /// are named after their list and index, such as `SEQ_0003.sseq`.
+    /// Files shared by several records take the name of the first one, and
+    /// files that no record points to are named `FILE_0012.bin`.
+    pub fn file_names(&self) -> BTreeMap<u16, String> {
+        let mut names = BTreeMap::new();
+
+        let mut add = |items: Vec<(Option<&String>, Option<u16>)>, prefix: &str, extension: &str| {
+            for (index, (name, id)) in items.into_iter().enumerate() {
+                if let Some(id) = id {
+                    names.entry(id).or_insert_with(|| match name {
+                        Some(name) => format!("{}.{}", name, extension),
+                        None => format!("{}_{:04}.{}", prefix, index, extension),
+                    });
+                }
+            }
+        };
+
+        add(file_ids(&self.sequences, |info| info.file_id), "SEQ", "sseq");
+        add(file_ids(&self.sequence_archives, |info| info.file_id), "SEQARC", "ssar");
+        add(file_ids(&self.banks, |info| info.file_id), "BANK", "sbnk");
+        add(file_ids(&self.wave_archives, |info| info.file_id), "WAVE", "swar");
+        add(file_ids(&self.streams, |info| info.file_id), "STRM", "strm");
+
+        for id in 0..self.files.len() as u16 {
+            names.entry(id).or_insert_with(|| format!("FILE_{:04}.bin", id));
+        }
+
+        names
+    }
+
+    /// Writes every file to a directory, named by [`file_names`].
+    ///
+    /// # Errors
+    /// Returns an error if a record points to a file that is not in the
+    /// archive, or if a name from `SYMB` is not a plain file name.
+    ///
+    /// [`file_names`]: #method.file_names
+    pub fn extract<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let root = path.as_ref();
+
+        create_dir_all(root)?;
+
+        for (id, name) in self.file_names() {
+            let data = self.files.get(id as usize).ok_or(SdatError::MissingFile(id))?;
+            write(file_path(root, &name)?, data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces files with those in a directory written by [`extract`].
+    /// Files that are missing from the directory are kept as they are, so
+    /// only the edited files need to be there. Returns how many files were
+    /// replaced.
+    ///
+    /// # Errors
+    /// The same as [`extract`].
+    ///
+    /// [`extract`]: #method.extract
+    pub fn import<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let root = path.as_ref();
+        let mut count = 0;
+
+        for (id, name) in self.file_names() {
+            let file = file_path(root, &name)?;
+            let slot = self.files.get_mut(id as usize).ok_or(SdatError::MissingFile(id))?;
+
+            if file.is_file() {
+                *slot = read(file)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// The file of a record, if it has one.
+    pub fn file(&self, id: u16) -> Option<&[u8]> {
+        self.files.get(id as usize).map(Vec::as_slice)
+    }
+
+    /// Writes the `SYMB` block. The lists come first, then the names.
+    fn symb(&self) -> Vec<u8> {
+        let lists: [Vec<Option<&String>>; 8] = [
+            names(&self.sequences),
+            Vec::new(),
+            names(&self.banks),
+            names(&self.wave_archives),
+            names(&self.players),
+            names(&self.groups),
+            names(&self.stream_players),
+            names(&self.streams),
+        ];
+
+        let mut block = vec![0; TABLE_BLOCK_HEADER_LEN];
+        let mut strings = Strings::default();
+
+        //  Name offsets are only known once the lists are done, so they are
+        //  noted and filled in at the end.
+        let mut fixups: Vec<(usize, Option<&String>)> = Vec::new();
+
+        for (index, list) in lists.iter().enumerate() {
+            let offset = block.len();
+            LittleEndian::write_u32(&mut block[0x08 + index * 4..], offset as u32);
+
+            if index == 1 {
+                block.extend_from_slice(&(self.sequence_archives.len() as u32).to_le_bytes());
+
+                let start = block.len();
+                block.resize(start + self.sequence_archives.len() * 8, 0);
+
+                for (archive, item) in self.sequence_archives.iter().enumerate() {
+                    let entry = start + archive * 8;
+
+                    let children = block.len() as u32;
+
+                    fixups.push((entry, item.name.as_ref()));
+                    LittleEndian::write_u32(&mut block[entry + 4..], children);
+
+                    block.extend_from_slice(&(item.children.len() as u32).to_le_bytes());
+
+                    for child in &item.children {
+                        fixups.push((block.len(), child.as_ref()));
+                        block.extend_from_slice(&[0; 4]);
+                    }
+                }
+
+                continue;
+            }
+
+            block.extend_from_slice(&(list.len() as u32).to_le_bytes());
+
+            for name in list {
+                fixups.push((block.len(), *name));
+                block.extend_from_slice(&[0; 4]);
+            }
+        }
+
+        let base = block.len();
+
+        for (at, name) in fixups {
+            if let Some(name) = name {
+                let offset = base + strings.add(name);
+                LittleEndian::write_u32(&mut block[at..], offset as u32);
+            }
+        }
+
+        block.extend_from_slice(&strings.data);
+        finish_block(block, SYMB)
+    }
+
+    /// Writes the `INFO` block. Each list is followed by its records.
+    fn info(&self) -> Vec<u8> {
+        let mut block = vec![0; TABLE_BLOCK_HEADER_LEN];
+
+        write_records(&mut block, 0, &self.sequences);
+        write_records(&mut block, 1, &self.sequence_archives);
+        write_records(&mut block, 2, &self.banks);
+        write_records(&mut block, 3, &self.wave_archives);
+        write_records(&mut block, 4, &self.players);
+        write_records(&mut block, 5, &self.groups);
+        write_records(&mut block, 6, &self.stream_players);
+        write_records(&mut block, 7, &self.streams);
+
+        finish_block(block, INFO)
+    }
+}
+
+/// Names that are written once and shared by offset.
+#[derive(Default)]
+struct Strings {
+    data: Vec<u8>,
+    offsets: BTreeMap<String, usize>,
+}
+
+impl Strings {
+    fn add(&mut self, name: &str) -> usize {
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+
+        let offset = self.data.len();
+
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        self.offsets.insert(name.to_string(), offset);
+
+        offset
+    }
+}
+
+/// Fills in the magic and size of a block, padded to 4 bytes.
+fn finish_block(mut block: Vec<u8>, magic: &[u8; 4]) -> Vec<u8> {
+    block.resize(block.len().next_multiple_of(4), 0);
+
+    let len = block.len() as u32;
+
+    block[..4].copy_from_slice(magic);
+    LittleEndian::write_u32(&mut block[0x04..], len);
+
+    block
+}
+
+fn write_records<T: Record>(block: &mut Vec<u8>, list: usize, items: &[Item<T>]) {
+    let offset = block.len();
+    LittleEndian::write_u32(&mut block[0x08 + list * 4..], offset as u32);
+
+    block.extend_from_slice(&(items.len() as u32).to_le_bytes());
+
+    let table = block.len();
+    block.resize(table + items.len() * 4, 0);
+
+    for (index, item) in items.iter().enumerate() {
+        if let Some(info) = &item.info {
+            let at = block.len() as u32;
+
+            LittleEndian::write_u32(&mut block[table + index * 4..], at);
+            info.write(block);
+        }
+    }
+}
+
+fn names<T>(items: &[Item<T>]) -> Vec<Option<&String>> {
+    items.iter().map(|item| item.name.as_ref()).collect()
+}
+
+fn file_ids<T, F: Fn(&T) -> u16>(items: &[Item<T>], id: F) -> Vec<(Option<&String>, Option<u16>)> {
+    items
+        .iter()
+        .map(|item| (item.name.as_ref(), item.info.as_ref().map(&id)))
+        .collect()
+}
+
+/// Joins a name from [`SoundArchive::file_names`] onto `root`, making sure
+/// that it names a file directly inside.
+fn file_path(root: &Path, name: &str) -> Result<PathBuf> {
+    let components = Path::new(name).components().collect::<Vec<_>>();
+
+    ensure!(
+        matches!(components[..], [Component::Normal(part)] if part == OsStr::new(name)),
+        SdatError::InvalidName(name.into())
+    );
+
+    Ok(root.join(name))
+}
+
+/// Pairs up names and records by index.
+fn items<T>(names: Vec<Option<String>>, infos: Vec<Option<T>>) -> Vec<Item<T>> {
+    let len = names.len().max(infos.len());
+    let mut names = names.into_iter();
+    let mut infos = infos.into_iter();
+
+    (0..len)
+        .map(|_| Item {
+            name: names.next().flatten(),
+            info: infos.next().flatten(),
+            children: Vec::new(),
+        })
+        .collect()
+}
+
+/// Finds a block through the offset and size in the header, or returns
+/// `None` if its offset is 0.
+fn block<'a>(data: &'a [u8], index: usize, magic: &[u8; 4]) -> Result<Option<&'a [u8]>> {
+    let offset = LittleEndian::read_u32(&data[BLOCK_TABLE + index * 8..]) as usize;
+    let len = LittleEndian::read_u32(&data[BLOCK_TABLE + index * 8 + 4..]) as usize;
+
+    if offset == 0 {
+        return Ok(None);
+    }
+
+    let block = data.get(offset..offset + len).ok_or(SdatError::NotEnoughData)?;
+
+    ensure!(block.len() >= 8 && &block[..4] == magic, SdatError::InvalidHeader);
+
+    Ok(Some(block))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).ok_or(SdatError::NotEnoughData)?;
+    Ok(LittleEndian::read_u32(bytes))
+}
+
+/// The offset of one of the eight lists in a `SYMB` or `INFO` block.
+fn list_offset(block: &[u8], index: usize) -> Result<usize> {
+    Ok(read_u32(block, 0x08 + index * 4)? as usize)
+}
+
+/// Reads a list of name offsets.
+fn name_list(symb: &[u8], offset: usize) -> Result<Vec<Option<String>>> {
+    if offset == 0 {
+        return Ok(Vec::new());
+    }
+
+    let count = read_u32(symb, offset)? as usize;
+
+    (0..count)
+        .map(|index| name_at(symb, read_u32(symb, offset + 4 + index * 4)? as usize))
+        .collect()
+}
+
+fn name_at(symb: &[u8], offset: usize) -> Result<Option<String>> {
+    if offset == 0 {
+        return Ok(None);
+    }
+
+    let name = symb.get(offset..).ok_or(SdatError::NotEnoughData)?;
+    let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+
+    Ok(Some(String::from_utf8_lossy(&name[..end]).into_owned()))
+}
+
+/// Reads one of the record lists of the `INFO` block.
+fn record_list<T: Record>(info: &[u8], index: usize) -> Result<Vec<Option<T>>> {
+    let offset = list_offset(info, index)?;
+
+    if offset == 0 {
+        return Ok(Vec::new());
+    }
+
+    let count = read_u32(info, offset)? as usize;
+
+    (0..count)
+        .map(|entry| {
+            let at = read_u32(info, offset + 4 + entry * 4)? as usize;
+
+            if at == 0 {
+                return Ok(None);
+            }
+
+            T::parse(info.get(at..).ok_or(SdatError::NotEnoughData)?).map(Some)
+        })
+        .collect()
+}
@@ -0,0 +1,242 @@
+//! The records in the `INFO` block of an SDAT.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use anyhow::{ensure, Result};
+
+use super::SdatError;
+
+/// A record that can be read from and written to the `INFO` block.
+pub trait Record: Sized {
+    /// Reads a record from the start of `data`.
+    fn parse(data: &[u8]) -> Result<Self>;
+
+    /// Appends the record to `data`.
+    fn write(&self, data: &mut Vec<u8>);
+}
+
+/// Returns the first `len` bytes of `data`, or an error if there are fewer.
+fn take(data: &[u8], len: usize) -> Result<&[u8]> {
+    ensure!(data.len() >= len, SdatError::NotEnoughData);
+    Ok(&data[..len])
+}
+
+/// A sequence, played with a bank of instruments.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SequenceInfo {
+    pub file_id: u16,
+    pub unknown: u16,
+    pub bank: u16,
+    pub volume: u8,
+    pub channel_priority: u8,
+    pub player_priority: u8,
+    pub player: u8,
+}
+
+impl Record for SequenceInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let data = take(data, 0x0C)?;
+
+        Ok(Self {
+            file_id: LittleEndian::read_u16(data),
+            unknown: LittleEndian::read_u16(&data[0x02..]),
+            bank: LittleEndian::read_u16(&data[0x04..]),
+            volume: data[0x06],
+            channel_priority: data[0x07],
+            player_priority: data[0x08],
+            player: data[0x09],
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.file_id.to_le_bytes());
+        data.extend_from_slice(&self.unknown.to_le_bytes());
+        data.extend_from_slice(&self.bank.to_le_bytes());
+        data.extend_from_slice(&[self.volume, self.channel_priority, self.player_priority, self.player, 0, 0]);
+    }
+}
+
+/// A sequence archive or a wave archive, which only point to their file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ArchiveInfo {
+    pub file_id: u16,
+    pub unknown: u16,
+}
+
+impl Record for ArchiveInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let data = take(data, 0x04)?;
+
+        Ok(Self {
+            file_id: LittleEndian::read_u16(data),
+            unknown: LittleEndian::read_u16(&data[0x02..]),
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.file_id.to_le_bytes());
+        data.extend_from_slice(&self.unknown.to_le_bytes());
+    }
+}
+
+/// A bank of instruments and the wave archives that its samples are in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct BankInfo {
+    pub file_id: u16,
+    pub unknown: u16,
+    /// Indices of wave archives, or 0xFFFF for none.
+    pub wave_archives: [u16; 4],
+}
+
+impl Record for BankInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let data = take(data, 0x0C)?;
+        let mut wave_archives = [0; 4];
+
+        LittleEndian::read_u16_into(&data[0x04..0x0C], &mut wave_archives);
+
+        Ok(Self {
+            file_id: LittleEndian::read_u16(data),
+            unknown: LittleEndian::read_u16(&data[0x02..]),
+            wave_archives,
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.file_id.to_le_bytes());
+        data.extend_from_slice(&self.unknown.to_le_bytes());
+
+        for archive in &self.wave_archives {
+            data.extend_from_slice(&archive.to_le_bytes());
+        }
+    }
+}
+
+/// A sequence player, which limits how many sequences and channels can
+/// play at once.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PlayerInfo {
+    pub max_sequences: u8,
+    /// Bit mask of the channels that the player may use.
+    pub channels: u16,
+    pub heap_size: u32,
+}
+
+impl Record for PlayerInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let data = take(data, 0x08)?;
+
+        Ok(Self {
+            max_sequences: data[0x00],
+            channels: LittleEndian::read_u16(&data[0x02..]),
+            heap_size: LittleEndian::read_u32(&data[0x04..]),
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&[self.max_sequences, 0]);
+        data.extend_from_slice(&self.channels.to_le_bytes());
+        data.extend_from_slice(&self.heap_size.to_le_bytes());
+    }
+}
+
+/// An item that a group loads.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct GroupEntry {
+    /// The list the item is in: 0 for sequences, 1 for banks, 2 for wave
+    /// archives and 3 for sequence archives.
+    pub kind: u8,
+    pub load_flags: u8,
+    pub index: u32,
+}
+
+/// A set of items that are loaded together.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct GroupInfo {
+    pub entries: Vec<GroupEntry>,
+}
+
+impl Record for GroupInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let count = LittleEndian::read_u32(take(data, 0x04)?) as usize;
+        let data = take(data, 0x04 + count * 0x08)?;
+
+        let entries = data[0x04..]
+            .chunks_exact(0x08)
+            .map(|entry| GroupEntry {
+                kind: entry[0x00],
+                load_flags: entry[0x01],
+                index: LittleEndian::read_u32(&entry[0x04..]),
+            })
+            .collect();
+
+        Ok(Self { entries })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        for entry in &self.entries {
+            data.extend_from_slice(&[entry.kind, entry.load_flags, 0, 0]);
+            data.extend_from_slice(&entry.index.to_le_bytes());
+        }
+    }
+}
+
+/// A stream player, which lists the channels that a stream plays on.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct StreamPlayerInfo {
+    /// How many of the channels are used, 1 for mono and 2 for stereo.
+    pub count: u8,
+    pub channels: [u8; 16],
+}
+
+impl Record for StreamPlayerInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let data = take(data, 0x18)?;
+        let mut channels = [0; 16];
+
+        channels.copy_from_slice(&data[0x01..0x11]);
+
+        Ok(Self {
+            count: data[0x00],
+            channels,
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.push(self.count);
+        data.extend_from_slice(&self.channels);
+        data.extend_from_slice(&[0; 7]);
+    }
+}
+
+/// A stream, such as music or a voice line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct StreamInfo {
+    pub file_id: u16,
+    pub unknown: u16,
+    pub volume: u8,
+    pub priority: u8,
+    pub player: u8,
+}
+
+impl Record for StreamInfo {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let data = take(data, 0x0C)?;
+
+        Ok(Self {
+            file_id: LittleEndian::read_u16(data),
+            unknown: LittleEndian::read_u16(&data[0x02..]),
+            volume: data[0x04],
+            priority: data[0x05],
+            player: data[0x06],
+        })
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.file_id.to_le_bytes());
+        data.extend_from_slice(&self.unknown.to_le_bytes());
+        data.extend_from_slice(&[self.volume, self.priority, self.player, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,455 @@
+//! SSEQ sequences and SSAR sequence archives, which play music and sound
+//! effects with the instruments of a bank.
+//!
+//! A sequence is a stream of commands, similar to a MIDI track. Track 0
+//! starts at the beginning and opens the other tracks, which run at the
+//! same time. Commands refer to each other by their offset from the start
+//! of the commands, so that loops and subroutines can be shared.
+//!
+//! Timing is in ticks, with 48 ticks to a quarter note.
+//!
+//! The last argument of most commands can be replaced by a random value in
+//! a range or by the value of a variable, which the `0xA0` and `0xA1`
+//! prefixes select. This is kept as an [`Argument`].
+//!
+//! [`Argument`]: enum.Argument.html
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::NitroFile;
+
+use std::collections::BTreeMap;
+
+use anyhow::{ensure, Result};
+
+const SSEQ: &[u8; 4] = b"SSEQ";
+const SSAR: &[u8; 4] = b"SSAR";
+const DATA: &[u8; 4] = b"DATA";
+
+/// Where the `DATA` block starts in the file, which its offsets count from.
+const DATA_START: usize = 0x10 + 0x08;
+/// Size of a sequence in the table of an SSAR.
+const ARCHIVED_LEN: usize = 0x0C;
+
+/// How many ticks make a quarter note.
+pub const TICKS_PER_BEAT: u16 = 48;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum SseqError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown command {command:#04X} at {offset:#X}.")]
+    UnknownCommand { command: u8, offset: usize },
+
+    #[error("Command at {0:#X} can't take a random or variable argument.")]
+    InvalidPrefix(usize),
+}
+
+/// The last argument of a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Argument {
+    Value(i32),
+    /// A random value between `min` and `max`, both included.
+    Random { min: i16, max: i16 },
+    /// The value of a variable.
+    Variable(u8),
+}
+
+/// An operation on a variable. The comparisons set the flag that
+/// [`Command::If`] checks.
+///
+/// [`Command::If`]: enum.Command.html#variant.If
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum VariableOp {
+    Set,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// Shifts left, or right if the value is negative.
+    Shift,
+    /// Sets a random value between 0 and the value.
+    Random,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+    LessOrEqual,
+    Less,
+    NotEqual,
+}
+
+impl VariableOp {
+    fn from_raw(value: u8) -> Option<Self> {
+        Some(match value {
+            0xB0 => VariableOp::Set,
+            0xB1 => VariableOp::Add,
+            0xB2 => VariableOp::Subtract,
+            0xB3 => VariableOp::Multiply,
+            0xB4 => VariableOp::Divide,
+            0xB5 => VariableOp::Shift,
+            0xB6 => VariableOp::Random,
+            0xB8 => VariableOp::Equal,
+            0xB9 => VariableOp::GreaterOrEqual,
+            0xBA => VariableOp::Greater,
+            0xBB => VariableOp::LessOrEqual,
+            0xBC => VariableOp::Less,
+            0xBD => VariableOp::NotEqual,
+            _ => return None,
+        })
+    }
+}
+
+/// A command of a sequence. Offsets are from the start of the commands.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Command {
+    /// Plays a note for `duration` ticks. The track waits until it ends
+    /// unless note wait is off.
+    Note {
+        key: u8,
+        velocity: u8,
+        duration: Argument,
+    },
+    Wait(Argument),
+    /// Selects an instrument of the bank.
+    Program(Argument),
+    /// Starts another track, usually from track 0 before anything else.
+    OpenTrack { track: u8, offset: u32 },
+    Jump(u32),
+    Call(u32),
+    /// Runs the command only if the last comparison was true.
+    If(Box<Command>),
+    Variable {
+        op: VariableOp,
+        variable: u8,
+        value: Argument,
+    },
+    Pan(Argument),
+    Volume(Argument),
+    MasterVolume(Argument),
+    /// Semitones added to every note.
+    Transpose(Argument),
+    /// Bends by up to the pitch bend range, from -128 to 127.
+    PitchBend(Argument),
+    /// The pitch bend range, in semitones.
+    PitchBendRange(Argument),
+    Expression(Argument),
+    /// Beats per minute.
+    Tempo(Argument),
+    /// Whether the track waits for notes to end, which is on at first.
+    NoteWait(Argument),
+    /// Whether notes are held until the next note.
+    Tie(Argument),
+    /// Starts a loop that plays `count` times, or forever if 0.
+    LoopStart(Argument),
+    LoopEnd,
+    Return,
+    /// A mask of the tracks that the sequence uses.
+    AllocateTracks(u16),
+    /// A command that only changes how notes sound on the DS, such as the
+    /// envelope, modulation and portamento.
+    Other { command: u8, value: Argument },
+    End,
+}
+
+/// How the last argument of a command is stored.
+#[derive(Clone, Copy)]
+enum Kind {
+    U8,
+    S8,
+    U16,
+    S16,
+    VarLen,
+}
+
+/// What replaces the last argument of a command.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Prefix {
+    None,
+    Random,
+    Variable,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let data = self.data.get(self.offset..self.offset + len).ok_or(SseqError::NotEnoughData)?;
+
+        self.offset += len;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(LittleEndian::read_u16(self.take(2)?))
+    }
+
+    fn u24(&mut self) -> Result<u32> {
+        Ok(LittleEndian::read_u24(self.take(3)?))
+    }
+
+    /// A big endian number with 7 bits per byte, the highest bit of which
+    /// is set on every byte but the last, as in MIDI files.
+    fn var_len(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+
+        loop {
+            let byte = self.u8()?;
+            value = (value << 7) | u32::from(byte & 0x7F);
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn argument(&mut self, prefix: Prefix, kind: Kind) -> Result<Argument> {
+        Ok(match prefix {
+            Prefix::None => Argument::Value(match kind {
+                Kind::U8 => i32::from(self.u8()?),
+                Kind::S8 => i32::from(self.u8()? as i8),
+                Kind::U16 => i32::from(self.u16()?),
+                Kind::S16 => i32::from(self.u16()? as i16),
+                Kind::VarLen => self.var_len()? as i32,
+            }),
+            Prefix::Random => Argument::Random {
+                min: self.u16()? as i16,
+                max: self.u16()? as i16,
+            },
+            Prefix::Variable => Argument::Variable(self.u8()?),
+        })
+    }
+
+    fn command(&mut self, prefix: Prefix) -> Result<Command> {
+        let offset = self.offset;
+        let command = self.u8()?;
+
+        //  Commands that jump or have no argument can't be prefixed.
+        let plain = || -> Result<()> {
+            ensure!(prefix == Prefix::None, SseqError::InvalidPrefix(offset));
+            Ok(())
+        };
+
+        Ok(match command {
+            0x00..=0x7F => Command::Note {
+                key: command,
+                velocity: self.u8()?,
+                duration: self.argument(prefix, Kind::VarLen)?,
+            },
+            0x80 => Command::Wait(self.argument(prefix, Kind::VarLen)?),
+            0x81 => Command::Program(self.argument(prefix, Kind::VarLen)?),
+            0x93 => {
+                plain()?;
+
+                Command::OpenTrack {
+                    track: self.u8()?,
+                    offset: self.u24()?,
+                }
+            }
+            0x94 => {
+                plain()?;
+                Command::Jump(self.u24()?)
+            }
+            0x95 => {
+                plain()?;
+                Command::Call(self.u24()?)
+            }
+            0xA0 => {
+                plain()?;
+                self.command(Prefix::Random)?
+            }
+            0xA1 => {
+                plain()?;
+                self.command(Prefix::Variable)?
+            }
+            0xA2 => {
+                plain()?;
+                Command::If(Box::new(self.command(Prefix::None)?))
+            }
+            0xB0..=0xBD => Command::Variable {
+                op: VariableOp::from_raw(command).ok_or(SseqError::UnknownCommand { command, offset })?,
+                variable: self.u8()?,
+                value: self.argument(prefix, Kind::S16)?,
+            },
+            0xC0 => Command::Pan(self.argument(prefix, Kind::U8)?),
+            0xC1 => Command::Volume(self.argument(prefix, Kind::U8)?),
+            0xC2 => Command::MasterVolume(self.argument(prefix, Kind::U8)?),
+            0xC3 => Command::Transpose(self.argument(prefix, Kind::S8)?),
+            0xC4 => Command::PitchBend(self.argument(prefix, Kind::S8)?),
+            0xC5 => Command::PitchBendRange(self.argument(prefix, Kind::U8)?),
+            0xC7 => Command::NoteWait(self.argument(prefix, Kind::U8)?),
+            0xC8 => Command::Tie(self.argument(prefix, Kind::U8)?),
+            0xD4 => Command::LoopStart(self.argument(prefix, Kind::U8)?),
+            0xD5 => Command::Expression(self.argument(prefix, Kind::U8)?),
+            0xC6 | 0xC9..=0xCF | 0xD0..=0xD3 | 0xD6 => Command::Other {
+                command,
+                value: self.argument(prefix, Kind::U8)?,
+            },
+            0xE0 => Command::Other {
+                command,
+                value: self.argument(prefix, Kind::U16)?,
+            },
+            0xE1 => Command::Tempo(self.argument(prefix, Kind::U16)?),
+            0xE3 => Command::Other {
+                command,
+                value: self.argument(prefix, Kind::S16)?,
+            },
+            0xFC => {
+                plain()?;
+                Command::LoopEnd
+            }
+            0xFD => {
+                plain()?;
+                Command::Return
+            }
+            0xFE => {
+                plain()?;
+                Command::AllocateTracks(self.u16()?)
+            }
+            0xFF => {
+                plain()?;
+                Command::End
+            }
+            _ => return Err(SseqError::UnknownCommand { command, offset }.into()),
+        })
+    }
+}
+
+/// Reads the command at `offset`, and returns it with the offset of the
+/// next one.
+pub fn read_command(data: &[u8], offset: u32) -> Result<(Command, u32)> {
+    let mut reader = Reader {
+        data,
+        offset: offset as usize,
+    };
+
+    let command = reader.command(Prefix::None)?;
+    Ok((command, reader.offset as u32))
+}
+
+/// Reads every command that can be reached from `start`, by following
+/// jumps, calls and opened tracks.
+pub fn read_commands(data: &[u8], start: u32) -> Result<BTreeMap<u32, Command>> {
+    let mut commands = BTreeMap::new();
+    let mut pending = vec![start];
+
+    while let Some(offset) = pending.pop() {
+        if commands.contains_key(&offset) {
+            continue;
+        }
+
+        let (command, next) = read_command(data, offset)?;
+
+        let target = match &command {
+            Command::Jump(target) | Command::Call(target) => Some(*target),
+            Command::OpenTrack { offset, .. } => Some(*offset),
+            Command::If(command) => match **command {
+                Command::Jump(target) | Command::Call(target) => Some(target),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        pending.extend(target);
+
+        if !matches!(command, Command::Jump(_) | Command::Return | Command::End) {
+            pending.push(next);
+        }
+
+        commands.insert(offset, command);
+    }
+
+    Ok(commands)
+}
+
+/// A single sequence from an SSEQ file.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Sequence {
+    /// The commands, which start with track 0.
+    pub data: Vec<u8>,
+}
+
+impl Sequence {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, SSEQ)?;
+        let block = &file.require(DATA)?.data;
+
+        ensure!(block.len() >= 4, SseqError::NotEnoughData);
+
+        let start = (LittleEndian::read_u32(block) as usize).saturating_sub(DATA_START);
+        let data = block.get(start..).ok_or(SseqError::NotEnoughData)?;
+
+        Ok(Self { data: data.to_vec() })
+    }
+
+    pub fn commands(&self) -> Result<BTreeMap<u32, Command>> {
+        read_commands(&self.data, 0)
+    }
+}
+
+/// A sequence in an SSAR, with the settings that SDAT records have for
+/// other sequences.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ArchivedSequence {
+    /// Where track 0 starts in the commands of the archive.
+    pub offset: u32,
+    pub bank: u16,
+    pub volume: u8,
+    pub channel_priority: u8,
+    pub player_priority: u8,
+    pub player: u8,
+}
+
+/// An SSAR archive of sequences, usually sound effects, that share their
+/// commands.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SequenceArchive {
+    pub sequences: Vec<ArchivedSequence>,
+    /// The commands of every sequence.
+    pub data: Vec<u8>,
+}
+
+impl SequenceArchive {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, SSAR)?;
+        let block = &file.require(DATA)?.data;
+
+        ensure!(block.len() >= 8, SseqError::NotEnoughData);
+
+        let start = (LittleEndian::read_u32(block) as usize).saturating_sub(DATA_START);
+        let count = LittleEndian::read_u32(&block[0x04..]) as usize;
+
+        let table = block.get(0x08..0x08 + count * ARCHIVED_LEN).ok_or(SseqError::NotEnoughData)?;
+        let sequences = table
+            .chunks_exact(ARCHIVED_LEN)
+            .map(|entry| ArchivedSequence {
+                offset: LittleEndian::read_u32(entry),
+                bank: LittleEndian::read_u16(&entry[0x04..]),
+                volume: entry[0x06],
+                channel_priority: entry[0x07],
+                player_priority: entry[0x08],
+                player: entry[0x09],
+            })
+            .collect();
+
+        let data = block.get(start..).ok_or(SseqError::NotEnoughData)?;
+
+        Ok(Self {
+            sequences,
+            data: data.to_vec(),
+        })
+    }
+
+    /// The commands of a sequence, or `None` if there is no such sequence.
+    pub fn commands(&self, index: usize) -> Option<Result<BTreeMap<u32, Command>>> {
+        let sequence = self.sequences.get(index)?;
+        Some(read_commands(&self.data, sequence.offset))
+    }
+}
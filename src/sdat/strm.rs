@@ -0,0 +1,247 @@
+//! STRM streams, which play long audio such as music and voice lines.
+//!
+//! The samples are split into blocks of a fixed size. Each block has the
+//! data of every channel one after the other, so a stereo stream stores
+//! block 0 of the left channel, block 0 of the right channel, block 1 of
+//! the left channel and so on. ADPCM data restarts with a header in every
+//! block. The last block is shorter and padded to 4 bytes.
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::{NitroFile, Section};
+
+use anyhow::{ensure, Result};
+
+use super::wave::{self, Sound, WaveError, WaveFormat};
+
+const STRM: &[u8; 4] = b"STRM";
+const HEAD: &[u8; 4] = b"HEAD";
+const DATA: &[u8; 4] = b"DATA";
+
+const VERSION: u16 = 0x0100;
+
+/// Size of the `HEAD` block without its header.
+const HEAD_LEN: usize = 0x48;
+/// Where the samples start when the file is written: after the file
+/// header, the `HEAD` block and the header of the `DATA` block.
+const DATA_OFFSET: usize = 0x10 + 0x08 + HEAD_LEN + 0x08;
+
+/// Size of a block of a single channel when encoding.
+const BLOCK_LEN: usize = 0x200;
+/// Most channels a stream can have.
+const MAX_CHANNELS: usize = 2;
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum StrmError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("The stream has no blocks of the right size.")]
+    InvalidBlocks,
+
+    #[error("The channels do not have the same number of samples.")]
+    UnequalChannels,
+}
+
+/// A stream of one or more channels.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Strm {
+    pub format: WaveFormat,
+    pub looping: bool,
+    pub sample_rate: u16,
+    pub timer: u16,
+    /// The sample where the loop starts.
+    pub loop_start: u32,
+    /// How many samples each channel has. Playback loops at the end.
+    pub sample_count: u32,
+    /// How many bytes each block of a channel has, except the last.
+    pub block_len: u32,
+    pub block_samples: u32,
+    /// How many bytes the last block of a channel has, before padding.
+    pub last_block_len: u32,
+    pub last_block_samples: u32,
+    /// The blocks of every channel, as `blocks[channel][block]`.
+    pub blocks: Vec<Vec<Vec<u8>>>,
+}
+
+impl Strm {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, STRM)?;
+        let head = &file.require(HEAD)?.data;
+
+        ensure!(head.len() >= 0x28, StrmError::NotEnoughData);
+
+        let channels = head[0x02] as usize;
+        let data_offset = LittleEndian::read_u32(&head[0x10..]) as usize;
+        let block_count = LittleEndian::read_u32(&head[0x14..]) as usize;
+        let block_len = LittleEndian::read_u32(&head[0x18..]);
+        let last_block_len = LittleEndian::read_u32(&head[0x20..]);
+
+        let mut blocks = vec![Vec::with_capacity(block_count); channels];
+        let mut offset = data_offset;
+
+        for index in 0..block_count {
+            let len = if index + 1 == block_count { last_block_len } else { block_len } as usize;
+            //  Only the last block is ever padded, the others are already
+            //  aligned.
+            let stored = len.next_multiple_of(4);
+
+            for channel in blocks.iter_mut() {
+                let block = data.get(offset..offset + len).ok_or(StrmError::NotEnoughData)?;
+
+                channel.push(block.to_vec());
+                offset += stored;
+            }
+        }
+
+        Ok(Self {
+            format: WaveFormat::from_raw(head[0x00])?,
+            looping: head[0x01] != 0,
+            sample_rate: LittleEndian::read_u16(&head[0x04..]),
+            timer: LittleEndian::read_u16(&head[0x06..]),
+            loop_start: LittleEndian::read_u32(&head[0x08..]),
+            sample_count: LittleEndian::read_u32(&head[0x0C..]),
+            block_len,
+            block_samples: LittleEndian::read_u32(&head[0x1C..]),
+            last_block_len,
+            last_block_samples: LittleEndian::read_u32(&head[0x24..]),
+            blocks,
+        })
+    }
+
+    /// Writes the stream with as many blocks as the first channel has. A
+    /// channel with fewer blocks is padded with silence.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let block_count = self.blocks.first().map(Vec::len).unwrap_or(0);
+
+        let mut head = vec![0; HEAD_LEN];
+
+        head[0x00] = self.format.to_raw();
+        head[0x01] = u8::from(self.looping);
+        head[0x02] = self.blocks.len() as u8;
+        LittleEndian::write_u16(&mut head[0x04..], self.sample_rate);
+        LittleEndian::write_u16(&mut head[0x06..], self.timer);
+        LittleEndian::write_u32(&mut head[0x08..], self.loop_start);
+        LittleEndian::write_u32(&mut head[0x0C..], self.sample_count);
+        LittleEndian::write_u32(&mut head[0x10..], DATA_OFFSET as u32);
+        LittleEndian::write_u32(&mut head[0x14..], block_count as u32);
+        LittleEndian::write_u32(&mut head[0x18..], self.block_len);
+        LittleEndian::write_u32(&mut head[0x1C..], self.block_samples);
+        LittleEndian::write_u32(&mut head[0x20..], self.last_block_len);
+        LittleEndian::write_u32(&mut head[0x24..], self.last_block_samples);
+
+        let mut data = Vec::new();
+
+        for index in 0..block_count {
+            let len = self.blocks[0][index].len();
+
+            for channel in &self.blocks {
+                match channel.get(index) {
+                    Some(block) => data.extend_from_slice(block),
+                    None => data.resize(data.len() + len, 0),
+                }
+
+                data.resize(data.len().next_multiple_of(4), 0);
+            }
+        }
+
+        let mut file = NitroFile::new(*STRM, VERSION);
+        file.sections.push(Section::new(*HEAD, head));
+        file.sections.push(Section::new(*DATA, data));
+
+        file.to_bytes()
+    }
+
+    /// Where the loop starts and ends, in samples, if the stream loops.
+    pub fn loop_points(&self) -> Option<(u32, u32)> {
+        if self.looping {
+            Some((self.loop_start, self.sample_count))
+        } else {
+            None
+        }
+    }
+
+    pub fn decode(&self) -> Sound {
+        let block_count = self.blocks.first().map(Vec::len).unwrap_or(0);
+
+        let channels = self
+            .blocks
+            .iter()
+            .map(|blocks| {
+                let mut samples = Vec::with_capacity(self.sample_count as usize);
+
+                for (index, block) in blocks.iter().enumerate() {
+                    let count = if index + 1 == block_count {
+                        self.last_block_samples
+                    } else {
+                        self.block_samples
+                    };
+
+                    samples.extend(wave::decode(block, self.format, count as usize));
+                }
+
+                samples.truncate(self.sample_count as usize);
+                samples
+            })
+            .collect();
+
+        Sound {
+            sample_rate: u32::from(self.sample_rate),
+            channels,
+            loop_start: if self.looping { Some(self.loop_start) } else { None },
+        }
+    }
+
+    /// Encodes mono or stereo audio in blocks of 0x200 bytes per channel.
+    ///
+    /// # Errors
+    /// Returns an error if the sound has no channels or more than two, if
+    /// its channels differ in length, or if its sample rate is above 65535.
+    pub fn encode(sound: &Sound, format: WaveFormat) -> Result<Self> {
+        ensure!(
+            (1..=MAX_CHANNELS).contains(&sound.channels.len()),
+            WaveError::ChannelCount {
+                expected: "1 or 2",
+                found: sound.channels.len(),
+            }
+        );
+
+        ensure!(
+            sound.sample_rate > 0 && sound.sample_rate <= u32::from(u16::MAX),
+            WaveError::InvalidSampleRate(sound.sample_rate)
+        );
+
+        let block_samples = format.samples(BLOCK_LEN);
+        let len = sound.len();
+
+        ensure!(len > 0, StrmError::InvalidBlocks);
+        ensure!(sound.channels.iter().all(|samples| samples.len() == len), StrmError::UnequalChannels);
+
+        let blocks: Vec<Vec<Vec<u8>>> = sound
+            .channels
+            .iter()
+            .map(|samples| {
+                samples
+                    .chunks(block_samples)
+                    .map(|block| wave::encode(block, format))
+                    .collect()
+            })
+            .collect();
+
+        let last_block_samples = len - (len - 1) / block_samples * block_samples;
+
+        Ok(Self {
+            format,
+            looping: sound.loop_start.is_some(),
+            sample_rate: sound.sample_rate as u16,
+            timer: wave::timer(sound.sample_rate),
+            loop_start: sound.loop_start.unwrap_or(0),
+            sample_count: len as u32,
+            block_len: BLOCK_LEN as u32,
+            block_samples: block_samples as u32,
+            last_block_len: format.data_len(last_block_samples) as u32,
+            last_block_samples: last_block_samples as u32,
+            blocks,
+        })
+    }
+}
@@ -0,0 +1,556 @@
+//! Samples: SWAV files, the SWAR archives that hold them, and conversion to
+//! and from RIFF WAV.
+//!
+//! Samples are stored as signed 8-bit PCM, signed 16-bit PCM or IMA-ADPCM.
+//! ADPCM data starts with a 4 byte header that has the first predicted
+//! value and step index, followed by 4-bit codes, low nibble first.
+//!
+//! Decoded audio is always 16-bit, so WAV files are written as 16-bit PCM.
+//! A loop is written as the first loop of a `smpl` chunk, which most audio
+//! editors and samplers read.
+
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::{NitroFile, Section};
+
+use anyhow::{ensure, Result};
+
+const SWAV: &[u8; 4] = b"SWAV";
+const SWAR: &[u8; 4] = b"SWAR";
+const DATA: &[u8; 4] = b"DATA";
+
+const VERSION: u16 = 0x0100;
+
+/// Size of the information before the samples of an SWAV.
+pub(crate) const INFO_LEN: usize = 0x0C;
+/// Size of the header that starts ADPCM data.
+pub(crate) const ADPCM_HEADER_LEN: usize = 4;
+/// Reserved bytes before the sample count in the `DATA` block of an SWAR.
+const SWAR_RESERVED: usize = 0x20;
+
+/// Half the ARM7 clock, which the sample rate timer counts.
+const TIMER_CLOCK: u32 = 16_756_991;
+
+const ADPCM_STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97, 107,
+    118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796, 876, 963,
+    1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894,
+    6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794,
+    32767,
+];
+
+const ADPCM_INDEX: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+// == Errors ==
+#[derive(Debug, thiserror::Error)]
+pub enum WaveError {
+    #[error("Not enough data.")]
+    NotEnoughData,
+
+    #[error("Unknown wave format: {0}.")]
+    InvalidFormat(u8),
+
+    #[error("Not a WAV file this can read: {0}.")]
+    UnsupportedWav(&'static str),
+
+    #[error("Expected {expected} channels, got {found}.")]
+    ChannelCount { expected: &'static str, found: usize },
+
+    #[error("Sample rate {0} does not fit.")]
+    InvalidSampleRate(u32),
+}
+
+/// How samples are encoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum WaveFormat {
+    Pcm8,
+    Pcm16,
+    Adpcm,
+}
+
+impl WaveFormat {
+    pub fn from_raw(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(WaveFormat::Pcm8),
+            1 => Ok(WaveFormat::Pcm16),
+            2 => Ok(WaveFormat::Adpcm),
+            _ => Err(WaveError::InvalidFormat(value).into()),
+        }
+    }
+
+    pub fn to_raw(self) -> u8 {
+        match self {
+            WaveFormat::Pcm8 => 0,
+            WaveFormat::Pcm16 => 1,
+            WaveFormat::Adpcm => 2,
+        }
+    }
+
+    /// How many samples `len` bytes of data hold, including the header of
+    /// ADPCM data.
+    pub fn samples(self, len: usize) -> usize {
+        match self {
+            WaveFormat::Pcm8 => len,
+            WaveFormat::Pcm16 => len / 2,
+            WaveFormat::Adpcm => len.saturating_sub(ADPCM_HEADER_LEN) * 2,
+        }
+    }
+
+    /// How many bytes `samples` samples take, including the header of ADPCM
+    /// data.
+    pub fn data_len(self, samples: usize) -> usize {
+        match self {
+            WaveFormat::Pcm8 => samples,
+            WaveFormat::Pcm16 => samples * 2,
+            WaveFormat::Adpcm => ADPCM_HEADER_LEN + samples.div_ceil(2),
+        }
+    }
+}
+
+/// The value of the sample rate timer for a sample rate.
+pub fn timer(sample_rate: u32) -> u16 {
+    (TIMER_CLOCK / sample_rate.max(1)) as u16
+}
+
+/// Decodes up to `samples` samples. Fewer are returned if the data ends
+/// first.
+pub fn decode(data: &[u8], format: WaveFormat, samples: usize) -> Vec<i16> {
+    match format {
+        WaveFormat::Pcm8 => data.iter().take(samples).map(|&sample| i16::from(sample as i8) << 8).collect(),
+        WaveFormat::Pcm16 => data.chunks_exact(2).take(samples).map(LittleEndian::read_i16).collect(),
+        WaveFormat::Adpcm => {
+            if data.len() < ADPCM_HEADER_LEN {
+                return Vec::new();
+            }
+
+            let mut state = Adpcm::new(LittleEndian::read_i16(data), data[2]);
+
+            data[ADPCM_HEADER_LEN..]
+                .iter()
+                .flat_map(|&byte| [byte & 0xF, byte >> 4])
+                .take(samples)
+                .map(|code| state.decode(code))
+                .collect()
+        }
+    }
+}
+
+/// Encodes samples. ADPCM data starts from the first sample, with the step
+/// that fits the change to the second one, and odd counts are padded with
+/// a zero code.
+pub fn encode(samples: &[i16], format: WaveFormat) -> Vec<u8> {
+    match format {
+        WaveFormat::Pcm8 => samples.iter().map(|&sample| (sample >> 8) as u8).collect(),
+        WaveFormat::Pcm16 => samples.iter().flat_map(|sample| sample.to_le_bytes()).collect(),
+        WaveFormat::Adpcm => {
+            let first = samples.first().copied().unwrap_or(0);
+            let change = match samples {
+                [first, second, ..] => (i32::from(*second) - i32::from(*first)).abs(),
+                _ => 0,
+            };
+
+            let index = ADPCM_STEPS.iter().position(|&step| step >= change).unwrap_or(ADPCM_STEPS.len() - 1);
+            let mut state = Adpcm::new(first, index as u8);
+            let mut data = Vec::with_capacity(format.data_len(samples.len()));
+
+            data.extend_from_slice(&first.to_le_bytes());
+            data.extend_from_slice(&[index as u8, 0]);
+
+            for pair in samples.chunks(2) {
+                let low = state.encode(pair[0]);
+                let high = pair.get(1).map(|&sample| state.encode(sample)).unwrap_or(0);
+
+                data.push(low | (high << 4));
+            }
+
+            data
+        }
+    }
+}
+
+/// The state of an IMA-ADPCM decoder, which the encoder also keeps so
+/// that both agree on every value.
+struct Adpcm {
+    value: i32,
+    index: usize,
+}
+
+impl Adpcm {
+    fn new(value: i16, index: u8) -> Self {
+        Self {
+            value: i32::from(value),
+            index: (index as usize).min(ADPCM_STEPS.len() - 1),
+        }
+    }
+
+    fn decode(&mut self, code: u8) -> i16 {
+        let step = ADPCM_STEPS[self.index];
+        let mut diff = step >> 3;
+
+        if code & 1 != 0 {
+            diff += step >> 2;
+        }
+
+        if code & 2 != 0 {
+            diff += step >> 1;
+        }
+
+        if code & 4 != 0 {
+            diff += step;
+        }
+
+        //  The DS clamps to a symmetric range.
+        self.value = if code & 8 != 0 { self.value - diff } else { self.value + diff }.clamp(-0x7FFF, 0x7FFF);
+
+        let index = self.index as i32 + ADPCM_INDEX[(code & 7) as usize];
+        self.index = index.clamp(0, ADPCM_STEPS.len() as i32 - 1) as usize;
+
+        self.value as i16
+    }
+
+    fn encode(&mut self, sample: i16) -> u8 {
+        let step = ADPCM_STEPS[self.index];
+        let mut diff = i32::from(sample) - self.value;
+        let mut code = 0;
+
+        if diff < 0 {
+            code = 8;
+            diff = -diff;
+        }
+
+        let mut threshold = step;
+
+        for bit in [4, 2, 1] {
+            if diff >= threshold {
+                code |= bit;
+                diff -= threshold;
+            }
+
+            threshold >>= 1;
+        }
+
+        self.decode(code);
+        code
+    }
+}
+
+/// Decoded audio.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Sound {
+    pub sample_rate: u32,
+    /// The samples of every channel, which all have the same length.
+    pub channels: Vec<Vec<i16>>,
+    /// The sample that playback goes back to at the end, if the sound
+    /// loops.
+    pub loop_start: Option<u32>,
+}
+
+impl Sound {
+    /// How many samples each channel has.
+    pub fn len(&self) -> usize {
+        self.channels.first().map(Vec::len).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes a 16-bit RIFF WAV file, with a `smpl` chunk if the sound
+    /// loops.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let channels = self.channels.len().max(1);
+        let data_len = self.len() * channels * 2;
+
+        let mut wav = Vec::with_capacity(44 + data_len + 68);
+
+        wav.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&(channels as u16).to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * channels as u32 * 2).to_le_bytes());
+        wav.extend_from_slice(&(channels as u16 * 2).to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data_len as u32).to_le_bytes());
+
+        for index in 0..self.len() {
+            for channel in &self.channels {
+                wav.extend_from_slice(&channel[index].to_le_bytes());
+            }
+        }
+
+        if let Some(start) = self.loop_start {
+            let end = (self.len() as u32).saturating_sub(1);
+            let period = 1_000_000_000 / self.sample_rate.max(1);
+
+            wav.extend_from_slice(b"smpl");
+            wav.extend_from_slice(&60u32.to_le_bytes());
+
+            //  Manufacturer, product, sample period, unity note, pitch
+            //  fraction, SMPTE format and offset, loop count and extra
+            //  data size, then the loop.
+            for value in &[0, 0, period, 60, 0, 0, 0, 1, 0] {
+                wav.extend_from_slice(&u32::to_le_bytes(*value));
+            }
+
+            for value in &[0, 0, start, end, 0, 0] {
+                wav.extend_from_slice(&u32::to_le_bytes(*value));
+            }
+        }
+
+        let len = wav.len() as u32 - 8;
+        LittleEndian::write_u32(&mut wav[4..], len);
+
+        wav
+    }
+
+    /// Reads an 8 or 16-bit PCM WAV file, along with the first loop of its
+    /// `smpl` chunk.
+    pub fn from_wav(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WAVE",
+            WaveError::UnsupportedWav("not a RIFF WAVE file")
+        );
+
+        let mut format = None;
+        let mut samples = None;
+        let mut loop_start = None;
+        let mut offset = 12;
+
+        while offset + 8 <= data.len() {
+            let id = &data[offset..offset + 4];
+            let len = LittleEndian::read_u32(&data[offset + 4..]) as usize;
+            let chunk = data.get(offset + 8..offset + 8 + len).ok_or(WaveError::NotEnoughData)?;
+
+            match id {
+                b"fmt " => {
+                    ensure!(chunk.len() >= 16, WaveError::NotEnoughData);
+                    ensure!(LittleEndian::read_u16(chunk) == 1, WaveError::UnsupportedWav("only PCM is supported"));
+
+                    format = Some((
+                        LittleEndian::read_u16(&chunk[2..]) as usize,
+                        LittleEndian::read_u32(&chunk[4..]),
+                        LittleEndian::read_u16(&chunk[14..]),
+                    ));
+                }
+                b"data" => samples = Some(chunk),
+                b"smpl" if chunk.len() >= 36 + 24 && LittleEndian::read_u32(&chunk[28..]) > 0 => {
+                    loop_start = Some(LittleEndian::read_u32(&chunk[36 + 8..]));
+                }
+                _ => {}
+            }
+
+            //  Chunks are padded to an even size.
+            offset += 8 + len + len % 2;
+        }
+
+        let (channels, sample_rate, bits) = format.ok_or(WaveError::UnsupportedWav("missing fmt chunk"))?;
+        let samples = samples.ok_or(WaveError::UnsupportedWav("missing data chunk"))?;
+
+        ensure!(channels > 0, WaveError::UnsupportedWav("no channels"));
+
+        let interleaved: Vec<i16> = match bits {
+            8 => samples.iter().map(|&sample| (i16::from(sample) - 0x80) << 8).collect(),
+            16 => samples.chunks_exact(2).map(LittleEndian::read_i16).collect(),
+            _ => return Err(WaveError::UnsupportedWav("only 8 and 16-bit samples are supported").into()),
+        };
+
+        //  A partial frame at the end is dropped.
+        let len = interleaved.len() / channels;
+        let channels = (0..channels)
+            .map(|channel| interleaved.iter().skip(channel).step_by(channels).take(len).copied().collect())
+            .collect();
+
+        Ok(Self {
+            sample_rate,
+            channels,
+            loop_start,
+        })
+    }
+}
+
+/// A single sample, as found in an SWAV file or an SWAR archive.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Swav {
+    pub format: WaveFormat,
+    pub looping: bool,
+    pub sample_rate: u16,
+    pub timer: u16,
+    /// Where the loop starts, in 32-bit words from the start of the data.
+    pub loop_offset: u16,
+    /// The encoded samples, including the header of ADPCM data.
+    pub data: Vec<u8>,
+}
+
+impl Swav {
+    /// Reads an SWAV file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, SWAV)?;
+        Self::parse_info(&file.require(DATA)?.data)
+    }
+
+    /// Reads a sample without the file header, as stored in an SWAR.
+    pub fn parse_info(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= INFO_LEN, WaveError::NotEnoughData);
+
+        let loop_offset = LittleEndian::read_u16(&data[0x06..]);
+        let len = (loop_offset as usize + LittleEndian::read_u32(&data[0x08..]) as usize) * 4;
+
+        let samples = data.get(INFO_LEN..INFO_LEN + len).ok_or(WaveError::NotEnoughData)?;
+
+        Ok(Self {
+            format: WaveFormat::from_raw(data[0x00])?,
+            looping: data[0x01] != 0,
+            sample_rate: LittleEndian::read_u16(&data[0x02..]),
+            timer: LittleEndian::read_u16(&data[0x04..]),
+            loop_offset,
+            data: samples.to_vec(),
+        })
+    }
+
+    /// Writes the sample without the file header.
+    pub fn info_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; INFO_LEN];
+        let words = self.data.len().div_ceil(4);
+
+        data[0x00] = self.format.to_raw();
+        data[0x01] = u8::from(self.looping);
+        LittleEndian::write_u16(&mut data[0x02..], self.sample_rate);
+        LittleEndian::write_u16(&mut data[0x04..], self.timer);
+        LittleEndian::write_u16(&mut data[0x06..], self.loop_offset);
+        LittleEndian::write_u32(&mut data[0x08..], (words - (self.loop_offset as usize).min(words)) as u32);
+
+        data.extend_from_slice(&self.data);
+        data.resize(INFO_LEN + words * 4, 0);
+
+        data
+    }
+
+    /// Writes an SWAV file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut file = NitroFile::new(*SWAV, VERSION);
+        file.sections.push(Section::new(*DATA, self.info_bytes()));
+
+        file.to_bytes()
+    }
+
+    /// The sample where the loop starts.
+    pub fn loop_start(&self) -> u32 {
+        let words = self.loop_offset as u32;
+
+        match self.format {
+            WaveFormat::Pcm8 => words * 4,
+            WaveFormat::Pcm16 => words * 2,
+            WaveFormat::Adpcm => words.saturating_sub(1) * 8,
+        }
+    }
+
+    pub fn decode(&self) -> Sound {
+        let samples = self.format.samples(self.data.len());
+
+        Sound {
+            sample_rate: u32::from(self.sample_rate),
+            channels: vec![decode(&self.data, self.format, samples)],
+            loop_start: if self.looping { Some(self.loop_start()) } else { None },
+        }
+    }
+
+    /// Encodes mono audio. The loop start is moved back to the nearest
+    /// 32-bit word, as that is where the DS can loop from.
+    ///
+    /// # Errors
+    /// Returns an error if the sound isn't mono, or if its sample rate is
+    /// above 65535.
+    pub fn encode(sound: &Sound, format: WaveFormat) -> Result<Self> {
+        ensure!(
+            sound.channels.len() == 1,
+            WaveError::ChannelCount {
+                expected: "1",
+                found: sound.channels.len(),
+            }
+        );
+
+        ensure!(
+            sound.sample_rate > 0 && sound.sample_rate <= u32::from(u16::MAX),
+            WaveError::InvalidSampleRate(sound.sample_rate)
+        );
+
+        let per_word = match format {
+            WaveFormat::Pcm8 => 4,
+            WaveFormat::Pcm16 => 2,
+            WaveFormat::Adpcm => 8,
+        };
+
+        let header_words = if format == WaveFormat::Adpcm { 1 } else { 0 };
+        let loop_offset = sound.loop_start.map(|start| header_words + start / per_word).unwrap_or(0);
+
+        let mut data = encode(&sound.channels[0], format);
+        data.resize(data.len().next_multiple_of(4), 0);
+
+        Ok(Self {
+            format,
+            looping: sound.loop_start.is_some(),
+            sample_rate: sound.sample_rate as u16,
+            timer: timer(sound.sample_rate),
+            loop_offset: loop_offset as u16,
+            data,
+        })
+    }
+}
+
+/// An SWAR archive of samples, which the instruments of a bank use.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Swar {
+    pub waves: Vec<Swav>,
+}
+
+impl Swar {
+    /// Reads an SWAR file. Sample offsets are from the start of the file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = NitroFile::parse_as(data, SWAR)?;
+        let block = &file.require(DATA)?.data;
+
+        ensure!(block.len() >= SWAR_RESERVED + 4, WaveError::NotEnoughData);
+
+        let count = LittleEndian::read_u32(&block[SWAR_RESERVED..]) as usize;
+        let table = block
+            .get(SWAR_RESERVED + 4..SWAR_RESERVED + 4 + count * 4)
+            .ok_or(WaveError::NotEnoughData)?;
+
+        let waves = table
+            .chunks_exact(4)
+            .map(|offset| {
+                let offset = LittleEndian::read_u32(offset) as usize;
+                Swav::parse_info(data.get(offset..).ok_or(WaveError::NotEnoughData)?)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { waves })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        //  The file and block headers come before the table.
+        let start = 0x10 + 0x08;
+        let mut block = vec![0; SWAR_RESERVED];
+
+        block.extend_from_slice(&(self.waves.len() as u32).to_le_bytes());
+
+        let table = block.len();
+        block.resize(table + self.waves.len() * 4, 0);
+
+        for (index, wave) in self.waves.iter().enumerate() {
+            let offset = (start + block.len()) as u32;
+
+            LittleEndian::write_u32(&mut block[table + index * 4..], offset);
+            block.extend_from_slice(&wave.info_bytes());
+        }
+
+        let mut file = NitroFile::new(*SWAR, VERSION);
+        file.sections.push(Section::new(*DATA, block));
+
+        file.to_bytes()
+    }
+}
@@ -43,3 +43,35 @@ pub fn crc16(data: &[u8]) -> u16 {
             (crc >> 8) ^ CRC16_TABLE[(crc as u8 ^ *byte) as usize]
         })
 }
+
+lazy_static! {
+    static ref CRC32_TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+
+        for (index, entry) in table.iter_mut().enumerate() {
+            *entry = (0..8).fold(index as u32, |crc, _| {
+                if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                }
+            });
+        }
+
+        table
+    };
+}
+
+/// The standard CRC-32 used by zip files and ROM patch formats.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Continues a CRC-32 with more data, so `crc32_update(crc32(a), b)` is the
+/// same as the CRC-32 of `a` followed by `b`.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter()
+        .fold(!crc, |crc, byte| {
+            (crc >> 8) ^ CRC32_TABLE[(crc as u8 ^ *byte) as usize]
+        })
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use crate::util::crc::crc32_update;
+
+/// The size and checksums that ROM databases use to identify a dump.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Hashes {
+    pub size: u64,
+    pub crc32: u32,
+    pub md5: [u8; 16],
+    pub sha1: [u8; 20],
+}
+
+impl Hashes {
+    /// Hashes all of `data` at once.
+    pub fn new(data: &[u8]) -> Self {
+        let mut hasher = Hasher::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// The MD5 as a lowercase hex string.
+    pub fn md5_hex(&self) -> String {
+        to_hex(&self.md5)
+    }
+
+    /// The SHA-1 as a lowercase hex string.
+    pub fn sha1_hex(&self) -> String {
+        to_hex(&self.sha1)
+    }
+}
+
+impl fmt::Display for Hashes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "size {} crc32 {:08x} md5 {} sha1 {}",
+            self.size,
+            self.crc32,
+            self.md5_hex(),
+            self.sha1_hex()
+        )
+    }
+}
+
+/// Computes [`Hashes`] from data given in pieces.
+///
+/// [`Hashes`]: struct.Hashes.html
+pub struct Hasher {
+    size: u64,
+    crc32: u32,
+    md5: md5::Context,
+    sha1: sha1_smol::Sha1,
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self {
+            size: 0,
+            crc32: 0,
+            md5: md5::Context::new(),
+            sha1: sha1_smol::Sha1::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+        self.crc32 = crc32_update(self.crc32, data);
+        self.md5.consume(data);
+        self.sha1.update(data);
+    }
+
+    pub fn finish(self) -> Hashes {
+        Hashes {
+            size: self.size,
+            crc32: self.crc32,
+            md5: self.md5.compute().0,
+            sha1: self.sha1.digest().bytes(),
+        }
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a hex string into a fixed number of bytes.
+pub(crate) fn from_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let text = text.trim();
+
+    if text.len() != N * 2 {
+        return None;
+    }
+
+    let mut bytes = [0; N];
+
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(bytes)
+}
+
+/// Parses a hex string of any even length.
+pub(crate) fn from_hex_vec(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
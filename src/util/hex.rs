@@ -0,0 +1,17 @@
+//! Serializes fixed size byte arrays as hex strings, for use with
+//! `#[serde(with = "crate::util::hex")]`.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+use super::hash::{from_hex, to_hex};
+
+pub(crate) fn serialize<S: Serializer, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_hex(bytes))
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error> {
+    let text = String::deserialize(deserializer)?;
+
+    from_hex(&text).ok_or_else(|| D::Error::custom(format!("expected {} bytes of hex", N)))
+}
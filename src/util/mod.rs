@@ -1 +1,5 @@
-pub mod crc;
\ No newline at end of file
+pub mod crc;
+pub mod hash;
+
+#[cfg(feature = "serde")]
+pub(crate) mod hex;
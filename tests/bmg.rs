@@ -0,0 +1,204 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nds::bmg::{Bmg, Encoding, Message};
+
+fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Adds a section, padded to 0x20 bytes.
+fn section(data: &mut Vec<u8>, magic: &[u8; 4], body: &[u8]) {
+    let len = (8 + body.len()).next_multiple_of(0x20);
+
+    data.extend_from_slice(magic);
+    data.extend_from_slice(&(len as u32).to_le_bytes());
+    data.extend_from_slice(body);
+    data.resize(data.len().next_multiple_of(0x20), 0);
+}
+
+/// A UTF-16 file as a game would have it: an empty message, then one with
+/// a color escape, with IDs.
+fn sample() -> Vec<u8> {
+    let mut text = vec![0, 0];
+    let second = text.len() as u32;
+
+    text.extend(utf16("Hi "));
+    text.extend_from_slice(&[0x1A, 0x00, 0x08, 0xFF, 0x00, 0x00, 0x02, 0x00]);
+    text.extend(utf16("Link{!\n"));
+    text.extend_from_slice(&[0, 0]);
+
+    let mut info = Vec::new();
+    info.extend_from_slice(&2u16.to_le_bytes());
+    info.extend_from_slice(&8u16.to_le_bytes());
+    info.extend_from_slice(&[0x07, 0x00, 0x00, 0x00]);
+    info.extend_from_slice(&0u32.to_le_bytes());
+    info.extend_from_slice(&[0, 0, 0, 0]);
+    info.extend_from_slice(&second.to_le_bytes());
+    info.extend_from_slice(&[1, 2, 3, 4]);
+
+    let mut ids = Vec::new();
+    ids.extend_from_slice(&2u16.to_le_bytes());
+    ids.extend_from_slice(&[0x10, 0x00, 0, 0, 0, 0]);
+    ids.extend_from_slice(&100u32.to_le_bytes());
+    ids.extend_from_slice(&101u32.to_le_bytes());
+
+    let mut data = b"MESGbmg1".to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&3u32.to_le_bytes());
+    data.push(2);
+    data.resize(0x20, 0);
+
+    section(&mut data, b"INF1", &info);
+    section(&mut data, b"DAT1", &text);
+    section(&mut data, b"MID1", &ids);
+
+    let len = data.len() as u32;
+    LittleEndian::write_u32(&mut data[0x08..], len);
+
+    data
+}
+
+#[test]
+fn parses_and_rewrites() {
+    let data = sample();
+    let bmg = Bmg::parse(&data).unwrap();
+
+    assert_eq!(bmg.encoding, Encoding::Utf16);
+    assert_eq!(bmg.info_header, [7, 0, 0, 0]);
+    assert_eq!(bmg.id_format, Some([0x10, 0x00]));
+    assert_eq!(
+        bmg.messages[1],
+        Message {
+            id: Some(101),
+            attributes: vec![1, 2, 3, 4],
+            text: "Hi {ff00000200}Link{{!\n".to_string(),
+        }
+    );
+    assert_eq!(bmg.messages[0].text, "");
+
+    assert_eq!(bmg.to_bytes().unwrap(), data);
+}
+
+#[test]
+fn recomputes_offsets() {
+    let mut bmg = Bmg::parse(&sample()).unwrap();
+
+    bmg.messages[0].text = "A longer first message".to_string();
+    bmg.messages[1].text.insert_str(0, "Well, ");
+
+    let data = bmg.to_bytes().unwrap();
+
+    assert_eq!(LittleEndian::read_u32(&data[0x08..]) as usize, data.len());
+    assert_eq!(data.len() % 0x20, 0);
+
+    let parsed = Bmg::parse(&data).unwrap();
+
+    assert_eq!(parsed, bmg);
+}
+
+#[test]
+fn encodes_8_bit_text() {
+    let mut bmg = Bmg::new(Encoding::ShiftJis);
+
+    bmg.messages.push(Message {
+        text: "あ{0102}ok".to_string(),
+        ..Message::default()
+    });
+
+    let data = bmg.to_bytes().unwrap();
+
+    //  The escape length counts the escape character and itself.
+    let text = 0x20 + 0x20 + 8;
+    assert_eq!(&data[text..text + 9], &[0, 0x82, 0xA0, 0x1A, 0x04, 0x01, 0x02, b'o', b'k']);
+    assert_eq!(Bmg::parse(&data).unwrap(), bmg);
+
+    let mut western = Bmg::new(Encoding::Cp1252);
+
+    western.messages.push(Message {
+        text: "Café".to_string(),
+        ..Message::default()
+    });
+
+    assert_eq!(Bmg::parse(&western.to_bytes().unwrap()).unwrap(), western);
+
+    western.messages[0].text = "あ".to_string();
+    assert!(western.to_bytes().is_err());
+
+    let mut unicode = Bmg::new(Encoding::Utf8);
+
+    unicode.messages.push(Message {
+        text: "Ünïcödé {aa}".to_string(),
+        ..Message::default()
+    });
+
+    assert_eq!(Bmg::parse(&unicode.to_bytes().unwrap()).unwrap(), unicode);
+}
+
+#[test]
+fn rejects_invalid_tags() {
+    let mut bmg = Bmg::new(Encoding::Utf16);
+
+    for text in &["{zz}", "{01", "{0102}"] {
+        bmg.messages = vec![Message {
+            text: text.to_string(),
+            ..Message::default()
+        }];
+
+        assert!(bmg.to_bytes().is_err(), "{}", text);
+    }
+
+    //  Escapes in UTF-16 must keep the characters aligned.
+    bmg.messages[0].text = "{010203}".to_string();
+    assert!(bmg.to_bytes().is_ok());
+
+    assert!(Bmg::parse(b"MESGbmg1").is_err());
+    assert!(Bmg::parse(&sample()[..0x30]).is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn exports_and_imports_json() {
+    let mut bmg = Bmg::parse(&sample()).unwrap();
+    let json = bmg.to_json();
+
+    assert!(json.contains("\"id\": 101"));
+    assert!(json.contains("\"attributes\": \"01020304\""));
+    assert!(json.contains("Hi {ff00000200}Link{{!\\n"));
+
+    //  Unchanged messages aren't counted.
+    assert_eq!(bmg.import_json(&json).unwrap(), 0);
+
+    let edited = json.replace("Hi {ff00000200}", "Salut {ff00000200}");
+    assert_eq!(bmg.import_json(&edited).unwrap(), 1);
+    assert_eq!(bmg.messages[1].text, "Salut {ff00000200}Link{{!\n");
+
+    assert!(bmg.import_json(r#"{"messages": [{"index": 5, "text": ""}]}"#).is_err());
+    assert!(bmg.import_json(r#"{"messages": [{"index": 0, "text": "{bad}"}]}"#).is_err());
+}
+
+#[test]
+fn exports_and_imports_po() {
+    let mut bmg = Bmg::parse(&sample()).unwrap();
+
+    bmg.messages[0].text = "Say \"hi\"\nthen leave".to_string();
+
+    let po = bmg.to_po();
+
+    assert!(po.contains("#. id 100\nmsgctxt \"0\"\nmsgid \"\"\n\"Say \\\"hi\\\"\\n\"\n\"then leave\"\nmsgstr \"\"\n"));
+    assert!(po.contains("msgctxt \"1\"\nmsgid \"Hi {ff00000200}Link{{!\\n\"\n"));
+
+    //  Nothing is translated yet.
+    assert_eq!(bmg.import_po(&po).unwrap(), 0);
+
+    let translated = po.replacen(
+        "msgstr \"\"\n\n#. id 101",
+        "msgstr \"\"\n\"Dis \\\"salut\\\"\\n\"\n\"puis pars\"\n\n#. id 101",
+        1,
+    );
+
+    assert_eq!(bmg.import_po(&translated).unwrap(), 1);
+    assert_eq!(bmg.messages[0].text, "Dis \"salut\"\npuis pars");
+    assert_eq!(bmg.messages[1].text, "Hi {ff00000200}Link{{!\n");
+
+    assert!(bmg.import_po("msgctxt \"x\"\nmsgid \"a\"\nmsgstr \"b\"\n").is_err());
+    assert!(bmg.import_po("msgctxt \"0\"\nmsgid \"a\nmsgstr \"b\"\n").is_err());
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use serde_json::Value;
+
+use std::fs::{create_dir_all, read, write};
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{build_with_files, TEST_HELLO_WORLD};
+
+fn nds(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nds"))
+        .args(args)
+        .output()
+        .expect("Could not run nds")
+}
+
+fn json(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).expect("Output is not JSON")
+}
+
+#[test]
+fn info_and_ls_print_json() {
+    let path = build_with_files("cli_info", &[("a/b.bin", b"nested")]);
+    let path = path.to_str().unwrap();
+
+    let info = nds(&["info", path, "--json"]);
+
+    assert!(info.status.success());
+    assert_eq!(json(&info)["title"], "HOMEBREW");
+    assert_eq!(json(&info)["arm9"]["load_address"], 0x0200_0000);
+    assert_eq!(json(&info)["banner"]["titles"].as_array().unwrap().len(), 6);
+
+    let ls = nds(&["ls", "--json", path]);
+    let entries = json(&ls);
+
+    assert!(ls.status.success());
+    assert_eq!(entries[0]["type"], "dir");
+    assert_eq!(entries[1]["path"], "a/b.bin");
+    assert_eq!(entries[1]["size"], 6);
+
+    let text = nds(&["ls", path]);
+
+    assert_eq!(String::from_utf8_lossy(&text.stdout).lines().collect::<Vec<_>>(), ["a/", "  b.bin  (id 0, offset 0x23E00, 6 bytes)"]);
+}
+
+#[test]
+fn cat_writes_file() {
+    let path = build_with_files("cli_cat", &[("data.bin", b"\x00\x01binary\xFF")]);
+    let path = path.to_str().unwrap();
+
+    let cat = nds(&["cat", path, "data.bin"]);
+
+    assert!(cat.status.success());
+    assert_eq!(cat.stdout, b"\x00\x01binary\xFF");
+
+    let missing = nds(&["cat", path, "missing.bin"]);
+
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(!missing.stderr.is_empty());
+}
+
+#[test]
+fn extract_and_build_round_trip() {
+    let root = Path::new("tmp").join("cli_round_trip");
+    let dir = root.join("extracted");
+    let built = root.join("built.nds");
+
+    create_dir_all(&root).unwrap();
+
+    assert!(nds(&["extract", TEST_HELLO_WORLD, dir.to_str().unwrap()]).status.success());
+    assert!(nds(&["build", dir.to_str().unwrap(), built.to_str().unwrap()]).status.success());
+    assert_eq!(read(built).unwrap(), read(TEST_HELLO_WORLD).unwrap());
+}
+
+#[test]
+fn verify_sets_exit_code() {
+    let good = nds(&["verify", TEST_HELLO_WORLD, "--json"]);
+
+    assert_eq!(good.status.code(), Some(0));
+    assert_eq!(json(&good)["valid"], true);
+
+    //  Break the banner checksum.
+    let mut data = read(TEST_HELLO_WORLD).unwrap();
+    let banner = u32::from_le_bytes([data[0x68], data[0x69], data[0x6A], data[0x6B]]) as usize;
+    data[banner + 0x40] ^= 0xFF;
+
+    let path = Path::new("tmp").join("cli_verify.nds");
+    create_dir_all("tmp").unwrap();
+    write(&path, data).unwrap();
+
+    let bad = nds(&["verify", path.to_str().unwrap(), "--json"]);
+    let checksums = json(&bad)["checksums"].clone();
+
+    assert_eq!(bad.status.code(), Some(1));
+    assert_eq!(checksums[0]["valid"], true);
+    assert_eq!(checksums[2]["name"], "banner");
+    assert_eq!(checksums[2]["valid"], false);
+}
+
+#[test]
+fn bad_arguments_fail() {
+    assert_eq!(nds(&[]).status.code(), Some(2));
+    assert_eq!(nds(&["unknown"]).status.code(), Some(2));
+    assert_eq!(nds(&["info"]).status.code(), Some(2));
+    assert_eq!(nds(&["info", TEST_HELLO_WORLD, "extra"]).status.code(), Some(2));
+    assert_eq!(nds(&["info", TEST_HELLO_WORLD, "--bogus"]).status.code(), Some(2));
+    assert!(nds(&["--help"]).status.success());
+}
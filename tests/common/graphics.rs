@@ -0,0 +1,24 @@
+//! Graphics shared by the tests of the graphics formats.
+
+use nds::graphics::ncgr::{Mapping, Tile, Tiles};
+use nds::graphics::nclr::ColorDepth;
+use nds::graphics::Rgba;
+
+/// Tiles without a size or position, using 1D mapping with a 32 byte
+/// boundary.
+pub fn tiles(depth: ColorDepth, tiles: Vec<Tile>) -> Tiles {
+    Tiles {
+        depth,
+        mapping: Mapping::OneD { boundary: 32 },
+        scanned: false,
+        size: None,
+        position: None,
+        tiles,
+    }
+}
+
+/// `count` opaque colors that get brighter, where color `n` is red
+/// `n * 256 / count`.
+pub fn reds(count: usize) -> Vec<Rgba> {
+    (0..count).map(|index| [(index * 256 / count) as u8, 0, 0, 0xFF]).collect()
+}
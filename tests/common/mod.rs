@@ -0,0 +1,78 @@
+#![allow(dead_code)]
+
+pub mod graphics;
+
+use nds::{Builder, Extractor};
+use nitro_fs::container::{NitroFile, Section};
+
+use std::fs::{create_dir_all, read, remove_dir_all, remove_file, write};
+use std::path::{Path, PathBuf};
+
+pub const TEST_HELLO_WORLD: &str = "tests/test_nds_files/hello_world.nds";
+pub const TEST_3D_BOTH_SCREENS: &str = "tests/test_nds_files/3D_Both_Screens.nds";
+
+/// Writes a version 1.0 Nitro file with the given sections.
+pub fn nitro_file(magic: &[u8; 4], sections: Vec<(&[u8; 4], Vec<u8>)>) -> Vec<u8> {
+    let mut file = NitroFile::new(*magic, 0x0100);
+
+    for (magic, data) in sections {
+        file.sections.push(Section::new(*magic, data));
+    }
+
+    file.to_bytes()
+}
+
+/// Builds a copy of the hello world ROM that has the given files in its
+/// file system. Everything is written to `tmp/<name>`, and the path of the
+/// built ROM is returned.
+pub fn build_with_files(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    build_with_edit(name, files, |_| ())
+}
+
+/// Same as [`build_with_files`], but clears the DSi bit of the unit code so
+/// that the ROM has no DSi region.
+///
+/// The bit is cleared in `header.bin`, and the manifest is removed so that
+/// the header fields in it don't take its place.
+pub fn build_ntr_with_files(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    build_with_edit(name, files, |dir| {
+        let path = dir.join("header.bin");
+        let mut header = read(&path).expect("Could not read header");
+
+        header[0x12] &= !0x02;
+        write(path, header).expect("Could not write header");
+
+        let _ = remove_file(dir.join("rom.toml"));
+    })
+}
+
+/// Same as [`build_with_files`], but calls `edit` with the extracted
+/// directory before building.
+pub fn build_with_edit<F: FnOnce(&Path)>(name: &str, files: &[(&str, &[u8])], edit: F) -> PathBuf {
+    let root = Path::new("tmp").join(name);
+    let dir = root.join("extracted");
+    let rom = root.join("built.nds");
+
+    let _ = remove_dir_all(&root);
+
+    Extractor::new(TEST_HELLO_WORLD, true)
+        .expect("Could not make Extractor")
+        .extract(&dir)
+        .expect("Could not extract");
+
+    for (path, data) in files {
+        let path = dir.join("data").join(path);
+
+        create_dir_all(path.parent().unwrap()).expect("Could not create directory");
+        write(path, data).expect("Could not write file");
+    }
+
+    edit(&dir);
+
+    Builder::new(&dir)
+        .expect("Could not create builder")
+        .build(&rom)
+        .expect("Could not build");
+
+    rom
+}
@@ -0,0 +1,84 @@
+use nds::compression::{compress, decompress, detect_and_decompress, detect_compression, Compression};
+
+const CODECS: [Compression; 3] = [Compression::Lz10, Compression::Lz11, Compression::Rle];
+
+/// Data with long runs, repeated phrases and some noise, similar to what
+/// is usually found in game files.
+fn sample() -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut seed: u32 = 0x1234_5678;
+
+    for index in 0..0x3000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+
+        match index % 0x400 {
+            0..=0xFF => data.push(0),
+            0x100..=0x1FF => data.extend_from_slice(b"nitro"),
+            _ => data.push((seed >> 16) as u8),
+        }
+    }
+
+    data
+}
+
+#[test]
+fn round_trip() {
+    let data = sample();
+
+    for &codec in &CODECS {
+        let compressed = compress(&data, codec).expect("Could not compress");
+
+        assert_eq!(compressed[0], codec.id());
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed).expect("Could not decompress"), data);
+    }
+}
+
+#[test]
+fn round_trip_large_lz11() {
+    let data = vec![0xAB; 0x100_0010];
+    let compressed = compress(&data, Compression::Lz11).expect("Could not compress");
+
+    assert_eq!(decompress(&compressed).expect("Could not decompress"), data);
+}
+
+#[test]
+fn detects_compressed_data() {
+    let data = sample();
+
+    for &codec in &CODECS {
+        let compressed = compress(&data, codec).expect("Could not compress");
+
+        assert_eq!(detect_compression(&compressed), Some(codec));
+    }
+}
+
+#[test]
+fn detects_and_decompresses_at_once() {
+    let data = sample();
+    let compressed = compress(&data, Compression::Lz11).expect("Could not compress");
+
+    assert_eq!(detect_and_decompress(&compressed), Some((Compression::Lz11, data.clone())));
+    assert_eq!(detect_and_decompress(&data), None);
+}
+
+#[test]
+fn rejects_plain_data() {
+    let mut data = sample();
+
+    assert_eq!(detect_compression(&data), None);
+
+    //  A valid header on its own shouldn't be enough.
+    data[0] = 0x10;
+    assert_eq!(detect_compression(&data), None);
+
+    assert_eq!(detect_compression(&[0x10, 0, 0]), None);
+}
+
+#[test]
+fn names_round_trip() {
+    for &codec in &CODECS {
+        assert_eq!(codec.name().parse::<Compression>().unwrap(), codec);
+        assert_eq!(Compression::from_id(codec.id()), Some(codec));
+    }
+}
@@ -0,0 +1,148 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nitro_fs::container::{NitroFile, Section};
+use nitro_fs::fat::AllocInfo;
+use nitro_fs::FileSystem;
+
+use std::fs::{create_dir_all, read, remove_dir_all, write};
+use std::path::Path;
+
+fn sample() -> NitroFile {
+    let mut file = NitroFile::new(*b"RLCN", 0x0100);
+
+    file.sections.push(Section::new(*b"TTLP", vec![1, 2, 3, 4, 5, 6, 7, 8]));
+    file.sections.push(Section::new(*b"PMCP", vec![9, 10, 11, 12]));
+
+    file
+}
+
+#[test]
+fn round_trips() {
+    let file = sample();
+    let data = file.to_bytes();
+
+    assert_eq!(&data[..4], b"RLCN");
+    assert_eq!(LittleEndian::read_u16(&data[4..]), 0xFEFF);
+    assert_eq!(LittleEndian::read_u32(&data[8..]) as usize, data.len());
+    assert_eq!(LittleEndian::read_u16(&data[0xE..]), 2);
+    assert_eq!(&data[0x10..0x14], b"TTLP");
+    assert_eq!(LittleEndian::read_u32(&data[0x14..]), 0x10);
+
+    let parsed = NitroFile::parse_as(&data, b"RLCN").unwrap();
+
+    assert_eq!(parsed, file);
+    assert_eq!(parsed.section(b"PMCP").unwrap().data, [9, 10, 11, 12]);
+    assert!(parsed.section(b"RAHC").is_none());
+    assert!(parsed.require(b"RAHC").is_err());
+    assert!(NitroFile::parse_as(&data, b"RGCN").is_err());
+}
+
+#[test]
+fn keeps_longer_headers() {
+    let mut file = sample();
+    file.extra_header = vec![0xAA; 0x30];
+
+    let data = file.to_bytes();
+
+    assert_eq!(LittleEndian::read_u16(&data[0xC..]), 0x40);
+    assert_eq!(NitroFile::parse(&data).unwrap(), file);
+}
+
+#[test]
+fn rejects_invalid_sizes() {
+    let data = sample().to_bytes();
+
+    let mut bom = data.clone();
+    bom[4] = 0xFE;
+    bom[5] = 0xFF;
+    assert!(NitroFile::parse(&bom).is_err());
+
+    let mut file_size = data.clone();
+    LittleEndian::write_u32(&mut file_size[8..], data.len() as u32 + 1);
+    assert!(NitroFile::parse(&file_size).is_err());
+
+    let mut section_size = data.clone();
+    LittleEndian::write_u32(&mut section_size[0x14..], 0x1000);
+    assert!(NitroFile::parse(&section_size).is_err());
+
+    let mut too_small = data.clone();
+    LittleEndian::write_u32(&mut too_small[0x14..], 4);
+    assert!(NitroFile::parse(&too_small).is_err());
+
+    assert!(NitroFile::parse(&data[..8]).is_err());
+}
+
+/// Makes a NARC with the given files, with or without file names.
+fn narc(name: &str, files: &[(&str, &[u8])], named: bool) -> Vec<u8> {
+    let source = Path::new("tmp").join(name).join("source");
+    let _ = remove_dir_all(&source);
+
+    for (path, data) in files {
+        let path = source.join(path);
+
+        create_dir_all(path.parent().unwrap()).unwrap();
+        write(path, data).unwrap();
+    }
+
+    let mut fs = FileSystem::from_path(&source, 0).unwrap();
+    let mut image = Vec::new();
+    let mut entries = fs.files().iter().map(|file| (file.id, file.path.clone())).collect::<Vec<_>>();
+
+    entries.sort();
+
+    for (id, path) in entries {
+        let data = read(source.join(path)).unwrap();
+        let start = image.len() as u32;
+
+        image.extend_from_slice(&data);
+        fs.set_alloc(id, AllocInfo { start, end: image.len() as u32 });
+
+        image.resize(image.len().next_multiple_of(4), 0xFF);
+    }
+
+    let mut fat = vec![0; 4];
+    LittleEndian::write_u16(&mut fat, files.len() as u16);
+    fat.extend_from_slice(&fs.to_fat().unwrap());
+
+    let fnt = if named {
+        fs.to_fnt().unwrap()
+    } else {
+        vec![4, 0, 0, 0, 0, 0, 1, 0]
+    };
+
+    let mut file = NitroFile::new(*b"NARC", 0x0100);
+
+    file.sections.push(Section::new(*b"BTAF", fat));
+    file.sections.push(Section::new(*b"BTNF", fnt));
+    file.sections.push(Section::new(*b"GMIF", image));
+
+    file.to_bytes()
+}
+
+fn extract(name: &str, data: &[u8]) -> std::path::PathBuf {
+    let root = Path::new("tmp").join(name);
+    let _ = remove_dir_all(root.join("out"));
+    write(root.join("archive.narc"), data).unwrap();
+
+    narc::Extractor::new(root.join("archive.narc"))
+        .expect("Could not open NARC")
+        .extract(root.join("out"))
+        .expect("Could not extract NARC");
+
+    root.join("out")
+}
+
+#[test]
+fn extracts_named_narc() {
+    let out = extract("narc_named", &narc("narc_named", &[("a/one.bin", b"first"), ("two.txt", b"second!")], true));
+
+    assert_eq!(read(out.join("a/one.bin")).unwrap(), b"first");
+    assert_eq!(read(out.join("two.txt")).unwrap(), b"second!");
+}
+
+#[test]
+fn extracts_unnamed_narc() {
+    let out = extract("narc_unnamed", &narc("narc_unnamed", &[("x", b"first"), ("y", b"second!")], false));
+
+    assert_eq!(read(out.join("0000.bin")).unwrap(), b"first");
+    assert_eq!(read(out.join("0001.bin")).unwrap(), b"second!");
+}
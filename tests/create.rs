@@ -0,0 +1,171 @@
+mod common;
+
+use nds::banner::Banner;
+use nds::graphics::IndexedImage;
+use nds::util::crc::crc16;
+use nds::{Binary, Creator, Rom};
+
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::path::Path;
+
+use common::TEST_HELLO_WORLD;
+
+#[test]
+fn creates_rom_from_binary() {
+    let arm9 = (0..0x1234).map(|byte| byte as u8).collect::<Vec<_>>();
+    let mut creator = Creator::new(Binary::new(arm9.clone(), 0x0200_0000));
+
+    creator.set_title("HELLO");
+
+    let rom = Rom::from_bytes(creator.create().expect("Could not create ROM"), true).expect("Could not open ROM");
+    let header = rom.header();
+
+    assert_eq!(header.game_title, "HELLO");
+    assert_eq!(header.gamecode, "####");
+    assert_eq!(header.arm9.rom_offset, 0x4000);
+    assert_eq!(header.arm9.load_address, 0x0200_0000);
+    assert_eq!(header.arm9.entry_address, 0x0200_0000);
+    assert_eq!(header.nintendo_logo_crc, 0xCF56);
+    assert_eq!(crc16(&header.nintendo_logo), 0xCF56);
+    assert!(rom.data().len() <= 0x2_0000 << header.devicecapacity);
+    assert_eq!(rom.arm9().unwrap(), &arm9[..]);
+    assert!(rom.file_system().files().is_empty());
+
+    let offset = header.icon_banner_offset as usize;
+    let banner = Banner::parse(&rom.data()[offset..]).expect("Could not parse banner");
+
+    assert_eq!(banner.titles, vec!["HELLO"; 6]);
+}
+
+#[test]
+fn creates_rom_with_file_system() {
+    let root = Path::new("tmp/create_fs");
+    let _ = remove_dir_all(root);
+
+    create_dir_all(root.join("data/sub")).unwrap();
+    write(root.join("data/readme.txt"), b"hello").unwrap();
+    write(root.join("data/sub/data.bin"), [1, 2, 3]).unwrap();
+
+    let mut creator = Creator::new(Binary::new(vec![0; 0x100], 0x0200_0000));
+    let mut arm7 = Binary::new(vec![0xAB; 0x80], 0x0238_0000);
+    arm7.entry_address = 0x0238_0040;
+
+    creator.set_arm7(arm7);
+    creator.set_data_dir(root.join("data"));
+    creator.set_gamecode("ABCE");
+    creator.set_makercode("01");
+    creator.build(root.join("created.nds")).expect("Could not create ROM");
+
+    let rom = Rom::new(root.join("created.nds"), true).expect("Could not open ROM");
+
+    assert_eq!(rom.header().gamecode, "ABCE");
+    assert_eq!(rom.header().makercode, "01");
+    assert_eq!(rom.header().arm7.entry_address, 0x0238_0040);
+    assert_eq!(rom.arm7().unwrap(), &[0xAB; 0x80][..]);
+    assert_eq!(rom.open("readme.txt").unwrap(), b"hello");
+    assert_eq!(rom.open("sub/data.bin").unwrap(), &[1, 2, 3]);
+}
+
+#[test]
+fn creates_rom_with_icon() {
+    let mut image = IndexedImage::new(32, 32);
+    let palette = vec![[0, 0, 0, 0], [0xFF, 0, 0, 0xFF], [0, 0xFF, 0, 0xFF]];
+
+    for (index, pixel) in image.pixels.iter_mut().enumerate() {
+        *pixel = (index % 3) as u8;
+    }
+
+    let mut creator = Creator::new(Binary::new(vec![0; 4], 0x0200_0000));
+    creator.set_title("ICON");
+    creator.set_icon(image.clone(), palette.clone());
+
+    let rom = Rom::from_bytes(creator.create().expect("Could not create ROM"), true).expect("Could not open ROM");
+    let banner = rom.banner().unwrap().expect("ROM has no banner");
+
+    assert_eq!(banner.icon_image(), image);
+    assert_eq!(banner.icon_palette()[..3], palette[..]);
+    assert_eq!(banner.titles, vec!["ICON"; 6]);
+}
+
+#[test]
+fn rejects_invalid_icon() {
+    let mut creator = Creator::new(Binary::new(vec![0; 4], 0x0200_0000));
+
+    creator.set_icon(IndexedImage::new(16, 16), vec![[0; 4]]);
+    assert!(creator.create().is_err());
+
+    creator.set_icon(IndexedImage::new(32, 32), vec![[0; 4]; 17]);
+    assert!(creator.create().is_err());
+
+    let mut image = IndexedImage::new(32, 32);
+    image.pixels[5] = 2;
+
+    creator.set_icon(image, vec![[0; 4]; 2]);
+    assert!(creator.create().is_err());
+}
+
+#[test]
+fn rejects_invalid_header_fields() {
+    let mut creator = Creator::new(Binary::new(vec![0; 4], 0x0200_0000));
+
+    creator.set_gamecode("TOOLONG");
+    assert!(creator.create().is_err());
+
+    creator.set_gamecode("ABCE");
+    creator.set_title("A TITLE THAT IS TOO LONG");
+    assert!(creator.create().is_err());
+}
+
+#[test]
+fn banner_round_trips() {
+    let rom = Rom::new(TEST_HELLO_WORLD, true).expect("Could not open ROM");
+    let offset = rom.header().icon_banner_offset as usize;
+    let data = &rom.data()[offset..offset + 0x840];
+
+    let banner = Banner::parse(data).expect("Could not parse banner");
+
+    assert_eq!(banner.version, 1);
+    assert_eq!(banner.to_bytes(), data);
+}
+
+#[test]
+fn renders_icon() {
+    let rom = Rom::new(TEST_HELLO_WORLD, true).expect("Could not open ROM");
+    let banner = rom.banner().unwrap().expect("ROM has no banner");
+
+    let image = banner.icon_image();
+    let icon = banner.icon();
+
+    assert_eq!((icon.width, icon.height), (32, 32));
+
+    //  The second pixel of the first row is the high nibble of the first
+    //  byte of the bitmap.
+    assert_eq!(image.pixels[1], banner.bitmap[0] >> 4);
+
+    //  The first row of the second tile starts 32 bytes in.
+    assert_eq!(image.pixels[8], banner.bitmap[32] & 0xF);
+
+    for (index, &color) in image.pixels.iter().enumerate() {
+        let expected = if color == 0 { 0 } else { 0xFF };
+        assert_eq!(icon.pixels[index * 4 + 3], expected);
+    }
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn extracts_icon_png() {
+    use nds::Extractor;
+
+    let dir = Path::new("tmp").join("icon_png");
+    let _ = remove_dir_all(&dir);
+
+    let mut extractor = Extractor::new(TEST_HELLO_WORLD, true).expect("Could not make Extractor");
+    extractor.set_icon_png(true);
+    extractor.extract(&dir).expect("Could not extract");
+
+    let banner = Banner::parse(&std::fs::read(dir.join("banner.bin")).unwrap()).unwrap();
+    let (image, palette) = IndexedImage::from_png(&std::fs::read(dir.join("icon.png")).unwrap()).unwrap();
+
+    assert_eq!(image, banner.icon_image());
+    assert_eq!(palette, banner.icon_palette());
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use nds::dat::{Dat, Identification};
+use nds::util::hash::Hashes;
+use nds::Rom;
+
+use std::fs::read;
+
+use common::TEST_HELLO_WORLD;
+
+/// The hello world ROM with the gamecode of a retail game.
+fn retail_rom(data: &[u8]) -> Rom {
+    let mut data = data.to_vec();
+    data[0xC..0x10].copy_from_slice(b"ABCE");
+
+    Rom::from_bytes(data, false).expect("Could not open ROM")
+}
+
+fn entry(name: &str, hashes: &Hashes) -> String {
+    format!(
+        r#"<game name="{name}"><description>{name}</description><rom name="{name}.nds" size="{}" crc="{:08X}" md5="{}" sha1="{}" serial="NTR-ABCE-USA"/></game>"#,
+        hashes.size,
+        hashes.crc32,
+        hashes.md5_hex(),
+        hashes.sha1_hex(),
+    )
+}
+
+fn dat(games: &[String]) -> Dat {
+    let text = format!(
+        r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/dtds/datafile.dtd">
+<datafile>
+    <header>
+        <name>Nintendo - Nintendo DS</name>
+        <description>Nintendo - Nintendo DS</description>
+        <version>20240101</version>
+    </header>
+    {}
+</datafile>"#,
+        games.concat()
+    );
+
+    Dat::parse(&text).expect("Could not parse DAT")
+}
+
+#[test]
+fn hashes_match_known_values() {
+    assert_eq!(Hashes::new(b"123456789").crc32, 0xCBF4_3926);
+    assert_eq!(Hashes::new(b"abc").md5_hex(), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(Hashes::new(b"abc").sha1_hex(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+    let data = read(TEST_HELLO_WORLD).unwrap();
+    let rom = Rom::new(TEST_HELLO_WORLD, true).unwrap();
+
+    assert_eq!(rom.hashes().md5, md5::compute(&data).0);
+
+    let files = rom.file_hashes().unwrap();
+
+    assert_eq!(files.len(), rom.file_system().overlays().len() + rom.file_system().files().len());
+}
+
+#[test]
+fn parses_header_and_games() {
+    let dat = dat(&[entry("Game (USA)", &Hashes::new(b"game"))]);
+
+    assert_eq!(dat.name, "Nintendo - Nintendo DS");
+    assert_eq!(dat.version, "20240101");
+    assert_eq!(dat.games.len(), 1);
+    assert_eq!(dat.games[0].roms[0].size, 4);
+    assert_eq!(dat.games[0].roms[0].serial.as_deref(), Some("NTR-ABCE-USA"));
+    assert!(Dat::parse("<notadat/>").is_err());
+}
+
+#[test]
+fn identifies_verified_dump() {
+    let data = read(TEST_HELLO_WORLD).unwrap();
+    let rom = retail_rom(&data);
+
+    let dat = dat(&[
+        entry("Game (USA) (Rev 1)", &Hashes::new(b"other revision")),
+        entry("Game (USA)", &rom.hashes()),
+    ]);
+
+    let candidates = dat.candidates(rom.header());
+
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates[0].name, "Game (USA)");
+
+    match dat.identify(&rom) {
+        Identification::Verified(game) => assert_eq!(game.name, "Game (USA)"),
+        other => panic!("Expected a verified dump, got {:?}", other),
+    }
+}
+
+#[test]
+fn identifies_trimmed_and_bad_dumps() {
+    let data = read(TEST_HELLO_WORLD).unwrap();
+    let rom = retail_rom(&data);
+
+    let mut untrimmed = rom.data().to_vec();
+    untrimmed.resize(untrimmed.len() + 0x1234, 0xFF);
+
+    let trimmed = dat(&[entry("Game (USA)", &Hashes::new(&untrimmed))]);
+
+    match trimmed.identify(&rom) {
+        Identification::Trimmed(game) => assert_eq!(game.name, "Game (USA)"),
+        other => panic!("Expected a trimmed dump, got {:?}", other),
+    }
+
+    let bad = dat(&[entry("Game (USA)", &Hashes::new(b"something else"))]);
+
+    assert!(matches!(bad.identify(&rom), Identification::BadDump(games) if games.len() == 1));
+
+    let homebrew = Rom::new(TEST_HELLO_WORLD, true).unwrap();
+
+    assert_eq!(bad.identify(&homebrew), Identification::Unknown);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use nds::diff::{FileChange, Rename, RomDiff};
+use nds::Rom;
+
+use std::path::PathBuf;
+
+use common::{build_with_files, TEST_3D_BOTH_SCREENS, TEST_HELLO_WORLD};
+
+#[test]
+fn same_rom_has_no_differences() {
+    let rom = Rom::new(TEST_HELLO_WORLD, true).expect("Could not open ROM");
+    let diff = RomDiff::new(&rom, &rom).unwrap();
+
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "ROMs are identical.\n");
+}
+
+#[test]
+fn finds_file_changes() {
+    let old = build_with_files("diff_old", &[
+        ("same.bin", b"unchanged"),
+        ("edited.bin", b"before"),
+        ("gone.bin", b"removed"),
+        ("moved.bin", b"moved around"),
+    ]);
+    let new = build_with_files("diff_new", &[
+        ("same.bin", b"unchanged"),
+        ("edited.bin", b"after!!"),
+        ("dir/moved.bin", b"moved around"),
+        ("new.bin", b"added"),
+    ]);
+
+    let old = Rom::new(old, true).expect("Could not open ROM");
+    let new = Rom::new(new, true).expect("Could not open ROM");
+    let diff = RomDiff::new(&old, &new).unwrap();
+
+    assert_eq!(diff.added, vec![PathBuf::from("new.bin")]);
+    assert_eq!(diff.removed, vec![PathBuf::from("gone.bin")]);
+    assert_eq!(diff.renamed, vec![Rename {
+        from: PathBuf::from("moved.bin"),
+        to: PathBuf::from("dir/moved.bin"),
+    }]);
+    assert_eq!(diff.changed, vec![FileChange {
+        path: PathBuf::from("edited.bin"),
+        old_size: 6,
+        new_size: 7,
+    }]);
+
+    assert!(!diff.arm9);
+    assert!(!diff.arm7);
+
+    let report = diff.to_string();
+
+    assert!(report.contains("  + new.bin\n"));
+    assert!(report.contains("  - gone.bin\n"));
+    assert!(report.contains("  > moved.bin -> dir/moved.bin\n"));
+    assert!(report.contains("  ~ edited.bin (6 -> 7 bytes)\n"));
+}
+
+#[test]
+fn finds_header_and_code_changes() {
+    let old = Rom::new(TEST_HELLO_WORLD, true).expect("Could not open ROM");
+    let new = Rom::new(TEST_3D_BOTH_SCREENS, true).expect("Could not open ROM");
+    let diff = RomDiff::new(&old, &new).unwrap();
+
+    assert!(diff.arm9);
+    assert!(diff.header.iter().any(|change| change.field == "arm9.size"));
+    assert!(diff.header.iter().any(|change| change.field == "header_crc"));
+    assert!(diff.to_string().contains("  ~ arm9\n"));
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use nds::dldi::{find_stub, patch, patch_rom, Driver, MAGIC};
+use nds::Rom;
+
+use std::fs::{read, OpenOptions};
+use std::io::Write;
+
+use common::build_with_edit;
+
+const DRIVER_ADDRESS: u32 = 0xBF80_0000;
+const STUB_ADDRESS: u32 = 0x0201_0000;
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// A stub that reserves 1KiB at `STUB_ADDRESS`, filled with junk.
+fn stub() -> Vec<u8> {
+    let mut stub = vec![0xAA; 0x400];
+
+    stub[..MAGIC.len()].copy_from_slice(MAGIC);
+    stub[0x0C] = 1;
+    stub[0x0D] = 10;
+    stub[0x0E] = 0;
+    stub[0x0F] = 10;
+    write_u32(&mut stub, 0x40, STUB_ADDRESS);
+
+    stub
+}
+
+/// Features that the driver says it has, which aren't an address.
+const FEATURES: u32 = 0x23;
+
+/// A 512 byte driver built for `DRIVER_ADDRESS`, with a pointer into itself
+/// at 0x98 and a BSS at 0x100..0x180.
+fn driver() -> Driver {
+    let mut data = vec![0; 0x100];
+
+    data[..MAGIC.len()].copy_from_slice(MAGIC);
+    data[0x0C] = 1;
+    data[0x0D] = 9;
+    data[0x0E] = 0x01 | 0x08;
+    data[0x10..0x1B].copy_from_slice(b"Test Driver");
+    data[0x60..0x64].copy_from_slice(b"TEST");
+
+    for (offset, value) in &[
+        (0x40, 0x000),
+        (0x44, 0x100),
+        (0x48, 0x100),
+        (0x4C, 0x100),
+        (0x50, 0x100),
+        (0x54, 0x100),
+        (0x58, 0x100),
+        (0x5C, 0x180),
+        (0x68, 0x80),
+        (0x6C, 0x84),
+        (0x70, 0x88),
+        (0x74, 0x8C),
+        (0x78, 0x90),
+        (0x7C, 0x94),
+        (0x98, 0xA0),
+    ] {
+        write_u32(&mut data, *offset, DRIVER_ADDRESS + value);
+    }
+
+    write_u32(&mut data, 0x64, FEATURES);
+
+    //  Not an address in the driver, so it must be left alone.
+    write_u32(&mut data, 0x9C, 0x1234_5678);
+
+    Driver::new(data).expect("Could not parse driver")
+}
+
+#[test]
+fn parses_driver() {
+    let driver = driver();
+
+    assert_eq!(driver.name(), "Test Driver");
+    assert_eq!(driver.io_type(), "TEST");
+    assert_eq!(driver.size(), 0x200);
+    assert!(Driver::new(vec![0; 0x100]).is_err());
+}
+
+#[test]
+fn patches_and_relocates() {
+    let mut binary = vec![0x55; 0x123];
+    binary.extend(stub());
+    binary.extend(vec![0x66; 0x40]);
+
+    let offset = find_stub(&binary).unwrap();
+    assert_eq!(offset, 0x123);
+
+    patch(&mut binary, &driver()).expect("Could not patch");
+
+    let stub = &binary[offset..];
+
+    //  The stub keeps its reserved size, and everything pointing into the
+    //  driver now points into the stub.
+    assert_eq!(stub[0x0F], 10);
+    assert_eq!(read_u32(stub, 0x40), STUB_ADDRESS);
+    assert_eq!(read_u32(stub, 0x5C), STUB_ADDRESS + 0x180);
+    assert_eq!(read_u32(stub, 0x64), FEATURES);
+    assert_eq!(read_u32(stub, 0x68), STUB_ADDRESS + 0x80);
+    assert_eq!(read_u32(stub, 0x7C), STUB_ADDRESS + 0x94);
+    assert_eq!(read_u32(stub, 0x98), STUB_ADDRESS + 0xA0);
+    assert_eq!(read_u32(stub, 0x9C), 0x1234_5678);
+    assert!(stub[0x100..0x180].iter().all(|&byte| byte == 0));
+    assert!(stub[0x180..0x400].iter().all(|&byte| byte == 0xAA));
+    assert!(binary[..0x123].iter().all(|&byte| byte == 0x55));
+    assert!(binary[0x523..].iter().all(|&byte| byte == 0x66));
+}
+
+#[test]
+fn finds_address_of_old_stubs() {
+    //  Old stubs leave the start of the text out, but their startup
+    //  function is the start of the code.
+    let mut stub = stub();
+    write_u32(&mut stub, 0x40, 0);
+    write_u32(&mut stub, 0x68, STUB_ADDRESS + 0x80);
+
+    patch(&mut stub, &driver()).expect("Could not patch");
+
+    assert_eq!(read_u32(&stub, 0x40), STUB_ADDRESS);
+    assert_eq!(read_u32(&stub, 0x98), STUB_ADDRESS + 0xA0);
+}
+
+#[test]
+fn refuses_bad_stubs() {
+    assert!(patch(&mut vec![0; 0x1000], &driver()).is_err());
+
+    let mut small = stub();
+    small[0x0F] = 8;
+    assert!(patch(&mut small, &driver()).is_err());
+
+    let mut other_version = stub();
+    other_version[0x0C] = 2;
+    assert!(patch(&mut other_version, &driver()).is_err());
+}
+
+#[test]
+fn patches_rom() {
+    let path = build_with_edit("dldi_rom", &[], |dir| {
+        let mut arm9 = OpenOptions::new().append(true).open(dir.join("arm9.bin")).unwrap();
+        arm9.write_all(&stub()).unwrap();
+    });
+
+    let mut rom = Rom::from_bytes(read(path).unwrap(), true).expect("Could not open ROM");
+
+    patch_rom(&mut rom, &driver()).expect("Could not patch ROM");
+
+    let arm9 = rom.arm9().unwrap();
+    let offset = find_stub(arm9).unwrap();
+
+    assert_eq!(&arm9[offset + 0x10..offset + 0x1B], b"Test Driver");
+    assert_eq!(read_u32(arm9, offset + 0x98), STUB_ADDRESS + 0xA0);
+}
@@ -0,0 +1,129 @@
+use nds::elf::Elf;
+use nds::{Binary, Creator, Rom};
+
+use std::fs::{create_dir_all, write};
+
+/// Program headers as (type, virtual address, load address, data, memory size).
+type Segment<'a> = (u32, u32, u32, &'a [u8], u32);
+
+/// Makes a minimal ARM ELF file with the given program headers.
+fn elf(entry: u32, segments: &[Segment]) -> Vec<u8> {
+    let mut data = vec![0; 0x34];
+
+    data[..4].copy_from_slice(b"\x7FELF");
+    data[4] = 1;
+    data[5] = 1;
+    data[6] = 1;
+    data[0x10..0x12].copy_from_slice(&2u16.to_le_bytes());
+    data[0x12..0x14].copy_from_slice(&40u16.to_le_bytes());
+    data[0x18..0x1C].copy_from_slice(&entry.to_le_bytes());
+    data[0x1C..0x20].copy_from_slice(&0x34u32.to_le_bytes());
+    data[0x2A..0x2C].copy_from_slice(&0x20u16.to_le_bytes());
+    data[0x2C..0x2E].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+    let mut offset = 0x34 + segments.len() * 0x20;
+
+    for (kind, virtual_address, load_address, contents, memory_size) in segments {
+        for value in &[
+            *kind,
+            offset as u32,
+            *virtual_address,
+            *load_address,
+            contents.len() as u32,
+            *memory_size,
+            7,
+            4,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        offset += contents.len();
+    }
+
+    for (_, _, _, contents, _) in segments {
+        data.extend_from_slice(contents);
+    }
+
+    data
+}
+
+fn arm9_elf() -> Vec<u8> {
+    elf(0x0200_0010, &[
+        //  ITCM code that runs at 0x01FF8000 but is loaded after the main code.
+        (1, 0x01FF_8000, 0x0200_0100, &[0xCC; 8], 8),
+        (1, 0x0200_0000, 0x0200_0000, &[0xAA; 0x20], 0x20),
+        //  BSS, which only takes memory.
+        (1, 0x0200_0108, 0x0200_0108, &[], 0x400),
+        //  Not loadable.
+        (4, 0, 0, &[0xEE; 4], 4),
+    ])
+}
+
+#[test]
+fn reads_loadable_segments() {
+    let elf = Elf::parse(&arm9_elf()).expect("Could not parse ELF");
+
+    assert_eq!(elf.entry_address, 0x0200_0010);
+    assert_eq!(elf.segments.len(), 2);
+    assert_eq!(elf.segments[0].load_address, 0x0200_0000);
+    assert_eq!(elf.segments[1].virtual_address, 0x01FF_8000);
+    assert_eq!(elf.segments[1].load_address, 0x0200_0100);
+}
+
+#[test]
+fn flattens_segments_at_load_addresses() {
+    let binary = Binary::from_elf(&arm9_elf()).expect("Could not load ELF");
+
+    assert_eq!(binary.load_address, 0x0200_0000);
+    assert_eq!(binary.entry_address, 0x0200_0010);
+    assert_eq!(binary.data.len(), 0x108);
+    assert_eq!(&binary.data[..0x20], &[0xAA; 0x20][..]);
+    assert!(binary.data[0x20..0x100].iter().all(|&byte| byte == 0));
+    assert_eq!(&binary.data[0x100..], &[0xCC; 8][..]);
+}
+
+#[test]
+fn places_tcm_sections_at_load_addresses() {
+    //  DTCM is mapped far past main RAM, so placing it at its VMA would need
+    //  a binary of over 100MB.
+    let data = elf(0x0200_0000, &[
+        (1, 0x0200_0000, 0x0200_0000, &[0xAA; 0x10], 0x10),
+        (1, 0x0B00_0000, 0x0200_0010, &[0xDD; 4], 4),
+    ]);
+
+    let elf = Elf::parse(&data).expect("Could not parse ELF");
+    assert_eq!(elf.segments[1].virtual_address, 0x0B00_0000);
+
+    let binary = Binary::from_elf(&data).expect("Could not load ELF");
+
+    assert_eq!(binary.load_address, 0x0200_0000);
+    assert_eq!(binary.data.len(), 0x14);
+    assert_eq!(&binary.data[0x10..], &[0xDD; 4][..]);
+}
+
+#[test]
+fn rejects_invalid_files() {
+    assert!(Elf::parse(b"not an elf").is_err());
+
+    let mut not_arm = arm9_elf();
+    not_arm[0x12] = 3;
+    assert!(Elf::parse(&not_arm).is_err());
+
+    //  Segments that would need a binary larger than memory.
+    let far_apart = elf(0, &[(1, 0, 0x0200_0000, &[1], 1), (1, 0, 0x0800_0000, &[1], 1)]);
+    assert!(Binary::from_elf(&far_apart).is_err());
+    assert!(Binary::from_elf(&elf(0, &[])).is_err());
+}
+
+#[test]
+fn creates_rom_from_elf() {
+    create_dir_all("tmp/elf").unwrap();
+    write("tmp/elf/arm9.elf", arm9_elf()).unwrap();
+
+    let creator = Creator::from_arm9("tmp/elf/arm9.elf").expect("Could not load ARM9");
+    let rom = Rom::from_bytes(creator.create().unwrap(), true).expect("Could not open ROM");
+
+    assert_eq!(rom.header().arm9.load_address, 0x0200_0000);
+    assert_eq!(rom.header().arm9.entry_address, 0x0200_0010);
+    assert_eq!(rom.arm9().unwrap().len(), 0x108);
+}
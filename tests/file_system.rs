@@ -0,0 +1,262 @@
+mod common;
+
+use nds::Rom;
+use nitro_fs::fat::AllocInfo;
+use nitro_fs::walk::Entry;
+use nitro_fs::FileSystem;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use common::build_with_files;
+
+fn rom(name: &str) -> Rom {
+    let path = build_with_files(name, &[
+        ("a/0/1/2", b"2"),
+        ("a/0/1/3", b"3"),
+        ("a/0/4", b"4"),
+        ("b/5", b"5"),
+        ("top", b"top"),
+    ]);
+
+    Rom::new(path, true).expect("Could not open ROM")
+}
+
+#[test]
+fn lookup() {
+    let rom = rom("fs_lookup");
+    let fs = rom.file_system();
+
+    let file = fs.lookup("a/0/1/2").expect("Missing file");
+    assert_eq!(file.path, Path::new("a/0/1/2"));
+    assert_eq!(rom.open_id(file.id).unwrap(), b"2");
+
+    assert!(fs.lookup("top").is_some());
+    assert!(fs.lookup("a/0/1").is_none());
+    assert!(fs.lookup("a/0/9").is_none());
+    assert!(fs.lookup("c/5").is_none());
+
+    assert!(fs.lookup_dir("").unwrap().is_root());
+    assert_eq!(fs.lookup_dir("a/0").unwrap().name(), "0");
+}
+
+#[test]
+fn read_dir() {
+    let rom = rom("fs_read_dir");
+    let fs = rom.file_system();
+
+    let names = fs.read_dir("a/0")
+        .expect("Missing directory")
+        .iter()
+        .map(|entry| entry.path().to_path_buf())
+        .collect::<Vec<_>>();
+
+    assert_eq!(names, vec![Path::new("a/0/4"), Path::new("a/0/1")]);
+    assert!(fs.read_dir("a/0/4").is_none());
+}
+
+#[test]
+fn parent_and_children() {
+    let rom = rom("fs_parent");
+    let fs = rom.file_system();
+
+    let root = fs.root().unwrap();
+    let a = fs.lookup_dir("a").unwrap();
+    let one = fs.lookup_dir("a/0/1").unwrap();
+
+    assert_eq!(root.parent(), None);
+    assert_eq!(a.parent(), Some(root.id()));
+    assert_eq!(fs.dir(one.parent().unwrap()).unwrap().path, Path::new("a/0"));
+
+    let children = root.children()
+        .iter()
+        .map(|&id| fs.dir(id).unwrap().name())
+        .collect::<Vec<_>>();
+
+    assert_eq!(children, vec!["a", "b"]);
+}
+
+#[test]
+fn walk_is_depth_first() {
+    let rom = rom("fs_walk");
+    let fs = rom.file_system();
+
+    let paths = fs.walk()
+        .map(|entry| match entry {
+            Entry::File(file) => format!("f {}", file.path.display()),
+            Entry::Dir(dir) => format!("d {}", dir.path.display()),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(paths, vec![
+        "d ", "f top",
+        "d a", "d a/0", "f a/0/4", "d a/0/1", "f a/0/1/2", "f a/0/1/3",
+        "d b", "f b/5",
+    ]);
+
+    let files = fs.files().iter().map(|file| file.name()).collect::<Vec<_>>();
+    assert_eq!(files, vec!["top", "4", "2", "3", "5"]);
+}
+
+/// Makes sure that a file system survives being written out and read back.
+fn reparse(fs: &FileSystem) -> FileSystem {
+    FileSystem::new(&fs.to_fnt().unwrap(), &fs.to_fat().unwrap()).expect("Could not parse written tables")
+}
+
+fn ids(fs: &FileSystem) -> Vec<(String, u16)> {
+    fs.files()
+        .iter()
+        .map(|file| (file.path.display().to_string(), file.id))
+        .collect()
+}
+
+#[test]
+fn add_and_remove_files() {
+    let rom = rom("fs_add_remove");
+    let mut fs = rom.file_system().clone();
+    let alloc = AllocInfo { start: 0x10, end: 0x20 };
+
+    let id = fs.add_file("a/0/new", alloc).expect("Could not add file");
+    assert_eq!(fs.lookup("a/0/new").unwrap().id, id);
+    assert_eq!(fs.lookup("a/0/new").unwrap().alloc, alloc);
+
+    //  Files after the new one have been shifted up.
+    assert_eq!(fs.lookup("a/0/4").unwrap().id + 1, id);
+    assert_eq!(fs.lookup("a/0/1/2").unwrap().id, id + 1);
+
+    assert!(fs.add_file("a/0/new", alloc).is_err());
+    assert!(fs.add_file("missing/new", alloc).is_err());
+
+    let removed = fs.remove_file("a/0/4").expect("Could not remove file");
+    assert_eq!(removed.name(), "4");
+    assert!(fs.lookup("a/0/4").is_none());
+    assert!(fs.remove_file("a/0/4").is_err());
+
+    assert_eq!(ids(&reparse(&fs)), ids(&fs));
+    assert_eq!(reparse(&fs).lookup("a/0/new").unwrap().alloc, alloc);
+}
+
+#[test]
+fn make_and_remove_directories() {
+    let rom = rom("fs_mkdir");
+    let mut fs = rom.file_system().clone();
+    let count = fs.count();
+
+    let id = fs.mkdir("a/new").expect("Could not make directory");
+    assert_eq!(id as usize, 0xF000 + count);
+    assert_eq!(fs.lookup_dir("a/new").unwrap().parent(), Some(fs.lookup_dir("a").unwrap().id()));
+
+    fs.add_file("a/new/file", AllocInfo::default()).unwrap();
+    assert!(fs.rmdir("a/new").is_err());
+    fs.remove_file("a/new/file").unwrap();
+
+    //  Removing a directory shifts the IDs of the ones after it.
+    let b = fs.lookup_dir("b").unwrap().id();
+    let a = fs.lookup_dir("a").unwrap().id();
+    assert!(a < b);
+
+    fs.remove_file("a/0/1/2").unwrap();
+    fs.remove_file("a/0/1/3").unwrap();
+    fs.rmdir("a/0/1").expect("Could not remove directory");
+    fs.remove_file("a/0/4").unwrap();
+    fs.rmdir("a/0").expect("Could not remove directory");
+
+    assert!(fs.rmdir("").is_err());
+    assert_eq!(fs.count(), count - 1);
+    assert_eq!(fs.lookup_dir("b").unwrap().id(), b - 2);
+    assert!(fs.lookup("b/5").is_some());
+
+    let reparsed = reparse(&fs);
+    assert_eq!(ids(&reparsed), ids(&fs));
+    assert!(reparsed.lookup_dir("a/new").is_some());
+}
+
+#[test]
+fn rename_and_move() {
+    let rom = rom("fs_rename");
+    let mut fs = rom.file_system().clone();
+
+    fs.rename("a/0", "zero").expect("Could not rename directory");
+    assert!(fs.lookup("a/zero/1/2").is_some());
+    assert!(fs.lookup_dir("a/0").is_none());
+
+    fs.rename("top", "bottom").expect("Could not rename file");
+    assert!(fs.lookup("bottom").is_some());
+
+    fs.move_entry("a/zero/1", "b/1").expect("Could not move directory");
+    assert_eq!(fs.lookup_dir("b/1").unwrap().parent(), Some(fs.lookup_dir("b").unwrap().id()));
+    assert!(fs.lookup("b/1/3").is_some());
+
+    fs.move_entry("b/5", "a/5").expect("Could not move file");
+    assert!(fs.lookup("a/5").is_some());
+
+    assert!(fs.move_entry("b", "b/1/b").is_err());
+    assert!(fs.move_entry("a/5", "b/1/3").is_err());
+    assert!(fs.rename("missing", "other").is_err());
+
+    let reparsed = reparse(&fs);
+    assert_eq!(ids(&reparsed), ids(&fs));
+    assert!(reparsed.lookup("b/1/2").is_some());
+}
+
+#[test]
+fn rename_keeps_ids() {
+    let rom = rom("fs_rename_ids");
+    let mut fs = rom.file_system().clone();
+    let before = fs.files().iter().map(|file| file.id).collect::<Vec<_>>();
+    let id = fs.lookup("a/0/1/2").unwrap().id;
+
+    //  The first of two files, so a rename that moved it to the end of its
+    //  directory would swap both IDs.
+    fs.rename("a/0/1/2", "renamed").expect("Could not rename file");
+
+    assert_eq!(fs.lookup("a/0/1/renamed").unwrap().id, id);
+    assert_eq!(fs.lookup("a/0/1/3").unwrap().id, id + 1);
+    assert_eq!(fs.files().iter().map(|file| file.id).collect::<Vec<_>>(), before);
+
+    let reparsed = reparse(&fs);
+    assert_eq!(ids(&reparsed), ids(&fs));
+    assert_eq!(reparsed.lookup("a/0/1/renamed").unwrap().id, id);
+}
+
+#[test]
+fn order_files_by_ids() {
+    let rom = rom("fs_order_ids");
+    let mut fs = rom.file_system().clone();
+    let before = ids(&fs);
+
+    let mut order = fs.files().iter().map(|file| (file.path.clone(), file.id)).collect::<BTreeMap<_, _>>();
+    fs.order_files(&order).expect("Could not order files");
+    assert_eq!(ids(&fs), before);
+
+    //  Place b before a/0, and swap the two files of a/0/1.
+    let start = fs.lookup("top").unwrap().id;
+
+    for (path, id) in [("b/5", 1), ("a/0/4", 2), ("a/0/1/3", 3), ("a/0/1/2", 4)] {
+        order.insert(path.into(), start + id);
+    }
+
+    fs.order_files(&order).expect("Could not order files");
+
+    for (path, id) in &order {
+        assert_eq!(fs.lookup(path).unwrap().id, *id, "{}", path.display());
+    }
+
+    assert_eq!(fs.start_id(), start);
+    assert_eq!(ids(&reparse(&fs)), ids(&fs));
+}
+
+#[test]
+fn rename_rejects_paths() {
+    let rom = rom("fs_rename_paths");
+    let mut fs = rom.file_system().clone();
+
+    for name in &["a/zz", "../zz", "/zz", "zz/", "..", ".", ""] {
+        assert!(fs.rename("top", name).is_err(), "{:?} was accepted", name);
+        assert!(fs.rename("a/0", name).is_err(), "{:?} was accepted", name);
+    }
+
+    assert!(fs.lookup("top").is_some());
+    assert!(fs.lookup_dir("a/0").is_some());
+    assert!(fs.lookup("a/zz").is_none());
+}
@@ -0,0 +1,156 @@
+#![cfg(feature = "manifest")]
+
+mod common;
+
+use nds::banner::Banner;
+use nds::{Builder, Extractor, Rom};
+
+use std::fs::{read, read_to_string, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+
+use common::{build_with_edit, build_with_files};
+
+/// Extracts `rom`, lets `edit` change the manifest and the extracted files,
+/// then builds it again.
+fn rebuild<F: FnOnce(&mut String, &Path)>(name: &str, rom: &Path, edit: F) -> PathBuf {
+    let root = Path::new("tmp").join(name);
+    let dir = root.join("extracted");
+    let built = root.join("rebuilt.nds");
+
+    let _ = remove_dir_all(&dir);
+
+    Extractor::new(rom, true)
+        .expect("Could not make Extractor")
+        .extract(&dir)
+        .expect("Could not extract");
+
+    let mut manifest = read_to_string(dir.join("rom.toml")).expect("Could not read manifest");
+    edit(&mut manifest, &dir);
+    write(dir.join("rom.toml"), manifest).expect("Could not write manifest");
+
+    Builder::new(&dir)
+        .expect("Could not create builder")
+        .build(&built)
+        .expect("Could not build");
+
+    built
+}
+
+#[test]
+fn edits_header_and_banner() {
+    let path = build_with_edit("manifest_header", &[], |dir| {
+        let path = dir.join("rom.toml");
+        let manifest = read_to_string(&path)
+            .unwrap()
+            .replace("game_title = \"HOMEBREW\"", "game_title = \"EDITED\"")
+            .replace("gamecode = \"####\"", "gamecode = \"ABCE\"")
+            .replace("hello_world\\nbuilt", "edited\\nbuilt");
+
+        write(path, manifest).unwrap();
+    });
+
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+
+    assert_eq!(rom.header().game_title, "EDITED");
+    assert_eq!(rom.header().gamecode, "ABCE");
+    assert_eq!(&rom.data()[..0xC], b"EDITED\0\0\0\0\0\0");
+
+    let offset = rom.header().icon_banner_offset as usize;
+    let banner = Banner::parse(&rom.data()[offset..]).unwrap();
+
+    assert!(banner.titles.iter().all(|title| title.starts_with("edited\nbuilt")));
+}
+
+#[test]
+fn rejects_invalid_fields() {
+    let path = build_with_files("manifest_invalid", &[]);
+    let root = Path::new("tmp").join("manifest_invalid").join("extracted");
+
+    let mut manifest = read_to_string(root.join("rom.toml")).unwrap();
+    manifest = manifest.replace("game_title = \"HOMEBREW\"", "game_title = \"MUCH TOO LONG TITLE\"");
+    write(root.join("rom.toml"), manifest).unwrap();
+
+    assert!(Builder::new(&root).unwrap().build(&path).is_err());
+}
+
+#[test]
+fn keeps_file_order_and_adds_new_files() {
+    let original = build_with_files("manifest_order", &[("a.bin", b"first"), ("b.bin", b"second")]);
+
+    let path = rebuild("manifest_order", &original, |manifest, dir| {
+        //  Swap the offsets so that b.bin is placed first.
+        let a = offset_line(manifest, "a.bin");
+        let b = offset_line(manifest, "b.bin");
+
+        *manifest = manifest.replacen(&a, "offset = A", 1).replacen(&b, &a, 1).replacen("offset = A", &b, 1);
+
+        write(dir.join("data").join("c.bin"), b"third").unwrap();
+    });
+
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+    let start = |path: &str| rom.entry(path).unwrap().alloc.start;
+
+    assert!(start("b.bin") < start("a.bin"));
+    assert!(start("a.bin") < start("c.bin"));
+    assert_eq!(rom.open("c.bin").unwrap(), b"third");
+}
+
+#[test]
+fn keeps_file_ids() {
+    let original = build_with_files("manifest_ids", &[("a.bin", b"first"), ("b.bin", b"second")]);
+
+    let path = rebuild("manifest_ids", &original, |manifest, dir| {
+        let a = "id = 0\npath = \"a.bin\"";
+        let b = "id = 1\npath = \"b.bin\"";
+
+        *manifest = manifest.replace(a, "id = 1\npath = \"a.bin\"").replace(b, "id = 0\npath = \"b.bin\"");
+
+        write(dir.join("data").join("c.bin"), b"third").unwrap();
+    });
+
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+    let id = |path: &str| rom.entry(path).unwrap().id;
+
+    assert_eq!(id("b.bin"), 0);
+    assert_eq!(id("a.bin"), 1);
+    assert_eq!(id("c.bin"), 2);
+    assert_eq!(rom.open("a.bin").unwrap(), b"first");
+    assert_eq!(rom.open("b.bin").unwrap(), b"second");
+}
+
+#[test]
+fn uses_alignment_and_padding() {
+    let original = build_with_files("manifest_layout", &[("a.bin", b"first"), ("b.bin", b"second")]);
+
+    let path = rebuild("manifest_layout", &original, |manifest, _| {
+        *manifest = manifest
+            .replace("alignment = 512", "alignment = 256")
+            .replace("padding = 0", "padding = 255");
+    });
+
+    let rom = Rom::new(&path, true).expect("Could not open ROM");
+    let a = rom.entry("a.bin").unwrap().alloc;
+
+    assert_eq!(a.start % 0x100, 0);
+    assert_eq!(rom.entry("b.bin").unwrap().alloc.start % 0x100, 0);
+    assert_eq!(rom.data()[a.end as usize], 0xFF);
+
+    //  The detected layout is written when extracting again.
+    let again = rebuild("manifest_layout_again", &path, |manifest, _| {
+        assert!(manifest.contains("alignment = 256"));
+        assert!(manifest.contains("padding = 255"));
+    });
+
+    assert_eq!(read(again).unwrap(), read(path).unwrap());
+}
+
+/// Finds the `offset = ...` line of a file in the manifest.
+fn offset_line(manifest: &str, path: &str) -> String {
+    let entry = manifest.find(&format!("path = \"{}\"", path)).expect("File not in manifest");
+
+    manifest[entry..]
+        .lines()
+        .find(|line| line.starts_with("offset = "))
+        .unwrap()
+        .to_string()
+}
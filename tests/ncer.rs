@@ -0,0 +1,204 @@
+mod common;
+
+use nds::graphics::nanr::{AnimationBank, Element, Playback};
+use nds::graphics::ncer::CellBank;
+use nds::graphics::ncgr::{Mapping, Tiles};
+use nds::graphics::nclr::{ColorDepth, Palette};
+
+use common::graphics::reds;
+use common::nitro_file;
+
+/// Eight 4bpp tiles where tile `n` is filled with index `n + 1`.
+fn tiles() -> Tiles {
+    common::graphics::tiles(ColorDepth::Bpp4, (0..8).map(|tile| [tile as u8 + 1; 64]).collect())
+}
+
+/// Index `n` is red `n * 16`.
+fn palette() -> Palette {
+    Palette::from_rgba(ColorDepth::Bpp4, &reds(16))
+}
+
+fn red(index: u8) -> [u8; 4] {
+    palette().palette_rgba(0)[index as usize]
+}
+
+fn labels(names: &[&str]) -> Vec<u8> {
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+
+    for name in names {
+        table.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+    }
+
+    table.extend_from_slice(&data);
+    table
+}
+
+/// Two cells with bounds and 1D mapping with a 32 byte boundary:
+///
+/// * a 16x16 object centered on the cell, using tiles 0 to 3
+/// * an 8x8 object at (0, 0) using tile 4, over a flipped 8x8 object at
+///   (-4, -4) using tile 5
+fn cell_bank() -> Vec<u8> {
+    let oams: [&[[u16; 3]]; 2] = [
+        &[[0x00F8, 0x41F8, 0x0000]],
+        &[[0x0000, 0x0000, 0x0004], [0x00FC, 0x11FC, 0x0005]],
+    ];
+
+    let mut cebk = vec![0; 0x18];
+
+    cebk[0..2].copy_from_slice(&2u16.to_le_bytes());
+    cebk[2..4].copy_from_slice(&1u16.to_le_bytes());
+    cebk[4..8].copy_from_slice(&0x18u32.to_le_bytes());
+    cebk[8..12].copy_from_slice(&1u32.to_le_bytes());
+
+    let mut oam_data = Vec::new();
+
+    for cell in &oams {
+        cebk.extend_from_slice(&(cell.len() as u16).to_le_bytes());
+        cebk.extend_from_slice(&0u16.to_le_bytes());
+        cebk.extend_from_slice(&(oam_data.len() as u32).to_le_bytes());
+        cebk.extend_from_slice(&[8, 0, 8, 0, 0xF8, 0xFF, 0xF8, 0xFF]);
+
+        for oam in cell.iter() {
+            for attribute in oam {
+                oam_data.extend_from_slice(&attribute.to_le_bytes());
+            }
+        }
+    }
+
+    cebk.extend_from_slice(&oam_data);
+
+    nitro_file(b"RECN", vec![(b"KBEC", cebk), (b"LBAL", labels(&["cell0", "cell1"]))])
+}
+
+/// One looping sequence that shows cell 0 for 4 frames, then cell 1 moved
+/// 10 pixels to the right for 8 frames.
+fn animation_bank() -> Vec<u8> {
+    let mut abnk = vec![0; 0x18];
+
+    abnk[0..2].copy_from_slice(&1u16.to_le_bytes());
+    abnk[2..4].copy_from_slice(&2u16.to_le_bytes());
+    abnk[4..8].copy_from_slice(&0x18u32.to_le_bytes());
+    abnk[8..12].copy_from_slice(&0x28u32.to_le_bytes());
+    abnk[12..16].copy_from_slice(&0x38u32.to_le_bytes());
+
+    //  Sequence: 2 frames, loop from 1, translation, cell, forward loop.
+    for value in &[2u16, 1, 2, 1] {
+        abnk.extend_from_slice(&value.to_le_bytes());
+    }
+
+    abnk.extend_from_slice(&2u32.to_le_bytes());
+    abnk.extend_from_slice(&0u32.to_le_bytes());
+
+    for (offset, duration) in &[(0u32, 4u16), (8, 8)] {
+        abnk.extend_from_slice(&offset.to_le_bytes());
+        abnk.extend_from_slice(&duration.to_le_bytes());
+        abnk.extend_from_slice(&0xBEEFu16.to_le_bytes());
+    }
+
+    for (cell, x) in &[(0u16, 0i16), (1, 10)] {
+        abnk.extend_from_slice(&cell.to_le_bytes());
+        abnk.extend_from_slice(&0u16.to_le_bytes());
+        abnk.extend_from_slice(&x.to_le_bytes());
+        abnk.extend_from_slice(&0i16.to_le_bytes());
+    }
+
+    nitro_file(b"RNAN", vec![(b"KNBA", abnk), (b"LBAL", labels(&["walk"]))])
+}
+
+#[test]
+fn parses_cells() {
+    let bank = CellBank::parse(&cell_bank()).unwrap();
+
+    assert_eq!(bank.mapping, Mapping::OneD { boundary: 32 });
+    assert_eq!(bank.labels, ["cell0", "cell1"]);
+    assert_eq!(bank.cells.len(), 2);
+
+    let oam = bank.cells[0].oams[0];
+
+    assert_eq!((oam.x(), oam.y()), (-8, -8));
+    assert_eq!(oam.size(), (16, 16));
+    assert_eq!(oam.depth(), ColorDepth::Bpp4);
+
+    let flipped = bank.cells[1].oams[1];
+
+    assert!(flipped.flip_x());
+    assert!(!flipped.flip_y());
+    assert_eq!(flipped.tile(), 5);
+
+    let bounds = bank.cells[1].bounds.unwrap();
+
+    assert_eq!((bounds.min_x, bounds.max_x), (-8, 8));
+    assert_eq!(bank.cells[1].extent(), (-4, -4, 12, 12));
+}
+
+#[test]
+fn renders_cells() {
+    let bank = CellBank::parse(&cell_bank()).unwrap();
+
+    let image = bank.render(0, &tiles(), &palette()).unwrap();
+
+    assert_eq!((image.width, image.height), (16, 16));
+    assert_eq!(image.pixel(0, 0), red(1));
+    assert_eq!(image.pixel(8, 0), red(2));
+    assert_eq!(image.pixel(0, 8), red(3));
+    assert_eq!(image.pixel(15, 15), red(4));
+
+    //  The first object is drawn over the second.
+    let image = bank.render(1, &tiles(), &palette()).unwrap();
+
+    assert_eq!(image.pixel(0, 0), red(6));
+    assert_eq!(image.pixel(4, 4), red(5));
+    assert_eq!(image.pixel(11, 11), red(5));
+    assert_eq!(image.pixel(11, 0), [0; 4]);
+
+    assert!(bank.render(2, &tiles(), &palette()).is_none());
+}
+
+#[test]
+fn parses_animations() {
+    let bank = AnimationBank::parse(&animation_bank()).unwrap();
+
+    assert_eq!(bank.labels, ["walk"]);
+
+    let sequence = &bank.sequences[0];
+
+    assert_eq!(sequence.element, Element::Translation);
+    assert_eq!(sequence.playback, Playback::ForwardLoop);
+    assert_eq!(sequence.loop_start, 1);
+    assert_eq!(sequence.frames.len(), 2);
+    assert_eq!(sequence.frames[1].cell, 1);
+    assert_eq!(sequence.frames[1].duration, 8);
+    assert_eq!(sequence.frames[1].transform.x, 10);
+}
+
+#[test]
+fn renders_animations() {
+    let cells = CellBank::parse(&cell_bank()).unwrap();
+    let bank = AnimationBank::parse(&animation_bank()).unwrap();
+    let animation = bank.sequences[0].render(&cells, &tiles(), &palette());
+
+    //  Cell 0 covers -8..8, and cell 1 moved right covers 6..18.
+    assert_eq!(animation.frame_size(), (26, 16));
+    assert_eq!(animation.origin, (8, 8));
+    assert_eq!(animation.frames[0].pixel(0, 0), red(1));
+    assert_eq!(animation.frames[1].pixel(18, 8), red(5));
+
+    let sheet = animation.sprite_sheet();
+
+    assert_eq!((sheet.width, sheet.height), (52, 16));
+    assert_eq!(sheet.pixel(26 + 18, 8), red(5));
+
+    #[cfg(feature = "json")]
+    {
+        let timing: serde_json::Value = serde_json::from_str(&animation.timing_json()).unwrap();
+
+        assert_eq!(timing["playback"], "forward_loop");
+        assert_eq!(timing["frames"][1]["x"], 26);
+        assert_eq!(timing["frames"][1]["duration"], 8);
+        assert_eq!(timing["frames"][1]["duration_ms"], 133);
+    }
+}
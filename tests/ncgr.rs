@@ -0,0 +1,202 @@
+mod common;
+
+use byteorder::{ByteOrder, LittleEndian};
+use nds::graphics::nclr::{ColorDepth, Palette};
+use nds::graphics::ncgr::{Mapping, Tiles};
+use nds::graphics::IndexedImage;
+use nitro_fs::container::NitroFile;
+
+/// Tiles where every pixel has the index of its tile, plus its column in
+/// the second tile.
+fn tiles(depth: ColorDepth, count: usize) -> Tiles {
+    let mut tiles = vec![[0; 64]; count];
+
+    for (index, tile) in tiles.iter_mut().enumerate() {
+        for (pixel, value) in tile.iter_mut().enumerate() {
+            *value = if index == 1 { (pixel % 8) as u8 } else { index as u8 };
+        }
+    }
+
+    common::graphics::tiles(depth, tiles)
+}
+
+#[test]
+fn round_trips() {
+    let tiles = tiles(ColorDepth::Bpp4, 4);
+    let data = tiles.to_bytes().unwrap();
+    let file = NitroFile::parse_as(&data, b"RGCN").unwrap();
+    let char = &file.section(b"RAHC").unwrap().data;
+
+    assert_eq!(LittleEndian::read_u16(char), 0xFFFF);
+    assert_eq!(LittleEndian::read_u32(&char[0x04..]), 3);
+    assert_eq!(LittleEndian::read_u32(&char[0x08..]), 0x10);
+    assert_eq!(LittleEndian::read_u32(&char[0x10..]), 4 * 32);
+
+    //  Tile 1 has 0, 1 in its first two pixels, which share a byte.
+    assert_eq!(char[0x18 + 32], 0x10);
+
+    assert_eq!(Tiles::parse(&data).unwrap(), tiles);
+
+    let mut tiles = self::tiles(ColorDepth::Bpp8, 3);
+    tiles.mapping = Mapping::OneD { boundary: 128 };
+    tiles.size = Some((3, 1));
+    tiles.position = Some((24, 8));
+
+    let data = tiles.to_bytes().unwrap();
+    let file = NitroFile::parse(&data).unwrap();
+    let char = &file.section(b"RAHC").unwrap().data;
+
+    assert_eq!(LittleEndian::read_u32(&char[0x08..]), 0x0020_0010);
+    assert_eq!(Tiles::parse(&data).unwrap(), tiles);
+}
+
+#[test]
+fn reads_scanned_graphics() {
+    let mut tiles = tiles(ColorDepth::Bpp8, 2);
+    tiles.scanned = true;
+    tiles.size = Some((2, 1));
+
+    let data = tiles.to_bytes().unwrap();
+    let file = NitroFile::parse(&data).unwrap();
+    let char = &file.section(b"RAHC").unwrap().data;
+
+    //  The first row of the bitmap crosses both tiles.
+    assert_eq!(&char[0x18..0x18 + 16], [0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(Tiles::parse(&data).unwrap(), tiles);
+
+    tiles.size = None;
+    assert!(tiles.to_bytes().is_err());
+
+    tiles.size = Some((0, 1));
+    assert!(tiles.to_bytes().is_err());
+}
+
+#[test]
+fn rejects_partial_tiles() {
+    let data = tiles(ColorDepth::Bpp4, 2).to_bytes().unwrap();
+    let mut file = NitroFile::parse(&data).unwrap();
+    let char = &mut file.sections[0].data;
+
+    //  Cut the last tile in half.
+    char.truncate(char.len() - 16);
+    LittleEndian::write_u32(&mut char[0x10..], 48);
+
+    assert!(Tiles::parse(&file.to_bytes()).is_err());
+
+    //  Scanned graphics with a width of 0.
+    let mut scanned = tiles(ColorDepth::Bpp8, 1);
+    scanned.scanned = true;
+    scanned.size = Some((1, 1));
+
+    let mut file = NitroFile::parse(&scanned.to_bytes().unwrap()).unwrap();
+    LittleEndian::write_u16(&mut file.sections[0].data[0x02..], 0);
+
+    assert!(Tiles::parse(&file.to_bytes()).is_err());
+}
+
+#[test]
+fn renders_with_palette() {
+    let tiles = tiles(ColorDepth::Bpp4, 3);
+    let image = tiles.to_image();
+
+    assert_eq!((image.width, image.height), (24, 8));
+    assert_eq!(image.pixels[8..16], [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(image.pixels[16], 2);
+
+    let palette = Palette {
+        depth: ColorDepth::Bpp4,
+        extended: false,
+        colors: vec![0x7FFF, 0x001F, 0x03E0],
+        palette_ids: None,
+    };
+
+    let render = tiles.render(&palette, 0);
+
+    assert_eq!(render.pixel(0, 0), [0xFF, 0xFF, 0xFF, 0]);
+    assert_eq!(render.pixel(9, 0), [0xFF, 0, 0, 0xFF]);
+    assert_eq!(render.pixel(16, 7), [0, 0xFF, 0, 0xFF]);
+    assert_eq!(render.pixel(11, 0), [0, 0, 0, 0]);
+}
+
+#[test]
+fn imports_images() {
+    let mut tiles = tiles(ColorDepth::Bpp4, 3);
+    let mut image = tiles.to_image();
+
+    image.pixels[0] = 15;
+    tiles.import(&image, false).unwrap();
+
+    assert_eq!(tiles.tiles[0][0], 15);
+    assert_eq!(tiles.tiles.len(), 3);
+
+    let mut wide = IndexedImage::new(32, 8);
+    wide.pixels[31] = 1;
+
+    assert!(tiles.import(&wide, false).is_err());
+    assert!(tiles.import(&IndexedImage::new(20, 8), true).is_err());
+
+    image.pixels[0] = 16;
+    assert!(tiles.import(&image, false).is_err());
+
+    tiles.import(&wide, true).unwrap();
+
+    assert_eq!(tiles.tiles.len(), 4);
+    assert_eq!(tiles.size, Some((4, 1)));
+}
+
+#[test]
+fn pads_partial_rows() {
+    let tiles = tiles(ColorDepth::Bpp8, 34);
+    let image = tiles.to_image();
+
+    assert_eq!((image.width, image.height), (256, 16));
+
+    let mut imported = tiles.clone();
+    imported.import(&image, false).unwrap();
+
+    assert_eq!(imported, tiles);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn converts_png() {
+    let tiles = tiles(ColorDepth::Bpp4, 2);
+    let image = tiles.to_image();
+    let palette: Vec<[u8; 4]> = (0..16).map(|index| [index * 16, 0, 0, if index == 0 { 0 } else { 0xFF }]).collect();
+
+    let png = image.to_png(&palette).unwrap();
+    let (read, read_palette) = IndexedImage::from_png(&png).unwrap();
+
+    assert_eq!(read, image);
+    assert_eq!(read_palette, palette);
+
+    let nclr = Palette::from_rgba(ColorDepth::Bpp4, &palette);
+    let (read, _) = IndexedImage::from_png(&tiles.to_png(&nclr, 0).unwrap()).unwrap();
+    assert_eq!(read, image);
+
+    let rgba = image.to_rgba(&palette).to_png().unwrap();
+    assert!(IndexedImage::from_png(&rgba).is_err());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn reads_packed_png() {
+    let mut data = Vec::new();
+
+    {
+        let mut encoder = png::Encoder::new(&mut data, 3, 1);
+
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Two);
+        encoder.set_palette(vec![0; 12]);
+
+        //  Indices 1, 2, 3, packed from the top bits.
+        encoder.write_header().unwrap().write_image_data(&[0b0110_1100]).unwrap();
+    }
+
+    let (image, palette) = IndexedImage::from_png(&data).unwrap();
+
+    assert_eq!(image.pixels, [1, 2, 3]);
+    assert_eq!(palette.len(), 4);
+    assert_eq!(palette[0], [0, 0, 0, 0xFF]);
+}
@@ -0,0 +1,99 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nds::graphics::nclr::{ColorDepth, Palette};
+use nds::graphics::{bgr555_to_rgba, rgba_to_bgr555};
+use nitro_fs::container::NitroFile;
+
+fn palette() -> Palette {
+    Palette {
+        depth: ColorDepth::Bpp4,
+        extended: false,
+        colors: vec![0x0000, 0x001F, 0x03E0, 0x7C00, 0x7FFF, 0x1234, 0x4321, 0x0421, 0, 0, 0, 0, 0, 0, 0, 0, 0x5555],
+        palette_ids: None,
+    }
+}
+
+#[test]
+fn converts_colors() {
+    assert_eq!(bgr555_to_rgba(0x001F), [0xFF, 0, 0, 0xFF]);
+    assert_eq!(bgr555_to_rgba(0x03E0), [0, 0xFF, 0, 0xFF]);
+    assert_eq!(bgr555_to_rgba(0x7C00), [0, 0, 0xFF, 0xFF]);
+    assert_eq!(bgr555_to_rgba(0x0421), [0x08, 0x08, 0x08, 0xFF]);
+
+    for color in 0..0x8000 {
+        assert_eq!(rgba_to_bgr555(bgr555_to_rgba(color)), color);
+    }
+}
+
+#[test]
+fn round_trips() {
+    let palette = palette();
+    let data = palette.to_bytes();
+    let file = NitroFile::parse_as(&data, b"RLCN").unwrap();
+    let pltt = &file.section(b"TTLP").unwrap().data;
+
+    assert_eq!(LittleEndian::read_u32(pltt), 3);
+    assert_eq!(LittleEndian::read_u32(&pltt[0x08..]), 34);
+    assert!(file.section(b"PMCP").is_none());
+
+    let parsed = Palette::parse(&data).unwrap();
+
+    assert_eq!(parsed, palette);
+    assert_eq!(parsed.palette_count(), 2);
+    assert_eq!(parsed.palette(1).unwrap(), [0x5555]);
+    assert_eq!(Palette::from_rgba(ColorDepth::Bpp4, &palette.to_rgba()), palette);
+}
+
+#[test]
+fn reads_extended_palettes() {
+    let mut palette = palette();
+
+    palette.depth = ColorDepth::Bpp8;
+    palette.extended = true;
+    palette.colors.resize(512, 0x7FFF);
+    palette.palette_ids = Some(vec![0, 3]);
+
+    let data = palette.to_bytes();
+    let file = NitroFile::parse(&data).unwrap();
+    let pcmp = &file.section(b"PMCP").unwrap().data;
+
+    assert_eq!(LittleEndian::read_u16(&pcmp[2..]), 0xBEEF);
+
+    let parsed = Palette::parse(&data).unwrap();
+
+    assert_eq!(parsed.palette_count(), 2);
+    assert_eq!(parsed.palette_ids, Some(vec![0, 3]));
+    assert_eq!(parsed, palette);
+}
+
+#[test]
+fn exports_palette_files() {
+    let palette = palette();
+
+    let pal = palette.to_pal();
+
+    assert_eq!(&pal[..4], b"RIFF");
+    assert_eq!(LittleEndian::read_u32(&pal[4..]) as usize, pal.len() - 8);
+    assert_eq!(&pal[8..16], b"PAL data");
+    assert_eq!(LittleEndian::read_u16(&pal[22..]), 17);
+    assert_eq!(&pal[28..32], [0xFF, 0, 0, 0]);
+
+    let act = palette.to_act();
+
+    assert_eq!(act.len(), 772);
+    assert_eq!(&act[3..6], [0xFF, 0, 0]);
+    assert_eq!(&act[768..770], [0, 17]);
+
+    let gpl = palette.to_gpl("test");
+
+    assert!(gpl.starts_with("GIMP Palette\nName: test\n"));
+    assert!(gpl.contains("255   0   0\tIndex 1\n"));
+    assert_eq!(gpl.lines().count(), 4 + 17);
+}
+
+#[test]
+fn rejects_unknown_depth() {
+    let mut data = palette().to_bytes();
+    data[0x18] = 7;
+
+    assert!(Palette::parse(&data).is_err());
+}
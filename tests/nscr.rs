@@ -0,0 +1,168 @@
+mod common;
+
+use byteorder::{ByteOrder, LittleEndian};
+use nds::graphics::nclr::ColorDepth;
+use nds::graphics::nscr::{Background, MapEntry, Screen, ScreenFormat};
+use nds::graphics::{bgr555_to_rgba, rgba_to_bgr555, IndexedImage};
+use nitro_fs::container::NitroFile;
+
+use common::graphics::reds;
+
+/// A 32x16 image of eight tiles:
+///
+/// * a tile with a gradient, then the same tile flipped each way
+/// * a copy of the first tile using the second palette
+/// * three empty tiles, then a solid tile in the second palette
+fn image() -> IndexedImage {
+    let mut image = IndexedImage::new(32, 16);
+
+    for y in 0..8 {
+        for x in 0..8 {
+            let value = (y * 8 + x) as u8 % 15 + 1;
+
+            image.pixels[y * 32 + x] = value;
+            image.pixels[y * 32 + 15 - x] = value;
+            image.pixels[(7 - y) * 32 + 16 + x] = value;
+            image.pixels[(7 - y) * 32 + 31 - x] = value;
+            image.pixels[(8 + y) * 32 + x] = 0x10 | value;
+            image.pixels[(8 + y) * 32 + 24 + x] = 0x13;
+        }
+    }
+
+    image
+}
+
+#[test]
+fn round_trips() {
+    let screen = Screen {
+        width: 16,
+        height: 8,
+        depth: ColorDepth::Bpp4,
+        format: ScreenFormat::Text,
+        entries: vec![
+            MapEntry {
+                tile: 0x3FF,
+                flip_x: true,
+                flip_y: false,
+                palette: 15,
+            },
+            MapEntry::default(),
+        ],
+    };
+
+    let data = screen.to_bytes();
+    let file = NitroFile::parse_as(&data, b"RCSN").unwrap();
+    let scrn = &file.section(b"NRCS").unwrap().data;
+
+    assert_eq!(LittleEndian::read_u32(&scrn[0x08..]), 4);
+    assert_eq!(LittleEndian::read_u16(&scrn[0x0C..]), 0xF7FF);
+    assert_eq!(Screen::parse(&data).unwrap(), screen);
+
+    let affine = Screen {
+        depth: ColorDepth::Bpp8,
+        format: ScreenFormat::Affine,
+        entries: vec![
+            MapEntry {
+                tile: 0xAB,
+                ..MapEntry::default()
+            },
+            MapEntry::default(),
+        ],
+        ..screen
+    };
+
+    let data = affine.to_bytes();
+
+    assert_eq!(data.len(), 0x10 + 8 + 0x0C + 2);
+    assert_eq!(Screen::parse(&data).unwrap(), affine);
+}
+
+#[test]
+fn imports_with_flips_and_palettes() {
+    let image = image();
+    let background = Background::import(&image, &reds(32), ColorDepth::Bpp4).unwrap();
+    let entries = &background.screen.entries;
+
+    //  The gradient, an empty tile and the solid tile.
+    assert_eq!(background.tiles.tiles.len(), 3);
+    assert_eq!(entries.len(), 8);
+
+    let flips = entries[..5]
+        .iter()
+        .map(|entry| (entry.tile, entry.flip_x, entry.flip_y, entry.palette))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        flips,
+        [
+            (0, false, false, 0),
+            (0, true, false, 0),
+            (0, false, true, 0),
+            (0, true, true, 0),
+            (0, false, false, 1),
+        ]
+    );
+
+    assert_eq!(entries[7].tile, 2);
+    assert_eq!(entries[7].palette, 1);
+    assert_eq!(background.tiles.tiles[2][0], 3);
+
+    //  The screen lays the tiles out as they were.
+    assert_eq!(background.screen.to_image(&background.tiles), image);
+
+    let render = background.render();
+
+    for y in 0..16 {
+        for x in 0..32 {
+            let index = image.pixels[y * 32 + x] as usize;
+            let expected = if index % 16 == 0 {
+                [0; 4]
+            } else {
+                bgr555_to_rgba(rgba_to_bgr555(reds(32)[index]))
+            };
+
+            assert_eq!(render.pixel(x, y), expected, "pixel ({}, {})", x, y);
+        }
+    }
+
+    let parsed = Screen::parse(&background.screen.to_bytes()).unwrap();
+    assert_eq!(parsed, background.screen);
+}
+
+#[test]
+fn imports_8bpp() {
+    let image = image();
+    let background = Background::import(&image, &reds(32), ColorDepth::Bpp8).unwrap();
+
+    //  The copy in the second palette is now a different tile.
+    assert_eq!(background.tiles.tiles.len(), 4);
+    assert!(background.screen.entries.iter().all(|entry| entry.palette == 0));
+    assert_eq!(background.screen.to_image(&background.tiles), image);
+}
+
+#[test]
+fn rejects_bad_images() {
+    let mut image = image();
+    image.pixels[1] = 0x21;
+
+    assert!(Background::import(&image, &reds(32), ColorDepth::Bpp4).is_err());
+    assert!(Background::import(&IndexedImage::new(12, 8), &reds(32), ColorDepth::Bpp4).is_err());
+
+    //  Index 0 of any palette fits in any tile.
+    image.pixels[1] = 0x20;
+    assert!(Background::import(&image, &reds(32), ColorDepth::Bpp4).is_ok());
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn imports_png() {
+    let image = image();
+    let png = image.to_png(&reds(32)).unwrap();
+    let background = Background::from_png(&png, ColorDepth::Bpp4).unwrap();
+
+    assert_eq!(background.screen.to_image(&background.tiles), image);
+    assert_eq!(background.palette.colors.len(), 32);
+
+    let again = Background::from_png(&background.to_png().unwrap(), ColorDepth::Bpp4).unwrap();
+    assert_eq!(again, background);
+}
@@ -9,3 +9,18 @@ const TEST_3D_BOTH_SCREENS: &'static str = "tests/test_nds_files/3D_Both_Screens
 fn test_parsing() {
     assert!(NDSParser::try_from(TEST_HELLO_WORLD).is_ok());
 }
+
+#[test]
+fn test_strings_drop_null_padding() {
+    let content = std::fs::read(TEST_HELLO_WORLD).unwrap();
+
+    //  The title only uses 8 of its 12 bytes and the maker code is empty,
+    //  so both are padded with null bytes in the header.
+    assert_eq!(&content[0..0x12], b"HOMEBREW\0\0\0\0####\0\0");
+
+    let parsed = NDSParser::try_from(&content).unwrap();
+
+    assert_eq!(parsed.game_title, "HOMEBREW");
+    assert_eq!(parsed.gamecode, "####");
+    assert_eq!(parsed.makercode, "");
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use nds::patch::{apply, create, PatchFormat};
+use nds::util::crc::crc32;
+
+use std::fs::read;
+
+use common::{TEST_3D_BOTH_SCREENS, TEST_HELLO_WORLD};
+
+const FORMATS: [PatchFormat; 3] = [PatchFormat::Ips, PatchFormat::Ups, PatchFormat::Bps];
+
+/// A copy of the hello world ROM with a few edits, a moved block and some
+/// new data at the end.
+fn modified(source: &[u8]) -> Vec<u8> {
+    let mut target = source.to_vec();
+
+    target[0x10..0x20].copy_from_slice(&[0xAA; 0x10]);
+    target[0x5000] ^= 0xFF;
+    target.copy_within(0x4000..0x6000, 0x10000);
+    target.extend_from_slice(&[0x55; 0x300]);
+    target.extend_from_slice(&source[0x8000..0x9000]);
+
+    target
+}
+
+#[test]
+fn crc32_matches_reference() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn round_trip() {
+    let source = read(TEST_HELLO_WORLD).unwrap();
+    let target = modified(&source);
+
+    for &format in &FORMATS {
+        let patch = create(&source, &target, format).expect("Could not create patch");
+
+        assert_eq!(PatchFormat::detect(&patch), Some(format));
+        assert_eq!(apply(&source, &patch).expect("Could not apply patch"), target);
+    }
+}
+
+#[test]
+fn round_trip_shrink() {
+    let source = read(TEST_HELLO_WORLD).unwrap();
+    let mut target = source.clone();
+
+    target.truncate(0x20000);
+    target[0x100] = 0x42;
+
+    for &format in &FORMATS {
+        let patch = create(&source, &target, format).expect("Could not create patch");
+
+        assert_eq!(apply(&source, &patch).expect("Could not apply patch"), target);
+    }
+}
+
+#[test]
+fn round_trip_different_roms() {
+    let source = read(TEST_HELLO_WORLD).unwrap();
+    let target = read(TEST_3D_BOTH_SCREENS).unwrap();
+
+    for &format in &FORMATS {
+        let patch = create(&source, &target, format).expect("Could not create patch");
+
+        assert_eq!(apply(&source, &patch).expect("Could not apply patch"), target);
+    }
+}
+
+#[test]
+fn bps_copies_moved_data() {
+    let source = read(TEST_HELLO_WORLD).unwrap();
+    let target = modified(&source);
+
+    let bps = create(&source, &target, PatchFormat::Bps).unwrap();
+    let ups = create(&source, &target, PatchFormat::Ups).unwrap();
+
+    //  The moved block and the appended copy should be copies, not literals.
+    assert!(bps.len() < 0x500);
+    assert!(bps.len() < ups.len());
+}
+
+#[test]
+fn checks_source_and_patch() {
+    let source = read(TEST_HELLO_WORLD).unwrap();
+    let target = modified(&source);
+    let mut wrong = source.clone();
+
+    wrong[0x20000] ^= 1;
+
+    for &format in &[PatchFormat::Ups, PatchFormat::Bps] {
+        let mut patch = create(&source, &target, format).unwrap();
+
+        assert!(apply(&wrong, &patch).is_err());
+
+        let middle = patch.len() / 2;
+        patch[middle] ^= 1;
+        assert!(apply(&source, &patch).is_err());
+    }
+
+    assert!(apply(&source, b"not a patch").is_err());
+}
+
+#[test]
+fn ips_refuses_large_offsets() {
+    let source = vec![0; 0x100_0010];
+    let mut target = source.clone();
+
+    target[0x100_0008] = 1;
+
+    assert!(create(&source, &target, PatchFormat::Ips).is_err());
+
+    let patch = create(&source, &target, PatchFormat::Bps).unwrap();
+    assert_eq!(apply(&source, &patch).unwrap(), target);
+}
+
+#[test]
+fn ips_avoids_eof_offset() {
+    let source = vec![0; 0x46_0000];
+    let mut target = source.clone();
+
+    target[0x45_4F46] = 1;
+    target[0x45_4F50..0x45_4F60].copy_from_slice(&[2; 0x10]);
+
+    let patch = create(&source, &target, PatchFormat::Ips).unwrap();
+
+    assert!(!patch.windows(3).take(patch.len() - 3).skip(5).any(|window| window == b"\x45\x4F\x46"));
+    assert_eq!(apply(&source, &patch).unwrap(), target);
+}
@@ -0,0 +1,245 @@
+mod common;
+
+use nds::Rom;
+
+use std::fs::read;
+use std::io::{Read, Seek, SeekFrom};
+
+use common::{build_ntr_with_files, build_with_files, TEST_HELLO_WORLD};
+
+#[test]
+fn opens_files_by_path_and_id() {
+    let path = build_with_files("rom_open", &[
+        ("a/0/1/2", b"first"),
+        ("a/0/1/3", b"second"),
+        ("readme.txt", b"hello"),
+    ]);
+
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+
+    assert_eq!(rom.open("a/0/1/2").unwrap(), b"first");
+    assert_eq!(rom.open("readme.txt").unwrap(), b"hello");
+    assert!(rom.open("a/0/1/4").is_err());
+
+    let id = rom.entry("a/0/1/3").unwrap().id;
+    assert_eq!(rom.open_id(id).unwrap(), b"second");
+    assert!(rom.open_id(0xEFFF).is_err());
+}
+
+#[test]
+fn reader_seeks() {
+    let path = build_with_files("rom_reader", &[("data.bin", b"0123456789")]);
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+
+    let mut reader = rom.reader("data.bin").unwrap();
+    let mut buffer = [0; 3];
+
+    reader.seek(SeekFrom::Start(5)).unwrap();
+    reader.read_exact(&mut buffer).unwrap();
+
+    assert_eq!(&buffer, b"567");
+}
+
+#[test]
+fn exposes_header_and_binaries() {
+    let rom = Rom::new(TEST_HELLO_WORLD, true).expect("Could not open ROM");
+
+    assert_eq!(rom.header().game_title, "HOMEBREW");
+    assert_eq!(rom.arm9().unwrap().len(), rom.header().arm9.size as usize);
+    assert_eq!(rom.arm7().unwrap().len(), rom.header().arm7.size as usize);
+    assert!(rom.file_system().files().is_empty());
+}
+
+#[test]
+fn replaces_file_in_place() {
+    let path = build_with_files("rom_replace_in_place", &[
+        ("a.bin", &[1; 0x100]),
+        ("b.bin", &[2; 0x10]),
+    ]);
+
+    let mut rom = Rom::from_bytes(read(path).unwrap(), true).expect("Could not open ROM");
+    let len = rom.data().len();
+    let start = rom.entry("a.bin").unwrap().alloc.start;
+
+    rom.replace_file("a.bin", &[3; 0x1F0]).expect("Could not replace file");
+    assert_eq!(rom.entry("a.bin").unwrap().alloc.start, start);
+    assert_eq!(rom.open("a.bin").unwrap(), &[3; 0x1F0][..]);
+
+    rom.replace_file("a.bin", b"short").expect("Could not replace file");
+    assert_eq!(rom.open("a.bin").unwrap(), b"short");
+    assert_eq!(rom.open("b.bin").unwrap(), &[2; 0x10][..]);
+    assert_eq!(rom.data().len(), len);
+
+    //  The FAT and header CRC are updated along with the data.
+    let reopened = Rom::from_bytes(rom.data().to_vec(), true).expect("Could not reopen ROM");
+    assert_eq!(reopened.open("a.bin").unwrap(), b"short");
+}
+
+#[test]
+fn moves_empty_file() {
+    let path = build_ntr_with_files("rom_replace_empty", &[("a.bin", &[]), ("b.bin", &[2; 0x10])]);
+    let rom = Rom::from_bytes(read(path).unwrap(), true).expect("Could not open ROM");
+    let id = rom.entry("a.bin").unwrap().id;
+
+    //  Place the empty file at 0, as some tools do.
+    let fat_entry = rom.header().fat.offset as usize + id as usize * 8;
+    let mut data = rom.data().to_vec();
+    data[fat_entry..fat_entry + 8].copy_from_slice(&[0; 8]);
+
+    //  Give the empty overlay tables an offset too, so that no section
+    //  starts at 0.
+    let fnt = rom.header().fnt.offset.to_le_bytes();
+    data[0x50..0x54].copy_from_slice(&fnt);
+    data[0x58..0x5C].copy_from_slice(&fnt);
+
+    let header = data[..0x200].to_vec();
+    let mut rom = Rom::from_bytes(data, false).expect("Could not open ROM");
+    assert_eq!(rom.entry("a.bin").unwrap().alloc.start, 0);
+
+    rom.replace_file("a.bin", b"not empty").expect("Could not replace file");
+
+    let alloc = rom.entry("a.bin").unwrap().alloc;
+    assert!(alloc.start >= rom.header().header_size);
+    assert_eq!(rom.open("a.bin").unwrap(), b"not empty");
+    assert_eq!(rom.open("b.bin").unwrap(), &[2; 0x10][..]);
+
+    //  Only the ROM size and the CRC of the header change.
+    assert_eq!(&rom.data()[..0x80], &header[..0x80]);
+    assert_eq!(&rom.data()[0x84..0x15E], &header[0x84..0x15E]);
+}
+
+#[test]
+fn moves_file_that_does_not_fit() {
+    let path = build_ntr_with_files("rom_replace_move", &[
+        ("a.bin", &[1; 0x10]),
+        ("b.bin", &[2; 0x10]),
+    ]);
+
+    let mut rom = Rom::writable(&path, true).expect("Could not open ROM");
+    let old_size = rom.header().ntr_region_rom_size;
+    let data = vec![4; 0x1000];
+
+    rom.replace_file("a.bin", &data).expect("Could not replace file");
+
+    let alloc = rom.entry("a.bin").unwrap().alloc;
+    assert!(alloc.start >= old_size);
+    assert_eq!(rom.header().ntr_region_rom_size, alloc.end);
+    rom.flush().unwrap();
+
+    let reopened = Rom::new(&path, true).expect("Could not reopen ROM");
+    assert_eq!(reopened.open("a.bin").unwrap(), &data[..]);
+    assert_eq!(reopened.open("b.bin").unwrap(), &[2; 0x10][..]);
+}
+
+#[test]
+fn moved_file_keeps_rsa_signature() {
+    let path = build_ntr_with_files("rom_replace_rsa", &[("a.bin", &[1; 0x10]), ("b.bin", &[2; 0x10])]);
+    let mut data = read(path).unwrap();
+    let used = Rom::from_bytes(data.clone(), true).unwrap().header().ntr_region_rom_size as usize;
+
+    let mut signature = b"ac".to_vec();
+    signature.resize(0x88, 0x11);
+
+    data.truncate(used);
+    data.extend_from_slice(&signature);
+
+    let mut rom = Rom::from_bytes(data, true).expect("Could not open ROM");
+    let grown = vec![4; 0x1000];
+
+    rom.replace_file("a.bin", &grown).expect("Could not replace file");
+
+    //  The file goes after the signature, which is also copied after it.
+    let alloc = rom.entry("a.bin").unwrap().alloc;
+    assert!(alloc.start as usize >= used + 0x88);
+    assert_eq!(&rom.data()[used..used + 0x88], &signature[..]);
+    assert_eq!(rom.header().ntr_region_rom_size, alloc.end);
+    assert_eq!(&rom.data()[alloc.end as usize..], &signature[..]);
+
+    let reopened = Rom::from_bytes(rom.data().to_vec(), true).expect("Could not reopen ROM");
+    assert_eq!(reopened.open("a.bin").unwrap(), &grown[..]);
+    assert_eq!(reopened.open("b.bin").unwrap(), &[2; 0x10][..]);
+}
+
+#[test]
+fn refuses_to_move_into_dsi_region() {
+    let path = build_with_files("rom_replace_twl", &[("a.bin", &[1; 0x10])]);
+    let mut rom = Rom::from_bytes(read(path).unwrap(), true).expect("Could not open ROM");
+
+    assert!(rom.replace_file("a.bin", &vec![0; 0x10000]).is_err());
+    assert!(Rom::new(TEST_HELLO_WORLD, true).unwrap().replace_file("a.bin", b"").is_err());
+}
+
+#[test]
+fn untrims_and_trims() {
+    let original = read(TEST_HELLO_WORLD).unwrap();
+    let mut rom = Rom::from_bytes(original.clone(), true).expect("Could not open ROM");
+
+    //  `devicecapacity` is the chip size as a power of two times 128KiB.
+    let capacity = 0x2_0000 << rom.header().devicecapacity;
+    let added = rom.untrim().expect("Could not untrim");
+
+    assert_eq!(added, (capacity - original.len()) as u64);
+    assert_eq!(rom.data().len(), capacity);
+    assert!(rom.data()[original.len()..].iter().all(|&byte| byte == 0xFF));
+    assert_eq!(rom.untrim().unwrap(), 0);
+
+    assert_eq!(rom.trim().expect("Could not trim"), added);
+    assert_eq!(rom.data(), &original[..]);
+    assert_eq!(rom.trim().unwrap(), 0);
+
+    assert!(Rom::new(TEST_HELLO_WORLD, true).unwrap().trim().is_err());
+}
+
+#[test]
+fn untrim_rejects_invalid_capacity() {
+    let mut data = read(TEST_HELLO_WORLD).unwrap();
+
+    //  4GiB is the largest chip, and larger shifts used to wrap around.
+    for (capacity, valid) in [(15u8, true), (16, false), (47, false), (0xFF, false)] {
+        data[0x14] = capacity;
+
+        let rom = Rom::from_bytes(data.clone(), false).expect("Could not open ROM");
+
+        assert_eq!(rom.capacity().is_ok(), valid, "{}", capacity);
+    }
+
+    assert!(Rom::from_bytes(data.clone(), false).unwrap().untrim().is_err());
+
+    data[0x14] = 15;
+    assert_eq!(Rom::from_bytes(data, false).unwrap().capacity().unwrap(), 1 << 32);
+}
+
+#[test]
+fn trim_keeps_rsa_signature() {
+    let path = build_ntr_with_files("rom_trim_rsa", &[("a.bin", &[1; 0x10])]);
+    let mut data = read(&path).unwrap();
+    let used = Rom::from_bytes(data.clone(), true).unwrap().header().ntr_region_rom_size as usize;
+
+    data.truncate(used);
+    data.extend_from_slice(b"ac");
+    data.extend_from_slice(&[0x11; 0x86]);
+    data.resize(used + 0x1000, 0xFF);
+    std::fs::write(&path, &data).unwrap();
+
+    let mut rom = Rom::writable(&path, true).expect("Could not open ROM");
+
+    assert_eq!(rom.trim().expect("Could not trim"), 0x1000 - 0x88);
+    assert_eq!(rom.data(), &data[..used + 0x88]);
+    rom.flush().unwrap();
+    drop(rom);
+
+    assert_eq!(read(&path).unwrap(), &data[..used + 0x88]);
+}
+
+#[test]
+fn refuses_to_trim_files() {
+    let path = build_ntr_with_files("rom_trim_files", &[("a.bin", &[1; 0x100])]);
+    let mut data = read(path).unwrap();
+    let end = Rom::from_bytes(data.clone(), true).unwrap().entry("a.bin").unwrap().alloc.end;
+
+    data[0x80..0x84].copy_from_slice(&(end - 0x10).to_le_bytes());
+
+    let mut rom = Rom::from_bytes(data, false).expect("Could not open ROM");
+
+    assert!(rom.trim().is_err());
+}
@@ -0,0 +1,197 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nds::sdat::{ArchiveInfo, BankInfo, GroupEntry, GroupInfo, Item, SequenceInfo, SoundArchive, StreamInfo};
+
+use std::fs::{read, remove_dir_all, remove_file, write};
+use std::path::Path;
+
+fn item<T>(name: &str, info: T) -> Item<T> {
+    Item {
+        name: Some(name.to_string()),
+        info: Some(info),
+        children: Vec::new(),
+    }
+}
+
+fn archive() -> SoundArchive {
+    SoundArchive {
+        sequences: vec![
+            item(
+                "SEQ_BGM_TITLE",
+                SequenceInfo {
+                    file_id: 0,
+                    bank: 0,
+                    volume: 127,
+                    channel_priority: 64,
+                    player_priority: 64,
+                    player: 1,
+                    ..SequenceInfo::default()
+                },
+            ),
+            //  A gap in the list, then a sequence without a name.
+            Item::default(),
+            Item {
+                name: None,
+                info: Some(SequenceInfo {
+                    file_id: 1,
+                    ..SequenceInfo::default()
+                }),
+                children: Vec::new(),
+            },
+        ],
+        sequence_archives: vec![Item {
+            name: Some("SEQARC_SE".to_string()),
+            info: Some(ArchiveInfo { file_id: 2, unknown: 0 }),
+            children: vec![Some("SE_JUMP".to_string()), None, Some("SE_COIN".to_string())],
+        }],
+        banks: vec![item(
+            "BANK_BGM",
+            BankInfo {
+                file_id: 3,
+                unknown: 0,
+                wave_archives: [0, 0xFFFF, 0xFFFF, 0xFFFF],
+            },
+        )],
+        wave_archives: vec![item("WAVE_BGM", ArchiveInfo { file_id: 4, unknown: 0 })],
+        groups: vec![item(
+            "GROUP_TITLE",
+            GroupInfo {
+                entries: vec![GroupEntry {
+                    kind: 0,
+                    load_flags: 0xF,
+                    index: 0,
+                }],
+            },
+        )],
+        streams: vec![item(
+            "STRM_OPENING",
+            StreamInfo {
+                file_id: 5,
+                volume: 100,
+                ..StreamInfo::default()
+            },
+        )],
+        has_symbols: true,
+        files: (0..7u8).map(|id| vec![id; 3 + id as usize * 10]).collect(),
+        ..SoundArchive::default()
+    }
+}
+
+#[test]
+fn round_trips() {
+    let archive = archive();
+    let data = archive.to_bytes();
+
+    assert_eq!(&data[..4], b"SDAT");
+    assert_eq!(LittleEndian::read_u32(&data[0x08..]) as usize, data.len());
+    assert_eq!(LittleEndian::read_u16(&data[0x0E..]), 4);
+
+    //  Blocks follow each other in order.
+    let symb = LittleEndian::read_u32(&data[0x10..]) as usize;
+    let info = LittleEndian::read_u32(&data[0x18..]) as usize;
+
+    assert_eq!(symb, 0x40);
+    assert_eq!(info, symb + LittleEndian::read_u32(&data[0x14..]) as usize);
+    assert_eq!(&data[info..info + 4], b"INFO");
+
+    let parsed = SoundArchive::parse(&data).unwrap();
+
+    assert_eq!(parsed, archive);
+    assert_eq!(parsed.to_bytes(), data);
+}
+
+#[test]
+fn aligns_files() {
+    let data = archive().to_bytes();
+    let fat = LittleEndian::read_u32(&data[0x20..]) as usize;
+
+    for index in 0..7 {
+        let offset = LittleEndian::read_u32(&data[fat + 0x0C + index * 0x10..]) as usize;
+
+        assert_eq!(offset % 0x20, 0);
+        assert_eq!(data[offset], index as u8);
+    }
+}
+
+#[test]
+fn writes_without_symbols() {
+    let mut archive = archive();
+    archive.has_symbols = false;
+
+    let data = archive.to_bytes();
+
+    assert_eq!(LittleEndian::read_u32(&data[0x10..]), 0);
+    assert_eq!(LittleEndian::read_u16(&data[0x0E..]), 3);
+
+    let parsed = SoundArchive::parse(&data).unwrap();
+
+    assert!(parsed.sequences.iter().all(|item| item.name.is_none()));
+    assert_eq!(parsed.sequences[0].info, archive.sequences[0].info);
+    assert_eq!(parsed.files, archive.files);
+}
+
+#[test]
+fn names_files() {
+    let names = archive().file_names();
+
+    assert_eq!(names[&0], "SEQ_BGM_TITLE.sseq");
+    assert_eq!(names[&1], "SEQ_0002.sseq");
+    assert_eq!(names[&2], "SEQARC_SE.ssar");
+    assert_eq!(names[&3], "BANK_BGM.sbnk");
+    assert_eq!(names[&4], "WAVE_BGM.swar");
+    assert_eq!(names[&5], "STRM_OPENING.strm");
+    assert_eq!(names[&6], "FILE_0006.bin");
+}
+
+#[test]
+fn extracts_and_imports() {
+    let dir = Path::new("tmp").join("sdat");
+    let _ = remove_dir_all(&dir);
+
+    let mut archive = archive();
+    archive.extract(&dir).unwrap();
+
+    assert_eq!(read(dir.join("STRM_OPENING.strm")).unwrap(), archive.files[5]);
+
+    //  Files missing from the directory are kept.
+    write(dir.join("STRM_OPENING.strm"), b"new stream").unwrap();
+    remove_file(dir.join("WAVE_BGM.swar")).unwrap();
+
+    assert_eq!(archive.import(&dir).unwrap(), 6);
+
+    let parsed = SoundArchive::parse(&archive.to_bytes()).unwrap();
+
+    assert_eq!(parsed.files[5], b"new stream");
+    assert_eq!(parsed.files[4], archive.files[4]);
+}
+
+#[test]
+fn rejects_unsafe_names_and_missing_files() {
+    let dir = Path::new("tmp").join("sdat_names");
+    let _ = remove_dir_all(&dir);
+
+    for name in ["../SEQ_OUT", "/SEQ_ABS", "SEQ/SUB"] {
+        let mut archive = archive();
+        archive.sequences[0].name = Some(name.to_string());
+
+        assert!(archive.extract(&dir).is_err(), "{:?} was accepted", name);
+        assert!(archive.import(&dir).is_err(), "{:?} was accepted", name);
+    }
+
+    assert!(!Path::new("tmp").join("SEQ_OUT.sseq").exists());
+
+    let mut archive = archive();
+    archive.files.truncate(5);
+
+    assert!(archive.extract(&dir).is_err());
+    assert!(archive.import(&dir).is_err());
+}
+
+#[test]
+fn rejects_invalid_data() {
+    let mut data = archive().to_bytes();
+
+    assert!(SoundArchive::parse(&data[..0x20]).is_err());
+
+    data[0] = b'X';
+    assert!(SoundArchive::parse(&data).is_err());
+}
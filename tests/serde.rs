@@ -0,0 +1,55 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use nds::overlay::{Overlay, Processor};
+use nds::parser::NDSParser;
+use nds::Rom;
+use nitro_fs::FileSystem;
+
+use common::build_with_files;
+
+#[test]
+fn header_round_trips_through_json() {
+    let path = build_with_files("serde_header", &[]);
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+
+    let json = serde_json::to_value(rom.header()).unwrap();
+
+    assert_eq!(json["game_title"], "HOMEBREW");
+    assert_eq!(json["arm9"]["load_address"], 0x0200_0000);
+    assert!(json["nintendo_logo"].as_str().unwrap().starts_with("24ffae51"));
+    assert_eq!(json["debugger"].as_str().unwrap().len(), 64);
+
+    let parsed: NDSParser = serde_json::from_value(json).unwrap();
+
+    assert_eq!(&parsed, rom.header());
+}
+
+#[test]
+fn rejects_wrong_hex_length() {
+    let path = build_with_files("serde_hex", &[]);
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+
+    let mut json = serde_json::to_value(rom.header()).unwrap();
+    json["debugger"] = "00ff".into();
+
+    assert!(serde_json::from_value::<NDSParser>(json).is_err());
+}
+
+#[test]
+fn file_system_round_trips_through_json() {
+    let path = build_with_files("serde_fs", &[("a/b.bin", b"nested"), ("c.bin", b"top")]);
+    let rom = Rom::new(path, true).expect("Could not open ROM");
+
+    let json = serde_json::to_string(rom.file_system()).unwrap();
+    let fs: FileSystem = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(&fs, rom.file_system());
+    assert_eq!(fs.lookup("a/b.bin").unwrap().alloc, rom.entry("a/b.bin").unwrap().alloc);
+
+    let overlays = rom.overlays(Processor::Arm9).unwrap();
+    let json = serde_json::to_string(&overlays).unwrap();
+
+    assert_eq!(serde_json::from_str::<Vec<Overlay>>(&json).unwrap(), overlays);
+}
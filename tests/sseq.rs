@@ -0,0 +1,300 @@
+mod common;
+
+use byteorder::{BigEndian, ByteOrder};
+use nds::sdat::midi::MidiConverter;
+use nds::sdat::sseq::{Argument, Command, Sequence, SequenceArchive, VariableOp};
+
+use common::nitro_file;
+
+/// Writes an SSEQ file with the given commands.
+fn sseq(commands: &[u8]) -> Vec<u8> {
+    let mut data = 0x1Cu32.to_le_bytes().to_vec();
+    data.extend_from_slice(commands);
+
+    nitro_file(b"SSEQ", vec![(b"DATA", data)])
+}
+
+/// Track 0 opens track 1, sets a few controls, then plays a note twice in
+/// a loop. Track 1 waits a random time and plays a note in a loop that
+/// never ends.
+const SONG: &[u8] = &[
+    0xFE, 0x03, 0x00, // allocate tracks 0 and 1
+    0x93, 0x01, 0x18, 0x00, 0x00, // open track 1 at 0x18
+    0xE1, 0x96, 0x00, // tempo 150
+    0x81, 0x05, // program 5
+    0xC1, 0x64, // volume 100
+    0xC4, 0x40, // pitch bend up by half
+    0xD4, 0x02, // loop twice
+    0x3C, 0x64, 0x30, // note 60 for a beat
+    0xFC, // loop end
+    0xFF, // 0x17
+    0xA0, 0x80, 0x10, 0x00, 0x20, 0x00, // 0x18: wait 16 to 32 ticks
+    0xC3, 0x02, // transpose up 2
+    0x40, 0x50, 0x18, // note 64 for half a beat
+    0x94, 0x1E, 0x00, 0x00, // jump back to the transpose
+];
+
+/// Reads the events of every track of a MIDI file, with their time.
+fn read_midi(midi: &[u8]) -> Vec<Vec<(u32, Vec<u8>)>> {
+    assert_eq!(&midi[..4], b"MThd");
+
+    let count = BigEndian::read_u16(&midi[10..]) as usize;
+    let mut offset = 14;
+    let mut tracks = Vec::new();
+
+    for _ in 0..count {
+        assert_eq!(&midi[offset..offset + 4], b"MTrk");
+
+        let end = offset + 8 + BigEndian::read_u32(&midi[offset + 4..]) as usize;
+        let mut position = offset + 8;
+        let mut time = 0;
+        let mut events = Vec::new();
+
+        let var_len = |position: &mut usize| {
+            let mut value = 0u32;
+
+            loop {
+                let byte = midi[*position];
+                *position += 1;
+                value = (value << 7) | u32::from(byte & 0x7F);
+
+                if byte & 0x80 == 0 {
+                    return value;
+                }
+            }
+        };
+
+        while position < end {
+            time += var_len(&mut position);
+
+            let start = position;
+            let len = match midi[position] {
+                0xFF => {
+                    position += 2;
+                    let len = var_len(&mut position) as usize;
+                    position - start + len
+                }
+                0xC0..=0xDF => 2,
+                _ => 3,
+            };
+
+            events.push((time, midi[start..start + len].to_vec()));
+            position = start + len;
+        }
+
+        tracks.push(events);
+        offset = end;
+    }
+
+    tracks
+}
+
+fn notes(events: &[(u32, Vec<u8>)]) -> Vec<(u32, u8, u8)> {
+    events
+        .iter()
+        .filter(|(_, event)| event[0] & 0xF0 == 0x90 || event[0] & 0xF0 == 0x80)
+        .map(|(time, event)| (*time, event[0], event[1]))
+        .collect()
+}
+
+#[test]
+fn reads_commands() {
+    let sequence = Sequence::parse(&sseq(SONG)).unwrap();
+    let commands = sequence.commands().unwrap();
+
+    assert_eq!(commands[&0x03], Command::OpenTrack { track: 1, offset: 0x18 });
+    assert_eq!(commands[&0x0B], Command::Program(Argument::Value(5)));
+    assert_eq!(commands[&0x0F], Command::PitchBend(Argument::Value(0x40)));
+    assert_eq!(
+        commands[&0x18],
+        Command::Wait(Argument::Random { min: 0x10, max: 0x20 })
+    );
+    assert_eq!(commands[&0x23], Command::Jump(0x1E));
+
+    //  Every command is found, including the opened track.
+    assert_eq!(commands.len(), 14);
+}
+
+#[test]
+fn converts_to_midi() {
+    let sequence = Sequence::parse(&sseq(SONG)).unwrap();
+    let midi = MidiConverter::new().convert(&sequence.data, 0).unwrap();
+
+    assert_eq!(BigEndian::read_u16(&midi[8..]), 1);
+    assert_eq!(BigEndian::read_u16(&midi[12..]), 48);
+
+    let tracks = read_midi(&midi);
+
+    assert_eq!(tracks.len(), 3);
+
+    //  150 beats per minute.
+    assert_eq!(tracks[0][0], (0, vec![0xFF, 0x51, 0x03, 0x06, 0x1A, 0x80]));
+
+    let first = &tracks[1];
+
+    assert_eq!(first[0].1, b"\xFF\x03\x07Track 0");
+    assert!(first.contains(&(0, vec![0xC0, 5])));
+    assert!(first.contains(&(0, vec![0xB0, 7, 100])));
+    assert!(first.contains(&(0, vec![0xE0, 0, 0x60])));
+    assert_eq!(
+        notes(first),
+        vec![(0, 0x90, 60), (48, 0x80, 60), (48, 0x90, 60), (96, 0x80, 60)]
+    );
+
+    //  The jump back is taken once, so the note plays twice, a random
+    //  wait apart.
+    let second = notes(&tracks[2]);
+
+    assert_eq!(second.len(), 4);
+    assert!(second.iter().all(|&(_, status, key)| status & 0x0F == 1 && key == 66));
+    assert!((16..=32).contains(&second[0].0));
+    assert_eq!(second[1].0, second[0].0 + 24);
+    assert_eq!(second[2].0, second[1].0);
+}
+
+#[test]
+fn clamps_slow_tempos() {
+    let commands = [
+        0xE1, 0x02, 0x00, // tempo 2
+        0x3C, 0x64, 0x30, // note 60 for a beat
+        0xFF,
+    ];
+    let sequence = Sequence::parse(&sseq(&commands)).unwrap();
+    let midi = MidiConverter::new().convert(&sequence.data, 0).unwrap();
+
+    //  30 seconds a beat doesn't fit in 24 bits.
+    assert_eq!(read_midi(&midi)[0][0], (0, vec![0xFF, 0x51, 0x03, 0xFF, 0xFF, 0xFF]));
+}
+
+#[test]
+fn unrolls_loops() {
+    let sequence = Sequence::parse(&sseq(SONG)).unwrap();
+    let mut converter = MidiConverter::new();
+
+    converter.set_loop_count(3);
+    let tracks = read_midi(&converter.convert(&sequence.data, 0).unwrap());
+
+    assert_eq!(notes(&tracks[2]).len(), 6);
+
+    //  Loops with a count don't change.
+    assert_eq!(notes(&tracks[1]).len(), 4);
+
+    //  A loop that never ends plays as many times as set.
+    let endless = sseq(&[0xD4, 0x00, 0x3C, 0x64, 0x10, 0xFC, 0xFF]);
+    let sequence = Sequence::parse(&endless).unwrap();
+    let tracks = read_midi(&converter.convert(&sequence.data, 0).unwrap());
+
+    assert_eq!(notes(&tracks[1]).len(), 6);
+}
+
+#[test]
+fn runs_variables_and_calls() {
+    let commands = [
+        0xB0, 0x00, 0x05, 0x00, // var 0 = 5
+        0xB1, 0x00, 0x03, 0x00, // var 0 += 3
+        0xB8, 0x00, 0x08, 0x00, // var 0 == 8
+        0xA2, 0x94, 0x14, 0x00, 0x00, // if true, jump to 0x14
+        0x3C, 0x64, 0x10, // skipped
+        0xA1, 0x80, 0x00, // 0x14: wait var 0
+        0x95, 0x1C, 0x00, 0x00, // call 0x1C
+        0xFF, // end
+        0x40, 0x64, 0x08, // 0x1C: note 64
+        0xFD, // return
+    ];
+
+    let sequence = Sequence::parse(&sseq(&commands)).unwrap();
+    let parsed = sequence.commands().unwrap();
+
+    assert_eq!(
+        parsed[&0x04],
+        Command::Variable {
+            op: VariableOp::Add,
+            variable: 0,
+            value: Argument::Value(3),
+        }
+    );
+    assert_eq!(parsed[&0x14], Command::Wait(Argument::Variable(0)));
+
+    let tracks = read_midi(&MidiConverter::new().convert(&sequence.data, 0).unwrap());
+
+    //  The note is only reached through the call, after waiting 8 ticks.
+    assert_eq!(notes(&tracks[1]), vec![(8, 0x90, 64), (16, 0x80, 64)]);
+}
+
+#[test]
+fn handles_full_random_ranges() {
+    let commands = [
+        0xD4, 0x08, // loop 8 times
+        0xA0, 0xC1, 0x00, 0x80, 0xFF, 0x7F, // volume from -32768 to 32767
+        0xB6, 0x00, 0x00, 0x80, // var 0 = random down to -32768
+        0xFC, // loop end
+        0xFF, // end
+    ];
+
+    let sequence = Sequence::parse(&sseq(&commands)).unwrap();
+    let tracks = read_midi(&MidiConverter::new().convert(&sequence.data, 0).unwrap());
+
+    let volumes = tracks[1]
+        .iter()
+        .filter(|(_, event)| event[..2] == [0xB0, 7])
+        .count();
+
+    assert_eq!(volumes, 8);
+}
+
+#[test]
+fn converts_archived_sequences() {
+    let shared = [
+        0x3C, 0x64, 0x10, 0xFF, // sequence 0
+        0xC0, 0x20, 0x3E, 0x64, 0x08, 0xFF, // sequence 1
+    ];
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(0x18u32 + 8 + 24).to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+
+    for (offset, bank) in [(0u32, 1u16), (4, 2)] {
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&bank.to_le_bytes());
+        data.extend_from_slice(&[100, 64, 64, 0, 0, 0]);
+    }
+
+    data.extend_from_slice(&shared);
+
+    let archive = SequenceArchive::parse(&nitro_file(b"SSAR", vec![(b"DATA", data)])).unwrap();
+
+    assert_eq!(archive.sequences.len(), 2);
+    assert_eq!(archive.sequences[1].bank, 2);
+    assert_eq!(archive.data, shared);
+    assert_eq!(archive.commands(1).unwrap().unwrap().len(), 3);
+    assert!(archive.commands(2).is_none());
+
+    let midi = MidiConverter::new().convert(&archive.data, archive.sequences[1].offset).unwrap();
+    let tracks = read_midi(&midi);
+
+    assert!(tracks[1].contains(&(0, vec![0xB0, 10, 0x20])));
+    assert_eq!(notes(&tracks[1]), vec![(0, 0x90, 0x3E), (8, 0x80, 0x3E)]);
+}
+
+#[test]
+fn rejects_invalid_sequences() {
+    let unknown = Sequence::parse(&sseq(&[0xE2, 0xFF])).unwrap();
+
+    assert!(unknown.commands().is_err());
+    assert!(MidiConverter::new().convert(&unknown.data, 0).is_err());
+
+    //  A prefix on a jump.
+    let prefixed = Sequence::parse(&sseq(&[0xA0, 0x94, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+
+    assert!(prefixed.commands().is_err());
+
+    //  Calls nested deeper than the DS allows.
+    let nested = Sequence::parse(&sseq(&[0x95, 0x00, 0x00, 0x00])).unwrap();
+
+    assert!(MidiConverter::new().convert(&nested.data, 0).is_err());
+
+    //  Running off the end.
+    let truncated = Sequence::parse(&sseq(&[0x3C, 0x64])).unwrap();
+
+    assert!(MidiConverter::new().convert(&truncated.data, 0).is_err());
+}
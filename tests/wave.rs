@@ -0,0 +1,214 @@
+use byteorder::{ByteOrder, LittleEndian};
+use nds::sdat::strm::Strm;
+use nds::sdat::wave::{decode, encode, Sound, Swar, Swav, WaveFormat};
+
+/// A sine wave, which ADPCM follows closely.
+fn sine(len: usize, period: f64) -> Vec<i16> {
+    (0..len)
+        .map(|index| ((index as f64 * std::f64::consts::TAU / period).sin() * 12000.0) as i16)
+        .collect()
+}
+
+fn max_error(a: &[i16], b: &[i16]) -> i32 {
+    a.iter().zip(b).map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs()).max().unwrap_or(0)
+}
+
+#[test]
+fn decodes_adpcm() {
+    //  Starting from 0 with the smallest step, code 7 adds 7 / 8 + 7 / 4 +
+    //  7 / 2 + 7 and moves the step up by 8.
+    let data = [0, 0, 0, 0, 0xF7, 0x8F];
+    let samples = decode(&data, WaveFormat::Adpcm, 4);
+
+    assert_eq!(samples[0], 11);
+    assert_eq!(samples.len(), 4);
+
+    //  The step is now 16, so 0xF takes away 2 + 4 + 8 + 16.
+    assert_eq!(samples[1], 11 - 30);
+    assert!(samples[3] < samples[2]);
+}
+
+#[test]
+fn round_trips_adpcm() {
+    let samples = sine(4000, 100.0);
+    let data = encode(&samples, WaveFormat::Adpcm);
+
+    assert_eq!(data.len(), 4 + 2000);
+
+    let decoded = decode(&data, WaveFormat::Adpcm, samples.len());
+
+    assert_eq!(decoded.len(), samples.len());
+    assert!(max_error(&samples, &decoded) < 1500);
+
+    //  PCM is lossless apart from the low byte of PCM8.
+    let pcm8 = decode(&encode(&samples, WaveFormat::Pcm8), WaveFormat::Pcm8, samples.len());
+
+    assert!(max_error(&samples, &pcm8) < 256);
+    assert_eq!(decode(&encode(&samples, WaveFormat::Pcm16), WaveFormat::Pcm16, 4000), samples);
+}
+
+#[test]
+fn writes_and_reads_wav() {
+    let sound = Sound {
+        sample_rate: 32000,
+        channels: vec![sine(100, 20.0), sine(100, 30.0)],
+        loop_start: Some(40),
+    };
+
+    let wav = sound.to_wav();
+
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(LittleEndian::read_u32(&wav[4..]) as usize, wav.len() - 8);
+    assert_eq!(LittleEndian::read_u16(&wav[22..]), 2);
+
+    let smpl = wav.len() - 68;
+
+    assert_eq!(&wav[smpl..smpl + 4], b"smpl");
+    assert_eq!(LittleEndian::read_u32(&wav[smpl + 8 + 44..]), 40);
+    assert_eq!(LittleEndian::read_u32(&wav[smpl + 8 + 48..]), 99);
+
+    assert_eq!(Sound::from_wav(&wav).unwrap(), sound);
+
+    let once = Sound {
+        loop_start: None,
+        ..sound
+    };
+
+    assert_eq!(Sound::from_wav(&once.to_wav()).unwrap(), once);
+    assert!(Sound::from_wav(b"RIFF\0\0\0\0WAVE").is_err());
+}
+
+#[test]
+fn round_trips_swav_and_swar() {
+    let sound = Sound {
+        sample_rate: 22050,
+        channels: vec![sine(1001, 50.0)],
+        loop_start: Some(203),
+    };
+
+    let swav = Swav::encode(&sound, WaveFormat::Adpcm).unwrap();
+
+    //  The loop is moved back to a word, after the ADPCM header.
+    assert_eq!(swav.loop_offset, 1 + 25);
+    assert_eq!(swav.loop_start(), 200);
+    assert_eq!(swav.timer, 759);
+
+    let data = swav.to_bytes();
+
+    assert_eq!(&data[..4], b"SWAV");
+    assert_eq!(data.len() % 4, 0);
+
+    let parsed = Swav::parse(&data).unwrap();
+    let decoded = parsed.decode();
+
+    assert_eq!(decoded.loop_start, Some(200));
+    assert_eq!(decoded.sample_rate, 22050);
+    assert!(decoded.len() >= 1001);
+    assert!(max_error(&sound.channels[0], &decoded.channels[0]) < 1500);
+
+    let pcm = Swav::encode(&sound, WaveFormat::Pcm16).unwrap();
+    let swar = Swar {
+        waves: vec![parsed, pcm],
+    };
+
+    let data = swar.to_bytes();
+
+    assert_eq!(&data[..4], b"SWAR");
+    assert_eq!(Swar::parse(&data).unwrap(), swar);
+    assert_eq!(swar.waves[1].decode().channels[0][..1001], sound.channels[0][..]);
+}
+
+#[test]
+fn rejects_unsupported_swav() {
+    let stereo = Sound {
+        sample_rate: 22050,
+        channels: vec![vec![0; 10], vec![0; 10]],
+        loop_start: None,
+    };
+
+    assert!(Swav::encode(&stereo, WaveFormat::Adpcm).is_err());
+
+    let fast = Sound {
+        sample_rate: 96000,
+        channels: vec![vec![0; 10]],
+        loop_start: None,
+    };
+
+    assert!(Swav::encode(&fast, WaveFormat::Pcm16).is_err());
+}
+
+#[test]
+fn round_trips_stereo_strm() {
+    let sound = Sound {
+        sample_rate: 32728,
+        channels: vec![sine(2500, 64.0), sine(2500, 90.0)],
+        loop_start: Some(1200),
+    };
+
+    let strm = Strm::encode(&sound, WaveFormat::Adpcm).unwrap();
+
+    //  1016 samples fit in a block of 0x200 bytes.
+    assert_eq!(strm.block_samples, 1016);
+    assert_eq!(strm.blocks[0].len(), 3);
+    assert_eq!(strm.last_block_samples, 2500 - 2032);
+    assert_eq!(strm.last_block_len, 4 + 234);
+    assert_eq!(strm.loop_points(), Some((1200, 2500)));
+
+    let data = strm.to_bytes();
+
+    assert_eq!(&data[..4], b"STRM");
+
+    //  Blocks alternate between the channels, starting at 0x68.
+    assert_eq!(LittleEndian::read_u32(&data[0x28..]), 0x68);
+    assert_eq!(data[0x68..0x268], strm.blocks[0][0][..]);
+    assert_eq!(data[0x268..0x468], strm.blocks[1][0][..]);
+
+    let parsed = Strm::parse(&data).unwrap();
+
+    assert_eq!(parsed, strm);
+
+    let decoded = parsed.decode();
+
+    assert_eq!(decoded.len(), 2500);
+    assert_eq!(decoded.loop_start, Some(1200));
+
+    for (original, decoded) in sound.channels.iter().zip(&decoded.channels) {
+        assert!(max_error(original, decoded) < 1500);
+    }
+}
+
+#[test]
+fn round_trips_pcm_strm() {
+    let sound = Sound {
+        sample_rate: 16000,
+        channels: vec![sine(700, 33.0)],
+        loop_start: None,
+    };
+
+    let strm = Strm::encode(&sound, WaveFormat::Pcm16).unwrap();
+    let parsed = Strm::parse(&strm.to_bytes()).unwrap();
+
+    assert_eq!(parsed.block_samples, 256);
+    assert_eq!(parsed.decode(), sound);
+    assert!(Strm::parse(&strm.to_bytes()[..0x40]).is_err());
+}
+
+#[test]
+fn rejects_unequal_strm_channels() {
+    let sound = Sound {
+        sample_rate: 16000,
+        channels: vec![sine(700, 33.0), sine(300, 33.0)],
+        loop_start: None,
+    };
+
+    assert!(Strm::encode(&sound, WaveFormat::Pcm16).is_err());
+
+    //  A stream built by hand with a short channel is padded with silence.
+    let mut strm = Strm::encode(&Sound { channels: vec![sine(700, 33.0); 2], ..sound }, WaveFormat::Pcm16).unwrap();
+    strm.blocks[1].truncate(1);
+
+    let decoded = Strm::parse(&strm.to_bytes()).unwrap().decode();
+
+    assert_eq!(decoded.channels[0], sine(700, 33.0));
+    assert!(decoded.channels[1][256..].iter().all(|&sample| sample == 0));
+}